@@ -0,0 +1,373 @@
+use std::fmt;
+use std::ops::{ Add, Div, Mul, Neg, Sub };
+use std::str::FromStr;
+
+// A software "double-double" float: an unevaluated sum hi + lo of two f64s
+// with |lo| <= ulp(hi) / 2, giving roughly 106 bits (~32 decimal digits) of
+// mantissa. Much slower than f64 but fast enough to push CPU deep zooms
+// from ~1e13 out to ~1e28 magnification.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct DoubleDouble {
+    pub hi: f64,
+    pub lo: f64,
+}
+
+impl DoubleDouble {
+    pub const ZERO: DoubleDouble = DoubleDouble { hi: 0.0, lo: 0.0 };
+
+    pub fn new(value: f64) -> Self {
+        DoubleDouble { hi: value, lo: 0.0 }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+
+    pub fn abs(self) -> Self {
+        if self.hi < 0.0 { -self } else { self }
+    }
+
+    pub fn square(self) -> Self {
+        let (p, e) = two_prod(self.hi, self.hi);
+        let e = e + 2.0 * self.hi * self.lo;
+        quick_two_sum(p, e)
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(value: f64) -> Self {
+        DoubleDouble::new(value)
+    }
+}
+
+// Error-free transformations (Knuth / Dekker). Each returns the rounded
+// result and the exact rounding error.
+fn two_sum(a: f64, b: f64) -> DoubleDouble {
+    let s = a + b;
+    let bb = s - a;
+    let err = (a - (s - bb)) + (b - bb);
+    DoubleDouble { hi: s, lo: err }
+}
+
+fn quick_two_sum(a: f64, b: f64) -> DoubleDouble {
+    let s = a + b;
+    DoubleDouble { hi: s, lo: b - (s - a) }
+}
+
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    (p, a.mul_add(b, -p))
+}
+
+impl Add for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn add(self, other: DoubleDouble) -> DoubleDouble {
+        let s = two_sum(self.hi, other.hi);
+        let t = two_sum(self.lo, other.lo);
+        let s = quick_two_sum(s.hi, s.lo + t.hi);
+        quick_two_sum(s.hi, s.lo + t.lo)
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn sub(self, other: DoubleDouble) -> DoubleDouble {
+        self + (-other)
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn neg(self) -> DoubleDouble {
+        DoubleDouble { hi: -self.hi, lo: -self.lo }
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn mul(self, other: DoubleDouble) -> DoubleDouble {
+        let (p, e) = two_prod(self.hi, other.hi);
+        let e = e + (self.hi * other.lo + self.lo * other.hi);
+        quick_two_sum(p, e)
+    }
+}
+
+impl Mul<f64> for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn mul(self, other: f64) -> DoubleDouble {
+        let (p, e) = two_prod(self.hi, other);
+        quick_two_sum(p, e + self.lo * other)
+    }
+}
+
+impl Div for DoubleDouble {
+    type Output = DoubleDouble;
+
+    // Long division: one f64 quotient digit at a time, three digits is enough
+    // to fill both halves.
+    fn div(self, other: DoubleDouble) -> DoubleDouble {
+        let q1 = self.hi / other.hi;
+        let r = self - other * q1;
+        let q2 = r.hi / other.hi;
+        let r = r - other * q2;
+        let q3 = r.hi / other.hi;
+        quick_two_sum(q1, q2) + DoubleDouble::new(q3)
+    }
+}
+
+// Prints up to 32 significant digits, the full precision of the type, so
+// `FromStr` reads back the same value to within the last bit of `lo`.
+// Moderate exponents use plain notation ("-0.75"), others "1.2345e-20".
+impl fmt::Display for DoubleDouble {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const DIGITS: usize = 32;
+        if self.hi == 0.0 || !self.hi.is_finite() {
+            return write!(f, "{}", self.hi);
+        }
+        let sign = if self.hi < 0.0 { "-" } else { "" };
+
+        // Scale into 1 <= x < 10, then peel off one decimal digit at a time
+        let mut x = self.abs();
+        let mut exponent = x.hi.log10().floor() as i32;
+        let mut power = DoubleDouble::new(1.0);
+        for _ in 0..exponent.unsigned_abs() {
+            power = power * 10.0;
+        }
+        x = if exponent < 0 { x * power } else { x / power };
+        if x.hi >= 10.0 {
+            x = x / DoubleDouble::new(10.0);
+            exponent += 1;
+        } else if x.hi < 1.0 {
+            x = x * 10.0;
+            exponent -= 1;
+        }
+        let mut digits = Vec::with_capacity(DIGITS + 1);
+        for _ in 0..=DIGITS {
+            let mut digit = x.hi.floor().clamp(0.0, 9.0);
+            if (x - DoubleDouble::new(digit)).hi < 0.0 && digit > 0.0 {
+                digit -= 1.0;
+            }
+            digits.push(digit as u8);
+            x = (x - DoubleDouble::new(digit)) * 10.0;
+        }
+
+        // Round the extra digit away, carrying into the leading ones
+        let round_up = digits.pop() >= Some(5);
+        if round_up {
+            let mut i = DIGITS;
+            loop {
+                if i == 0 {
+                    digits.insert(0, 1);
+                    digits.pop();
+                    exponent += 1;
+                    break;
+                }
+                i -= 1;
+                if digits[i] == 9 {
+                    digits[i] = 0;
+                } else {
+                    digits[i] += 1;
+                    break;
+                }
+            }
+        }
+        while digits.len() > 1 && digits.last() == Some(&0) {
+            digits.pop();
+        }
+
+        let text: String = digits.iter().map(|&d| (b'0' + d) as char).collect();
+        if (-5..21).contains(&exponent) {
+            let point = exponent + 1;
+            if point <= 0 {
+                write!(f, "{}0.{}{}", sign, "0".repeat(point.unsigned_abs() as usize), text)
+            } else if point as usize >= text.len() {
+                write!(f, "{}{}{}", sign, text, "0".repeat(point as usize - text.len()))
+            } else {
+                let (int, frac) = text.split_at(point as usize);
+                write!(f, "{}{}.{}", sign, int, frac)
+            }
+        } else if text.len() > 1 {
+            write!(f, "{}{}.{}e{}", sign, &text[..1], &text[1..], exponent)
+        } else {
+            write!(f, "{}{}e{}", sign, text, exponent)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDoubleDoubleError(String);
+
+impl fmt::Display for ParseDoubleDoubleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid number: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseDoubleDoubleError {}
+
+// Parses decimal strings such as "-0.743643887037158704752191506114774"
+// or "1.5e-20" without going through f64, so no digits are lost.
+impl FromStr for DoubleDouble {
+    type Err = ParseDoubleDoubleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseDoubleDoubleError(s.to_string());
+        let text = s.trim();
+        let (negative, text) = match text.as_bytes().first() {
+            Some(b'-') => (true, &text[1..]),
+            Some(b'+') => (false, &text[1..]),
+            _ => (false, text),
+        };
+        let (mantissa, exponent) = match text.find(['e', 'E']) {
+            Some(i) => (&text[..i], text[i + 1..].parse::<i32>().map_err(|_| err())?),
+            None => (text, 0),
+        };
+
+        let mut value = DoubleDouble::ZERO;
+        let mut scale = exponent;
+        let mut seen_digit = false;
+        let mut seen_point = false;
+        for ch in mantissa.chars() {
+            match ch {
+                '0'..='9' => {
+                    value = value * 10.0 + DoubleDouble::new(ch as u8 as f64 - b'0' as f64);
+                    if seen_point {
+                        scale -= 1;
+                    }
+                    seen_digit = true;
+                }
+                '.' if !seen_point => seen_point = true,
+                _ => return Err(err()),
+            }
+        }
+        if !seen_digit {
+            return Err(err());
+        }
+
+        let mut power = DoubleDouble::new(1.0);
+        for _ in 0..scale.unsigned_abs() {
+            power = power * 10.0;
+        }
+        let value = if scale < 0 { value / power } else { value * power };
+        Ok(if negative { -value } else { value })
+    }
+}
+
+// Helpers shared by the deep-zoom paths of the Mandelbrot labs, which keep
+// points of the complex plane as (re, im) pairs of double-doubles.
+
+// Parses "re,im". Each part goes straight to double-double so deep-zoom
+// coordinates keep all of their digits.
+pub fn parse_complex(raw: &str) -> Option<(DoubleDouble, DoubleDouble)> {
+    raw.split_once(',').and_then(|(re, im)| Some((re.parse().ok()?, im.parse().ok()?)))
+}
+
+// f64 stops resolving neighbouring pixels once the spacing between them gets
+// within a few dozen ulps of the coordinates themselves.
+pub fn needs_deep_precision(center: (DoubleDouble, DoubleDouble), spacing: f64) -> bool {
+    let magnitude = center.0.to_f64().abs().max(center.1.to_f64().abs()).max(1.0);
+    spacing < magnitude * f64::EPSILON * 64.0
+}
+
+// Escape-time iteration carried out in double-double so that neighbouring
+// pixels stay distinct at deep zoom levels. `step` maps (z, c) to the next
+// z; the iteration count and the last z, rounded to f64, come back.
+pub fn iterate_deep<S>(step: S, c: (DoubleDouble, DoubleDouble), max_iterations: u32, escape_radius: f64) -> (u32, (f64, f64))
+where
+    S: Fn((DoubleDouble, DoubleDouble), (DoubleDouble, DoubleDouble)) -> (DoubleDouble, DoubleDouble),
+{
+    let bailout = escape_radius * escape_radius;
+    let mut z = (DoubleDouble::ZERO, DoubleDouble::ZERO);
+    let mut iteration = 0;
+    while iteration < max_iterations && (z.0.square() + z.1.square()).hi <= bailout {
+        z = step(z, c);
+        iteration += 1;
+    }
+    (iteration, (z.0.to_f64(), z.1.to_f64()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dd(text: &str) -> DoubleDouble {
+        text.parse().unwrap()
+    }
+
+    // |a - b| relative to |b|, in double-double
+    fn relative_error(a: DoubleDouble, b: DoubleDouble) -> f64 {
+        ((a - b).abs().to_f64() / b.abs().to_f64()).abs()
+    }
+
+    // Printing keeps the 32 digits a double-double holds, and reading them
+    // back gives the same value to within the last bit of `lo`
+    #[test]
+    fn display_round_trips_beyond_f64_digits() {
+        for text in ["-0.7436438870371587047521915061147", "0.1318259042053119704931320563851", "1.234567890123456789012345678901e-20", "98765432109876543210.12345678901"] {
+            let value = dd(text);
+            let printed = value.to_string();
+            let mantissa = |text: &str| text.replace(['-', '.'], "").trim_start_matches('0').chars().take(30).collect::<String>();
+            assert_eq!(mantissa(&printed), mantissa(text), "{} printed as {}", text, printed);
+            assert!(relative_error(dd(&printed), value) < 2f64.powi(-104), "{} printed as {}", text, printed);
+        }
+        assert_eq!(dd("1.5e-20").to_string(), "1.5e-20");
+        assert_eq!(dd("-0.75").to_string(), "-0.75");
+        assert_eq!(dd("12345678901234567890").to_string(), "12345678901234567890");
+    }
+
+    #[test]
+    fn mul_and_div_keep_about_32_digits() {
+        let third = DoubleDouble::new(1.0) / DoubleDouble::new(3.0);
+        assert!(third.to_string().starts_with("0.3333333333333333333333333333333"), "1/3 = {}", third);
+        assert!(relative_error(third * DoubleDouble::new(3.0), DoubleDouble::new(1.0)) < 1e-31);
+
+        let sqrt2 = dd("1.41421356237309504880168872420969808");
+        assert!(relative_error(sqrt2 * sqrt2, DoubleDouble::new(2.0)) < 1e-31);
+        assert!(relative_error(DoubleDouble::new(2.0) / sqrt2, sqrt2) < 1e-31);
+        assert!(relative_error(sqrt2.square(), DoubleDouble::new(2.0)) < 1e-31);
+
+        // The cross term of (1 + 2^-60)² lies far below an f64's last bit
+        let x = DoubleDouble::new(1.0) + DoubleDouble::new(2f64.powi(-60));
+        assert_eq!(x * x, DoubleDouble { hi: 1.0, lo: 2f64.powi(-59) });
+        assert_eq!((x * x) / x, x);
+    }
+
+    #[test]
+    fn deep_precision_starts_where_f64_runs_out() {
+        let threshold = f64::EPSILON * 64.0;
+        let origin = (DoubleDouble::ZERO, DoubleDouble::ZERO);
+        assert!(!needs_deep_precision(origin, 1e-10));
+        assert!(!needs_deep_precision(origin, threshold * 1.01));
+        assert!(needs_deep_precision(origin, threshold * 0.99));
+        // The threshold grows with the coordinates, not below 1
+        let far = (dd("-1.5"), dd("0.2"));
+        assert!(needs_deep_precision(far, threshold * 1.4));
+        assert!(!needs_deep_precision(far, threshold * 1.6));
+        assert!(needs_deep_precision((dd("0.001"), DoubleDouble::ZERO), threshold * 0.99));
+    }
+
+    #[test]
+    fn parse_complex_takes_exactly_two_numbers() {
+        assert_eq!(parse_complex("-0.75,0.1"), Some((dd("-0.75"), dd("0.1"))));
+        assert_eq!(parse_complex(" 1e-3 , -2 "), Some((dd("0.001"), dd("-2"))));
+        for raw in ["", "1", "1,", ",2", "1,2,3", "a,b", "1.2.3,0", "1e,0", "--1,0", "1,.", "1;2"] {
+            assert_eq!(parse_complex(raw), None, "{:?} parsed", raw);
+        }
+    }
+
+    #[test]
+    fn deep_iteration_matches_f64_on_shallow_points() {
+        let step = |(re, im): (DoubleDouble, DoubleDouble), (c_re, c_im): (DoubleDouble, DoubleDouble)| {
+            (re.square() - im.square() + c_re, (re * im) * 2.0 + c_im)
+        };
+        // Escapes after 15 steps, and 0 never does
+        let (iteration, _) = iterate_deep(step, (dd("0.3"), dd("0.6")), 1000, 2.0);
+        assert_eq!(iteration, 15);
+        assert_eq!(iterate_deep(step, (DoubleDouble::ZERO, DoubleDouble::ZERO), 1000, 2.0), (1000, (0.0, 0.0)));
+    }
+}
@@ -0,0 +1,42 @@
+use crate::Palette;
+
+// Built-in colormaps, stored as 17 evenly spaced samples of the matplotlib
+// originals and linearly interpolated by `Palette::sample`.
+pub const COLORMAPS: &[&str] = &["viridis", "inferno", "magma", "grayscale"];
+
+const VIRIDIS: [[u8; 3]; 17] = [
+    [68, 1, 84], [72, 24, 106], [71, 44, 122], [66, 63, 133],
+    [58, 81, 139], [50, 98, 141], [44, 113, 142], [38, 129, 142],
+    [32, 144, 140], [31, 159, 136], [39, 173, 128], [61, 187, 115],
+    [92, 200, 98], [129, 211, 76], [170, 219, 49], [213, 226, 25],
+    [253, 231, 37],
+];
+
+const INFERNO: [[u8; 3]; 17] = [
+    [0, 0, 4], [10, 7, 35], [32, 12, 73], [60, 9, 100],
+    [86, 15, 109], [112, 25, 110], [137, 34, 105], [162, 43, 97],
+    [187, 54, 84], [208, 69, 68], [227, 89, 50], [240, 113, 30],
+    [249, 140, 9], [251, 170, 15], [248, 201, 50], [241, 232, 101],
+    [252, 255, 164],
+];
+
+const MAGMA: [[u8; 3]; 17] = [
+    [0, 0, 4], [9, 7, 33], [28, 16, 70], [53, 16, 106],
+    [80, 18, 123], [105, 28, 128], [130, 37, 129], [155, 46, 127],
+    [181, 54, 121], [207, 64, 111], [229, 80, 99], [244, 105, 92],
+    [251, 135, 96], [253, 165, 113], [254, 194, 135], [253, 224, 161],
+    [252, 253, 191],
+];
+
+const GRAYSCALE: [[u8; 3]; 2] = [[0, 0, 0], [255, 255, 255]];
+
+pub fn colormap(name: &str) -> Option<Palette> {
+    let lut: &[[u8; 3]] = match name {
+        "viridis" => &VIRIDIS,
+        "inferno" => &INFERNO,
+        "magma" => &MAGMA,
+        "grayscale" | "greyscale" => &GRAYSCALE,
+        _ => return None,
+    };
+    Some(Palette::new(lut.to_vec()))
+}
@@ -1,6 +1,14 @@
 use image::Rgb;
 
-pub fn hsv_to_rgb(h: f32, s: f32, v: f32) -> Rgb<u8> {
+mod colormap;
+mod palette;
+pub use colormap::{ colormap, COLORMAPS };
+pub use palette::Palette;
+
+// Hue in degrees, saturation and value in 0.0..=1.0. Components come back
+// in 0.0..=1.0 too, unquantized for callers that need more than 8 bits per
+// channel; the u8 variants below round these down.
+pub fn hsv_to_rgb_f32(h: f32, s: f32, v: f32) -> [f32; 3] {
     let c = v * s;
     let h_prime = h / 60.0;
     let x = c * (1.0 - ((h_prime % 2.0) - 1.0).abs());
@@ -20,24 +28,26 @@ pub fn hsv_to_rgb(h: f32, s: f32, v: f32) -> Rgb<u8> {
         (c, 0.0, x)
     };
 
-    Rgb([
-        ((r + m) * 255.0) as u8,
-        ((g + m) * 255.0) as u8,
-        ((b + m) * 255.0) as u8,
-    ])
+    [r + m, g + m, b + m]
+}
+
+pub fn hsv_to_rgb(h: f32, s: f32, v: f32) -> Rgb<u8> {
+    Rgb(hsv_to_rgb_f32(h, s, v).map(|c| (c * 255.0) as u8))
 }
 
 pub fn hsv_to_rgb_u8(h: f32, s: f32, v: f32) -> (u8, u8, u8) {
-    if s == 0.0 { let val = (v * 255.0) as u8; return (val, val, val); }
-    let h_sector = h / 60.0;
-    let i = h_sector.floor() as i32;
-    let f = h_sector - i as f32;
-    let p = v * (1.0 - s);
-    let q = v * (1.0 - s * f);
-    let t = v * (1.0 - s * (1.0 - f));
-    let (r, g, b) = match i {
-        0 => (v, t, p), 1 => (q, v, p), 2 => (p, v, t),
-        3 => (p, q, v), 4 => (t, p, v), _ => (v, p, q),
-    };
-    ((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8)
+    let Rgb([r, g, b]) = hsv_to_rgb(h, s, v);
+    (r, g, b)
 }
+
+// The sRGB transfer function, between stored 0.0..=1.0 component values and
+// linear light. Mixing colors (gradients, supersample averages) is only
+// physically right on linear values; PNG and friends store the encoded ones.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+}
+
@@ -0,0 +1,138 @@
+use image::Rgb;
+use std::io;
+use std::path::Path;
+
+use crate::srgb_to_linear;
+
+// A color gradient built from evenly spaced RGB stops.
+//
+// Palettes can be loaded from either a JSON array of `[r, g, b]` triples or a
+// plain text file with one stop per line, written as `r g b`, `r, g, b` or
+// `#rrggbb`. Blank lines and lines starting with `//` are ignored.
+#[derive(Clone, Debug)]
+pub struct Palette {
+    stops: Vec<[f32; 3]>,
+}
+
+impl Palette {
+    pub fn new(stops: Vec<[u8; 3]>) -> Self {
+        assert!(!stops.is_empty(), "a palette needs at least one color stop");
+        Palette {
+            stops: stops.iter().map(|s| [s[0] as f32, s[1] as f32, s[2] as f32]).collect(),
+        }
+    }
+
+    // The fully saturated HSV hue wheel the renderers use without a palette.
+    // It is piecewise linear in RGB between multiples of 60°, so seven stops
+    // reproduce it exactly.
+    pub fn hsv_wheel() -> Self {
+        Palette::new(vec![
+            [255, 0, 0],
+            [255, 255, 0],
+            [0, 255, 0],
+            [0, 255, 255],
+            [0, 0, 255],
+            [255, 0, 255],
+            [255, 0, 0],
+        ])
+    }
+
+    // Crossfade from `self` (s = 0.0) to `other` (s = 1.0), resampled to 256
+    // stops so palettes with different stop counts line up.
+    pub fn blend(&self, other: &Palette, s: f32) -> Palette {
+        let stops = (0..256)
+            .map(|i| {
+                let t = i as f32 / 255.0;
+                let (a, b) = (self.interpolate(t), other.interpolate(t));
+                [0, 1, 2].map(|c| a[c] + (b[c] - a[c]) * s)
+            })
+            .collect();
+        Palette { stops }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let stops = if text.trim_start().starts_with('[') {
+            serde_json::from_str::<Vec<[u8; 3]>>(text)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        } else {
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with("//"))
+                .map(parse_stop)
+                .collect::<io::Result<Vec<_>>>()?
+        };
+        if stops.is_empty() {
+            return Err(invalid("palette has no color stops"));
+        }
+        Ok(Palette::new(stops))
+    }
+
+    // Samples the gradient at t in 0.0..=1.0, linearly interpolating between
+    // the two nearest stops. Values outside the range are clamped.
+    pub fn sample(&self, t: f32) -> Rgb<u8> {
+        Rgb(self.interpolate(t).map(|c| c.round() as u8))
+    }
+
+    // Unquantized version of `sample` with components in 0.0..=1.0.
+    pub fn sample_f32(&self, t: f32) -> [f32; 3] {
+        self.interpolate(t).map(|c| c / 255.0)
+    }
+
+    // Samples the gradient like `sample_f32`, but interpolates between the
+    // stops in linear light and returns linear components, for callers that
+    // keep mixing colors and encode to sRGB at the end.
+    pub fn sample_linear(&self, t: f32) -> [f32; 3] {
+        let (a, b, frac) = self.neighbours(t);
+        [0, 1, 2].map(|c| {
+            let (a, b) = (srgb_to_linear(a[c] / 255.0), srgb_to_linear(b[c] / 255.0));
+            a + (b - a) * frac
+        })
+    }
+
+    fn interpolate(&self, t: f32) -> [f32; 3] {
+        let (a, b, frac) = self.neighbours(t);
+        [
+            a[0] + (b[0] - a[0]) * frac,
+            a[1] + (b[1] - a[1]) * frac,
+            a[2] + (b[2] - a[2]) * frac,
+        ]
+    }
+
+    // The two stops around t and how far t is from the first to the second
+    fn neighbours(&self, t: f32) -> ([f32; 3], [f32; 3], f32) {
+        let last = self.stops.len() - 1;
+        let pos = t.clamp(0.0, 1.0) * last as f32;
+        let i = (pos as usize).min(last);
+        let j = (i + 1).min(last);
+        (self.stops[i], self.stops[j], pos - i as f32)
+    }
+}
+
+fn parse_stop(line: &str) -> io::Result<[u8; 3]> {
+    if let Some(hex) = line.strip_prefix('#') {
+        let value = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 6)
+            .ok_or_else(|| invalid(&format!("bad hex color stop: {}", line)))?;
+        return Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8]);
+    }
+    let parts: Vec<u8> = line
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<u8>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid(&format!("bad color stop: {}", line)))?;
+    match parts[..] {
+        [r, g, b] => Ok([r, g, b]),
+        _ => Err(invalid(&format!("color stop needs three components: {}", line))),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
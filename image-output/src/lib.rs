@@ -0,0 +1,175 @@
+use image::codecs::pnm::{ PnmEncoder, PnmSubtype, SampleEncoding };
+use image::error::{ EncodingError, ImageFormatHint };
+use image::{ ImageBuffer, ImageError, ImageFormat, ImageResult, Rgb, RgbImage };
+use std::fs::File;
+use std::io::{ self, BufReader, BufWriter };
+use std::path::Path;
+
+// Output image formats.
+//
+// The 8-bit formats store the colored image. EXR is different: it stores
+// the raw (smooth) iteration value of every pixel as 32-bit float in all
+// three channels, so the image can be recolored later without re-rendering.
+// Interior points hold `max_iterations`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Jpeg,
+    Tiff,
+    Ppm,
+    Exr,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "png" => Some(Format::Png),
+            "jpg" | "jpeg" => Some(Format::Jpeg),
+            "tif" | "tiff" => Some(Format::Tiff),
+            "ppm" => Some(Format::Ppm),
+            "exr" => Some(Format::Exr),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &str) -> Option<Self> {
+        Path::new(path).extension()?.to_str().and_then(Format::from_name)
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Jpeg => "jpg",
+            Format::Tiff => "tiff",
+            Format::Ppm => "ppm",
+            Format::Exr => "exr",
+        }
+    }
+}
+
+// Picks the output path and format: an explicit format wins, then the
+// extension of an explicit path, then PNG. Without a path the image goes to
+// ./out/<stem>.<ext>.
+pub fn resolve(output: Option<&str>, format: Option<Format>, stem: &str) -> (String, Format) {
+    let format = format
+        .or_else(|| output.and_then(Format::from_path))
+        .unwrap_or(Format::Png);
+    let path = match output {
+        Some(path) => path.to_string(),
+        None => format!("./out/{}.{}", stem, format.extension()),
+    };
+    (path, format)
+}
+
+// PNG output carries `metadata` as text chunks (keyword, text); the other
+// formats ignore it.
+pub fn save(path: &str, format: Format, image: &RgbImage, metadata: &[(&str, String)]) -> ImageResult<()> {
+    let image_format = match format {
+        Format::Png => {
+            return write_png(path, image.width(), image.height(), png::BitDepth::Eight, image.as_raw(), metadata);
+        }
+        Format::Jpeg => ImageFormat::Jpeg,
+        Format::Tiff => ImageFormat::Tiff,
+        Format::Ppm => {
+            let encoder = PnmEncoder::new(BufWriter::new(File::create(path)?))
+                .with_subtype(PnmSubtype::Pixmap(SampleEncoding::Binary));
+            return image.write_with_encoder(encoder);
+        }
+        Format::Exr => panic!("EXR output stores iteration values, use save_exr"),
+    };
+    image.save_with_format(path, image_format)
+}
+
+// 16 bits per channel, only PNG and TIFF support it
+pub fn save16(
+    path: &str,
+    format: Format,
+    image: &ImageBuffer<Rgb<u16>, Vec<u16>>,
+    metadata: &[(&str, String)],
+) -> ImageResult<()> {
+    let image_format = match format {
+        Format::Png => {
+            // PNG stores 16-bit samples big-endian
+            let bytes: Vec<u8> = image.as_raw().iter().flat_map(|c| c.to_be_bytes()).collect();
+            return write_png(path, image.width(), image.height(), png::BitDepth::Sixteen, &bytes, metadata);
+        }
+        Format::Tiff => ImageFormat::Tiff,
+        other => panic!("{:?} output does not support 16-bit channels", other),
+    };
+    image.save_with_format(path, image_format)
+}
+
+// Same encoder settings as the `image` crate's PNG encoder, plus text chunks
+fn write_png(
+    path: &str,
+    width: u32,
+    height: u32,
+    depth: png::BitDepth,
+    data: &[u8],
+    metadata: &[(&str, String)],
+) -> ImageResult<()> {
+    let encoding_error = |e: png::EncodingError| {
+        ImageError::Encoding(EncodingError::new(ImageFormatHint::Exact(ImageFormat::Png), e))
+    };
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(depth);
+    encoder.set_filter(png::FilterType::Sub);
+    encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+    // tEXt only holds Latin-1, so text beyond it, such as a path with other
+    // characters, goes into a UTF-8 iTXt chunk instead
+    for (keyword, text) in metadata {
+        if text.chars().all(|c| u32::from(c) <= 0xff) {
+            encoder.add_text_chunk(keyword.to_string(), text.clone()).map_err(encoding_error)?;
+        } else {
+            encoder.add_itxt_chunk(keyword.to_string(), text.clone()).map_err(encoding_error)?;
+        }
+    }
+    let mut writer = encoder.write_header().map_err(encoding_error)?;
+    writer.write_image_data(data).map_err(encoding_error)?;
+    writer.finish().map_err(encoding_error)
+}
+
+// The tEXt and iTXt chunks of a PNG file as (keyword, text) pairs
+pub fn read_png_text(path: &str) -> io::Result<Vec<(String, String)>> {
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+    let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    let reader = decoder.read_info().map_err(invalid)?;
+    let info = reader.info();
+    let mut text: Vec<_> = info.uncompressed_latin1_text.iter().map(|chunk| (chunk.keyword.clone(), chunk.text.clone())).collect();
+    for chunk in &info.utf8_text {
+        text.push((chunk.keyword.clone(), chunk.get_text().map_err(invalid)?));
+    }
+    Ok(text)
+}
+
+pub fn save_exr(path: &str, width: u32, height: u32, values: &[f32]) -> ImageResult<()> {
+    let buffer: ImageBuffer<Rgb<f32>, Vec<f32>> = ImageBuffer::from_fn(width, height, |x, y| {
+        let value = values[(y * width + x) as usize];
+        Rgb([value, value, value])
+    });
+    buffer.save_with_format(path, ImageFormat::OpenExr)
+}
+
+pub fn create_parent_dir(path: &str) {
+    if let Some(parent) = Path::new(path).parent() {
+        std::fs::create_dir_all(parent).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_text_round_trips_beyond_latin1() {
+        let path = std::env::temp_dir().join(format!("image_output_text_{}.png", std::process::id()));
+        let path = path.to_str().unwrap();
+        let metadata = [("Palette", "/tmp/café.json".to_string()), ("Formula", "z^2 + c·π".to_string())];
+        save(path, Format::Png, &RgbImage::new(2, 1), &metadata).unwrap();
+        let text = read_png_text(path);
+        std::fs::remove_file(path).unwrap();
+        let expected: Vec<_> = metadata.iter().map(|(keyword, text)| (keyword.to_string(), text.clone())).collect();
+        assert_eq!(text.unwrap(), expected);
+    }
+}
@@ -0,0 +1,193 @@
+use double_double::DoubleDouble;
+use num_complex::Complex;
+
+// Escape-time formulas selectable with `--fractal` and `--power`.
+//
+// Each formula is one step z -> f(z, c) of the orbit, in f64 and in
+// double-double for deep zooms. The render loop is generic over the
+// formula; `Formula` picks the instantiation once per pixel. Every formula
+// has degree >= 2, which keeps the bailout radius of 2 valid.
+pub trait Fractal {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64>;
+
+    fn step_deep(&self, z: (DoubleDouble, DoubleDouble), c: (DoubleDouble, DoubleDouble)) -> (DoubleDouble, DoubleDouble);
+
+    // Closed-form test for points known never to escape; they skip the
+    // iteration loop entirely.
+    fn is_interior(&self, _c: Complex<f64>) -> bool {
+        false
+    }
+
+    // Growth rate of |z| once the orbit escapes, used by smooth coloring
+    fn degree(&self) -> f64 {
+        2.0
+    }
+}
+
+// z² + c
+pub struct Mandelbrot;
+
+impl Fractal for Mandelbrot {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        z * z + c
+    }
+
+    fn step_deep(&self, (z_re, z_im): (DoubleDouble, DoubleDouble), (c_re, c_im): (DoubleDouble, DoubleDouble)) -> (DoubleDouble, DoubleDouble) {
+        (z_re.square() - z_im.square() + c_re, z_re * z_im * 2.0 + c_im)
+    }
+
+    // Points inside the main cardioid or the period-2 bulb never escape
+    fn is_interior(&self, c: Complex<f64>) -> bool {
+        let q = (c.re - 0.25) * (c.re - 0.25) + c.im * c.im;
+        let in_cardioid = q * (q + (c.re - 0.25)) <= 0.25 * c.im * c.im;
+        let in_bulb = (c.re + 1.0) * (c.re + 1.0) + c.im * c.im <= 0.0625;
+        in_cardioid || in_bulb
+    }
+}
+
+// (|Re z| + i|Im z|)² + c. Only the imaginary part changes: squaring
+// cancels the signs of the real part, so it is 2|Re z||Im z| + Im c.
+pub struct BurningShip;
+
+impl Fractal for BurningShip {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        let z = Complex::new(z.re.abs(), z.im.abs());
+        z * z + c
+    }
+
+    fn step_deep(&self, (z_re, z_im): (DoubleDouble, DoubleDouble), (c_re, c_im): (DoubleDouble, DoubleDouble)) -> (DoubleDouble, DoubleDouble) {
+        (z_re.square() - z_im.square() + c_re, z_re.abs() * z_im.abs() * 2.0 + c_im)
+    }
+}
+
+// conj(z)² + c, the Tricorn or Mandelbar set. Conjugating only flips the
+// sign of the imaginary part of the square.
+pub struct Tricorn;
+
+impl Fractal for Tricorn {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        let z = z.conj();
+        z * z + c
+    }
+
+    fn step_deep(&self, (z_re, z_im): (DoubleDouble, DoubleDouble), (c_re, c_im): (DoubleDouble, DoubleDouble)) -> (DoubleDouble, DoubleDouble) {
+        (z_re.square() - z_im.square() + c_re, c_im - z_re * z_im * 2.0)
+    }
+}
+
+// z^d + c for `--power d`. Integer powers use binary exponentiation, which
+// takes about 2·log2(d) complex multiplications and works in both number
+// types. Other powers go through the polar form of `Complex::powf`, which
+// has no double-double version, so those steps are always done in f64.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Multibrot {
+    power: f64,
+    integer: Option<u32>,
+}
+
+impl Multibrot {
+    pub fn new(power: f64) -> Self {
+        let integer = (power.fract() == 0.0 && power <= u32::MAX as f64).then_some(power as u32);
+        Multibrot { power, integer }
+    }
+}
+
+impl Fractal for Multibrot {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        match self.integer {
+            Some(n) => pow_by_squaring(z, n, |a, b| a * b) + c,
+            None => z.powf(self.power) + c,
+        }
+    }
+
+    fn step_deep(&self, z: (DoubleDouble, DoubleDouble), (c_re, c_im): (DoubleDouble, DoubleDouble)) -> (DoubleDouble, DoubleDouble) {
+        let Some(n) = self.integer else {
+            let z = self.step(Complex::new(z.0.to_f64(), z.1.to_f64()), Complex::new(c_re.to_f64(), c_im.to_f64()));
+            return (DoubleDouble::new(z.re), DoubleDouble::new(z.im));
+        };
+        let (re, im) = pow_by_squaring(z, n, |(a_re, a_im), (b_re, b_im)| (a_re * b_re - a_im * b_im, a_re * b_im + a_im * b_re));
+        (re + c_re, im + c_im)
+    }
+
+    fn degree(&self) -> f64 {
+        self.power
+    }
+}
+
+// z^n for n >= 1, generic over the number type
+fn pow_by_squaring<T: Copy>(z: T, mut n: u32, mul: impl Fn(T, T) -> T) -> T {
+    let mut base = z;
+    let mut result = None;
+    loop {
+        if n & 1 == 1 {
+            result = Some(match result {
+                Some(result) => mul(result, base),
+                None => base,
+            });
+        }
+        n >>= 1;
+        if n == 0 {
+            return result.unwrap();
+        }
+        base = mul(base, base);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Mandelbrot,
+    BurningShip,
+    Tricorn,
+}
+
+impl Kind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mandelbrot" => Some(Kind::Mandelbrot),
+            "burning-ship" => Some(Kind::BurningShip),
+            "tricorn" | "mandelbar" => Some(Kind::Tricorn),
+            _ => None,
+        }
+    }
+}
+
+// A `Kind` together with its parameters, resolved once per render
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Formula {
+    Mandelbrot,
+    BurningShip,
+    Tricorn,
+    Multibrot(Multibrot),
+}
+
+impl Formula {
+    // `power` only applies to the Mandelbrot family; 2 is the plain set
+    pub fn new(kind: Kind, power: f64) -> Self {
+        match kind {
+            Kind::Mandelbrot if power != 2.0 => Formula::Multibrot(Multibrot::new(power)),
+            Kind::Mandelbrot => Formula::Mandelbrot,
+            Kind::BurningShip => Formula::BurningShip,
+            Kind::Tricorn => Formula::Tricorn,
+        }
+    }
+
+    // Center and zoom used when the command line gives none, chosen so the
+    // whole set fits the 3:2 view. The Tricorn reaches from -2 to 1 on the
+    // real axis and about ±1.75 above and below it; Multibrots shrink
+    // towards the unit disk as the power grows.
+    pub fn default_view(self) -> ((f64, f64), f64) {
+        match self {
+            Formula::Mandelbrot => ((-0.5, 0.0), 1.0),
+            Formula::BurningShip => ((-0.5, -0.5), 0.75),
+            Formula::Tricorn => ((-0.5, 0.0), 0.55),
+            Formula::Multibrot(_) => ((0.0, 0.0), 0.75),
+        }
+    }
+
+    pub fn degree(self) -> f64 {
+        match self {
+            Formula::Multibrot(multibrot) => multibrot.degree(),
+            _ => Mandelbrot.degree(),
+        }
+    }
+}
@@ -1,45 +1,283 @@
 use image::{ ImageBuffer, Rgb };
+use std::sync::atomic::{ AtomicBool, Ordering };
 use std::time::Instant;
 use num_complex::Complex;
-use hsv_to_rgb::hsv_to_rgb;
+use hsv_to_rgb::{ colormap, hsv_to_rgb, Palette, COLORMAPS };
+use double_double::{ iterate_deep, needs_deep_precision, DoubleDouble };
+use image_output::{ self as output, Format };
+
+mod fractal;
+use fractal::{ BurningShip, Formula, Fractal, Kind, Mandelbrot, Tricorn };
+
+struct Args {
+    smooth: bool,
+    palette: Option<String>,
+    colormap: Option<String>,
+    center: (DoubleDouble, DoubleDouble),
+    zoom: f64,
+    max_iterations: u32,
+    output: Option<String>,
+    format: Option<Format>,
+    fractal: Kind,
+    power: f64,
+}
+
+impl Args {
+    fn parse() -> Self {
+        let mut args = Args {
+            smooth: false,
+            palette: None,
+            colormap: None,
+            center: (DoubleDouble::ZERO, DoubleDouble::ZERO),
+            zoom: 1.0,
+            max_iterations: 1000,
+            output: None,
+            format: None,
+            fractal: Kind::Mandelbrot,
+            power: 2.0,
+        };
+        // Without --center or --zoom the view defaults to the whole fractal
+        let (mut center, mut zoom) = (None, None);
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--smooth" => args.smooth = true,
+                "--palette" => args.palette = Some(value(&mut iter, &arg)),
+                "--colormap" => args.colormap = Some(value(&mut iter, &arg)),
+                "--center" => center = Some(parse_center(&value(&mut iter, &arg))),
+                "--zoom" => zoom = Some(parse_value(&mut iter, &arg)),
+                "--iterations" => args.max_iterations = parse_value(&mut iter, &arg),
+                "--output" => args.output = Some(value(&mut iter, &arg)),
+                "--format" => args.format = Some(parse_format(&value(&mut iter, &arg))),
+                "--fractal" => args.fractal = parse_fractal(&value(&mut iter, &arg)),
+                "--power" => args.power = parse_value(&mut iter, &arg),
+                other => {
+                    eprintln!("Unknown argument: {}", other);
+                    std::process::exit(2);
+                }
+            }
+        }
+        if args.power.is_nan() || args.power < 2.0 {
+            eprintln!("Invalid value for --power: {} (must be at least 2)", args.power);
+            std::process::exit(2);
+        }
+        if args.power != 2.0 && args.fractal != Kind::Mandelbrot {
+            eprintln!("--power only applies to --fractal mandelbrot");
+            std::process::exit(2);
+        }
+        let ((default_re, default_im), default_zoom) = args.formula().default_view();
+        args.center = center.unwrap_or((DoubleDouble::new(default_re), DoubleDouble::new(default_im)));
+        args.zoom = zoom.unwrap_or(default_zoom);
+        args
+    }
+
+    fn formula(&self) -> Formula {
+        Formula::new(self.fractal, self.power)
+    }
+}
+
+fn value(iter: &mut impl Iterator<Item = String>, flag: &str) -> String {
+    iter.next().unwrap_or_else(|| {
+        eprintln!("Missing value for {}", flag);
+        std::process::exit(2);
+    })
+}
+
+fn parse_value<T: std::str::FromStr>(iter: &mut impl Iterator<Item = String>, flag: &str) -> T {
+    let raw = value(iter, flag);
+    raw.parse().unwrap_or_else(|_| {
+        eprintln!("Invalid value for {}: {}", flag, raw);
+        std::process::exit(2);
+    })
+}
+
+fn parse_format(raw: &str) -> Format {
+    Format::from_name(raw).unwrap_or_else(|| {
+        eprintln!("Unknown format {} (expected png, jpeg, tiff, ppm or exr)", raw);
+        std::process::exit(2);
+    })
+}
+
+fn parse_fractal(raw: &str) -> Kind {
+    Kind::from_name(raw).unwrap_or_else(|| {
+        eprintln!("Unknown fractal {} (expected mandelbrot, burning-ship or tricorn)", raw);
+        std::process::exit(2);
+    })
+}
+
+fn parse_center(raw: &str) -> (DoubleDouble, DoubleDouble) {
+    double_double::parse_complex(raw).unwrap_or_else(|| {
+        eprintln!("Invalid value for --center: {} (expected re,im)", raw);
+        std::process::exit(2);
+    })
+}
+
+// A gradient file takes precedence over a named colormap; with neither the
+// renderer falls back to the HSV wheel.
+fn select_palette(args: &Args) -> Option<Palette> {
+    if let Some(path) = &args.palette {
+        return Some(Palette::load(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load palette {}: {}", path, e);
+            std::process::exit(1);
+        }));
+    }
+    args.colormap.as_deref().map(|name| {
+        colormap(name).unwrap_or_else(|| {
+            eprintln!("Unknown colormap {} (expected one of: {})", name, COLORMAPS.join(", "));
+            std::process::exit(2);
+        })
+    })
+}
+
+// Normalized iteration count: removes the integer banding by using how far
+// past the bailout radius |z| landed when the orbit escaped. Each step
+// raises |z| to about the `degree`-th power, which sets the log base.
+fn smooth_iteration(iteration: u32, z: Complex<f64>, degree: f64) -> f64 {
+    let log_zn = z.norm_sqr().ln() / 2.0;
+    let nu = (log_zn / std::f64::consts::LN_2).ln() / degree.ln();
+    iteration as f64 + 1.0 - nu
+}
+
+// Escape-time kernel. Points the fractal's closed-form test places in the
+// interior skip the whole iteration loop.
+fn iterate<F: Fractal>(fractal: &F, c: Complex<f64>, max_iterations: u32) -> (u32, Complex<f64>) {
+    let mut z = Complex::new(0.0, 0.0);
+    let mut iteration = 0;
+    if fractal.is_interior(c) {
+        iteration = max_iterations;
+    }
+    while iteration < max_iterations && z.norm_sqr() <= 4.0 {
+        z = fractal.step(z, c);
+        iteration += 1;
+    }
+    (iteration, z)
+}
+
+// Set by the first Ctrl-C; the render loop stops at the next row boundary
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+fn install_interrupt_handler() {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        eprintln!("\nInterrupted, finishing current row (Ctrl-C again to abort)");
+    })
+    .expect("failed to install Ctrl-C handler");
+}
+
+// Same format as lab82's resume files: the rows finished so far, alongside
+// the arguments needed to start the render again
+fn write_resume_file(path: &str, width: u32, height: u32, rows_done: u32) -> std::io::Result<()> {
+    let resume = serde_json::json!({
+        "args": std::env::args().skip(1).collect::<Vec<_>>(),
+        "width": width,
+        "height": height,
+        "completed_rows": [[0, rows_done]],
+    });
+    std::fs::write(path, serde_json::to_string_pretty(&resume)?)
+}
 
 fn main() {
+    let args = Args::parse();
+    install_interrupt_handler();
+    let palette = select_palette(&args);
+
     let image_width:u32 = 1920;
     let image_height:u32 = 1080;
-    let max_iterations:u32 = 1000;
+    let max_iterations:u32 = args.max_iterations;
+    let formula = args.formula();
+
+    let (output_path, format) = output::resolve(args.output.as_deref(), args.format, "mandelbrot_single");
 
     let mut imgbuf = ImageBuffer::new(image_width, image_height);
+    // Raw per-pixel values, only kept when writing EXR
+    let mut values = if format == Format::Exr {
+        vec![f32::NAN; (image_width * image_height) as usize]
+    } else {
+        Vec::new()
+    };
 
-    let x_min:f64 = -2.0;
-    let x_max:f64 = 1.0;
-    let y_min:f64 = -1.0;
-    let y_max:f64 = 1.0;
+    let (center_re, center_im) = args.center;
+    let view_width = 3.0 / args.zoom;
+    let view_height = 2.0 / args.zoom;
+
+    let x_min:f64 = center_re.to_f64() - view_width / 2.0;
+    let x_max:f64 = center_re.to_f64() + view_width / 2.0;
+    let y_min:f64 = center_im.to_f64() - view_height / 2.0;
+    let y_max:f64 = center_im.to_f64() + view_height / 2.0;
+
+    let deep = needs_deep_precision(args.center, view_width / image_width as f64);
+    if deep {
+        println!("Pixel spacing below f64 resolution, using double-double arithmetic");
+    }
 
     let start = Instant::now();
+    let mut rows_done = 0;
     for y in 0..image_height {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            break;
+        }
         for x in 0..image_width {
-            // TODO: Optimize mapping from pixel to complex plane
-            let cx = x_min + (x as f64 / image_width as f64) * (x_max - x_min);
-            let cy = y_min + (y as f64 / image_height as f64) * (y_max - y_min);
-            let c = Complex::new(cx, cy);   
-            let mut z = Complex::new(0.0, 0.0);
-            let mut iteration = 0;
-            while iteration < max_iterations && z.norm_sqr() <= 4.0 {
-                z = z * z + c;
-                iteration += 1;
-            }
-            let hue = (iteration as f32 / max_iterations as f32) * 360.0;
-            let pixel: Rgb<u8> = hsv_to_rgb(hue, 1.0, 1.0);
+            let (iteration, z) = if deep {
+                let c_re = center_re + DoubleDouble::new((x as f64 / image_width as f64 - 0.5) * view_width);
+                let c_im = center_im + DoubleDouble::new((y as f64 / image_height as f64 - 0.5) * view_height);
+                let c = (c_re, c_im);
+                let (iteration, (z_re, z_im)) = match formula {
+                    Formula::Mandelbrot => iterate_deep(|z, c| Mandelbrot.step_deep(z, c), c, max_iterations, 2.0),
+                    Formula::BurningShip => iterate_deep(|z, c| BurningShip.step_deep(z, c), c, max_iterations, 2.0),
+                    Formula::Tricorn => iterate_deep(|z, c| Tricorn.step_deep(z, c), c, max_iterations, 2.0),
+                    Formula::Multibrot(multibrot) => iterate_deep(|z, c| multibrot.step_deep(z, c), c, max_iterations, 2.0),
+                };
+                (iteration, Complex::new(z_re, z_im))
+            } else {
+                // TODO: Optimize mapping from pixel to complex plane
+                let cx = x_min + (x as f64 / image_width as f64) * (x_max - x_min);
+                let cy = y_min + (y as f64 / image_height as f64) * (y_max - y_min);
+                let c = Complex::new(cx, cy);
+                match formula {
+                    Formula::Mandelbrot => iterate(&Mandelbrot, c, max_iterations),
+                    Formula::BurningShip => iterate(&BurningShip, c, max_iterations),
+                    Formula::Tricorn => iterate(&Tricorn, c, max_iterations),
+                    Formula::Multibrot(multibrot) => iterate(&multibrot, c, max_iterations),
+                }
+            };
+            let value = if args.smooth && iteration < max_iterations {
+                smooth_iteration(iteration, z, formula.degree()).max(0.0) as f32
+            } else {
+                iteration as f32
+            };
+            let t = value / max_iterations as f32;
+            let pixel: Rgb<u8> = match &palette {
+                Some(palette) => palette.sample(t),
+                None => hsv_to_rgb(t * 360.0, 1.0, 1.0),
+            };
             imgbuf.put_pixel(x, y, pixel);
+            if !values.is_empty() {
+                values[(y * image_width + x) as usize] = value;
+            }
             // let pixel: Rgb<u8> = Rgb([0, 0, 0]);
             // imgbuf.put_pixel(x, y, pixel);
         }
+        rows_done = y + 1;
     }
 
     let duration = start.elapsed();
     println!("Rendering time: {:?}", duration);
 
-    std::fs::create_dir_all("./out").unwrap();
-    imgbuf.save("./out/mandelbrot_single.png").unwrap();
-    println!("Image saved to ./out/mandelbrot_single.png");
-}
\ No newline at end of file
+    output::create_parent_dir(&output_path);
+    if format == Format::Exr {
+        output::save_exr(&output_path, image_width, image_height, &values).unwrap();
+    } else {
+        output::save(&output_path, format, &imgbuf, &[]).unwrap();
+    }
+    println!("Image saved to {}", output_path);
+
+    if rows_done < image_height {
+        let resume_path = std::path::Path::new(&output_path).with_extension("resume.json");
+        let resume_path = resume_path.to_str().unwrap();
+        write_resume_file(resume_path, image_width, image_height, rows_done).unwrap();
+        println!("Render interrupted: {} of {} rows completed, resume info saved to {}", rows_done, image_height, resume_path);
+        std::process::exit(130);
+    }
+}
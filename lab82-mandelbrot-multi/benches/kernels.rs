@@ -0,0 +1,145 @@
+use criterion::{ criterion_group, criterion_main, BenchmarkId, Criterion, Throughput };
+use num_complex::Complex;
+use rayon::prelude::*;
+use std::hint::black_box;
+
+use lab82_mandelbrot_multi::fractal::{ Fractal, Mandelbrot };
+use lab82_mandelbrot_multi::kernel;
+use lab82_mandelbrot_multi::locations;
+use lab82_mandelbrot_multi::simd::{ self, LANES };
+
+// Throughput of the escape-time kernels on standard views.
+//
+//   cargo bench --bench kernels [-- FILTER]
+//
+// Every kernel renders every view of the Mandelbrot set at WIDTH×HEIGHT,
+// one criterion group per view, with throughput in pixels per second.
+// FILTER is criterion's: it keeps the benchmarks whose "view/kernel" id
+// matches it. The sum of the iteration counts must come out the same for
+// every kernel of a view, so a kernel cannot get faster by computing
+// something else.
+//
+// - lab81: the loop of the single-threaded renderer, one escape test per
+//   iteration
+// - scalar: the batched kernel of this crate
+// - simd: LANES points per SIMD vector
+//
+// Each runs on one thread and, as "+rayon", with rows spread over the
+// rayon pool.
+const WIDTH: u32 = 300;
+const HEIGHT: u32 = 200;
+
+// Renders of the deeper views take a good fraction of a second on one
+// thread, too slow for criterion's default of 100 samples
+const SAMPLE_SIZE: usize = 10;
+
+struct View {
+    name: &'static str,
+    center: (f64, f64),
+    zoom: f64,
+    max_iterations: u32,
+}
+
+impl View {
+    fn location(name: &'static str) -> Self {
+        let location = locations::find(name).unwrap();
+        let (re, im) = location.center.split_once(',').unwrap();
+        View { name, center: (re.parse().unwrap(), im.parse().unwrap()), zoom: location.zoom, max_iterations: location.iterations }
+    }
+
+    // Center of pixel (x, y), with the 3:2 view of the renderer
+    fn point(&self, x: u32, y: u32) -> Complex<f64> {
+        let (width, height) = (3.0 / self.zoom, 2.0 / self.zoom);
+        Complex::new(
+            self.center.0 + ((x as f64 + 0.5) / WIDTH as f64 - 0.5) * width,
+            self.center.1 + ((y as f64 + 0.5) / HEIGHT as f64 - 0.5) * height,
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kernel {
+    Lab81,
+    Scalar,
+    Simd,
+}
+
+impl Kernel {
+    fn name(self) -> &'static str {
+        match self {
+            Kernel::Lab81 => "lab81",
+            Kernel::Scalar => "scalar",
+            Kernel::Simd => "simd",
+        }
+    }
+}
+
+// The kernel of lab81-mandelbrot-single
+fn lab81_iterate<F: Fractal>(fractal: &F, c: Complex<f64>, max_iterations: u32) -> u32 {
+    let mut z = Complex::new(0.0, 0.0);
+    let mut iteration = if fractal.is_interior(c) { max_iterations } else { 0 };
+    while iteration < max_iterations && z.norm_sqr() <= 4.0 {
+        z = fractal.step(z, c);
+        iteration += 1;
+    }
+    iteration
+}
+
+// Sum of the iteration counts of row y
+fn row(view: &View, kernel: Kernel, y: u32) -> u64 {
+    let max_iterations = view.max_iterations;
+    let points: Vec<Complex<f64>> = (0..WIDTH).map(|x| view.point(x, y)).collect();
+    match kernel {
+        Kernel::Lab81 => points.iter().map(|&c| lab81_iterate(&Mandelbrot, c, max_iterations) as u64).sum(),
+        Kernel::Scalar => points
+            .iter()
+            .map(|&c| if Mandelbrot.is_interior(c) { max_iterations } else { kernel::iterate(&Mandelbrot, c, max_iterations, 2.0).0 } as u64)
+            .sum(),
+        Kernel::Simd => points
+            .chunks(LANES)
+            .map(|chunk| {
+                let c: [Complex<f64>; LANES] = std::array::from_fn(|lane| chunk[lane.min(chunk.len() - 1)]);
+                let orbits = simd::iterate4(&Mandelbrot, c.map(|c| c.re), c.map(|c| c.im), max_iterations, 2.0, c.map(|c| Mandelbrot.is_interior(c)));
+                orbits[..chunk.len()].iter().map(|&(iteration, _)| iteration as u64).sum::<u64>()
+            })
+            .sum(),
+    }
+}
+
+fn render(view: &View, kernel: Kernel, parallel: bool) -> u64 {
+    if parallel {
+        (0..HEIGHT).into_par_iter().map(|y| row(view, kernel, y)).sum()
+    } else {
+        (0..HEIGHT).map(|y| row(view, kernel, y)).sum()
+    }
+}
+
+fn kernels(c: &mut Criterion) {
+    let views = [
+        View { name: "whole", center: (-0.5, 0.0), zoom: 1.0, max_iterations: 1000 },
+        View::location("seahorse"),
+        View::location("minibrot"),
+        View::location("double-spiral"),
+    ];
+    let kernels = [Kernel::Lab81, Kernel::Scalar, Kernel::Simd];
+    for view in &views {
+        let reference = render(view, Kernel::Lab81, false);
+        for kernel in kernels {
+            assert_eq!(render(view, kernel, true), reference, "{} {} disagrees with the other kernels", view.name, kernel.name());
+        }
+
+        let mut group = c.benchmark_group(format!("{} ({} iterations)", view.name, view.max_iterations));
+        group.sample_size(SAMPLE_SIZE);
+        group.throughput(Throughput::Elements((WIDTH * HEIGHT) as u64));
+        for kernel in kernels {
+            for parallel in [false, true] {
+                let label = format!("{}{}", kernel.name(), if parallel { "+rayon" } else { "" });
+                group.bench_function(BenchmarkId::from_parameter(label), |b| b.iter(|| render(black_box(view), kernel, parallel)));
+            }
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, kernels);
+criterion_main!(benches);
@@ -0,0 +1,33 @@
+use rayon::prelude::*;
+
+use crate::color::Channel;
+
+// Edge detection for adaptive antialiasing.
+//
+// A pixel is flagged when any of its 8 neighbours differs from it by more
+// than `threshold` (in 8-bit units) in some color channel. Only flagged
+// pixels are worth supersampling; flat regions (including the set interior)
+// are left alone. `channels` is the raw RGB image buffer.
+pub fn find_edges<T: Channel>(channels: &[T], width: u32, height: u32, threshold: u8) -> Vec<usize> {
+    let (w, h) = (width as i64, height as i64);
+    let threshold = threshold as u32 * (T::DEFAULT_MAX_VALUE.to_u32() / 255);
+    let pixel = |i: usize| &channels[i * 3..i * 3 + 3];
+    let differs = |a: &[T], b: &[T]| {
+        (0..3).any(|channel| a[channel].to_u32().abs_diff(b[channel].to_u32()) > threshold)
+    };
+
+    (0..channels.len() / 3)
+        .into_par_iter()
+        .filter(|&i| {
+            let (x, y) = (i as i64 % w, i as i64 / w);
+            let center = pixel(i);
+            (-1..=1).any(|dy| {
+                (-1..=1).any(|dx| {
+                    let (nx, ny) = (x + dx, y + dy);
+                    nx >= 0 && ny >= 0 && nx < w && ny < h
+                        && differs(center, pixel((ny * w + nx) as usize))
+                })
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,176 @@
+use double_double::DoubleDouble;
+use serde::Deserialize;
+use std::io;
+
+// Zoom animation paths.
+//
+// The zoom factor is interpolated exponentially, so every frame magnifies by
+// the same ratio and the zoom feels steady. The center follows the zoom: it
+// moves by the same fraction of the way as the view width shrinks, which
+// keeps the end point fixed on screen while the camera closes in on it.
+#[derive(Clone, Copy, Debug)]
+pub struct View {
+    pub center: (DoubleDouble, DoubleDouble),
+    pub zoom: f64,
+    pub max_iterations: u32,
+}
+
+// The view at `t` in 0.0..=1.0 between `start` and `end`. Iterations grow
+// linearly with t, i.e. with the number of decades zoomed.
+pub fn interpolate(start: View, end: View, t: f64) -> View {
+    let zoom = start.zoom * (end.zoom / start.zoom).powf(t);
+    let (start_width, end_width) = (1.0 / start.zoom, 1.0 / end.zoom);
+    let remaining = if start_width != end_width {
+        (1.0 / zoom - end_width) / (start_width - end_width)
+    } else {
+        1.0 - t
+    };
+    let center = (
+        end.center.0 + (start.center.0 - end.center.0) * remaining,
+        end.center.1 + (start.center.1 - end.center.1) * remaining,
+    );
+    let iterations = start.max_iterations as f64 + (end.max_iterations as f64 - start.max_iterations as f64) * t;
+    View { center, zoom, max_iterations: iterations.round() as u32 }
+}
+
+// Default iteration count for the end view: half the starting count is
+// added per decade of zoom so deep frames keep their detail.
+pub fn scaled_iterations(max_iterations: u32, start_zoom: f64, end_zoom: f64) -> u32 {
+    let decades = (end_zoom / start_zoom).log10().max(0.0);
+    (max_iterations as f64 * (1.0 + decades / 2.0)).round() as u32
+}
+
+// Iteration count recommended for a view at `zoom` (`--auto-iterations`).
+// Detail near the boundary needs more iterations the deeper the view, a bit
+// faster than linearly in the number of decades; the whole set at zoom 1
+// gets AUTO_ITERATIONS.
+pub const AUTO_ITERATIONS: f64 = 200.0;
+
+pub fn auto_iterations(zoom: f64) -> u32 {
+    let decades = zoom.log10().max(0.0);
+    (AUTO_ITERATIONS * (1.0 + decades).powf(1.25)).round() as u32
+}
+
+// Timing curve applied to the progress through each keyframe segment
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn apply(self, s: f64) -> f64 {
+        match self {
+            Easing::Linear => s,
+            Easing::EaseIn => s * s,
+            Easing::EaseOut => s * (2.0 - s),
+            Easing::EaseInOut => s * s * (3.0 - 2.0 * s),
+        }
+    }
+}
+
+// A keyframe animation script, loaded from JSON:
+//
+//   {
+//     "easing": "ease-in-out",
+//     "keyframes": [
+//       { "time": 0, "center": "-0.5,0", "zoom": 1, "iterations": 500, "palette": "viridis" },
+//       { "time": 8, "center": "-0.7436438870371587,0.1318259042053120", "zoom": 1e6,
+//         "iterations": 3000, "palette": "inferno", "easing": "linear" }
+//     ]
+//   }
+//
+// Times are in seconds and must increase. A keyframe's `easing` applies to
+// the segment leading away from it and defaults to the script-wide one
+// (linear if unset). `iterations` defaults to the command-line value and
+// `palette` ("hsv", a colormap name or a gradient file) to the command-line
+// palette; palettes crossfade across a segment.
+#[derive(Clone, Debug)]
+pub struct Keyframe {
+    pub time: f64,
+    pub view: View,
+    pub palette: Option<String>,
+    pub easing: Easing,
+}
+
+#[derive(Deserialize)]
+struct ScriptFile {
+    easing: Option<Easing>,
+    keyframes: Vec<KeyframeEntry>,
+}
+
+#[derive(Deserialize)]
+struct KeyframeEntry {
+    time: f64,
+    center: String,
+    zoom: f64,
+    iterations: Option<u32>,
+    palette: Option<String>,
+    easing: Option<Easing>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Script {
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl Script {
+    pub fn load(path: &str, default_iterations: u32) -> io::Result<Self> {
+        let file: ScriptFile = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let easing = file.easing.unwrap_or(Easing::Linear);
+        let keyframes = file
+            .keyframes
+            .into_iter()
+            .map(|entry| {
+                let center = entry
+                    .center
+                    .split_once(',')
+                    .and_then(|(re, im)| Some((re.trim().parse().ok()?, im.trim().parse().ok()?)))
+                    .ok_or_else(|| invalid(&format!("bad center {:?} (expected \"re,im\")", entry.center)))?;
+                Ok(Keyframe {
+                    time: entry.time,
+                    view: View { center, zoom: entry.zoom, max_iterations: entry.iterations.unwrap_or(default_iterations) },
+                    palette: entry.palette,
+                    easing: entry.easing.unwrap_or(easing),
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        if keyframes.len() < 2 {
+            return Err(invalid("a script needs at least two keyframes"));
+        }
+        if keyframes.windows(2).any(|pair| pair[1].time <= pair[0].time) {
+            return Err(invalid("keyframe times must increase"));
+        }
+        Ok(Script { keyframes })
+    }
+
+    // A single zoom from `start` at time 0 to `end` at time 1
+    pub fn between(start: View, end: View) -> Self {
+        let keyframe = |time, view| Keyframe { time, view, palette: None, easing: Easing::Linear };
+        Script { keyframes: vec![keyframe(0.0, start), keyframe(1.0, end)] }
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.keyframes.last().unwrap().time - self.keyframes[0].time
+    }
+
+    // The segment containing `time` (seconds from the first keyframe) as the
+    // index of its starting keyframe and the eased progress through it
+    pub fn at(&self, time: f64) -> (usize, f64) {
+        let time = time + self.keyframes[0].time;
+        let i = self.keyframes[..self.keyframes.len() - 1]
+            .iter()
+            .rposition(|keyframe| keyframe.time <= time)
+            .unwrap_or(0);
+        let (from, to) = (&self.keyframes[i], &self.keyframes[i + 1]);
+        let s = ((time - from.time) / (to.time - from.time)).clamp(0.0, 1.0);
+        (i, from.easing.apply(s))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
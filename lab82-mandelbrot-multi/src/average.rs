@@ -0,0 +1,95 @@
+use num_complex::Complex;
+
+use crate::fractal::Fractal;
+
+// Average colorings for `--stripe` and `--tia`.
+//
+// Both add up a quantity along the escaping orbit and color by its mean,
+// which brings out the orbit's geometry instead of just its length:
+//
+// - stripe average: ½·sin(s·arg z) + ½, s being `--stripe-density`
+// - triangle inequality average: where |z^d + c| falls between the bounds
+//   | |z^d| - |c| | and |z^d| + |c| the triangle inequality gives it
+//
+// The mean over the whole orbit and the mean without its last point are
+// blended by the fractional part of the smooth iteration count, which
+// removes the banding a plain mean shows between iteration counts. That
+// needs a bailout radius well past 2 to be accurate.
+const BAILOUT: f64 = 1000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Average {
+    Stripe { density: f64 },
+    TriangleInequality,
+}
+
+impl Average {
+    // The smoothed mean in 0.0..=1.0, or None for orbits that never escape
+    pub fn value<F: Fractal>(&self, fractal: &F, c: Complex<f64>, max_iterations: u32) -> Option<f64> {
+        let mut z = Complex::new(0.0, 0.0);
+        let (mut sum, mut last) = (0.0, 0.0);
+        let mut count = 0;
+        for _ in 0..max_iterations {
+            let power = fractal.step(z, Complex::new(0.0, 0.0));
+            z = power + c;
+            if z.is_nan() {
+                return None;
+            }
+            let term = match *self {
+                Average::Stripe { density } => Some(0.5 * (density * z.arg()).sin() + 0.5),
+                Average::TriangleInequality => {
+                    let (power, c) = (power.norm(), c.norm());
+                    let (lower, upper) = ((power - c).abs(), power + c);
+                    (upper > lower).then(|| (z.norm() - lower) / (upper - lower))
+                }
+            };
+            if let Some(term) = term {
+                sum += term;
+                last = term;
+                count += 1;
+            }
+            if z.norm_sqr() > BAILOUT * BAILOUT {
+                if count < 2 {
+                    return Some(if count == 1 { sum } else { 0.0 });
+                }
+                let mean = sum / count as f64;
+                let previous = (sum - last) / (count - 1) as f64;
+                // How far past the bailout the orbit got, as a fraction of
+                // one iteration
+                let overshoot = (z.norm().ln() / BAILOUT.ln()).ln() / fractal.degree().ln();
+                let fraction = (1.0 - overshoot).clamp(0.0, 1.0);
+                return Some(previous + (mean - previous) * fraction);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fractal::{ Mandelbrot, Multibrot };
+
+    #[test]
+    fn means_stay_in_the_unit_interval() {
+        let averages = [Average::Stripe { density: 5.0 }, Average::TriangleInequality];
+        for average in averages {
+            for k in 0..200 {
+                let c = Complex::from_polar(0.5 + k as f64 * 0.01, k as f64 * 0.7);
+                if let Some(value) = average.value(&Multibrot::new(3.0), c, 500) {
+                    assert!((0.0..=1.0).contains(&value), "{:?} at {}: {}", average, c, value);
+                }
+                if let Some(value) = average.value(&Mandelbrot, c, 500) {
+                    assert!((0.0..=1.0).contains(&value), "{:?} at {}: {}", average, c, value);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bounded_orbits_have_no_value() {
+        let average = Average::TriangleInequality;
+        assert_eq!(average.value(&Mandelbrot, Complex::new(-1.0, 0.0), 1000), None);
+        assert!(average.value(&Mandelbrot, Complex::new(0.5, 0.5), 1000).is_some());
+    }
+}
@@ -0,0 +1,159 @@
+use hsv_to_rgb::Palette;
+use image::{ ImageBuffer, Rgb };
+use rayon::prelude::*;
+use image_output::{ self as output, Format };
+
+use lab82_mandelbrot_multi::{ parse_format, parse_value, value };
+use lab82_mandelbrot_multi::color::{ select_palette, Blending, Channel, Cycle };
+use lab82_mandelbrot_multi::histogram::Histogram;
+use lab82_mandelbrot_multi::interior::Interior;
+use lab82_mandelbrot_multi::raw;
+
+// Applies a palette to iteration data saved with `lab82 --raw`, without
+// re-running the fractal computation.
+//
+//   recolor data.npy [--palette file | --colormap name] [--histogram]
+//           [--palette-scale S] [--palette-offset F]
+//           [--interior mode] [--iterations N] [--depth 8|16]
+//           [--srgb-blending] [--output path] [--format fmt]
+//
+// `--iterations` must match the render so values normalize the same way.
+// `--interior angle` and `period` need data rendered with that mode.
+struct Args {
+    input: String,
+    histogram: bool,
+    palette: Option<String>,
+    colormap: Option<String>,
+    cycle: Cycle,
+    interior: Interior,
+    max_iterations: u32,
+    depth: u32,
+    blending: Blending,
+    output: Option<String>,
+    format: Option<Format>,
+}
+
+impl Args {
+    fn parse() -> Self {
+        let mut iter = std::env::args().skip(1);
+        let mut input = None;
+        let mut args = Args {
+            input: String::new(),
+            histogram: false,
+            palette: None,
+            colormap: None,
+            cycle: Cycle::IDENTITY,
+            interior: Interior::Palette,
+            max_iterations: 1000,
+            depth: 8,
+            blending: Blending::Linear,
+            output: None,
+            format: None,
+        };
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--histogram" => args.histogram = true,
+                "--palette" => args.palette = Some(value(&mut iter, &arg)),
+                "--colormap" => args.colormap = Some(value(&mut iter, &arg)),
+                "--palette-scale" => args.cycle.scale = parse_value(&mut iter, &arg),
+                "--palette-offset" => args.cycle.offset = parse_value(&mut iter, &arg),
+                "--interior" => args.interior = parse_interior(&value(&mut iter, &arg)),
+                "--iterations" => args.max_iterations = parse_value(&mut iter, &arg),
+                "--depth" => args.depth = parse_value(&mut iter, &arg),
+                "--srgb-blending" => args.blending = Blending::Srgb,
+                "--output" => args.output = Some(value(&mut iter, &arg)),
+                "--format" => args.format = Some(parse_format(&value(&mut iter, &arg))),
+                other if !other.starts_with("--") && input.is_none() => input = Some(arg),
+                other => {
+                    eprintln!("Unknown argument: {}", other);
+                    std::process::exit(2);
+                }
+            }
+        }
+        args.input = input.unwrap_or_else(|| {
+            eprintln!("Usage: recolor <data.npy> [options]");
+            std::process::exit(2);
+        });
+        args
+    }
+}
+
+fn parse_interior(raw: &str) -> Interior {
+    Interior::parse(raw).unwrap_or_else(|| {
+        eprintln!("Invalid value for --interior: {} (expected palette, angle, period or a color such as #000000)", raw);
+        std::process::exit(2);
+    })
+}
+
+fn main() {
+    let args = Args::parse();
+    let palette = select_palette(args.palette.as_deref(), args.colormap.as_deref());
+    let max_iterations = args.max_iterations;
+
+    let (output_path, format) = output::resolve(args.output.as_deref(), args.format, "recolored");
+    if format == Format::Exr {
+        eprintln!("recolor writes colored images, EXR output is not supported");
+        std::process::exit(2);
+    }
+    if args.depth != 8 && args.depth != 16 {
+        eprintln!("--depth must be 8 or 16");
+        std::process::exit(2);
+    }
+    if args.depth == 16 && !matches!(format, Format::Png | Format::Tiff) {
+        eprintln!("--depth 16 needs PNG or TIFF output");
+        std::process::exit(2);
+    }
+
+    let (width, height, values) = raw::load_npy(&args.input).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", args.input, e);
+        std::process::exit(1);
+    });
+
+    let histogram = if args.histogram {
+        Some(Histogram::new(values.iter().copied(), max_iterations))
+    } else {
+        None
+    };
+    let normalize = |value: f32| -> f32 {
+        let t = match &histogram {
+            Some(histogram) => histogram.equalize(value),
+            None => value / max_iterations as f32,
+        };
+        args.cycle.apply(t)
+    };
+    let (interior, blending) = (args.interior, args.blending);
+
+    output::create_parent_dir(&output_path);
+    if args.depth == 16 {
+        let pixels = colorize::<u16>(&values, &palette, blending, normalize, |value| interior.color(&palette, value, max_iterations, blending));
+        let imgbuf = ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, pixels).unwrap();
+        output::save16(&output_path, format, &imgbuf, &[]).unwrap();
+    } else {
+        let pixels = colorize::<u8>(&values, &palette, blending, normalize, |value| interior.color(&palette, value, max_iterations, blending));
+        let imgbuf = ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, pixels).unwrap();
+        output::save(&output_path, format, &imgbuf, &[]).unwrap();
+    }
+    println!("Image saved to {}", output_path);
+}
+
+// Flat RGB channel data; NaN (unrendered) pixels come out black and
+// `interior` overrides the palette where it returns a color
+fn colorize<T: Channel>(
+    values: &[f32],
+    palette: &Option<Palette>,
+    blending: Blending,
+    normalize: impl Fn(f32) -> f32 + Sync,
+    interior: impl Fn(f32) -> Option<Rgb<T>> + Sync,
+) -> Vec<T> {
+    values
+        .par_iter()
+        .flat_map_iter(|&value| {
+            let Rgb(rgb) = if value.is_nan() {
+                Rgb([T::DEFAULT_MIN_VALUE; 3])
+            } else {
+                interior(value).unwrap_or_else(|| T::from_palette(palette, normalize(value), blending))
+            };
+            rgb
+        })
+        .collect()
+}
@@ -0,0 +1,161 @@
+use image::{ GenericImage, RgbImage };
+use rayon::prelude::*;
+use image_output::{ self as output, Format };
+use std::path::Path;
+
+use lab82_mandelbrot_multi::{ parse_format, parse_value, value };
+use lab82_mandelbrot_multi::color::Blending;
+use lab82_mandelbrot_multi::stitch::{ halve, DeepZoom, Manifest };
+
+// Puts a render back together from tiles listed in a manifest, see
+// `stitch.rs` for the manifest format.
+//
+//   stitch <directory> [--manifest file] [--output path] [--format fmt]
+//          [--deepzoom path.dzi] [--tile-size N] [--overlap N]
+//          [--srgb-blending]
+//
+// The manifest defaults to manifest.json in the directory. Without
+// --deepzoom the tiles become one image; with it they become a Deep Zoom
+// pyramid instead, the descriptor at path.dzi and the tiles, in --format
+// (png or jpeg), under path_files/. Pixels no tile covers are black.
+struct Args {
+    directory: String,
+    manifest: Option<String>,
+    output: Option<String>,
+    format: Option<Format>,
+    deepzoom: Option<String>,
+    pyramid: DeepZoom,
+    blending: Blending,
+}
+
+impl Args {
+    fn parse() -> Self {
+        let mut iter = std::env::args().skip(1);
+        let mut directory = None;
+        let mut args = Args {
+            directory: String::new(),
+            manifest: None,
+            output: None,
+            format: None,
+            deepzoom: None,
+            pyramid: DeepZoom { tile_size: 254, overlap: 1 },
+            blending: Blending::Linear,
+        };
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--manifest" => args.manifest = Some(value(&mut iter, &arg)),
+                "--output" => args.output = Some(value(&mut iter, &arg)),
+                "--format" => args.format = Some(parse_format(&value(&mut iter, &arg))),
+                "--deepzoom" => args.deepzoom = Some(value(&mut iter, &arg)),
+                "--tile-size" => args.pyramid.tile_size = parse_value(&mut iter, &arg),
+                "--overlap" => args.pyramid.overlap = parse_value(&mut iter, &arg),
+                "--srgb-blending" => args.blending = Blending::Srgb,
+                other if !other.starts_with("--") && directory.is_none() => directory = Some(arg),
+                other => {
+                    eprintln!("Unknown argument: {}", other);
+                    std::process::exit(2);
+                }
+            }
+        }
+        args.directory = directory.unwrap_or_else(|| {
+            eprintln!("Usage: stitch <directory> [options]");
+            std::process::exit(2);
+        });
+        args
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let directory = Path::new(&args.directory);
+    let manifest_path = match &args.manifest {
+        Some(path) => Path::new(path).to_path_buf(),
+        None => directory.join("manifest.json"),
+    };
+    let text = std::fs::read_to_string(&manifest_path).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", manifest_path.display(), e);
+        std::process::exit(1);
+    });
+    let manifest = Manifest::parse(&text).unwrap_or_else(|e| {
+        eprintln!("Invalid manifest {}: {}", manifest_path.display(), e);
+        std::process::exit(2);
+    });
+
+    let (output_path, format) = output::resolve(args.output.as_deref(), args.format, "stitched");
+    if format == Format::Exr {
+        eprintln!("stitch writes colored images, EXR output is not supported");
+        std::process::exit(2);
+    }
+    if args.deepzoom.is_some() {
+        if !matches!(format, Format::Png | Format::Jpeg) {
+            eprintln!("Deep Zoom tiles must be PNG or JPEG");
+            std::process::exit(2);
+        }
+        if args.pyramid.tile_size == 0 {
+            eprintln!("--tile-size must be at least 1");
+            std::process::exit(2);
+        }
+    }
+
+    let mut image = RgbImage::new(manifest.width, manifest.height);
+    let mut covered = vec![false; manifest.width as usize * manifest.height as usize];
+    for placement in &manifest.tiles {
+        let path = directory.join(&placement.file);
+        let tile = image::open(&path).unwrap_or_else(|e| {
+            eprintln!("Failed to read tile {}: {}", path.display(), e);
+            std::process::exit(1);
+        });
+        let tile = tile.to_rgb8();
+        if placement.x + tile.width() > manifest.width || placement.y + tile.height() > manifest.height {
+            eprintln!("{} ({}x{} at {},{}) does not fit in the {}x{} image", placement.file, tile.width(), tile.height(), placement.x, placement.y, manifest.width, manifest.height);
+            std::process::exit(2);
+        }
+        image.copy_from(&tile, placement.x, placement.y).unwrap();
+        for y in placement.y..placement.y + tile.height() {
+            let row = y as usize * manifest.width as usize;
+            covered[row + placement.x as usize..row + (placement.x + tile.width()) as usize].fill(true);
+        }
+    }
+    let gaps = covered.iter().filter(|&&covered| !covered).count();
+    println!("Stitched {} tiles into {}x{}", manifest.tiles.len(), manifest.width, manifest.height);
+    if gaps > 0 {
+        println!("{} pixels are not covered by any tile and stay black", gaps);
+    }
+
+    match &args.deepzoom {
+        Some(path) => save_pyramid(&image, path, args.pyramid, format, args.blending),
+        None => {
+            output::create_parent_dir(&output_path);
+            output::save(&output_path, format, &image, &[]).unwrap();
+            println!("Image saved to {}", output_path);
+        }
+    }
+}
+
+// Writes path.dzi and the tiles of every level under path_files/LEVEL/,
+// named COLUMN_ROW.ext
+fn save_pyramid(image: &RgbImage, path: &str, pyramid: DeepZoom, format: Format, blending: Blending) {
+    let (width, height) = image.dimensions();
+    let descriptor = Path::new(path).with_extension("dzi");
+    let files = format!("{}_files", descriptor.with_extension("").display());
+    let mut level_image = image.clone();
+    let mut count = 0;
+    for level in (0..=DeepZoom::max_level(width, height)).rev() {
+        if level < DeepZoom::max_level(width, height) {
+            level_image = halve(&level_image, blending);
+        }
+        debug_assert_eq!(level_image.dimensions(), DeepZoom::level_size(width, height, level));
+        let directory = format!("{}/{}", files, level);
+        std::fs::create_dir_all(&directory).unwrap();
+        let tiles = pyramid.tiles(level_image.width(), level_image.height());
+        tiles.par_iter().for_each(|tile| {
+            let pixels = image::imageops::crop_imm(&level_image, tile.x, tile.y, tile.width, tile.height).to_image();
+            let tile_path = format!("{}/{}_{}.{}", directory, tile.column, tile.row, format.extension());
+            output::save(&tile_path, format, &pixels, &[]).unwrap();
+        });
+        count += tiles.len();
+    }
+    output::create_parent_dir(descriptor.to_str().unwrap());
+    std::fs::write(&descriptor, pyramid.descriptor(width, height, format.extension())).unwrap();
+    println!("Deep Zoom pyramid of {} tiles saved to {} and {}", count, descriptor.display(), files);
+}
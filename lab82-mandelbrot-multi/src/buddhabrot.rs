@@ -0,0 +1,206 @@
+use num_complex::Complex;
+use std::sync::atomic::{ AtomicU32, Ordering };
+
+use crate::fractal::{ Fractal, Mandelbrot };
+use crate::rng::SplitMix64;
+
+// Buddhabrot and Nebulabrot rendering for `--buddhabrot`.
+//
+// Instead of coloring c by its own escape time, random c values are drawn
+// from the region around the set and every point visited by an escaping
+// orbit is counted in the pixel it falls in. The counts live in one shared
+// array of atomics per channel: orbits land all over the image, so per-tile
+// buffers do not work, and a relaxed fetch_add is cheap next to iterating
+// the orbit. The Nebulabrot gives each RGB channel its own iteration cap;
+// an orbit is iterated once up to the largest cap and counted in every
+// channel whose cap it escaped within.
+//
+// The anti-Buddhabrot counts the orbits that stay bounded instead, which
+// traces the attracting cycles inside the set. A minimum iteration count
+// isolates structure at one depth: escaping orbits that leave sooner are
+// dropped, and bounded orbits skip that many steps of transient before
+// their points are counted.
+
+// Samples drawn per work item. Each chunk seeds its own generator from its
+// index and `--seed`, so the image does not depend on how rayon schedules
+// the chunks.
+pub const CHUNK: u64 = 65536;
+
+// Where the random c values are drawn from; it covers the whole set
+const SAMPLE_RE: (f64, f64) = (-2.0, 1.0);
+const SAMPLE_IM: (f64, f64) = (-1.5, 1.5);
+
+// Which orbits are counted
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Orbits {
+    Escaping,
+    Bounded,
+}
+
+// Maps points of the complex plane to the pixel they fall in
+#[derive(Clone, Copy)]
+pub struct Grid {
+    pub x_min: f64,
+    pub y_min: f64,
+    pub x_max: f64,
+    pub y_max: f64,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Grid {
+    pub fn pixel(&self, z: Complex<f64>) -> Option<usize> {
+        let u = (z.re - self.x_min) / (self.x_max - self.x_min);
+        let v = (z.im - self.y_min) / (self.y_max - self.y_min);
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return None;
+        }
+        let (x, y) = ((u * self.width as f64) as usize, (v * self.height as f64) as usize);
+        Some(y * self.width as usize + x)
+    }
+}
+
+// Per-channel hit counts, one channel for the plain Buddhabrot and three
+// for the Nebulabrot
+pub struct Density {
+    grid: Grid,
+    caps: Vec<u32>,
+    orbits: Orbits,
+    min_iterations: u32,
+    channels: Vec<Vec<AtomicU32>>,
+}
+
+impl Density {
+    pub fn new(grid: Grid, caps: &[u32], orbits: Orbits, min_iterations: u32) -> Self {
+        let pixels = grid.width as usize * grid.height as usize;
+        let channels = caps.iter().map(|_| (0..pixels).map(|_| AtomicU32::new(0)).collect()).collect();
+        Density { grid, caps: caps.to_vec(), orbits, min_iterations, channels }
+    }
+
+    // Draws `CHUNK` samples with the generator of chunk `index` under
+    // `seed` and adds the orbits selected by `orbits` and `min_iterations`
+    pub fn add_chunk(&self, index: u64, seed: u64) {
+        let max_cap = self.caps.iter().copied().max().unwrap_or(0);
+        let mut rng = SplitMix64::new(index.wrapping_mul(0xd1b5_4a32_d192_ed03) ^ seed.wrapping_mul(0xbf58_476d_1ce4_e5b9));
+        let mut orbit = Vec::with_capacity(max_cap as usize);
+        let mut pixels = Vec::with_capacity(max_cap as usize);
+        for _ in 0..CHUNK {
+            let c = Complex::new(
+                SAMPLE_RE.0 + rng.unit() * (SAMPLE_RE.1 - SAMPLE_RE.0),
+                SAMPLE_IM.0 + rng.unit() * (SAMPLE_IM.1 - SAMPLE_IM.0),
+            );
+            if self.orbits == Orbits::Escaping && Mandelbrot.is_interior(c) {
+                continue;
+            }
+            orbit.clear();
+            let mut z = Complex::new(0.0, 0.0);
+            while orbit.len() < max_cap as usize && z.norm_sqr() <= 4.0 {
+                z = Mandelbrot.step(z, c);
+                orbit.push(z);
+            }
+            let escaped = z.norm_sqr() > 4.0;
+            match self.orbits {
+                Orbits::Escaping => {
+                    if !escaped || orbit.len() < self.min_iterations as usize {
+                        continue;
+                    }
+                    pixels.clear();
+                    pixels.extend(orbit.iter().filter_map(|&z| self.grid.pixel(z)));
+                    for (channel, &cap) in self.channels.iter().zip(&self.caps) {
+                        if orbit.len() <= cap as usize {
+                            self.add(channel, &pixels);
+                        }
+                    }
+                }
+                // A channel counts the orbit if it is still bounded after
+                // that channel's cap, over the first `cap` points
+                Orbits::Bounded => {
+                    for (channel, &cap) in self.channels.iter().zip(&self.caps) {
+                        if escaped && orbit.len() <= cap as usize {
+                            continue;
+                        }
+                        pixels.clear();
+                        let counted = orbit[..cap as usize].iter().skip(self.min_iterations as usize);
+                        pixels.extend(counted.filter_map(|&z| self.grid.pixel(z)));
+                        self.add(channel, &pixels);
+                    }
+                }
+            }
+        }
+    }
+
+    fn add(&self, channel: &[AtomicU32], pixels: &[usize]) {
+        for &pixel in pixels {
+            channel[pixel].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // Counts tone-mapped to 0.0..=1.0 per channel: a square root of the
+    // count relative to the busiest pixel, so faint orbits stay visible
+    pub fn levels(&self) -> Vec<Vec<f32>> {
+        self.channels
+            .iter()
+            .map(|channel| {
+                let counts: Vec<u32> = channel.iter().map(|count| count.load(Ordering::Relaxed)).collect();
+                let max = counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+                counts.iter().map(|&count| (count as f32 / max).sqrt()).collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(width: u32, height: u32) -> Grid {
+        Grid { x_min: -2.0, y_min: -1.0, x_max: 1.0, y_max: 1.0, width, height }
+    }
+
+    #[test]
+    fn grid_maps_points_to_pixels() {
+        let grid = grid(30, 20);
+        assert_eq!(grid.pixel(Complex::new(-2.0, -1.0)), Some(0));
+        assert_eq!(grid.pixel(Complex::new(0.95, 0.95)), Some(19 * 30 + 29));
+        assert_eq!(grid.pixel(Complex::new(1.0, 0.0)), None);
+        assert_eq!(grid.pixel(Complex::new(f64::NAN, 0.0)), None);
+    }
+
+    #[test]
+    fn higher_caps_see_every_orbit_of_lower_ones() {
+        let density = Density::new(grid(24, 16), &[5000, 500, 50], Orbits::Escaping, 0);
+        density.add_chunk(7, 0);
+        let counts: Vec<Vec<u32>> = density
+            .channels
+            .iter()
+            .map(|channel| channel.iter().map(|count| count.load(Ordering::Relaxed)).collect())
+            .collect();
+        assert!(counts[2].iter().sum::<u32>() > 0);
+        for ((red, green), blue) in counts[0].iter().zip(&counts[1]).zip(&counts[2]) {
+            assert!(red >= green && green >= blue);
+        }
+    }
+
+    #[test]
+    fn bounded_orbits_skip_their_transient() {
+        let grid = Grid { x_min: -1.5, y_min: -0.5, x_max: 0.5, y_max: 0.5, width: 4, height: 2 };
+        let all = Density::new(grid, &[200], Orbits::Bounded, 0);
+        let settled = Density::new(grid, &[200], Orbits::Bounded, 150);
+        all.add_chunk(3, 0);
+        settled.add_chunk(3, 0);
+        let total = |density: &Density| density.channels[0].iter().map(|count| count.load(Ordering::Relaxed) as u64).sum::<u64>();
+        assert!(total(&settled) > 0);
+        assert!(total(&settled) < total(&all));
+    }
+
+    #[test]
+    fn min_iterations_drops_short_orbits() {
+        let all = Density::new(grid(24, 16), &[100], Orbits::Escaping, 0);
+        let deep = Density::new(grid(24, 16), &[100], Orbits::Escaping, 20);
+        all.add_chunk(1, 0);
+        deep.add_chunk(1, 0);
+        for (all, deep) in all.channels[0].iter().zip(&deep.channels[0]) {
+            assert!(all.load(Ordering::Relaxed) >= deep.load(Ordering::Relaxed));
+        }
+    }
+}
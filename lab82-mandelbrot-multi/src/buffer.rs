@@ -0,0 +1,112 @@
+use memmap2::MmapMut;
+use std::fs::{ File, OpenOptions };
+use std::io;
+use std::ops::{ Deref, DerefMut };
+use std::path::PathBuf;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+
+// The escape-value buffer of a render, in memory or, with `--mmap-buffer`,
+// in a temporary file mapped into memory.
+//
+// A 40000×40000 render holds 6.4 GB of samples at one sample per pixel and
+// four times that with --ssaa 2. Mapped, the kernel pages them out to the
+// file when memory runs short instead of the render failing to allocate.
+// The file goes in the system temporary directory (TMPDIR on Unix), which
+// needs room for the whole buffer. On Unix it is unlinked as soon as it is
+// mapped, so it disappears however the render ends; elsewhere it is
+// removed when the buffer is dropped.
+pub enum Samples {
+    Heap(Vec<f32>),
+    // Fields drop in order, so the mapping is gone before the file is
+    // removed, which Windows insists on
+    Mapped(MmapMut, TempFile),
+}
+
+// Removes the file at its path, if it is still there, when dropped
+pub struct TempFile(Option<PathBuf>);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if let Some(path) = &self.0 {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+impl Samples {
+    // `len` values of `value`
+    pub fn new(len: usize, value: f32, mapped: bool) -> io::Result<Self> {
+        if !mapped {
+            return Ok(Samples::Heap(vec![value; len]));
+        }
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("mandelbrot-samples-{}-{}.f32", std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed));
+        let path = std::env::temp_dir().join(name);
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        let mut temp = TempFile(Some(path));
+        let map = map_file(&file, len)?;
+        if cfg!(unix) {
+            std::fs::remove_file(temp.0.take().unwrap())?;
+        }
+        let mut samples = Samples::Mapped(map, temp);
+        samples.fill(value);
+        Ok(samples)
+    }
+
+    // Where the buffer lives, for the log
+    pub fn location(&self) -> String {
+        match self {
+            Samples::Heap(_) => "memory".to_string(),
+            Samples::Mapped(..) => format!("a file mapped from {}", std::env::temp_dir().display()),
+        }
+    }
+}
+
+fn map_file(file: &File, len: usize) -> io::Result<MmapMut> {
+    file.set_len((len * size_of::<f32>()) as u64)?;
+    // SAFETY: the file was just created by this process and nothing else
+    // opens it
+    unsafe { MmapMut::map_mut(file) }
+}
+
+impl Deref for Samples {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        match self {
+            Samples::Heap(values) => values,
+            // SAFETY: mappings are page-aligned and the file holds exactly
+            // whole f32s, any bit pattern of which is a valid f32
+            Samples::Mapped(map, _) => unsafe { std::slice::from_raw_parts(map.as_ptr().cast(), map.len() / size_of::<f32>()) },
+        }
+    }
+}
+
+impl DerefMut for Samples {
+    fn deref_mut(&mut self) -> &mut [f32] {
+        match self {
+            Samples::Heap(values) => values,
+            // SAFETY: as in `deref`
+            Samples::Mapped(map, _) => unsafe { std::slice::from_raw_parts_mut(map.as_mut_ptr().cast(), map.len() / size_of::<f32>()) },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapped_buffers_behave_like_vectors() {
+        let mut samples = Samples::new(1000, f32::NAN, true).unwrap();
+        assert_eq!(samples.len(), 1000);
+        assert!(samples.iter().all(|value| value.is_nan()));
+        samples[999] = 42.0;
+        samples[..10].fill(1.5);
+        assert_eq!((samples[0], samples[9], samples[999]), (1.5, 1.5, 42.0));
+        assert!(samples[10].is_nan());
+        if let Samples::Mapped(_, TempFile(path)) = &samples {
+            assert_eq!(path.is_none(), cfg!(unix));
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use serde::{ Deserialize, Serialize };
+use std::fs::{ File, OpenOptions };
+use std::io::{ self, BufReader, BufWriter, Read, Seek, Write };
+use std::sync::Mutex;
+use std::time::{ Duration, Instant };
+
+use crate::tiling::Layout;
+
+// Checkpoint files for resumable renders.
+//
+// The file is an append-only log: a small header describing the render,
+// followed by one record per finished tile holding its raw escape values.
+// Records are appended as tiles complete and flushed every few seconds, so
+// a killed render loses at most the last few seconds of work. On resume a
+// truncated trailing record is simply dropped, and so is everything from a
+// record whose tile or value count does not fit the header's layout.
+//
+//   b"MBCK" | header length: u32 | header JSON
+//   (tile index: u32 | value count: u32 | values: f32 * count)*
+//
+// All integers and floats are little-endian.
+const MAGIC: &[u8; 4] = b"MBCK";
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Header {
+    // Original command line minus the checkpoint flags, used to restart the
+    // render with exactly the same parameters
+    pub args: Vec<String>,
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub tile_rows: u32,
+    // Edge of the square tiles of the curve orders, absent for full-width
+    // bands
+    pub tile_width: Option<u32>,
+}
+
+impl Header {
+    pub fn layout(&self) -> Layout {
+        Layout::new(self.width, self.height, self.tile_width.unwrap_or(self.width), self.tile_rows)
+    }
+}
+
+// A finished tile: its index and raw escape values
+pub type TileRecord = (u32, Vec<f32>);
+
+pub struct Checkpoint {
+    writer: Mutex<(BufWriter<File>, Instant)>,
+}
+
+impl Checkpoint {
+    pub fn create(path: &str, header: &Header) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        let json = serde_json::to_vec(header)?;
+        file.write_all(MAGIC)?;
+        file.write_all(&(json.len() as u32).to_le_bytes())?;
+        file.write_all(&json)?;
+        file.sync_data()?;
+        Ok(Self::from_file(file))
+    }
+
+    // Reopens an existing checkpoint for appending after `load` has dropped
+    // any partial trailing record.
+    pub fn reopen(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Self::from_file(file))
+    }
+
+    fn from_file(file: File) -> Self {
+        Checkpoint { writer: Mutex::new((BufWriter::new(file), Instant::now())) }
+    }
+
+    pub fn record(&self, tile: u32, values: &[f32]) -> io::Result<()> {
+        let mut guard = self.writer.lock().unwrap();
+        let (writer, last_flush) = &mut *guard;
+        writer.write_all(&tile.to_le_bytes())?;
+        writer.write_all(&(values.len() as u32).to_le_bytes())?;
+        for value in values {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        if last_flush.elapsed() >= FLUSH_INTERVAL {
+            writer.flush()?;
+            *last_flush = Instant::now();
+        }
+        Ok(())
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        self.writer.lock().unwrap().0.flush()
+    }
+}
+
+pub fn read_header(path: &str) -> io::Result<Header> {
+    let mut reader = BufReader::new(File::open(path)?);
+    read_header_from(&mut reader)
+}
+
+fn read_header_from(reader: &mut impl Read) -> io::Result<Header> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a checkpoint file"));
+    }
+    let len = read_u32(reader)? as usize;
+    let mut json = vec![0u8; len];
+    reader.read_exact(&mut json)?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+// Reads every complete tile record and truncates the file after the last
+// one, so new records can be appended cleanly. A record for a tile outside
+// the layout, or with other than that tile's number of samples, ends the
+// log like a truncated one: everything after it is garbage as well.
+pub fn load(path: &str) -> io::Result<(Header, Vec<TileRecord>)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let header = read_header_from(&mut reader)?;
+    let layout = header.layout();
+    let mut valid_len = reader.stream_position()?;
+    let mut tiles = Vec::new();
+
+    loop {
+        let record = (|| -> io::Result<TileRecord> {
+            let tile = read_u32(&mut reader)?;
+            let count = read_u32(&mut reader)? as usize;
+            let expected = (tile as usize) < layout.count()
+                && layout.tile(tile as usize).samples() * header.samples_per_pixel as usize == count;
+            if !expected {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "tile record does not fit the layout"));
+            }
+            let mut bytes = vec![0u8; count * 4];
+            reader.read_exact(&mut bytes)?;
+            let values = bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            Ok((tile, values))
+        })();
+        match record {
+            Ok(record) => {
+                tiles.push(record);
+                valid_len = reader.stream_position()?;
+            }
+            Err(e) if matches!(e.kind(), io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData) => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    OpenOptions::new().write(true).open(path)?.set_len(valid_len)?;
+    Ok((header, tiles))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_stops_at_records_outside_the_layout() {
+        let header = Header {
+            args: Vec::new(),
+            width: 4,
+            height: 3,
+            samples_per_pixel: 1,
+            tile_rows: 2,
+            tile_width: None,
+        };
+        let path = std::env::temp_dir().join(format!("checkpoint_{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        let checkpoint = Checkpoint::create(path, &header).unwrap();
+        checkpoint.record(1, &[1.0; 4]).unwrap();
+        checkpoint.record(0, &[2.0; 8]).unwrap();
+        checkpoint.record(2, &[3.0; 4]).unwrap();
+        checkpoint.record(0, &[4.0; 8]).unwrap();
+        checkpoint.flush().unwrap();
+        let loaded = load(path);
+        let reloaded = load(path);
+        std::fs::remove_file(path).unwrap();
+        let expected = (header, vec![(1, vec![1.0; 4]), (0, vec![2.0; 8])]);
+        assert_eq!(loaded.unwrap(), expected);
+        assert_eq!(reloaded.unwrap(), expected);
+    }
+
+    #[test]
+    fn load_stops_at_records_of_the_wrong_length() {
+        let header = Header {
+            args: Vec::new(),
+            width: 4,
+            height: 3,
+            samples_per_pixel: 4,
+            tile_rows: 2,
+            tile_width: Some(2),
+        };
+        let path = std::env::temp_dir().join(format!("checkpoint_length_{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        let checkpoint = Checkpoint::create(path, &header).unwrap();
+        checkpoint.record(3, &[1.0; 8]).unwrap();
+        checkpoint.record(0, &[2.0; 15]).unwrap();
+        checkpoint.flush().unwrap();
+        let loaded = load(path);
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.unwrap(), (header, vec![(3, vec![1.0; 8])]));
+    }
+}
@@ -0,0 +1,183 @@
+use hsv_to_rgb::{ colormap, hsv_to_rgb, hsv_to_rgb_f32, linear_to_srgb, srgb_to_linear, Palette, COLORMAPS };
+use image::{ Primitive, Rgb };
+
+// How colors are mixed: between palette stops, around the HSV wheel and
+// when supersamples are averaged into a pixel. Output files store sRGB
+// values, and mixing those directly darkens and muddies every blend, so by
+// default colors are mixed in linear light and only encoded at the end.
+// `--srgb-blending` mixes the stored values as earlier versions did, to
+// compare the two.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Blending {
+    Linear,
+    Srgb,
+}
+
+// `--palette-scale` and `--palette-offset`: the palette runs `scale` times
+// over the normalized range of escape values and starts `offset` of a cycle
+// in, wrapping around. A larger scale makes the colors repeat every
+// max_iterations / scale iterations, which brings out the bands close to
+// the boundary where the counts are high. The default leaves positions as
+// they are, so the last stop is still reached at 1.0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cycle {
+    pub scale: f32,
+    pub offset: f32,
+}
+
+impl Cycle {
+    pub const IDENTITY: Cycle = Cycle { scale: 1.0, offset: 0.0 };
+
+    pub fn apply(self, t: f32) -> f32 {
+        if self == Cycle::IDENTITY {
+            return t;
+        }
+        (t * self.scale + self.offset).rem_euclid(1.0)
+    }
+}
+
+// Channel types the renderer can produce: u8 for regular output and u16 for
+// `--depth 16`, which keeps smooth gradients from banding when the image is
+// post-processed.
+pub trait Channel: Primitive + Send + Sync + 'static {
+    fn from_palette(palette: &Option<Palette>, t: f32, blending: Blending) -> Rgb<Self>;
+    fn to_u32(self) -> u32;
+    fn from_u32(value: u32) -> Self;
+}
+
+impl Channel for u8 {
+    fn from_palette(palette: &Option<Palette>, t: f32, blending: Blending) -> Rgb<u8> {
+        match (blending, palette) {
+            (Blending::Linear, _) => encode(linear_color(palette, t)),
+            (Blending::Srgb, Some(palette)) => palette.sample(t),
+            (Blending::Srgb, None) => hsv_to_rgb(t * 360.0, 1.0, 1.0),
+        }
+    }
+
+    fn to_u32(self) -> u32 {
+        self as u32
+    }
+
+    fn from_u32(value: u32) -> Self {
+        value as u8
+    }
+}
+
+impl Channel for u16 {
+    fn from_palette(palette: &Option<Palette>, t: f32, blending: Blending) -> Rgb<u16> {
+        let rgb = match (blending, palette) {
+            (Blending::Linear, _) => return encode(linear_color(palette, t)),
+            (Blending::Srgb, Some(palette)) => palette.sample_f32(t),
+            (Blending::Srgb, None) => hsv_to_rgb_f32(t * 360.0, 1.0, 1.0),
+        };
+        Rgb(rgb.map(|c| (c.clamp(0.0, 1.0) * 65535.0).round() as u16))
+    }
+
+    fn to_u32(self) -> u32 {
+        self as u32
+    }
+
+    fn from_u32(value: u32) -> Self {
+        value as u16
+    }
+}
+
+// Palette color at t in linear light. The HSV wheel is piecewise linear
+// between the primaries, so its values are taken as linear light as well.
+fn linear_color(palette: &Option<Palette>, t: f32) -> [f32; 3] {
+    match palette {
+        Some(palette) => palette.sample_linear(t),
+        None => hsv_to_rgb_f32(t * 360.0, 1.0, 1.0),
+    }
+}
+
+fn max_value<T: Channel>() -> f32 {
+    T::DEFAULT_MAX_VALUE.to_u32() as f32
+}
+
+// Linear-light components in 0.0..=1.0 to stored sRGB values
+fn encode<T: Channel>(linear: [f32; 3]) -> Rgb<T> {
+    Rgb(linear.map(|c| T::from_u32((linear_to_srgb(c.clamp(0.0, 1.0)) * max_value::<T>()).round() as u32)))
+}
+
+// Channel-wise mean of several colors, rounded to nearest
+pub fn average_color<T: Channel>(colors: impl Iterator<Item = Rgb<T>>, blending: Blending) -> Rgb<T> {
+    if blending == Blending::Linear {
+        let mut sum = [0.0f32; 3];
+        let mut count = 0;
+        for Rgb(rgb) in colors {
+            for channel in 0..3 {
+                sum[channel] += srgb_to_linear(rgb[channel].to_u32() as f32 / max_value::<T>());
+            }
+            count += 1;
+        }
+        return encode(sum.map(|s| s / count.max(1) as f32));
+    }
+    let mut sum = [0u32; 3];
+    let mut count = 0;
+    for Rgb(rgb) in colors {
+        for channel in 0..3 {
+            sum[channel] += rgb[channel].to_u32();
+        }
+        count += 1;
+    }
+    Rgb(sum.map(|s| T::from_u32((s + count / 2) / count.max(1))))
+}
+
+// A gradient file takes precedence over a named colormap; with neither the
+// renderer falls back to the HSV wheel.
+pub fn select_palette(path: Option<&str>, name: Option<&str>) -> Option<Palette> {
+    if let Some(path) = path {
+        return Some(Palette::load(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load palette {}: {}", path, e);
+            std::process::exit(1);
+        }));
+    }
+    name.map(|name| {
+        colormap(name).unwrap_or_else(|| {
+            eprintln!("Unknown colormap {} (expected one of: {})", name, COLORMAPS.join(", "));
+            std::process::exit(2);
+        })
+    })
+}
+
+// Resolves a palette as written in keyframe scripts and image metadata:
+// "hsv", a colormap name or a gradient file path.
+pub fn palette_from_spec(spec: &str) -> Option<Palette> {
+    match spec {
+        "hsv" => None,
+        name if colormap(name).is_some() => colormap(name),
+        path => select_palette(Some(path), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycles_repeat_and_shift_the_palette() {
+        assert_eq!(Cycle::IDENTITY.apply(1.0), 1.0);
+        let cycle = Cycle { scale: 4.0, offset: 0.25 };
+        assert_eq!(cycle.apply(0.0), 0.25);
+        assert_eq!(cycle.apply(0.125), 0.75);
+        assert_eq!(cycle.apply(0.25), 0.25);
+        assert_eq!(Cycle { scale: 1.0, offset: -0.25 }.apply(0.0), 0.75);
+    }
+
+    #[test]
+    fn blends_in_linear_light() {
+        let (black, white) = (Rgb([0u8; 3]), Rgb([255u8; 3]));
+        assert_eq!(average_color([black, white].into_iter(), Blending::Srgb), Rgb([128; 3]));
+        // Half the light of white is stored as 188, not 128
+        assert_eq!(average_color([black, white].into_iter(), Blending::Linear), Rgb([188; 3]));
+        assert_eq!(average_color([white, white].into_iter(), Blending::Linear), white);
+
+        let gradient = Some(Palette::new(vec![[0, 0, 0], [255, 255, 255]]));
+        assert_eq!(u8::from_palette(&gradient, 0.5, Blending::Srgb), Rgb([128; 3]));
+        assert_eq!(u8::from_palette(&gradient, 0.5, Blending::Linear), Rgb([188; 3]));
+        // Stops come out unchanged either way
+        assert_eq!(u16::from_palette(&gradient, 1.0, Blending::Linear), Rgb([65535; 3]));
+        assert_eq!(u8::from_palette(&None, 0.0, Blending::Linear), Rgb([255, 0, 0]));
+    }
+}
@@ -0,0 +1,143 @@
+use image::{ Rgb, RgbImage };
+
+use crate::font;
+use crate::locations::{ self, Location };
+
+// Contact sheets for `--contact-sheet`.
+//
+// A grid of small renders, each captioned with its name, center and zoom,
+// for picking which view to render at full size. The views come from a
+// list, one per line:
+//
+//   # Named locations
+//   seahorse
+//   double-spiral
+//   # Or a center and zoom, optionally iterations and a caption
+//   -0.1011,0.9563 400
+//   -1.25066,0.02012 3000 2000 tendrils
+//
+// `#` starts a comment. On the command line the list can also be `all`
+// for every named location or location names separated by commas.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub label: String,
+    pub center: String,
+    pub zoom: f64,
+    pub iterations: Option<u32>,
+}
+
+impl Entry {
+    pub fn from_location(location: &Location) -> Self {
+        Entry { label: location.name.to_string(), center: location.center.to_string(), zoom: location.zoom, iterations: Some(location.iterations) }
+    }
+
+    // Caption lines under the thumbnail
+    pub fn caption(&self) -> [String; 3] {
+        let zoom = if self.zoom < 1e6 { format!("zoom {}", self.zoom) } else { format!("zoom {:e}", self.zoom) };
+        [self.label.clone(), self.center.clone(), zoom]
+    }
+}
+
+pub fn parse(text: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let entry = match fields[..] {
+            [name] => locations::find(name).map(Entry::from_location).ok_or_else(|| format!("line {}: unknown location {}", number + 1, name)),
+            [center, zoom, ref rest @ ..] => parse_view(center, zoom, rest).ok_or_else(|| format!("line {}: expected RE,IM ZOOM [ITERATIONS] [CAPTION]", number + 1)),
+            [] => unreachable!(),
+        };
+        entries.push(entry?);
+    }
+    if entries.is_empty() {
+        return Err("no views listed".to_string());
+    }
+    Ok(entries)
+}
+
+// The command-line form: `all`, or location names separated by commas
+pub fn parse_names(spec: &str) -> Result<Vec<Entry>, String> {
+    if spec == "all" {
+        return Ok(locations::LOCATIONS.iter().map(Entry::from_location).collect());
+    }
+    parse(&spec.replace(',', "\n"))
+}
+
+fn parse_view(center: &str, zoom: &str, rest: &[&str]) -> Option<Entry> {
+    let (re, im) = center.split_once(',')?;
+    re.parse::<f64>().ok()?;
+    im.parse::<f64>().ok()?;
+    let zoom: f64 = zoom.parse().ok().filter(|&zoom: &f64| zoom > 0.0)?;
+    let (iterations, caption) = match rest.first().map(|first| first.parse::<u32>()) {
+        Some(Ok(iterations)) => (Some(iterations), &rest[1..]),
+        _ => (None, rest),
+    };
+    let label = if caption.is_empty() { center.to_string() } else { caption.join(" ") };
+    Some(Entry { label, center: center.to_string(), zoom, iterations })
+}
+
+// Layout of the sheet: thumbnails of `cell` pixels in `columns` columns,
+// each with CAPTION_HEIGHT pixels of caption below and MARGIN around
+pub const MARGIN: u32 = 8;
+const LINE_HEIGHT: u32 = font::HEIGHT + 3;
+pub const CAPTION_HEIGHT: u32 = 3 * LINE_HEIGHT + 2;
+const BACKGROUND: Rgb<u8> = Rgb([24, 24, 24]);
+const TEXT: Rgb<u8> = Rgb([230, 230, 230]);
+
+// Columns for `count` thumbnails, as close to square as the count allows
+pub fn columns(count: usize) -> u32 {
+    (count as f64).sqrt().ceil().max(1.0) as u32
+}
+
+pub fn compose(thumbnails: &[(RgbImage, [String; 3])], (cell_width, cell_height): (u32, u32)) -> RgbImage {
+    let columns = columns(thumbnails.len());
+    let rows = (thumbnails.len() as u32).div_ceil(columns);
+    let (step_x, step_y) = (cell_width + MARGIN, cell_height + CAPTION_HEIGHT + MARGIN);
+    let mut sheet = RgbImage::from_pixel(columns * step_x + MARGIN, rows * step_y + MARGIN, BACKGROUND);
+    // Characters that fit under a thumbnail; longer captions are cut short
+    let fit = ((cell_width + font::ADVANCE - 5) / font::ADVANCE) as usize;
+    for (i, (thumbnail, caption)) in thumbnails.iter().enumerate() {
+        let (x, y) = (MARGIN + i as u32 % columns * step_x, MARGIN + i as u32 / columns * step_y);
+        image::imageops::replace(&mut sheet, thumbnail, x as i64, y as i64);
+        for (line, text) in caption.iter().enumerate() {
+            let text = if text.chars().count() > fit { format!("{}..", text.chars().take(fit.saturating_sub(2)).collect::<String>()) } else { text.clone() };
+            font::draw(&mut sheet, (x, y + cell_height + 3 + line as u32 * LINE_HEIGHT), &text, TEXT);
+        }
+    }
+    sheet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_names_and_views() {
+        let entries = parse("seahorse # the classic\n\n-0.1,0.9 400\n-1.25,0.02 3000 2000 tendrils here\n").unwrap();
+        assert_eq!(entries[0], Entry::from_location(locations::find("seahorse").unwrap()));
+        assert_eq!(entries[1], Entry { label: "-0.1,0.9".to_string(), center: "-0.1,0.9".to_string(), zoom: 400.0, iterations: None });
+        assert_eq!(entries[2].label, "tendrils here");
+        assert_eq!(entries[2].iterations, Some(2000));
+        assert_eq!(parse("nowhere").unwrap_err(), "line 1: unknown location nowhere");
+        assert_eq!(parse("# only a comment").unwrap_err(), "no views listed");
+        assert_eq!(parse_names("seahorse,minibrot").unwrap().len(), 2);
+        assert_eq!(parse_names("all").unwrap().len(), locations::LOCATIONS.len());
+        assert_eq!(entries[2].caption()[2], "zoom 3000");
+        assert_eq!(Entry { zoom: 2.5e13, ..entries[2].clone() }.caption()[2], "zoom 2.5e13");
+    }
+
+    #[test]
+    fn lays_out_a_grid() {
+        let thumbnail = RgbImage::from_pixel(30, 20, Rgb([255, 0, 0]));
+        let caption = ["a".to_string(), "b".to_string(), "c".to_string()];
+        let sheet = compose(&vec![(thumbnail, caption); 5], (30, 20));
+        // Three columns, two rows
+        assert_eq!(sheet.dimensions(), (3 * 38 + 8, 2 * (28 + CAPTION_HEIGHT) + 8));
+        assert_eq!(*sheet.get_pixel(8 + 2 * 38, 8), Rgb([255, 0, 0]));
+        assert_eq!(*sheet.get_pixel(8 + 2 * 38, 8 + 28 + CAPTION_HEIGHT), BACKGROUND);
+    }
+}
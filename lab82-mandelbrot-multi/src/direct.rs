@@ -0,0 +1,226 @@
+use num_complex::Complex;
+
+// Formulas of `--fractal` that the escape-time kernels cannot run, and
+// which are rendered pixel by pixel like `--newton` instead.
+//
+// - phoenix: Ushiki's Phoenix, z <- z² + C + P·z', where z' is the point
+//   before z. The step needs the previous point as well as the current one,
+//   so it does not fit a z -> f(z, c) kernel. Pixels are starting points
+//   of the orbit, with C and P fixed to the classic 0.5667 and -0.5.
+// - nova: Newton's method for z³ - 1 relaxed by R and shifted by c,
+//   z <- z - R·(z³ - 1)/(3z²) + c from z = 1. Pixels are c, and the
+//   interesting points are the ones whose orbit converges.
+// - magnet1 and magnet2: the renormalization maps of the Ising model,
+//   rational maps in z and the pixel c, started from z = 0. Orbits escape
+//   or settle on the fixed point 1, except in the Mandelbrot-like islands
+//   between the two.
+// - collatz: the Collatz map extended to the plane,
+//   z <- (2 + 7z - (2 + 5z)·cos(πz)) / 4, which is z/2 at even integers and
+//   3z + 1 at odd ones. Pixels are starting points. The cosine grows
+//   exponentially away from the real axis, so orbits escape in a handful of
+//   steps there but can wander along the axis for a long time.
+//
+// Each pixel gets a count of the steps to what its formula looks for
+// (escape for Phoenix and Collatz, convergence for Nova, either for
+// Magnet) and is black if that never happens within the iteration cap.
+// The counts are smooth except for Collatz, whose orbits neither grow at a
+// steady power nor converge.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direct {
+    Phoenix,
+    Nova,
+    MagnetI,
+    MagnetII,
+    Collatz,
+}
+
+// The Phoenix constants
+const PHOENIX_C: f64 = 0.5667;
+const PHOENIX_P: f64 = -0.5;
+// The Nova relaxation; 1 is plain Newton's method
+const NOVA_R: f64 = 1.0;
+
+// Escape radius of Phoenix; the P·z' term means 2 is not quite enough
+const PHOENIX_BAILOUT: f64 = 4.0;
+// Magnet orbits head for 1 from well outside the unit disk, so they only
+// count as escaped much farther out
+const MAGNET_BAILOUT: f64 = 100.0;
+// Escape radius of Collatz. Real starting points drift outwards under 3z + 1
+// without escaping, so it sits well beyond the structure along the axis.
+const COLLATZ_BAILOUT: f64 = 1e3;
+// Distance between steps, or from the fixed point 1, at which an orbit
+// counts as converged
+const TOLERANCE: f64 = 1e-6;
+// Nova orbits past this have been thrown off by a near-zero derivative and
+// will not come back
+const NOVA_DIVERGED: f64 = 1e10;
+
+impl Direct {
+    // Center and zoom used when the command line gives none, framing the
+    // structure in the 3:2 view. The Nova plane goes on forever, its view
+    // is the crescent and the Mandelbrot-like disks along its inner edge.
+    pub fn default_view(self) -> ((f64, f64), f64) {
+        match self {
+            Direct::Phoenix => ((0.0, 0.0), 0.6),
+            Direct::Nova => ((-0.2, 0.0), 1.2),
+            Direct::MagnetI => ((1.5, 0.0), 0.35),
+            Direct::MagnetII => ((1.0, 0.0), 0.45),
+            Direct::Collatz => ((0.0, 0.0), 0.6),
+        }
+    }
+
+    // Smooth step count at `point`, or None if the orbit does not escape or
+    // converge, as the formula expects, within max_iterations
+    pub fn value(self, point: Complex<f64>, max_iterations: u32) -> Option<f64> {
+        match self {
+            Direct::Phoenix => phoenix(point, max_iterations),
+            Direct::Nova => nova(point, max_iterations),
+            Direct::MagnetI => magnet(point, max_iterations, magnet_i),
+            Direct::MagnetII => magnet(point, max_iterations, magnet_ii),
+            Direct::Collatz => collatz(point, max_iterations),
+        }
+    }
+}
+
+// Fractional steps left once |z| passed `bailout` at step `iteration` of a
+// degree-2 orbit, as for escape-time smooth coloring
+fn smooth_escape(iteration: u32, z: Complex<f64>, bailout: f64) -> f64 {
+    let log_ratio = z.norm().ln() / bailout.ln();
+    (iteration as f64 + 1.0 - log_ratio.log2()).max(0.0)
+}
+
+fn phoenix(mut z: Complex<f64>, max_iterations: u32) -> Option<f64> {
+    let mut previous = Complex::new(0.0, 0.0);
+    for iteration in 0..max_iterations {
+        if z.norm_sqr() > PHOENIX_BAILOUT * PHOENIX_BAILOUT {
+            return Some(smooth_escape(iteration, z, PHOENIX_BAILOUT));
+        }
+        (z, previous) = (z * z + PHOENIX_C + previous * PHOENIX_P, z);
+    }
+    None
+}
+
+// Fractional steps left once an orbit converging quadratically got within
+// `distance` < TOLERANCE at step `iteration`. The distance squares with
+// every step, so how far below TOLERANCE it went on a log scale gives the
+// fraction of a step.
+fn smooth_convergence(iteration: u32, distance: f64) -> f64 {
+    let overshoot = if distance > 0.0 { (distance.ln() / TOLERANCE.ln()).log2().min(1.0) } else { 1.0 };
+    (iteration as f64 + 1.0 - overshoot).max(0.0)
+}
+
+// Newton's method converges quadratically, and so does Nova near its
+// attracting fixed points
+fn nova(c: Complex<f64>, max_iterations: u32) -> Option<f64> {
+    let mut z = Complex::new(1.0, 0.0);
+    for iteration in 0..max_iterations {
+        let next = z - (z * z * z - 1.0) / (z * z * 3.0) * NOVA_R + c;
+        let step = (next - z).norm();
+        if step < TOLERANCE {
+            return Some(smooth_convergence(iteration, step));
+        }
+        // Also catches the NaN of a zero derivative
+        if next.norm_sqr() >= NOVA_DIVERGED || next.is_nan() {
+            return None;
+        }
+        z = next;
+    }
+    None
+}
+
+// 1 is a superattracting fixed point of both maps, so orbits settle on it
+// quadratically
+fn magnet(c: Complex<f64>, max_iterations: u32, step: impl Fn(Complex<f64>, Complex<f64>) -> Complex<f64>) -> Option<f64> {
+    let mut z = Complex::new(0.0, 0.0);
+    for iteration in 0..max_iterations {
+        if z.norm_sqr() > MAGNET_BAILOUT * MAGNET_BAILOUT {
+            return Some(smooth_escape(iteration, z, MAGNET_BAILOUT));
+        }
+        let distance = (z - 1.0).norm();
+        if distance < TOLERANCE {
+            return Some(smooth_convergence(iteration, distance));
+        }
+        // Lost to a zero denominator
+        if z.is_nan() {
+            return None;
+        }
+        z = step(z, c);
+    }
+    None
+}
+
+// ((z² + c - 1) / (2z + c - 2))²
+fn magnet_i(z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+    let w = (z * z + c - 1.0) / (z * 2.0 + c - 2.0);
+    w * w
+}
+
+// ((z³ + 3(c - 1)z + (c - 1)(c - 2)) / (3z² + 3(c - 2)z + (c - 1)(c - 2) + 1))²
+fn magnet_ii(z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+    let (a, b) = (c - 1.0, c - 2.0);
+    let w = (z * z * z + a * z * 3.0 + a * b) / (z * z * 3.0 + b * z * 3.0 + a * b + 1.0);
+    w * w
+}
+
+fn collatz(mut z: Complex<f64>, max_iterations: u32) -> Option<f64> {
+    for iteration in 0..max_iterations {
+        // The cosine overflows to inf or NaN, which also counts as escaped
+        if z.norm_sqr() > COLLATZ_BAILOUT * COLLATZ_BAILOUT || z.is_nan() {
+            return Some(iteration as f64);
+        }
+        z = (z * 7.0 + 2.0 - (z * 5.0 + 2.0) * (z * std::f64::consts::PI).cos()) / 4.0;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phoenix_feeds_back_the_previous_point() {
+        // Three steps by hand from z = 1: z1 = 1 + C, z2 = z1² + C + P·1,
+        // z3 = z2² + C + P·z1, the first one past the bailout
+        let z1 = 1.0 + PHOENIX_C;
+        let z2 = z1 * z1 + PHOENIX_C + PHOENIX_P;
+        let z3 = z2 * z2 + PHOENIX_C + PHOENIX_P * z1;
+        assert!(z2 <= PHOENIX_BAILOUT && z3 > PHOENIX_BAILOUT);
+        let value = Direct::Phoenix.value(Complex::new(1.0, 0.0), 100).unwrap();
+        assert!((value - smooth_escape(3, Complex::new(z3, 0.0), PHOENIX_BAILOUT)).abs() < 1e-12);
+        assert_eq!(Direct::Phoenix.value(Complex::new(0.0, 0.7), 2000), None);
+    }
+
+    #[test]
+    fn nova_colors_converging_orbits() {
+        // With c = 0 the orbit starts on the root 1 and stops at once
+        assert_eq!(Direct::Nova.value(Complex::new(0.0, 0.0), 100), Some(0.0));
+        let value = Direct::Nova.value(Complex::new(0.3, 0.0), 100).unwrap();
+        assert!(value > 1.0 && value < 100.0);
+        // The big disk left of the crescent, where the orbit cycles instead
+        assert_eq!(Direct::Nova.value(Complex::new(-0.5, 0.0), 1000), None);
+    }
+
+    #[test]
+    fn magnets_escape_or_settle_on_one() {
+        let cases = [(Direct::MagnetI, Complex::new(1.0, 1.75), Complex::new(0.0, 0.0)), (Direct::MagnetII, Complex::new(2.0, 0.25), Complex::new(1.5, 0.0))];
+        for (formula, escaping, bounded) in cases {
+            assert!(formula.value(escaping, 500).is_some(), "{:?}", formula);
+            assert!(formula.value(Complex::new(6.0, 4.0), 500).is_some(), "{:?}", formula);
+            assert_eq!(formula.value(bounded, 500), None, "{:?}", formula);
+        }
+        // 1 is a fixed point of both maps for every c
+        let c = Complex::new(0.3, -0.7);
+        assert!((magnet_i(Complex::new(1.0, 0.0), c) - 1.0).norm() < 1e-12);
+        assert!((magnet_ii(Complex::new(1.0, 0.0), c) - 1.0).norm() < 1e-12);
+    }
+
+    #[test]
+    fn collatz_extends_the_integer_map() {
+        // 1 -> 4 -> 2 -> 1 never escapes
+        assert_eq!(Direct::Collatz.value(Complex::new(1.0, 0.0), 1000), None);
+        // Off the axis the cosine takes over within a few steps
+        let value = Direct::Collatz.value(Complex::new(0.5, 2.0), 1000).unwrap();
+        assert!(value < 5.0);
+        assert_eq!(Direct::Collatz.value(Complex::new(f64::NAN, 0.0), 10), Some(0.0));
+    }
+}
@@ -0,0 +1,181 @@
+use image::{ ImageBuffer, Rgb, RgbImage };
+use std::sync::OnceLock;
+
+use crate::rng::SplitMix64;
+
+// Dithered quantization for `--dither`.
+//
+// The image is colored at 16 bits per channel and only then brought down
+// to 8, adding a per-pixel threshold in -0.5..0.5 of an 8-bit step before
+// rounding. Gradients that change by less than a step over many pixels,
+// as in the dark outer bands of a smooth render, then come out as a fine
+// mix of the two neighbouring levels instead of visible bands.
+//
+// - ordered: an 8×8 Bayer matrix, a regular cross-hatch pattern
+// - blue-noise: a 64×64 void-and-cluster texture, which has no low
+//   frequencies and so reads as even grain
+//
+// Both patterns are fixed, so dithered renders stay reproducible.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dither {
+    Ordered,
+    BlueNoise,
+}
+
+const BAYER_SIZE: usize = 8;
+const NOISE_SIZE: usize = 64;
+// Spread of the energy function of void-and-cluster, in pixels
+const NOISE_SIGMA: f64 = 1.9;
+
+impl Dither {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ordered" => Some(Dither::Ordered),
+            "blue-noise" => Some(Dither::BlueNoise),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Dither::Ordered => "ordered",
+            Dither::BlueNoise => "blue-noise",
+        }
+    }
+
+    // Offset in -0.5..0.5 added to pixel (x, y) before rounding
+    pub fn threshold(self, x: u32, y: u32) -> f32 {
+        let (rank, count) = match self {
+            Dither::Ordered => (bayer(x as usize % BAYER_SIZE, y as usize % BAYER_SIZE), BAYER_SIZE * BAYER_SIZE),
+            Dither::BlueNoise => {
+                let (x, y) = (x as usize % NOISE_SIZE, y as usize % NOISE_SIZE);
+                (blue_noise()[y * NOISE_SIZE + x] as usize, NOISE_SIZE * NOISE_SIZE)
+            }
+        };
+        (rank as f32 + 0.5) / count as f32 - 0.5
+    }
+
+    pub fn quantize(self, image: &ImageBuffer<Rgb<u16>, Vec<u16>>) -> RgbImage {
+        RgbImage::from_fn(image.width(), image.height(), |x, y| {
+            let threshold = self.threshold(x, y);
+            Rgb(image.get_pixel(x, y).0.map(|c| (c as f32 / 257.0 + threshold).round().clamp(0.0, 255.0) as u8))
+        })
+    }
+}
+
+// Rank of (x, y) in the Bayer matrix of BAYER_SIZE, built up bit by bit
+// from the 2×2 matrix [0 2; 3 1], the lowest bits of x and y giving the
+// most significant digit so that neighbouring pixels are far apart in rank
+fn bayer(x: usize, y: usize) -> usize {
+    let mut rank = 0;
+    let mut bit = 1;
+    while bit < BAYER_SIZE {
+        let (bx, by) = (x & bit != 0, y & bit != 0);
+        rank = rank * 4 + [[0, 2], [3, 1]][by as usize][bx as usize];
+        bit *= 2;
+    }
+    rank
+}
+
+// Ranks 0..NOISE_SIZE² of the blue-noise texture, row-major. Computed on
+// first use; it takes a moment, and only --dither blue-noise needs it.
+fn blue_noise() -> &'static [u16] {
+    static TEXTURE: OnceLock<Vec<u16>> = OnceLock::new();
+    TEXTURE.get_or_init(void_and_cluster)
+}
+
+// Ulichney's void-and-cluster method: start from a few scattered points
+// evened out by moving the point in the tightest cluster to the largest
+// void, then rank them by taking clusters away and the remaining pixels
+// by filling voids. Energies are Gaussian-weighted counts of the set
+// pixels around each pixel, on a torus so the texture tiles seamlessly.
+fn void_and_cluster() -> Vec<u16> {
+    let n = NOISE_SIZE * NOISE_SIZE;
+    // Gaussian weight by wrapped offset
+    let kernel: Vec<f64> = (0..n)
+        .map(|i| {
+            let wrap = |d: usize| d.min(NOISE_SIZE - d) as f64;
+            let (dx, dy) = (wrap(i % NOISE_SIZE), wrap(i / NOISE_SIZE));
+            (-(dx * dx + dy * dy) / (2.0 * NOISE_SIGMA * NOISE_SIGMA)).exp()
+        })
+        .collect();
+    let toggle = |energy: &mut [f64], set: &mut [bool], i: usize| {
+        set[i] = !set[i];
+        let sign = if set[i] { 1.0 } else { -1.0 };
+        let (x, y) = (i % NOISE_SIZE, i / NOISE_SIZE);
+        for (j, e) in energy.iter_mut().enumerate() {
+            let dx = (j % NOISE_SIZE + NOISE_SIZE - x) % NOISE_SIZE;
+            let dy = (j / NOISE_SIZE + NOISE_SIZE - y) % NOISE_SIZE;
+            *e += sign * kernel[dy * NOISE_SIZE + dx];
+        }
+    };
+    let tightest_cluster = |energy: &[f64], set: &[bool]| (0..n).filter(|&i| set[i]).max_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap();
+    let largest_void = |energy: &[f64], set: &[bool]| (0..n).filter(|&i| !set[i]).min_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap();
+
+    let (mut energy, mut set) = (vec![0.0; n], vec![false; n]);
+    let mut rng = SplitMix64::new(0x5eed);
+    let initial = n / 10;
+    while set.iter().filter(|&&s| s).count() < initial {
+        let i = (rng.next_u64() % n as u64) as usize;
+        if !set[i] {
+            toggle(&mut energy, &mut set, i);
+        }
+    }
+    loop {
+        let cluster = tightest_cluster(&energy, &set);
+        toggle(&mut energy, &mut set, cluster);
+        let void = largest_void(&energy, &set);
+        toggle(&mut energy, &mut set, void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0u16; n];
+    let (pattern, pattern_energy) = (set.clone(), energy.clone());
+    for rank in (0..initial).rev() {
+        let cluster = tightest_cluster(&energy, &set);
+        toggle(&mut energy, &mut set, cluster);
+        ranks[cluster] = rank as u16;
+    }
+    let (mut set, mut energy) = (pattern, pattern_energy);
+    for rank in initial..n {
+        let void = largest_void(&energy, &set);
+        toggle(&mut energy, &mut set, void);
+        ranks[void] = rank as u16;
+    }
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds_cover_every_level_once() {
+        for (dither, size) in [(Dither::Ordered, BAYER_SIZE), (Dither::BlueNoise, NOISE_SIZE)] {
+            let mut ranks: Vec<usize> = (0..size * size)
+                .map(|i| ((dither.threshold((i % size) as u32, (i / size) as u32) + 0.5) * (size * size) as f32) as usize)
+                .collect();
+            ranks.sort();
+            assert!(ranks.into_iter().eq(0..size * size), "{:?}", dither);
+            // The pattern tiles
+            assert_eq!(dither.threshold(3, 5), dither.threshold(3 + size as u32, 5 + 2 * size as u32));
+        }
+        // First row of the usual 8×8 matrix
+        assert_eq!((0..8).map(|x| bayer(x, 0)).collect::<Vec<_>>(), [0, 32, 8, 40, 2, 34, 10, 42]);
+    }
+
+    #[test]
+    fn keeps_the_mean_of_levels_between_steps() {
+        // A quarter of the way from 100 to 101
+        let level = (100.25 * 257.0) as u16;
+        let image = ImageBuffer::from_pixel(64, 64, Rgb([level, 0, u16::MAX]));
+        for dither in [Dither::Ordered, Dither::BlueNoise] {
+            let quantized = dither.quantize(&image);
+            let mean = quantized.pixels().map(|p| p[0] as f64).sum::<f64>() / (64.0 * 64.0);
+            assert!((mean - 100.25).abs() < 0.01, "{:?}: {}", dither, mean);
+            assert!(quantized.pixels().all(|p| (100..=101).contains(&p[0]) && p[1] == 0 && p[2] == 255));
+        }
+    }
+}
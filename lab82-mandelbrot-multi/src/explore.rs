@@ -0,0 +1,110 @@
+use crate::rng::SplitMix64;
+
+// Scoring for `--explore`.
+//
+// The view is cut into GRID×GRID cells and each cell of a low-resolution
+// render is scored by how much is going on in it: the spread of its escape
+// values (log-scaled, so the bands far from the set do not dominate) times
+// the share of neighbouring samples that straddle the boundary of the set.
+// A cell is then drawn with probability proportional to its squared score,
+// which mostly follows the best cell but still wanders elsewhere.
+
+pub const GRID: u32 = 3;
+
+// Keeps cells that show detailed escape bands but no interior points in
+// the running
+const BOUNDARY_FLOOR: f64 = 0.02;
+
+// Interestingness of the `width`×`height` block of escape values starting
+// at (x0, y0) in a buffer with rows of `stride` values
+pub fn score(values: &[f32], stride: usize, (x0, y0, width, height): (usize, usize, usize, usize), max_iterations: u32) -> f64 {
+    let at = |x: usize, y: usize| values[(y0 + y) * stride + x0 + x];
+    let interior = |value: f32| value >= max_iterations as f32;
+
+    let (mut sum, mut sum_sq, mut escaped) = (0.0, 0.0, 0usize);
+    let (mut straddling, mut pairs) = (0usize, 0usize);
+    for y in 0..height {
+        for x in 0..width {
+            let value = at(x, y);
+            if !interior(value) {
+                let level = (1.0 + value as f64).ln();
+                sum += level;
+                sum_sq += level * level;
+                escaped += 1;
+            }
+            for (nx, ny) in [(x + 1, y), (x, y + 1)] {
+                if nx < width && ny < height {
+                    pairs += 1;
+                    straddling += (interior(value) != interior(at(nx, ny))) as usize;
+                }
+            }
+        }
+    }
+    if escaped < 2 || pairs == 0 {
+        return 0.0;
+    }
+    let mean = sum / escaped as f64;
+    let deviation = (sum_sq / escaped as f64 - mean * mean).max(0.0).sqrt();
+    deviation * (straddling as f64 / pairs as f64 + BOUNDARY_FLOOR)
+}
+
+// Scores of the GRID×GRID cells of a `width`×`height` buffer, row by row
+pub fn cell_scores(values: &[f32], width: u32, height: u32, max_iterations: u32) -> Vec<f64> {
+    let (cell_width, cell_height) = ((width / GRID) as usize, (height / GRID) as usize);
+    (0..GRID * GRID)
+        .map(|cell| {
+            let (x0, y0) = ((cell % GRID) as usize * cell_width, (cell / GRID) as usize * cell_height);
+            score(values, width as usize, (x0, y0, cell_width, cell_height), max_iterations)
+        })
+        .collect()
+}
+
+// Draws a cell with probability proportional to its squared score; None if
+// no cell scores above zero
+pub fn pick(scores: &[f64], rng: &mut SplitMix64) -> Option<usize> {
+    let weights: Vec<f64> = scores.iter().map(|score| score * score).collect();
+    let total: f64 = weights.iter().sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let mut target = rng.unit() * total;
+    for (cell, &weight) in weights.iter().enumerate() {
+        if target < weight {
+            return Some(cell);
+        }
+        target -= weight;
+    }
+    weights.iter().rposition(|&weight| weight > 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boundaries_score_higher_than_plain_bands() {
+        let max_iterations = 100;
+        // A gradient of escape values, then the same with an interior blob
+        let bands: Vec<f32> = (0..64).map(|i| (i % 8 * 5) as f32).collect();
+        let mut boundary = bands.clone();
+        for i in [27, 28, 35, 36] {
+            boundary[i] = max_iterations as f32;
+        }
+        let whole = (0, 0, 8, 8);
+        assert!(score(&boundary, 8, whole, max_iterations) > score(&bands, 8, whole, max_iterations));
+        assert_eq!(score(&[max_iterations as f32; 64], 8, whole, max_iterations), 0.0);
+        assert_eq!(score(&[7.0; 64], 8, whole, max_iterations), 0.0);
+    }
+
+    #[test]
+    fn pick_skips_dull_cells_and_follows_the_seed() {
+        let scores = [0.0, 0.5, 0.0, 2.0];
+        let picks = |seed| {
+            let mut rng = SplitMix64::new(seed);
+            (0..50).map(|_| pick(&scores, &mut rng).unwrap()).collect::<Vec<_>>()
+        };
+        assert!(picks(1).iter().all(|&cell| cell == 1 || cell == 3));
+        assert_eq!(picks(7), picks(7));
+        assert_eq!(pick(&[0.0; 4], &mut SplitMix64::new(1)), None);
+    }
+}
@@ -0,0 +1,458 @@
+use double_double::DoubleDouble;
+use num_complex::Complex;
+use wide::f64x4;
+
+use crate::fractal::Fractal;
+
+// Complex expressions for `--formula` and `--color-script`.
+//
+// An expression such as "z*z*z + c*z + c" is parsed once into a syntax tree
+// and compiled to a short stack program, which then runs once per orbit
+// step or per pixel. The language is deliberately small:
+//
+// - the variables of the caller (z and c for formulas), the imaginary unit
+//   i, pi and real numbers
+// - + - * / and ^, with the usual precedence; ^ binds tightest and groups
+//   to the right
+// - the functions sin, cos, exp, log, sqrt and conj, plus abs, arg, re, im
+//   and floor, which take a complex number apart
+//
+// Integer constant powers compile to repeated multiplication, anything else
+// goes through the principal branch of the complex power. There is no
+// double-double or SIMD version of a program, so deep zooms and `--simd`
+// run it in f64 one point at a time. The orbit escapes past --escape-radius
+// like any other, which suits the polynomial formulas this is mostly meant
+// for, but is checked after every step: nothing keeps an arbitrary formula
+// from taking it back inside.
+
+// Values a program can hold at once; deeper expressions are rejected
+const STACK: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Function {
+    Sin,
+    Cos,
+    Exp,
+    Log,
+    Sqrt,
+    Conj,
+    Abs,
+    Arg,
+    Re,
+    Im,
+    Floor,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sin" => Some(Function::Sin),
+            "cos" => Some(Function::Cos),
+            "exp" => Some(Function::Exp),
+            "log" => Some(Function::Log),
+            "sqrt" => Some(Function::Sqrt),
+            "conj" => Some(Function::Conj),
+            "abs" => Some(Function::Abs),
+            "arg" => Some(Function::Arg),
+            "re" => Some(Function::Re),
+            "im" => Some(Function::Im),
+            "floor" => Some(Function::Floor),
+            _ => None,
+        }
+    }
+
+    fn apply(self, w: Complex<f64>) -> Complex<f64> {
+        match self {
+            Function::Sin => w.sin(),
+            Function::Cos => w.cos(),
+            Function::Exp => w.exp(),
+            Function::Log => w.ln(),
+            Function::Sqrt => w.sqrt(),
+            Function::Conj => w.conj(),
+            Function::Abs => Complex::new(w.norm(), 0.0),
+            Function::Arg => Complex::new(w.arg(), 0.0),
+            Function::Re => Complex::new(w.re, 0.0),
+            Function::Im => Complex::new(w.im, 0.0),
+            Function::Floor => Complex::new(w.re.floor(), w.im.floor()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    // Index into the variables of the program
+    Var(usize),
+    Number(Complex<f64>),
+    Neg(Box<Node>),
+    Add(Box<Node>, Box<Node>),
+    Sub(Box<Node>, Box<Node>),
+    Mul(Box<Node>, Box<Node>),
+    Div(Box<Node>, Box<Node>),
+    Pow(Box<Node>, Box<Node>),
+    Call(Function, Box<Node>),
+}
+
+impl Node {
+    // Degree of the node as a polynomial in the first variable; None where
+    // it is not one
+    fn degree(&self) -> Option<f64> {
+        match self {
+            Node::Var(0) => Some(1.0),
+            Node::Var(_) | Node::Number(_) => Some(0.0),
+            Node::Neg(a) => a.degree(),
+            Node::Add(a, b) | Node::Sub(a, b) => Some(a.degree()?.max(b.degree()?)),
+            Node::Mul(a, b) => Some(a.degree()? + b.degree()?),
+            Node::Div(a, b) => (b.degree()? == 0.0).then_some(a.degree()?),
+            Node::Pow(a, b) => match **b {
+                Node::Number(n) if n.im == 0.0 => Some(a.degree()? * n.re),
+                _ => (a.degree()? == 0.0 && b.degree()? == 0.0).then_some(0.0),
+            },
+            Node::Call(Function::Conj, a) => a.degree(),
+            Node::Call(_, a) => (a.degree()? == 0.0).then_some(0.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Var(usize),
+    Push(Complex<f64>),
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    // Power by a positive integer constant
+    Powu(u32),
+    Pow,
+    Call(Function),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    ops: Vec<Op>,
+    degree: f64,
+}
+
+impl Program {
+    // An escape-time formula in z and c
+    pub fn compile(source: &str) -> Result<Self, String> {
+        Self::compile_with(source, &["z", "c"])
+    }
+
+    // An expression in the given variables, which `eval` then takes in the
+    // same order
+    pub fn compile_with(source: &str, variables: &[&str]) -> Result<Self, String> {
+        let mut parser = Parser { tokens: tokenize(source)?, next: 0, variables };
+        let node = parser.expression()?;
+        if let Some(token) = parser.tokens.get(parser.next) {
+            return Err(format!("unexpected {}", token));
+        }
+        let mut ops = Vec::new();
+        let depth = emit(&node, &mut ops);
+        if depth > STACK {
+            return Err(format!("expression needs {} values at once, at most {} are supported", depth, STACK));
+        }
+        // Smooth coloring takes the log of the degree, so anything that
+        // does not grow like a power of z is treated as quadratic. Only
+        // formulas use it.
+        let degree = node.degree().filter(|&degree| degree > 1.0).unwrap_or(2.0);
+        Ok(Program { ops, degree })
+    }
+
+    pub fn eval(&self, variables: &[Complex<f64>]) -> Complex<f64> {
+        let mut stack = [Complex::new(0.0, 0.0); STACK];
+        let mut top = 0;
+        for op in &self.ops {
+            match *op {
+                Op::Var(index) => {
+                    stack[top] = variables[index];
+                    top += 1;
+                }
+                Op::Push(value) => {
+                    stack[top] = value;
+                    top += 1;
+                }
+                Op::Neg => stack[top - 1] = -stack[top - 1],
+                Op::Powu(n) => stack[top - 1] = stack[top - 1].powu(n),
+                Op::Call(function) => stack[top - 1] = function.apply(stack[top - 1]),
+                Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow => {
+                    top -= 1;
+                    let (a, b) = (stack[top - 1], stack[top]);
+                    stack[top - 1] = match *op {
+                        Op::Add => a + b,
+                        Op::Sub => a - b,
+                        Op::Mul => a * b,
+                        Op::Div => a / b,
+                        _ => a.powc(b),
+                    };
+                }
+            }
+        }
+        stack[0]
+    }
+}
+
+// Appends the ops of `node` and returns how many stack slots they need
+fn emit(node: &Node, ops: &mut Vec<Op>) -> usize {
+    match node {
+        Node::Var(index) => {
+            ops.push(Op::Var(*index));
+            1
+        }
+        Node::Number(value) => {
+            ops.push(Op::Push(*value));
+            1
+        }
+        Node::Neg(a) => {
+            let depth = emit(a, ops);
+            ops.push(Op::Neg);
+            depth
+        }
+        Node::Call(function, a) => {
+            let depth = emit(a, ops);
+            ops.push(Op::Call(*function));
+            depth
+        }
+        Node::Pow(a, b) if matches!(**b, Node::Number(n) if n.im == 0.0 && n.re >= 1.0 && n.re.fract() == 0.0 && n.re <= 64.0) => {
+            let Node::Number(n) = **b else { unreachable!() };
+            let depth = emit(a, ops);
+            ops.push(Op::Powu(n.re as u32));
+            depth
+        }
+        Node::Add(a, b) | Node::Sub(a, b) | Node::Mul(a, b) | Node::Div(a, b) | Node::Pow(a, b) => {
+            let depth = emit(a, ops).max(1 + emit(b, ops));
+            ops.push(match node {
+                Node::Add(..) => Op::Add,
+                Node::Sub(..) => Op::Sub,
+                Node::Mul(..) => Op::Mul,
+                Node::Div(..) => Op::Div,
+                _ => Op::Pow,
+            });
+            depth
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Symbol(char),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "number {}", value),
+            Token::Name(name) => write!(f, "'{}'", name),
+            Token::Symbol(symbol) => write!(f, "'{}'", symbol),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, ch)) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+        } else if ch.is_ascii_digit() || ch == '.' {
+            let mut end = start;
+            let mut previous = ' ';
+            while let Some(&(i, ch)) = chars.peek() {
+                let exponent_sign = (ch == '+' || ch == '-') && (previous == 'e' || previous == 'E');
+                if !(ch.is_ascii_digit() || ch == '.' || ch == 'e' || ch == 'E' || exponent_sign) {
+                    break;
+                }
+                end = i + ch.len_utf8();
+                previous = ch;
+                chars.next();
+            }
+            let text = &source[start..end];
+            let value = text.parse().map_err(|_| format!("invalid number {}", text))?;
+            tokens.push(Token::Number(value));
+        } else if ch.is_ascii_alphabetic() {
+            let mut name = String::new();
+            while let Some(&(_, ch)) = chars.peek().filter(|(_, ch)| ch.is_ascii_alphanumeric()) {
+                name.push(ch);
+                chars.next();
+            }
+            tokens.push(Token::Name(name));
+        } else if "+-*/^()".contains(ch) {
+            tokens.push(Token::Symbol(ch));
+            chars.next();
+        } else {
+            return Err(format!("unexpected character '{}'", ch));
+        }
+    }
+    Ok(tokens)
+}
+
+// Recursive descent over the grammar
+//
+//   expression = term (("+" | "-") term)*
+//   term       = unary (("*" | "/") unary)*
+//   unary      = "-" unary | power
+//   power      = atom ("^" unary)?
+//   atom       = number | variable | "i" | "pi" | name "(" expression ")" | "(" expression ")"
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    next: usize,
+    variables: &'a [&'a str],
+}
+
+impl Parser<'_> {
+    fn peek_symbol(&self) -> Option<char> {
+        match self.tokens.get(self.next) {
+            Some(Token::Symbol(symbol)) => Some(*symbol),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        match self.tokens.get(self.next) {
+            Some(Token::Symbol(found)) if *found == symbol => {
+                self.next += 1;
+                Ok(())
+            }
+            Some(token) => Err(format!("expected '{}', found {}", symbol, token)),
+            None => Err(format!("expected '{}' at the end", symbol)),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Node, String> {
+        let mut node = self.term()?;
+        while let Some(symbol @ ('+' | '-')) = self.peek_symbol() {
+            self.next += 1;
+            let rhs = Box::new(self.term()?);
+            node = if symbol == '+' { Node::Add(Box::new(node), rhs) } else { Node::Sub(Box::new(node), rhs) };
+        }
+        Ok(node)
+    }
+
+    fn term(&mut self) -> Result<Node, String> {
+        let mut node = self.unary()?;
+        while let Some(symbol @ ('*' | '/')) = self.peek_symbol() {
+            self.next += 1;
+            let rhs = Box::new(self.unary()?);
+            node = if symbol == '*' { Node::Mul(Box::new(node), rhs) } else { Node::Div(Box::new(node), rhs) };
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.peek_symbol() == Some('-') {
+            self.next += 1;
+            return Ok(Node::Neg(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Node, String> {
+        let base = self.atom()?;
+        if self.peek_symbol() == Some('^') {
+            self.next += 1;
+            return Ok(Node::Pow(Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let token = self.tokens.get(self.next).cloned().ok_or("unexpected end of formula")?;
+        self.next += 1;
+        match token {
+            Token::Number(value) => Ok(Node::Number(Complex::new(value, 0.0))),
+            Token::Name(name) if self.variables.contains(&name.as_str()) => {
+                Ok(Node::Var(self.variables.iter().position(|&variable| variable == name).unwrap()))
+            }
+            Token::Name(name) => match name.as_str() {
+                "i" => Ok(Node::Number(Complex::new(0.0, 1.0))),
+                "pi" => Ok(Node::Number(Complex::new(std::f64::consts::PI, 0.0))),
+                _ => {
+                    let function = Function::from_name(&name).ok_or_else(|| format!("unknown name '{}'", name))?;
+                    self.expect('(')?;
+                    let argument = self.expression()?;
+                    self.expect(')')?;
+                    Ok(Node::Call(function, Box::new(argument)))
+                }
+            },
+            Token::Symbol('(') => {
+                let node = self.expression()?;
+                self.expect(')')?;
+                Ok(node)
+            }
+            token => Err(format!("unexpected {}", token)),
+        }
+    }
+}
+
+impl Fractal for Program {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        self.eval(&[z, c])
+    }
+
+    fn step_deep(&self, z: (DoubleDouble, DoubleDouble), c: (DoubleDouble, DoubleDouble)) -> (DoubleDouble, DoubleDouble) {
+        let z = self.step(Complex::new(z.0.to_f64(), z.1.to_f64()), Complex::new(c.0.to_f64(), c.1.to_f64()));
+        (DoubleDouble::new(z.re), DoubleDouble::new(z.im))
+    }
+
+    fn step4(&self, zr: f64x4, zi: f64x4, cr: f64x4, ci: f64x4) -> (f64x4, f64x4) {
+        let (zr, zi, cr, ci) = (zr.to_array(), zi.to_array(), cr.to_array(), ci.to_array());
+        let z: [Complex<f64>; 4] = std::array::from_fn(|lane| self.step(Complex::new(zr[lane], zi[lane]), Complex::new(cr[lane], ci[lane])));
+        (f64x4::new(z.map(|z| z.re)), f64x4::new(z.map(|z| z.im)))
+    }
+
+    fn degree(&self) -> f64 {
+        self.degree
+    }
+
+    fn escape_is_final(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_with_precedence() {
+        let program = Program::compile("z*z*z + c*z + c").unwrap();
+        let (z, c) = (Complex::new(0.3, -0.7), Complex::new(-0.1, 0.4));
+        assert!((program.step(z, c) - (z * z * z + c * z + c)).norm() < 1e-15);
+        assert_eq!(program.degree(), 3.0);
+
+        let program = Program::compile("-z^2^1 + 2*i - c/2").unwrap();
+        assert!((program.step(z, c) - (-(z * z) + Complex::new(0.0, 2.0) - c / 2.0)).norm() < 1e-15);
+        assert_eq!(Program::compile("z^2 + c").unwrap().step(z, c), z * z + c);
+        assert_eq!(Program::compile("conj(z)^2 + c").unwrap().step(z, c), z.conj() * z.conj() + c);
+    }
+
+    #[test]
+    fn takes_the_callers_variables() {
+        let program = Program::compile_with("floor(n) + abs(w) * im(w) / pi", &["n", "w"]).unwrap();
+        let value = program.eval(&[Complex::new(2.5, 0.0), Complex::new(3.0, 4.0)]);
+        assert!((value - Complex::new(2.0 + 20.0 / std::f64::consts::PI, 0.0)).norm() < 1e-12);
+        assert_eq!(Program::compile_with("z + 1", &["n"]).unwrap_err(), "unknown name 'z'");
+    }
+
+    #[test]
+    fn degree_falls_back_to_two() {
+        assert_eq!(Program::compile("z^5 - z + c").unwrap().degree(), 5.0);
+        assert_eq!(Program::compile("z^2.5 + c").unwrap().degree(), 2.5);
+        assert_eq!(Program::compile("sin(z) * c").unwrap().degree(), 2.0);
+        assert_eq!(Program::compile("z + c").unwrap().degree(), 2.0);
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(Program::compile("z^2 +").unwrap_err(), "unexpected end of formula");
+        assert_eq!(Program::compile("z^2 + q").unwrap_err(), "unknown name 'q'");
+        assert_eq!(Program::compile("(z + c").unwrap_err(), "expected ')' at the end");
+        assert_eq!(Program::compile("z c").unwrap_err(), "unexpected 'c'");
+        assert_eq!(Program::compile("z % c").unwrap_err(), "unexpected character '%'");
+        let nested = format!("{}z{}", "z+(".repeat(40), ")".repeat(40));
+        assert!(Program::compile(&nested).unwrap_err().contains("at most 32"));
+    }
+}
@@ -0,0 +1,430 @@
+use double_double::DoubleDouble;
+use num_complex::Complex;
+use wide::f64x4;
+
+use crate::direct::Direct;
+use crate::expression::Program;
+
+// Escape-time formulas selectable with `--fractal` and `--power`.
+//
+// Each formula is one step z -> f(z, c) of the orbit, written once per
+// number type the kernels use (f64, double-double and the SIMD lanes). The
+// kernels are generic over the formula, so the step is inlined and only the
+// choice of kernel is dispatched at runtime through `Formula`. Every
+// formula has degree >= 2, which keeps any escape radius of 2 or more
+// valid. `--fractal` also names the Phoenix, Nova, Magnet and Collatz
+// formulas, but those are rendered by `direct.rs` and never reach the
+// kernels.
+pub trait Fractal {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64>;
+
+    fn step_deep(&self, z: (DoubleDouble, DoubleDouble), c: (DoubleDouble, DoubleDouble)) -> (DoubleDouble, DoubleDouble);
+
+    fn step4(&self, zr: f64x4, zi: f64x4, cr: f64x4, ci: f64x4) -> (f64x4, f64x4);
+
+    // Closed-form test for points known never to escape; they skip the
+    // iteration loop entirely.
+    fn is_interior(&self, _c: Complex<f64>) -> bool {
+        false
+    }
+
+    // Growth rate of |z| once the orbit escapes, used by smooth coloring
+    fn degree(&self) -> f64 {
+        2.0
+    }
+
+    // Whether an orbit past the escape radius stays past it, so the scalar
+    // kernel may check for escape only every few steps
+    fn escape_is_final(&self) -> bool {
+        true
+    }
+}
+
+// z² + c
+pub struct Mandelbrot;
+
+impl Fractal for Mandelbrot {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        z * z + c
+    }
+
+    fn step_deep(&self, (z_re, z_im): (DoubleDouble, DoubleDouble), (c_re, c_im): (DoubleDouble, DoubleDouble)) -> (DoubleDouble, DoubleDouble) {
+        (z_re.square() - z_im.square() + c_re, z_re * z_im * 2.0 + c_im)
+    }
+
+    fn step4(&self, zr: f64x4, zi: f64x4, cr: f64x4, ci: f64x4) -> (f64x4, f64x4) {
+        (zr * zr - zi * zi + cr, (zr + zr) * zi + ci)
+    }
+
+    // Points inside the main cardioid or the period-2 bulb never escape
+    fn is_interior(&self, c: Complex<f64>) -> bool {
+        let q = (c.re - 0.25) * (c.re - 0.25) + c.im * c.im;
+        let in_cardioid = q * (q + (c.re - 0.25)) <= 0.25 * c.im * c.im;
+        let in_bulb = (c.re + 1.0) * (c.re + 1.0) + c.im * c.im <= 0.0625;
+        in_cardioid || in_bulb
+    }
+}
+
+// (|Re z| + i|Im z|)² + c. Only the imaginary part changes: squaring
+// cancels the signs of the real part, so it is 2|Re z||Im z| + Im c.
+pub struct BurningShip;
+
+impl Fractal for BurningShip {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        let z = Complex::new(z.re.abs(), z.im.abs());
+        z * z + c
+    }
+
+    fn step_deep(&self, (z_re, z_im): (DoubleDouble, DoubleDouble), (c_re, c_im): (DoubleDouble, DoubleDouble)) -> (DoubleDouble, DoubleDouble) {
+        (z_re.square() - z_im.square() + c_re, z_re.abs() * z_im.abs() * 2.0 + c_im)
+    }
+
+    fn step4(&self, zr: f64x4, zi: f64x4, cr: f64x4, ci: f64x4) -> (f64x4, f64x4) {
+        let (zr, zi) = (zr.abs(), zi.abs());
+        (zr * zr - zi * zi + cr, (zr + zr) * zi + ci)
+    }
+}
+
+// conj(z)² + c, the Tricorn or Mandelbar set. Conjugating only flips the
+// sign of the imaginary part of the square.
+pub struct Tricorn;
+
+impl Fractal for Tricorn {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        let z = z.conj();
+        z * z + c
+    }
+
+    fn step_deep(&self, (z_re, z_im): (DoubleDouble, DoubleDouble), (c_re, c_im): (DoubleDouble, DoubleDouble)) -> (DoubleDouble, DoubleDouble) {
+        (z_re.square() - z_im.square() + c_re, c_im - z_re * z_im * 2.0)
+    }
+
+    fn step4(&self, zr: f64x4, zi: f64x4, cr: f64x4, ci: f64x4) -> (f64x4, f64x4) {
+        (zr * zr - zi * zi + cr, ci - (zr + zr) * zi)
+    }
+}
+
+// |Re z²| + i·Im z² + c, the Celtic Mandelbrot. The absolute value is
+// taken after squaring, of the real part only.
+pub struct Celtic;
+
+impl Fractal for Celtic {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        let square = z * z;
+        Complex::new(square.re.abs(), square.im) + c
+    }
+
+    fn step_deep(&self, (z_re, z_im): (DoubleDouble, DoubleDouble), (c_re, c_im): (DoubleDouble, DoubleDouble)) -> (DoubleDouble, DoubleDouble) {
+        ((z_re.square() - z_im.square()).abs() + c_re, z_re * z_im * 2.0 + c_im)
+    }
+
+    fn step4(&self, zr: f64x4, zi: f64x4, cr: f64x4, ci: f64x4) -> (f64x4, f64x4) {
+        ((zr * zr - zi * zi).abs() + cr, (zr + zr) * zi + ci)
+    }
+}
+
+// (Re z - i|Im z|)² + c, the Perpendicular Burning Ship: only the imaginary
+// part is folded, which makes the imaginary part of the square
+// -2·Re z·|Im z| + Im c.
+pub struct PerpendicularBurningShip;
+
+impl Fractal for PerpendicularBurningShip {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        let z = Complex::new(z.re, -z.im.abs());
+        z * z + c
+    }
+
+    fn step_deep(&self, (z_re, z_im): (DoubleDouble, DoubleDouble), (c_re, c_im): (DoubleDouble, DoubleDouble)) -> (DoubleDouble, DoubleDouble) {
+        (z_re.square() - z_im.square() + c_re, c_im - z_re * z_im.abs() * 2.0)
+    }
+
+    fn step4(&self, zr: f64x4, zi: f64x4, cr: f64x4, ci: f64x4) -> (f64x4, f64x4) {
+        (zr * zr - zi * zi + cr, ci - (zr + zr) * zi.abs())
+    }
+}
+
+// z^d + c for `--power d`. Integer powers use binary exponentiation, which
+// takes about 2·log2(d) complex multiplications and works in every number
+// type. Other powers go through the polar form of `Complex::powf`; there is
+// no double-double or SIMD version of that, so those steps are done in f64
+// one lane at a time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Multibrot {
+    power: f64,
+    integer: Option<u32>,
+}
+
+impl Multibrot {
+    pub fn new(power: f64) -> Self {
+        let integer = (power.fract() == 0.0 && power <= u32::MAX as f64).then_some(power as u32);
+        Multibrot { power, integer }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.integer.is_some()
+    }
+}
+
+impl Fractal for Multibrot {
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        match self.integer {
+            Some(n) => pow_by_squaring(z, n, |a, b| a * b) + c,
+            None => z.powf(self.power) + c,
+        }
+    }
+
+    fn step_deep(&self, z: (DoubleDouble, DoubleDouble), (c_re, c_im): (DoubleDouble, DoubleDouble)) -> (DoubleDouble, DoubleDouble) {
+        let Some(n) = self.integer else {
+            let z = self.step(Complex::new(z.0.to_f64(), z.1.to_f64()), Complex::new(c_re.to_f64(), c_im.to_f64()));
+            return (DoubleDouble::new(z.re), DoubleDouble::new(z.im));
+        };
+        let (re, im) = pow_by_squaring(z, n, |(a_re, a_im), (b_re, b_im)| (a_re * b_re - a_im * b_im, a_re * b_im + a_im * b_re));
+        (re + c_re, im + c_im)
+    }
+
+    fn step4(&self, zr: f64x4, zi: f64x4, cr: f64x4, ci: f64x4) -> (f64x4, f64x4) {
+        let Some(n) = self.integer else {
+            let (zr, zi, cr, ci) = (zr.to_array(), zi.to_array(), cr.to_array(), ci.to_array());
+            let z: [Complex<f64>; 4] = std::array::from_fn(|lane| self.step(Complex::new(zr[lane], zi[lane]), Complex::new(cr[lane], ci[lane])));
+            return (f64x4::new(z.map(|z| z.re)), f64x4::new(z.map(|z| z.im)));
+        };
+        let (re, im) = pow_by_squaring((zr, zi), n, |(a_re, a_im), (b_re, b_im)| (a_re * b_re - a_im * b_im, a_re * b_im + a_im * b_re));
+        (re + cr, im + ci)
+    }
+
+    fn degree(&self) -> f64 {
+        self.power
+    }
+}
+
+// z^n for n >= 1. The multiplications happen in the same order for every
+// number type, so the f64 and SIMD kernels stay bit for bit identical.
+fn pow_by_squaring<T: Copy>(z: T, mut n: u32, mul: impl Fn(T, T) -> T) -> T {
+    let mut base = z;
+    let mut result = None;
+    loop {
+        if n & 1 == 1 {
+            result = Some(match result {
+                Some(result) => mul(result, base),
+                None => base,
+            });
+        }
+        n >>= 1;
+        if n == 0 {
+            return result.unwrap();
+        }
+        base = mul(base, base);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Mandelbrot,
+    BurningShip,
+    Tricorn,
+    Celtic,
+    PerpendicularBurningShip,
+    Phoenix,
+    Nova,
+    MagnetI,
+    MagnetII,
+    Collatz,
+}
+
+impl Kind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mandelbrot" => Some(Kind::Mandelbrot),
+            "burning-ship" => Some(Kind::BurningShip),
+            "tricorn" | "mandelbar" => Some(Kind::Tricorn),
+            "celtic" => Some(Kind::Celtic),
+            "perpendicular-burning-ship" => Some(Kind::PerpendicularBurningShip),
+            "phoenix" => Some(Kind::Phoenix),
+            "nova" => Some(Kind::Nova),
+            "magnet1" => Some(Kind::MagnetI),
+            "magnet2" => Some(Kind::MagnetII),
+            "collatz" => Some(Kind::Collatz),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Mandelbrot => "mandelbrot",
+            Kind::BurningShip => "burning-ship",
+            Kind::Tricorn => "tricorn",
+            Kind::Celtic => "celtic",
+            Kind::PerpendicularBurningShip => "perpendicular-burning-ship",
+            Kind::Phoenix => "phoenix",
+            Kind::Nova => "nova",
+            Kind::MagnetI => "magnet1",
+            Kind::MagnetII => "magnet2",
+            Kind::Collatz => "collatz",
+        }
+    }
+
+    // The formulas that do not fit the escape-time kernels, see `direct.rs`
+    pub fn direct(self) -> Option<Direct> {
+        match self {
+            Kind::Mandelbrot | Kind::BurningShip | Kind::Tricorn | Kind::Celtic | Kind::PerpendicularBurningShip => None,
+            Kind::Phoenix => Some(Direct::Phoenix),
+            Kind::Nova => Some(Direct::Nova),
+            Kind::MagnetI => Some(Direct::MagnetI),
+            Kind::MagnetII => Some(Direct::MagnetII),
+            Kind::Collatz => Some(Direct::Collatz),
+        }
+    }
+}
+
+// A `Kind` together with its parameters, or a `--formula`, resolved once
+// per render. A custom formula borrows its compiled program from the
+// arguments of the render.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Formula<'a> {
+    Mandelbrot,
+    BurningShip,
+    Tricorn,
+    Celtic,
+    PerpendicularBurningShip,
+    Multibrot(Multibrot),
+    Custom(&'a Program),
+    // Rendered pixel by pixel, it never reaches the kernels
+    Direct(Direct),
+}
+
+impl Formula<'_> {
+    // `power` only applies to the Mandelbrot family; 2 is the plain set
+    pub fn new(kind: Kind, power: f64) -> Self {
+        match kind {
+            Kind::Mandelbrot if power != 2.0 => Formula::Multibrot(Multibrot::new(power)),
+            Kind::Mandelbrot => Formula::Mandelbrot,
+            Kind::BurningShip => Formula::BurningShip,
+            Kind::Tricorn => Formula::Tricorn,
+            Kind::Celtic => Formula::Celtic,
+            Kind::PerpendicularBurningShip => Formula::PerpendicularBurningShip,
+            Kind::Phoenix | Kind::Nova | Kind::MagnetI | Kind::MagnetII | Kind::Collatz => Formula::Direct(kind.direct().unwrap()),
+        }
+    }
+
+    // Center and zoom used when the command line gives none, chosen so the
+    // whole set fits the 3:2 view. The Tricorn reaches from -2 to 1 on the
+    // real axis and about ±1.75 above and below it; Multibrots shrink
+    // towards the unit disk as the power grows. The Celtic set is the
+    // Mandelbrot set's width but reaches out to about ±1.3, and the
+    // Perpendicular Burning Ship reaches down to about Im c = -1 and has a
+    // tail up to about Im c = 1.2.
+    pub fn default_view(self) -> ((f64, f64), f64) {
+        match self {
+            Formula::Mandelbrot => ((-0.5, 0.0), 1.0),
+            Formula::BurningShip => ((-0.5, -0.5), 0.75),
+            Formula::Tricorn => ((-0.5, 0.0), 0.55),
+            Formula::Celtic => ((-0.75, 0.0), 0.8),
+            Formula::PerpendicularBurningShip => ((-0.55, 0.1), 0.8),
+            Formula::Multibrot(_) | Formula::Custom(_) => ((0.0, 0.0), 0.75),
+            Formula::Direct(direct) => direct.default_view(),
+        }
+    }
+
+    // The Mandelbrot, Tricorn and Celtic sets are their own mirror images
+    // in the real axis, as are integer Multibrots. The Burning Ships are
+    // not: the absolute values fold the orbit into one half of the plane
+    // regardless of the sign of Im c. Non-integer powers are cut along the negative real
+    // axis by the branch of `powf`. Nothing is assumed of custom formulas,
+    // nor of the direct ones, which the escape-time renders never see.
+    pub fn is_symmetric(self) -> bool {
+        match self {
+            Formula::Mandelbrot | Formula::Tricorn | Formula::Celtic => true,
+            Formula::BurningShip | Formula::PerpendicularBurningShip | Formula::Custom(_) | Formula::Direct(_) => false,
+            Formula::Multibrot(multibrot) => multibrot.is_integer(),
+        }
+    }
+
+    // Subdivision fills a rectangle whose border is uniform, which is only
+    // safe when the set is connected. The Burning Ships and the Celtic set
+    // have islands that could sit entirely inside such a rectangle, and the
+    // branch cut breaks non-integer Multibrots apart.
+    pub fn is_connected(self) -> bool {
+        match self {
+            Formula::Mandelbrot | Formula::Tricorn => true,
+            Formula::BurningShip | Formula::Celtic | Formula::PerpendicularBurningShip | Formula::Custom(_) | Formula::Direct(_) => false,
+            Formula::Multibrot(multibrot) => multibrot.is_integer(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn burning_ship_folds_before_squaring() {
+        let z = Complex::new(-0.5, 0.75);
+        let c = Complex::new(0.1, -0.2);
+        assert_eq!(BurningShip.step(z, c), Complex::new(0.25 - 0.5625 + 0.1, 0.75 - 0.2));
+        assert_eq!(Mandelbrot.step(z, c), Complex::new(0.25 - 0.5625 + 0.1, -0.75 - 0.2));
+    }
+
+    #[test]
+    fn tricorn_conjugates_before_squaring() {
+        let z = Complex::new(-0.5, 0.75);
+        let c = Complex::new(0.1, -0.2);
+        assert_eq!(Tricorn.step(z, c), Complex::new(0.25 - 0.5625 + 0.1, 0.75 - 0.2));
+        let splat = f64x4::splat;
+        let (re4, im4) = Tricorn.step4(splat(z.re), splat(z.im), splat(c.re), splat(c.im));
+        assert_eq!((re4.to_array()[0], im4.to_array()[0]), (0.25 - 0.5625 + 0.1, 0.75 - 0.2));
+    }
+
+    #[test]
+    fn celtic_and_perpendicular_fold_one_part() {
+        let z = Complex::new(-0.5, 0.75);
+        let c = Complex::new(0.1, -0.2);
+        // z² = -0.3125 - 0.75i
+        assert_eq!(Celtic.step(z, c), Complex::new(0.3125 + 0.1, -0.75 - 0.2));
+        assert_eq!(PerpendicularBurningShip.step(z, c), Complex::new(-0.3125 + 0.1, 0.75 - 0.2));
+        assert_eq!(PerpendicularBurningShip.step(z.conj(), c), PerpendicularBurningShip.step(z, c));
+    }
+
+    #[test]
+    fn kernels_agree_across_number_types() {
+        let z = Complex::new(-0.3, -1.1);
+        let c = Complex::new(-1.75, -0.04);
+        let deep = |z: Complex<f64>| (DoubleDouble::new(z.re), DoubleDouble::new(z.im));
+        let splat = f64x4::splat;
+        let formulas: [&dyn Fractal; 5] = [&BurningShip, &Celtic, &PerpendicularBurningShip, &Multibrot::new(5.0), &Multibrot::new(2.5)];
+        for fractal in formulas {
+            let expected = fractal.step(z, c);
+            let (re, im) = fractal.step_deep(deep(z), deep(c));
+            assert!((re.to_f64() - expected.re).abs() < 1e-14 && (im.to_f64() - expected.im).abs() < 1e-14);
+            let (re4, im4) = fractal.step4(splat(z.re), splat(z.im), splat(c.re), splat(c.im));
+            assert_eq!((re4.to_array()[0], im4.to_array()[0]), (expected.re, expected.im));
+        }
+    }
+
+    #[test]
+    fn integer_powers_match_powu() {
+        let z = Complex::new(0.7, -0.4);
+        for n in 1..=9 {
+            let power = pow_by_squaring(z, n, |a, b| a * b);
+            assert!((power - z.powu(n)).norm() < 1e-15, "z^{}", n);
+        }
+    }
+
+    #[test]
+    fn names_round_trip() {
+        let kinds = [
+            Kind::Mandelbrot,
+            Kind::BurningShip,
+            Kind::Tricorn,
+            Kind::Celtic,
+            Kind::PerpendicularBurningShip,
+            Kind::Phoenix,
+            Kind::Nova,
+            Kind::MagnetI,
+            Kind::MagnetII,
+            Kind::Collatz,
+        ];
+        for kind in kinds {
+            assert_eq!(Kind::from_name(kind.name()), Some(kind));
+        }
+    }
+}
@@ -0,0 +1,43 @@
+// Histogram equalization for escape-time values.
+//
+// Counts how many escaped pixels landed on each integer iteration and turns
+// that into a cumulative distribution, so colors are spread by how many pixels
+// share an iteration range rather than by the raw iteration number.
+pub struct Histogram {
+    cdf: Vec<f32>,
+    max_iterations: u32,
+}
+
+impl Histogram {
+    pub fn new(values: impl Iterator<Item = f32>, max_iterations: u32) -> Self {
+        let mut counts = vec![0u64; max_iterations as usize];
+        let mut total = 0u64;
+        for value in values {
+            if value < max_iterations as f32 {
+                counts[value as usize] += 1;
+                total += 1;
+            }
+        }
+
+        let mut cdf = Vec::with_capacity(counts.len());
+        let mut running = 0u64;
+        for count in counts {
+            running += count;
+            cdf.push(if total > 0 { running as f32 / total as f32 } else { 0.0 });
+        }
+        Histogram { cdf, max_iterations }
+    }
+
+    // Maps an iteration value to 0.0..=1.0. Fractional (smooth) values are
+    // interpolated between neighbouring bins; interior points map to 1.0.
+    pub fn equalize(&self, value: f32) -> f32 {
+        if value >= self.max_iterations as f32 {
+            return 1.0;
+        }
+        let bin = value as usize;
+        let frac = value - bin as f32;
+        let lo = if bin == 0 { 0.0 } else { self.cdf[bin - 1] };
+        let hi = self.cdf[bin];
+        lo + (hi - lo) * frac
+    }
+}
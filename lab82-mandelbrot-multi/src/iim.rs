@@ -0,0 +1,128 @@
+use num_complex::Complex;
+use std::sync::atomic::{ AtomicU32, Ordering };
+
+use crate::buddhabrot::Grid;
+use crate::rng::SplitMix64;
+
+// Julia sets by inverse iteration for `--iim`.
+//
+// Running z <- z² + c backwards, z <- ±√(z - c), pulls every point towards
+// the Julia set of c: the set repels forward orbits, so it attracts
+// backward ones. Picking one of the two square roots at random each step
+// walks over the whole set, and counting where the walk lands draws it as
+// a cloud of points. Only the boundary is drawn, never the filled-in
+// interior, but the filigree of a dendrite or dust comes out in a fraction
+// of the time escape-time needs to resolve it.
+//
+// The walk visits the set very unevenly, crowding the outer tips and
+// rarely reaching the deep fjords, so the counts are tone-mapped on a log
+// scale.
+
+// Points plotted per work item. Each chunk starts its own walk from a
+// point and generator seeded by its index and `--seed`, so the image does
+// not depend on how rayon schedules the chunks.
+pub const CHUNK: u64 = 65536;
+
+// Points plotted when `--iim-points` is not given
+pub const DEFAULT_POINTS: u64 = 1 << 24;
+
+// Steps each walk takes before plotting, by which point it lies on the
+// set to well under a pixel
+const WARM_UP: u32 = 64;
+
+// The view used when the command line gives none
+pub const DEFAULT_VIEW: ((f64, f64), f64) = ((0.0, 0.0), 0.8);
+
+// The Julia constant, "re,im"
+pub fn parse_constant(spec: &str) -> Option<Complex<f64>> {
+    let (re, im) = spec.split_once(',')?;
+    let c: Complex<f64> = Complex::new(re.trim().parse().ok()?, im.trim().parse().ok()?);
+    (c.re.is_finite() && c.im.is_finite()).then_some(c)
+}
+
+// One random preimage of z under z² + c
+fn preimage(z: Complex<f64>, c: Complex<f64>, rng: &mut SplitMix64) -> Complex<f64> {
+    let root = (z - c).sqrt();
+    if rng.next_u64() & 1 == 0 { root } else { -root }
+}
+
+// Hit counts of the walks
+pub struct Cloud {
+    grid: Grid,
+    c: Complex<f64>,
+    counts: Vec<AtomicU32>,
+}
+
+impl Cloud {
+    pub fn new(grid: Grid, c: Complex<f64>) -> Self {
+        let pixels = grid.width as usize * grid.height as usize;
+        Cloud { grid, c, counts: (0..pixels).map(|_| AtomicU32::new(0)).collect() }
+    }
+
+    // Walks `CHUNK` steps with the generator of chunk `index` under `seed`
+    // and counts every point after the warm-up
+    pub fn add_chunk(&self, index: u64, seed: u64) {
+        let mut rng = SplitMix64::new(index.wrapping_mul(0xd1b5_4a32_d192_ed03) ^ seed.wrapping_mul(0xbf58_476d_1ce4_e5b9));
+        let mut z = Complex::new(rng.unit() * 2.0 - 1.0, rng.unit() * 2.0 - 1.0);
+        for _ in 0..WARM_UP {
+            z = preimage(z, self.c, &mut rng);
+        }
+        for _ in 0..CHUNK {
+            z = preimage(z, self.c, &mut rng);
+            if let Some(pixel) = self.grid.pixel(z) {
+                self.counts[pixel].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // Counts tone-mapped to 0.0..=1.0: the log of the count relative to
+    // that of the busiest pixel, 0.0 only where the walks never landed
+    pub fn levels(&self) -> Vec<f32> {
+        let counts: Vec<u32> = self.counts.iter().map(|count| count.load(Ordering::Relaxed)).collect();
+        let max = (counts.iter().copied().max().unwrap_or(0) as f32).ln_1p().max(f32::MIN_POSITIVE);
+        counts.iter().map(|&count| (count as f32).ln_1p() / max).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preimages_map_back_under_the_iteration() {
+        let c = Complex::new(-0.12, 0.75);
+        let mut rng = SplitMix64::new(5);
+        let mut z = Complex::new(0.9, -0.4);
+        for _ in 0..100 {
+            let previous = z;
+            z = preimage(z, c, &mut rng);
+            assert!((z * z + c - previous).norm() < 1e-12);
+        }
+        // By now the walk lies on the set, so iterating forward from it
+        // stays bounded
+        let mut w = z;
+        let mut iterations = 0;
+        while iterations < 40 && w.norm_sqr() <= 4.0 {
+            w = w * w + c;
+            iterations += 1;
+        }
+        assert_eq!(iterations, 40);
+        assert_eq!(parse_constant("-0.12, 0.75"), Some(c));
+        assert_eq!(parse_constant("-0.12"), None);
+    }
+
+    #[test]
+    fn walks_stay_inside_the_set() {
+        let grid = Grid { x_min: -2.0, y_min: -2.0, x_max: 2.0, y_max: 2.0, width: 32, height: 32 };
+        let cloud = Cloud::new(grid, Complex::new(-1.0, 0.0));
+        cloud.add_chunk(0, 0);
+        let counts: Vec<u32> = cloud.counts.iter().map(|count| count.load(Ordering::Relaxed)).collect();
+        // The basilica fits in |z| < 2, so every step lands on the grid
+        assert_eq!(counts.iter().sum::<u32>() as u64, CHUNK);
+        // Nothing lands in the basin of the attracting cycle around 0
+        assert_eq!(counts[16 * 32 + 16], 0);
+        let levels = cloud.levels();
+        assert!(levels.iter().all(|level| (0.0..=1.0).contains(level)));
+        assert!(levels.contains(&1.0));
+    }
+}
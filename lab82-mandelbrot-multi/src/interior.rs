@@ -0,0 +1,101 @@
+use hsv_to_rgb::Palette;
+use image::Rgb;
+use num_complex::Complex;
+
+use crate::color::{ Blending, Channel };
+
+// Interior coloring for `--interior`.
+//
+// Samples carry a single f32, with max_iterations marking points that never
+// escaped. Interior modes that shade those points add their palette
+// position t in 0.0..1.0 on top, so an interior value is max_iterations + t.
+// Everything that only asks "escaped or not" keeps working unchanged, and
+// the shading survives checkpoints and mirroring like any other value.
+
+// Consecutive periods step this far along the palette; the golden ratio
+// keeps any run of periods well apart and never lands on 0
+const PERIOD_STEP: f32 = 0.618_034;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interior {
+    // The top of the palette, as the escape-time coloring gives it
+    Palette,
+    Solid(Rgb<u8>),
+    // Direction of the last point of the orbit, as the lab84 preview does
+    Angle,
+    // Length of the attracting cycle found by periodicity checking
+    Period,
+}
+
+impl Interior {
+    // "palette", "angle", "period" or a color as a palette stop ("#rrggbb"
+    // or "r,g,b")
+    pub fn parse(spec: &str) -> Option<Self> {
+        match spec {
+            "palette" => Some(Interior::Palette),
+            "angle" => Some(Interior::Angle),
+            "period" => Some(Interior::Period),
+            color => Palette::parse(color).ok().map(|palette| Interior::Solid(palette.sample(0.0))),
+        }
+    }
+
+    // Whether interior points need their actual orbit, so the closed-form
+    // interior test cannot be used to skip it
+    pub fn needs_orbit(self) -> bool {
+        matches!(self, Interior::Angle | Interior::Period)
+    }
+
+    // Palette position of an interior point from the last point of its
+    // orbit and its period, if one was found. Undetected periods give 0.
+    pub fn shade(self, z: Complex<f64>, period: Option<u32>) -> f32 {
+        match self {
+            Interior::Palette | Interior::Solid(_) => 0.0,
+            Interior::Angle => ((z.arg() / std::f64::consts::TAU + 0.5) as f32).clamp(0.0, 0.999),
+            Interior::Period => period.map_or(0.0, |period| (period as f32 * PERIOD_STEP).fract()),
+        }
+    }
+
+    // Color of a sample value if it is an interior point this mode colors
+    // itself; None leaves it to the palette
+    pub fn color<T: Channel>(self, palette: &Option<Palette>, value: f32, max_iterations: u32, blending: Blending) -> Option<Rgb<T>> {
+        if value < max_iterations as f32 {
+            return None;
+        }
+        let shade = value - max_iterations as f32;
+        match self {
+            Interior::Palette => None,
+            Interior::Solid(Rgb(rgb)) => {
+                let max = T::DEFAULT_MAX_VALUE.to_u32();
+                Some(Rgb(rgb.map(|c| T::from_u32(c as u32 * max / 255))))
+            }
+            Interior::Period if shade == 0.0 => Some(Rgb([T::DEFAULT_MIN_VALUE; 3])),
+            Interior::Angle | Interior::Period => Some(T::from_palette(palette, shade, blending)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_modes_and_colors() {
+        assert_eq!(Interior::parse("angle"), Some(Interior::Angle));
+        assert_eq!(Interior::parse("#ff8000"), Some(Interior::Solid(Rgb([255, 128, 0]))));
+        assert_eq!(Interior::parse("10,20,30"), Some(Interior::Solid(Rgb([10, 20, 30]))));
+        assert_eq!(Interior::parse("sideways"), None);
+    }
+
+    #[test]
+    fn only_interior_values_are_colored() {
+        let solid = Interior::Solid(Rgb([255, 0, 51]));
+        assert_eq!(solid.color::<u8>(&None, 99.5, 100, Blending::Linear), None);
+        assert_eq!(solid.color::<u16>(&None, 100.0, 100, Blending::Linear), Some(Rgb([65535, 0, 13107])));
+        assert_eq!(Interior::Palette.color::<u8>(&None, 100.0, 100, Blending::Linear), None);
+        // An undetected period is black, a detected one takes the palette
+        assert_eq!(Interior::Period.color::<u8>(&None, 100.0, 100, Blending::Linear), Some(Rgb([0, 0, 0])));
+        let shade = Interior::Period.shade(Complex::new(0.0, 0.0), Some(2));
+        assert!(shade > 0.0 && shade < 1.0);
+        assert_ne!(shade, Interior::Period.shade(Complex::new(0.0, 0.0), Some(3)));
+    }
+}
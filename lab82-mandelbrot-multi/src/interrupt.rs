@@ -0,0 +1,96 @@
+use serde::{ Deserialize, Serialize };
+use std::io;
+use std::sync::atomic::{ AtomicBool, Ordering };
+
+// Ctrl-C handling.
+//
+// The first Ctrl-C only raises a flag: workers stop picking up new pixels and
+// main saves whatever finished. A second Ctrl-C exits immediately.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+pub fn install() {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        eprintln!("\nInterrupted, finishing in-flight pixels (Ctrl-C again to abort)");
+    })
+    .expect("failed to install Ctrl-C handler");
+}
+
+// Same as a first Ctrl-C, for other ways of cancelling a render
+pub fn request() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+pub fn requested() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+// Collapses per-row completion flags into [start, end) ranges.
+pub fn completed_ranges(rows_done: &[bool]) -> Vec<[u32; 2]> {
+    let mut ranges: Vec<[u32; 2]> = Vec::new();
+    for (y, &done) in rows_done.iter().enumerate() {
+        let y = y as u32;
+        match ranges.last_mut() {
+            Some(range) if done && range[1] == y => range[1] = y + 1,
+            _ if done => ranges.push([y, y + 1]),
+            _ => {}
+        }
+    }
+    ranges
+}
+
+// What an interrupted render already finished, alongside the arguments
+// needed to start it again. `--resume` with this file renders only the
+// missing rows and takes the rest from the partial image.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Resume {
+    pub args: Vec<String>,
+    pub width: u32,
+    pub height: u32,
+    pub completed_rows: Vec<[u32; 2]>,
+}
+
+impl Resume {
+    pub fn new(args: Vec<String>, width: u32, height: u32, rows_done: &[bool]) -> Self {
+        Resume { args, width, height, completed_rows: completed_ranges(rows_done) }
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn row_done(&self, y: u32) -> bool {
+        self.completed_rows.iter().any(|&[start, end]| (start..end).contains(&y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_cover_exactly_the_finished_rows() {
+        let rows_done = [true, true, false, true, false, false, true];
+        assert_eq!(completed_ranges(&rows_done), vec![[0, 2], [3, 4], [6, 7]]);
+        let resume = Resume::new(Vec::new(), 4, rows_done.len() as u32, &rows_done);
+        for (y, &done) in rows_done.iter().enumerate() {
+            assert_eq!(resume.row_done(y as u32), done);
+        }
+    }
+
+    #[test]
+    fn resume_file_round_trips() {
+        let path = std::env::temp_dir().join(format!("resume_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let resume = Resume::new(vec!["--zoom".to_string(), "4".to_string()], 8, 3, &[true, false, true]);
+        resume.save(path).unwrap();
+        assert_eq!(Resume::load(path).unwrap(), resume);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,145 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+// Batch files for `--jobs`.
+//
+// A TOML file lists independent renders, each as the command line it would
+// be run with:
+//
+//   # Renders at once (default 1)
+//   concurrency = 2
+//   # Threads for each render (default: the machine's threads shared out
+//   # evenly between the concurrent renders)
+//   threads = 4
+//
+//   [[job]]
+//   name = "seahorse"
+//   args = ["--location", "seahorse", "--size", "3840x2160", "--output", "out/seahorse.png"]
+//
+//   [[job]]
+//   name = "ship"
+//   args = ["--fractal", "burning-ship", "--smooth", "--output", "out/ship.png"]
+//
+// Every job runs as a child process of the renderer with `--threads` set
+// to its share, so a failing job or one exiting on bad flags cannot take
+// the others down, and the jobs never oversubscribe the cores between
+// them. What a job prints goes to out/jobs/NAME.log.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Batch {
+    #[serde(default = "one")]
+    pub concurrency: usize,
+    pub threads: Option<usize>,
+    #[serde(rename = "job")]
+    pub jobs: Vec<Job>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+// How a job went
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub name: String,
+    pub threads: usize,
+    pub time: Duration,
+    // Exit code, None when the job was killed by a signal or never started
+    pub status: Option<i32>,
+}
+
+fn one() -> usize {
+    1
+}
+
+// Flags the batch sets itself
+const RESERVED: [&str; 2] = ["--threads", "--jobs"];
+
+impl Batch {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let batch: Batch = toml::from_str(text).map_err(|e| e.message().to_string())?;
+        if batch.jobs.is_empty() {
+            return Err("no [[job]] entries".to_string());
+        }
+        if batch.concurrency == 0 || batch.threads == Some(0) {
+            return Err("concurrency and threads must be at least 1".to_string());
+        }
+        for (i, job) in batch.jobs.iter().enumerate() {
+            if job.name.is_empty() || job.name.contains(['/', '\\']) {
+                return Err(format!("job {} needs a name that can be used as a file name", i + 1));
+            }
+            if batch.jobs[..i].iter().any(|other| other.name == job.name) {
+                return Err(format!("two jobs are named {}", job.name));
+            }
+            if let Some(flag) = job.args.iter().find(|arg| RESERVED.contains(&arg.as_str())) {
+                return Err(format!("job {} sets {}, which the batch controls", job.name, flag));
+            }
+        }
+        Ok(batch)
+    }
+
+    // Threads each job gets out of `available`, at least one
+    pub fn threads_per_job(&self, available: usize) -> usize {
+        self.threads.unwrap_or_else(|| (available / self.concurrency.min(self.jobs.len())).max(1))
+    }
+}
+
+// The table printed once all jobs are done
+pub fn summary(outcomes: &[Outcome], total: Duration) -> String {
+    let width = outcomes.iter().map(|outcome| outcome.name.len()).max().unwrap_or(0).max("job".len());
+    let mut table = format!("{:<width$}  {:>7}  {:>10}  {}\n", "job", "threads", "time", "result", width = width);
+    for outcome in outcomes {
+        let result = match outcome.status {
+            Some(0) => "ok".to_string(),
+            Some(code) => format!("failed (exit code {})", code),
+            None => "failed".to_string(),
+        };
+        table += &format!("{:<width$}  {:>7}  {:>10.2?}  {}\n", outcome.name, outcome.threads, outcome.time, result, width = width);
+    }
+    let failed = outcomes.iter().filter(|outcome| outcome.status != Some(0)).count();
+    let busy: Duration = outcomes.iter().map(|outcome| outcome.time).sum();
+    table += &format!("{} jobs, {} failed, {:.2?} wall time, {:.2?} summed job time\n", outcomes.len(), failed, total, busy);
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_shares_out_threads() {
+        let batch = Batch::parse(
+            "concurrency = 3\n[[job]]\nname = \"a\"\nargs = [\"--zoom\", \"2\"]\n[[job]]\nname = \"b\"\nargs = []\n",
+        )
+        .unwrap();
+        assert_eq!(batch.jobs[0], Job { name: "a".to_string(), args: vec!["--zoom".to_string(), "2".to_string()] });
+        // Only two jobs can run at once, so each gets half
+        assert_eq!(batch.threads_per_job(16), 8);
+        assert_eq!(batch.threads_per_job(1), 1);
+        assert_eq!(Batch { threads: Some(3), ..batch }.threads_per_job(16), 3);
+    }
+
+    #[test]
+    fn rejects_bad_batches() {
+        assert_eq!(Batch::parse("concurrency = 2").unwrap_err(), "missing field `job`");
+        assert_eq!(Batch::parse("job = []").unwrap_err(), "no [[job]] entries");
+        let twice = "[[job]]\nname = \"a\"\nargs = []\n[[job]]\nname = \"a\"\nargs = []\n";
+        assert_eq!(Batch::parse(twice).unwrap_err(), "two jobs are named a");
+        let threads = "[[job]]\nname = \"a\"\nargs = [\"--threads\", \"64\"]\n";
+        assert_eq!(Batch::parse(threads).unwrap_err(), "job a sets --threads, which the batch controls");
+    }
+
+    #[test]
+    fn summarizes_every_job() {
+        let outcome = |name: &str, status| Outcome { name: name.to_string(), threads: 4, time: Duration::from_millis(1500), status };
+        let table = summary(&[outcome("seahorse", Some(0)), outcome("ship", Some(2))], Duration::from_secs(2));
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "job       threads        time  result");
+        assert_eq!(lines[1], "seahorse        4       1.50s  ok");
+        assert_eq!(lines[2], "ship            4       1.50s  failed (exit code 2)");
+        assert_eq!(lines[3], "2 jobs, 1 failed, 2.00s wall time, 3.00s summed job time");
+    }
+}
@@ -0,0 +1,79 @@
+use num_complex::Complex;
+
+use crate::fractal::Fractal;
+
+// Scalar escape-time kernel, shared by the renderer and the benchmarks.
+//
+// An orbit escapes once |z| passes `escape_radius`, which is at least 2:
+// beyond 2 every formula of degree >= 2 runs off to infinity. Smooth
+// coloring gets more even with a larger radius.
+
+// Iterations run between two escape checks in `iterate`
+const ESCAPE_BATCH: usize = 8;
+
+// The escape test is only done every ESCAPE_BATCH iterations; in between
+// the orbit is recorded. Once |z| passes 2 it keeps growing, so "escaped"
+// is monotonic within a batch and a binary search over the recorded points
+// finds the exact escape iteration. With high powers the rest of a batch
+// can overflow to inf or NaN, but both fail the bound check as well, and
+// the point found is the first escaped one, which is still finite.
+// Fractals whose orbits may come back inside the radius
+// (`Fractal::escape_is_final`) are checked after every step instead.
+pub fn iterate<F: Fractal>(fractal: &F, c: Complex<f64>, max_iterations: u32, escape_radius: f64) -> (u32, Complex<f64>) {
+    iterate_from(fractal, c, (0, Complex::new(0.0, 0.0)), max_iterations, escape_radius)
+}
+
+// `iterate` picking up an orbit that has not escaped after `iteration` steps
+pub fn iterate_from<F: Fractal>(
+    fractal: &F,
+    c: Complex<f64>,
+    (mut iteration, mut z): (u32, Complex<f64>),
+    max_iterations: u32,
+    escape_radius: f64,
+) -> (u32, Complex<f64>) {
+    let bailout = escape_radius * escape_radius;
+    let mut orbit = [z; ESCAPE_BATCH];
+    while fractal.escape_is_final() && iteration + ESCAPE_BATCH as u32 <= max_iterations {
+        for point in orbit.iter_mut() {
+            z = fractal.step(z, c);
+            *point = z;
+        }
+        let norm = z.norm_sqr();
+        if norm > bailout || norm.is_nan() {
+            let escaped_at = orbit.partition_point(|point| point.norm_sqr() <= bailout);
+            return (iteration + escaped_at as u32 + 1, orbit[escaped_at]);
+        }
+        iteration += ESCAPE_BATCH as u32;
+    }
+    while iteration < max_iterations && z.norm_sqr() <= bailout {
+        z = fractal.step(z, c);
+        iteration += 1;
+    }
+    (iteration, z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expression::Program;
+    use crate::fractal::Mandelbrot;
+
+    #[test]
+    fn batches_find_the_exact_escape() {
+        let c = Complex::new(0.3, 0.6);
+        let (mut z, mut expected) = (Complex::new(0.0, 0.0), 0);
+        while z.norm_sqr() <= 4.0 {
+            z = z * z + c;
+            expected += 1;
+        }
+        assert_eq!(iterate(&Mandelbrot, c, 1000, 2.0), (expected, z));
+    }
+
+    #[test]
+    fn formula_orbits_that_come_back_still_escape() {
+        // 0, 3, 0, 3, ...: outside the radius after one step, back inside
+        // after the next
+        let program = Program::compile("3 - z*z/3").unwrap();
+        assert_eq!(iterate(&program, Complex::new(0.0, 0.0), 100, 2.0), (1, Complex::new(3.0, 0.0)));
+    }
+}
@@ -0,0 +1,70 @@
+use image_output::Format;
+
+// Building blocks of the multi-threaded renderer, shared by the `lab82`
+// binary and the `recolor` and `stitch` tools.
+pub mod adaptive;
+pub mod animation;
+pub mod average;
+pub mod buffer;
+pub mod buddhabrot;
+pub mod checkpoint;
+pub mod color;
+pub mod contact;
+pub mod direct;
+pub mod dither;
+pub mod explore;
+pub mod expression;
+pub mod font;
+pub mod fractal;
+pub mod histogram;
+pub mod iim;
+pub mod interior;
+pub mod interrupt;
+pub mod jobs;
+pub mod kernel;
+pub mod locations;
+pub mod lyapunov;
+pub mod mapping;
+pub mod newton;
+pub mod orbit_cache;
+pub mod preview;
+pub mod progress;
+pub mod quaternion;
+pub mod raw;
+pub mod rng;
+pub mod sampling;
+pub mod scaling;
+pub mod script;
+pub mod simd;
+pub mod stats;
+pub mod stitch;
+pub mod stream;
+pub mod subdivide;
+pub mod terminal;
+pub mod tiling;
+pub mod trap;
+pub mod video;
+
+// Command-line helpers of the binaries. A missing or malformed value is
+// reported and ends the program with status 2.
+pub fn value(iter: &mut impl Iterator<Item = String>, flag: &str) -> String {
+    iter.next().unwrap_or_else(|| {
+        eprintln!("Missing value for {}", flag);
+        std::process::exit(2);
+    })
+}
+
+pub fn parse_value<T: std::str::FromStr>(iter: &mut impl Iterator<Item = String>, flag: &str) -> T {
+    let raw = value(iter, flag);
+    raw.parse().unwrap_or_else(|_| {
+        eprintln!("Invalid value for {}: {}", flag, raw);
+        std::process::exit(2);
+    })
+}
+
+pub fn parse_format(raw: &str) -> Format {
+    Format::from_name(raw).unwrap_or_else(|| {
+        eprintln!("Unknown format {} (expected png, jpeg, tiff, ppm or exr)", raw);
+        std::process::exit(2);
+    })
+}
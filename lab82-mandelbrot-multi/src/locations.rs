@@ -0,0 +1,86 @@
+// Named views for `--location`.
+//
+// Each entry is a well-known spot of the Mandelbrot set with a zoom that
+// frames it and an iteration count that resolves it; explicit --center,
+// --zoom and --iterations still override any of them. Centers are kept as
+// strings so the deep ones parse straight into double-double with all of
+// their digits.
+pub struct Location {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub center: &'static str,
+    pub zoom: f64,
+    pub iterations: u32,
+}
+
+pub const LOCATIONS: &[Location] = &[
+    Location {
+        name: "seahorse",
+        description: "Seahorse Valley, between the main cardioid and the period-2 bulb",
+        center: "-0.7453,0.1127",
+        zoom: 250.0,
+        iterations: 1500,
+    },
+    Location {
+        name: "elephant",
+        description: "Elephant Valley, at the cusp on the right of the main cardioid",
+        center: "0.2855,0.0110",
+        zoom: 120.0,
+        iterations: 1000,
+    },
+    Location {
+        name: "minibrot",
+        description: "the period-3 mini-Mandelbrot on the negative real axis",
+        center: "-1.7499,0",
+        zoom: 40.0,
+        iterations: 1000,
+    },
+    Location {
+        name: "triple-spiral",
+        description: "the triple-spiral valley below the period-3 bulb",
+        center: "-0.0884,0.6540",
+        zoom: 150.0,
+        iterations: 1500,
+    },
+    Location {
+        name: "double-spiral",
+        description: "a double spiral deep in Seahorse Valley",
+        center: "-0.7756837,0.1364674",
+        zoom: 20000.0,
+        iterations: 3000,
+    },
+    Location {
+        name: "dendrite",
+        description: "the dendrite around the Misiurewicz point c = i",
+        center: "0,1",
+        zoom: 12.0,
+        iterations: 1000,
+    },
+    Location {
+        name: "seahorse-deep",
+        description: "a deep zoom into Seahorse Valley, past f64 precision",
+        center: "-0.743643887037158704752191506114774,0.131825904205311970493132056385139",
+        zoom: 1e14,
+        iterations: 20000,
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Location> {
+    LOCATIONS.iter().find(|location| location.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use double_double::DoubleDouble;
+
+    #[test]
+    fn every_center_parses() {
+        for location in LOCATIONS {
+            let (re, im) = location.center.split_once(',').unwrap();
+            assert!(re.parse::<DoubleDouble>().is_ok() && im.parse::<DoubleDouble>().is_ok(), "{}", location.name);
+            assert!(location.zoom > 0.0 && location.iterations > 0);
+            assert_eq!(LOCATIONS.iter().filter(|other| other.name == location.name).count(), 1, "duplicate name {}", location.name);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use image::Rgb;
+
+// Lyapunov (Markus) fractals for `--lyapunov`.
+//
+// The view is the (a, b) parameter plane rather than the complex plane: a
+// along the x axis, b along y. At every point the logistic map
+// x <- r·x·(1 - x) is run with r taken from a or b as the sequence string
+// says ("AB" alternates them, "AABAB" repeats that pattern). The Lyapunov
+// exponent λ, the mean of ln|r·(1 - 2x)| along the orbit, tells stable
+// points (λ < 0) from chaotic ones (λ > 0).
+
+// Steps run before averaging so the orbit forgets its starting point
+const WARMUP: u32 = 200;
+
+// The classic framing: a from 2 to 4, b over the middle of that range
+pub const DEFAULT_VIEW: ((f64, f64), f64) = ((3.0, 3.0), 1.5);
+
+pub struct Lyapunov {
+    // true where the sequence says B
+    sequence: Vec<bool>,
+    iterations: u32,
+}
+
+impl Lyapunov {
+    // Parses a sequence of A and B, in either case. `iterations` is the
+    // number of steps averaged into the exponent.
+    pub fn parse(spec: &str, iterations: u32) -> Option<Self> {
+        let sequence: Vec<bool> = spec
+            .chars()
+            .map(|letter| match letter.to_ascii_uppercase() {
+                'A' => Some(false),
+                'B' => Some(true),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+        (!sequence.is_empty()).then_some(Lyapunov { sequence, iterations: iterations.max(1) })
+    }
+
+    pub fn exponent(&self, a: f64, b: f64) -> f64 {
+        let mut x = 0.5;
+        let rates = self.sequence.iter().map(|&is_b| if is_b { b } else { a }).cycle();
+        let mut sum = 0.0;
+        for (n, r) in rates.take((WARMUP + self.iterations) as usize).enumerate() {
+            x = r * x * (1.0 - x);
+            if n >= WARMUP as usize {
+                sum += (r * (1.0 - 2.0 * x)).abs().ln();
+            }
+        }
+        sum / self.iterations as f64
+    }
+}
+
+// Stable regions in yellow, brightest where λ is most negative, fading to
+// black at λ = 0; chaotic regions in blue. Superstable points, where a
+// derivative of exactly zero sends λ to -∞, are full yellow.
+pub fn color(exponent: f64) -> Rgb<u8> {
+    if exponent.is_nan() {
+        return Rgb([0, 0, 0]);
+    }
+    if exponent < 0.0 {
+        let level = 1.0 - exponent.exp();
+        Rgb([(255.0 * level).round() as u8, (230.0 * level).round() as u8, 0])
+    } else {
+        let level = 1.0 - (-exponent).exp();
+        Rgb([0, (40.0 * level).round() as u8, (255.0 * level).round() as u8])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_letter_matches_the_logistic_map() {
+        let lyapunov = Lyapunov::parse("a", 2000).unwrap();
+        // Attracting fixed point, attracting 2-cycle, chaos
+        assert!(lyapunov.exponent(2.5, 0.0) < 0.0);
+        assert!(lyapunov.exponent(3.2, 0.0) < 0.0);
+        assert!(lyapunov.exponent(3.9, 0.0) > 0.0);
+    }
+
+    #[test]
+    fn b_steps_use_the_second_parameter() {
+        let lyapunov = Lyapunov::parse("B", 500).unwrap();
+        assert_eq!(lyapunov.exponent(4.0, 2.5), Lyapunov::parse("A", 500).unwrap().exponent(2.5, 4.0));
+    }
+
+    #[test]
+    fn parse_rejects_other_letters() {
+        assert!(Lyapunov::parse("", 10).is_none());
+        assert!(Lyapunov::parse("ABC", 10).is_none());
+    }
+}
@@ -1,55 +1,2518 @@
-use image::{ ImageBuffer, Rgb };
+use image::{ DynamicImage, ImageBuffer, Pixel, Rgb, RgbImage };
+use std::io::{ IsTerminal, Write };
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicUsize, Ordering };
 use std::time::Instant;
 use num_complex::Complex;
 use rayon::prelude::*;
-use hsv_to_rgb::hsv_to_rgb;
+use double_double::{ iterate_deep, needs_deep_precision, DoubleDouble };
+use hsv_to_rgb::{ colormap, Palette };
+use image_output::{ self as output, Format };
+
+use lab82_mandelbrot_multi::{ parse_format, parse_value, value };
+use lab82_mandelbrot_multi::{ animation, checkpoint, contact, explore, interrupt, orbit_cache, raw, scaling, simd, stream, subdivide, terminal, video };
+use lab82_mandelbrot_multi::animation::{ Script, View };
+use lab82_mandelbrot_multi::adaptive::find_edges;
+use lab82_mandelbrot_multi::average::Average;
+use lab82_mandelbrot_multi::buddhabrot::{ self, Density, Grid, Orbits };
+use lab82_mandelbrot_multi::buffer::Samples;
+use lab82_mandelbrot_multi::checkpoint::Checkpoint;
+use lab82_mandelbrot_multi::color::{ average_color, palette_from_spec, select_palette, Blending, Channel, Cycle };
+use lab82_mandelbrot_multi::direct::Direct;
+use lab82_mandelbrot_multi::dither::Dither;
+use lab82_mandelbrot_multi::expression::Program;
+use lab82_mandelbrot_multi::fractal::{ BurningShip, Celtic, Formula, Fractal, Kind, Mandelbrot, PerpendicularBurningShip, Tricorn };
+use lab82_mandelbrot_multi::histogram::Histogram;
+use lab82_mandelbrot_multi::iim::{ self, Cloud };
+use lab82_mandelbrot_multi::interior::Interior;
+use lab82_mandelbrot_multi::interrupt::Resume;
+use lab82_mandelbrot_multi::jobs::{ self, Batch, Outcome };
+use lab82_mandelbrot_multi::kernel::{ iterate, iterate_from };
+use lab82_mandelbrot_multi::locations::{ self, Location };
+use lab82_mandelbrot_multi::lyapunov::{ self, Lyapunov };
+use lab82_mandelbrot_multi::mapping::{ Aspect, Sampling, ViewMapping };
+use lab82_mandelbrot_multi::newton::{ self, Newton, Polynomial };
+use lab82_mandelbrot_multi::orbit_cache::State;
+use lab82_mandelbrot_multi::preview::Preview;
+use lab82_mandelbrot_multi::progress::Progress;
+use lab82_mandelbrot_multi::quaternion::{ self, Quaternion, QuaternionJulia };
+use lab82_mandelbrot_multi::rng::SplitMix64;
+use lab82_mandelbrot_multi::sampling::subpixel_offset;
+use lab82_mandelbrot_multi::scaling::Measurement;
+use lab82_mandelbrot_multi::script::{ ColorScript, Sample };
+use lab82_mandelbrot_multi::simd::LANES;
+use lab82_mandelbrot_multi::stats::Stats;
+use lab82_mandelbrot_multi::terminal::Protocol;
+use lab82_mandelbrot_multi::tiling::{ Layout, Order, Tile };
+use lab82_mandelbrot_multi::trap::{ self, Trap };
+use lab82_mandelbrot_multi::video::VideoEncoder;
+
+#[derive(Clone)]
+struct Args {
+    smooth: bool,
+    escape_radius: f64,
+    histogram: bool,
+    palette: Option<String>,
+    colormap: Option<String>,
+    cycle: Cycle,
+    center: (DoubleDouble, DoubleDouble),
+    zoom: f64,
+    max_iterations: u32,
+    ssaa: u32,
+    adaptive_aa: bool,
+    aa_threshold: u8,
+    checkpoint: Option<String>,
+    resume: Option<String>,
+    // The rows a resume file records as finished, when `resume` names one
+    // rather than a checkpoint
+    resumed: Option<Resume>,
+    // The command line these arguments came from, with `--from-image`
+    // expanded and the checkpoint flags dropped, as checkpoints and resume
+    // files record it
+    command_line: Vec<String>,
+    size: (u32, u32),
+    aspect: Aspect,
+    sampling: Sampling,
+    stream_rows: Option<u32>,
+    output: Option<String>,
+    format: Option<Format>,
+    depth: u32,
+    dither: Option<Dither>,
+    blending: Blending,
+    raw: Option<String>,
+    animate: Option<u32>,
+    end_center: Option<(DoubleDouble, DoubleDouble)>,
+    end_zoom: Option<f64>,
+    end_iterations: Option<u32>,
+    fps: f64,
+    keyframes: Option<String>,
+    periodicity: bool,
+    subdivide: bool,
+    simd: bool,
+    threads: Option<usize>,
+    chunk_rows: Option<u32>,
+    tile_order: Order,
+    preview: bool,
+    term_preview: bool,
+    inline: Option<Protocol>,
+    fractal: Kind,
+    power: f64,
+    newton: Option<String>,
+    lyapunov: Option<String>,
+    quaternion: Option<String>,
+    quaternion_slice: (f64, f64),
+    quaternion_projection: Option<u32>,
+    iim: Option<String>,
+    iim_points: Option<u64>,
+    buddhabrot: Option<u64>,
+    nebulabrot: Option<String>,
+    anti_buddhabrot: bool,
+    min_iterations: u32,
+    trap: Option<String>,
+    trap_center: (f64, f64),
+    trap_radius: f64,
+    trap_angle: f64,
+    stripe: bool,
+    stripe_density: f64,
+    tia: bool,
+    interior: Option<String>,
+    explore: Option<u32>,
+    contact_sheet: Option<String>,
+    jobs: Option<String>,
+    scaling: Option<String>,
+    mmap_buffer: bool,
+    seed: Option<u64>,
+    stats: Option<String>,
+    auto_iterations: bool,
+    orbit_cache: Option<String>,
+    formula: Option<String>,
+    // `formula` compiled, kept for the whole run so samplers can hold it
+    program: Option<Arc<Program>>,
+    color_script: Option<String>,
+}
+
+impl Args {
+    // `--resume file` replaces the command line with the one stored in the
+    // checkpoint and keeps appending to that same file, or with the one in
+    // the .json resume file a render interrupted without a checkpoint
+    // leaves. `--from-image file` puts the view stored in a rendered PNG in
+    // front of the command line, so explicit flags (e.g. a larger --size)
+    // still win.
+    fn parse() -> Self {
+        let command_line = command_line();
+        let mut args = Args::parse_from(command_line.iter().cloned());
+        args.command_line = render_args(command_line);
+        let Some(path) = args.resume else { return args };
+        if path.ends_with(".json") {
+            let resume = Resume::load(&path).unwrap_or_else(|e| {
+                eprintln!("Failed to read resume file {}: {}", path, e);
+                std::process::exit(1);
+            });
+            let mut resumed = Args::parse_from(resume.args.iter().cloned());
+            resumed.command_line = resume.args.clone();
+            resumed.resume = Some(path);
+            resumed.resumed = Some(resume);
+            return resumed;
+        }
+        let header = checkpoint::read_header(&path).unwrap_or_else(|e| {
+            eprintln!("Failed to read checkpoint {}: {}", path, e);
+            std::process::exit(1);
+        });
+        let mut resumed = Args::parse_from(header.args.iter().cloned());
+        resumed.command_line = header.args;
+        resumed.checkpoint = Some(path.clone());
+        resumed.resume = Some(path);
+        resumed
+    }
+
+    fn parse_from(mut iter: impl Iterator<Item = String>) -> Self {
+        let mut args = Args {
+            smooth: false,
+            escape_radius: 2.0,
+            histogram: false,
+            palette: None,
+            colormap: None,
+            cycle: Cycle::IDENTITY,
+            center: (DoubleDouble::ZERO, DoubleDouble::ZERO),
+            zoom: 1.0,
+            max_iterations: 1000,
+            ssaa: 1,
+            adaptive_aa: false,
+            aa_threshold: 24,
+            checkpoint: None,
+            resume: None,
+            resumed: None,
+            command_line: Vec::new(),
+            size: (1920, 1080),
+            aspect: Aspect::Extend,
+            sampling: Sampling::Center,
+            stream_rows: None,
+            output: None,
+            format: None,
+            depth: 8,
+            dither: None,
+            blending: Blending::Linear,
+            raw: None,
+            animate: None,
+            end_center: None,
+            end_zoom: None,
+            end_iterations: None,
+            fps: 30.0,
+            keyframes: None,
+            periodicity: false,
+            subdivide: false,
+            simd: false,
+            threads: None,
+            chunk_rows: None,
+            tile_order: Order::RowMajor,
+            preview: false,
+            term_preview: false,
+            inline: None,
+            fractal: Kind::Mandelbrot,
+            power: 2.0,
+            newton: None,
+            lyapunov: None,
+            quaternion: None,
+            quaternion_slice: (0.0, 0.0),
+            quaternion_projection: None,
+            iim: None,
+            iim_points: None,
+            buddhabrot: None,
+            nebulabrot: None,
+            anti_buddhabrot: false,
+            min_iterations: 0,
+            trap: None,
+            trap_center: (0.0, 0.0),
+            trap_radius: 0.5,
+            trap_angle: 0.0,
+            stripe: false,
+            stripe_density: 5.0,
+            tia: false,
+            interior: None,
+            explore: None,
+            contact_sheet: None,
+            jobs: None,
+            scaling: None,
+            mmap_buffer: false,
+            seed: None,
+            stats: None,
+            auto_iterations: false,
+            orbit_cache: None,
+            formula: None,
+            program: None,
+            color_script: None,
+        };
+        // Without --center or --zoom the view defaults to the --location or
+        // else the whole fractal
+        let (mut center, mut zoom, mut iterations) = (None, None, None);
+        let mut location: Option<&Location> = None;
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--smooth" => args.smooth = true,
+                "--escape-radius" => args.escape_radius = parse_value(&mut iter, &arg),
+                "--histogram" => args.histogram = true,
+                "--periodicity" => args.periodicity = true,
+                "--subdivide" => args.subdivide = true,
+                "--simd" => args.simd = true,
+                "--preview" => args.preview = true,
+                "--term-preview" => args.term_preview = true,
+                "--inline" => args.inline = Some(parse_protocol(&value(&mut iter, &arg))),
+                "--threads" => args.threads = Some(parse_value(&mut iter, &arg)),
+                "--chunk-rows" => args.chunk_rows = Some(parse_value(&mut iter, &arg)),
+                "--tile-order" => args.tile_order = parse_order(&value(&mut iter, &arg)),
+                "--fractal" => args.fractal = parse_fractal(&value(&mut iter, &arg)),
+                "--power" => args.power = parse_value(&mut iter, &arg),
+                "--formula" => args.formula = Some(value(&mut iter, &arg)),
+                "--color-script" => args.color_script = Some(value(&mut iter, &arg)),
+                "--newton" => args.newton = Some(value(&mut iter, &arg)),
+                "--lyapunov" => args.lyapunov = Some(value(&mut iter, &arg)),
+                "--quaternion" => args.quaternion = Some(value(&mut iter, &arg)),
+                "--quaternion-slice" => {
+                    let (j, k) = parse_center(&value(&mut iter, &arg));
+                    args.quaternion_slice = (j.to_f64(), k.to_f64());
+                }
+                "--quaternion-projection" => args.quaternion_projection = Some(parse_value(&mut iter, &arg)),
+                "--iim" => args.iim = Some(value(&mut iter, &arg)),
+                "--iim-points" => args.iim_points = Some(parse_value(&mut iter, &arg)),
+                "--buddhabrot" => args.buddhabrot = Some(parse_value(&mut iter, &arg)),
+                "--nebulabrot" => args.nebulabrot = Some(value(&mut iter, &arg)),
+                "--anti-buddhabrot" => args.anti_buddhabrot = true,
+                "--min-iterations" => args.min_iterations = parse_value(&mut iter, &arg),
+                "--trap" => args.trap = Some(value(&mut iter, &arg)),
+                "--trap-center" => {
+                    let (re, im) = parse_center(&value(&mut iter, &arg));
+                    args.trap_center = (re.to_f64(), im.to_f64());
+                }
+                "--trap-radius" => args.trap_radius = parse_value(&mut iter, &arg),
+                "--trap-angle" => args.trap_angle = parse_value(&mut iter, &arg),
+                "--stripe" => args.stripe = true,
+                "--stripe-density" => args.stripe_density = parse_value(&mut iter, &arg),
+                "--tia" => args.tia = true,
+                "--interior" => args.interior = Some(value(&mut iter, &arg)),
+                "--explore" => args.explore = Some(parse_value(&mut iter, &arg)),
+                "--contact-sheet" => args.contact_sheet = Some(value(&mut iter, &arg)),
+                "--jobs" => args.jobs = Some(value(&mut iter, &arg)),
+                "--scaling" => args.scaling = Some(value(&mut iter, &arg)),
+                "--mmap-buffer" => args.mmap_buffer = true,
+                "--seed" => args.seed = Some(parse_value(&mut iter, &arg)),
+                "--stats" => args.stats = Some(value(&mut iter, &arg)),
+                "--auto-iterations" => args.auto_iterations = true,
+                "--orbit-cache" => args.orbit_cache = Some(value(&mut iter, &arg)),
+                "--palette" => args.palette = Some(value(&mut iter, &arg)),
+                "--colormap" => args.colormap = Some(value(&mut iter, &arg)),
+                "--palette-scale" => args.cycle.scale = parse_value(&mut iter, &arg),
+                "--palette-offset" => args.cycle.offset = parse_value(&mut iter, &arg),
+                "--center" => center = Some(parse_center(&value(&mut iter, &arg))),
+                "--zoom" => zoom = Some(parse_value(&mut iter, &arg)),
+                "--iterations" => iterations = Some(parse_value(&mut iter, &arg)),
+                "--location" => location = Some(parse_location(&value(&mut iter, &arg))),
+                "--ssaa" => args.ssaa = parse_value(&mut iter, &arg),
+                "--adaptive-aa" => args.adaptive_aa = true,
+                "--aa-threshold" => args.aa_threshold = parse_value(&mut iter, &arg),
+                "--checkpoint" => args.checkpoint = Some(value(&mut iter, &arg)),
+                "--resume" => args.resume = Some(value(&mut iter, &arg)),
+                "--size" => args.size = parse_size(&value(&mut iter, &arg)),
+                "--aspect" => args.aspect = parse_aspect(&value(&mut iter, &arg)),
+                "--corner-sampling" => args.sampling = Sampling::Corner,
+                "--stream-rows" => args.stream_rows = Some(parse_value(&mut iter, &arg)),
+                "--output" => args.output = Some(value(&mut iter, &arg)),
+                "--format" => args.format = Some(parse_format(&value(&mut iter, &arg))),
+                "--depth" => args.depth = parse_value(&mut iter, &arg),
+                "--srgb-blending" => args.blending = Blending::Srgb,
+                "--dither" => args.dither = Some(parse_dither(&value(&mut iter, &arg))),
+                "--raw" => args.raw = Some(value(&mut iter, &arg)),
+                "--animate" => args.animate = Some(parse_value(&mut iter, &arg)),
+                "--end-center" => args.end_center = Some(parse_center(&value(&mut iter, &arg))),
+                "--end-zoom" => args.end_zoom = Some(parse_value(&mut iter, &arg)),
+                "--end-iterations" => args.end_iterations = Some(parse_value(&mut iter, &arg)),
+                "--fps" => args.fps = parse_value(&mut iter, &arg),
+                "--keyframes" => args.keyframes = Some(value(&mut iter, &arg)),
+                other => {
+                    eprintln!("Unknown argument: {}", other);
+                    std::process::exit(2);
+                }
+            }
+        }
+        if args.power.is_nan() || args.power < 2.0 {
+            eprintln!("Invalid value for --power: {} (must be at least 2)", args.power);
+            std::process::exit(2);
+        }
+        if args.power != 2.0 && args.fractal != Kind::Mandelbrot {
+            eprintln!("--power only applies to --fractal mandelbrot");
+            std::process::exit(2);
+        }
+        if let Some(source) = &args.formula {
+            if args.fractal != Kind::Mandelbrot || args.power != 2.0 {
+                eprintln!("--formula replaces --fractal and --power, drop them");
+                std::process::exit(2);
+            }
+            let program = Program::compile(source).unwrap_or_else(|e| {
+                eprintln!("Invalid value for --formula: {}: {}", source, e);
+                std::process::exit(2);
+            });
+            args.program = Some(Arc::new(program));
+        }
+        if (args.nebulabrot.is_some() || args.anti_buddhabrot || args.min_iterations > 0) && args.buddhabrot.is_none() {
+            eprintln!("--nebulabrot, --anti-buddhabrot and --min-iterations need --buddhabrot for the number of samples");
+            std::process::exit(2);
+        }
+        if !(args.escape_radius >= 2.0 && args.escape_radius.is_finite()) {
+            eprintln!("--escape-radius must be at least 2, smaller radii let bounded orbits escape");
+            std::process::exit(2);
+        }
+        let degree = args.program.as_ref().map_or(args.power.max(2.0), |program| program.degree());
+        if args.escape_radius > max_escape_radius(degree) {
+            eprintln!("--escape-radius must be at most {:.3e} for degree {}, escaping orbits overflow beyond it", max_escape_radius(degree), degree);
+            std::process::exit(2);
+        }
+        if (args.quaternion_slice != (0.0, 0.0) || args.quaternion_projection.is_some()) && args.quaternion.is_none() {
+            eprintln!("--quaternion-slice and --quaternion-projection need --quaternion for the Julia constant");
+            std::process::exit(2);
+        }
+        if args.iim_points.is_some() && args.iim.is_none() {
+            eprintln!("--iim-points needs --iim for the Julia constant");
+            std::process::exit(2);
+        }
+        if args.trap.is_some() && args.orbit_trap().is_none() {
+            eprintln!("Unknown trap {} (expected point, line or circle)", args.trap.as_deref().unwrap());
+            std::process::exit(2);
+        }
+        if [args.trap.is_some(), args.stripe, args.tia].iter().filter(|&&set| set).count() > 1 {
+            eprintln!("--trap, --stripe and --tia are separate colorings, pick one");
+            std::process::exit(2);
+        }
+        if args.stats.is_some() && (args.trap.is_some() || args.stripe || args.tia) {
+            eprintln!("--stats reports iteration counts, which --trap, --stripe and --tia replace");
+            std::process::exit(2);
+        }
+        if args.orbit_cache.is_some() && (args.trap.is_some() || args.stripe || args.tia || args.interior_coloring().needs_orbit()) {
+            eprintln!("--orbit-cache keeps escape-time orbits, it cannot be combined with --trap, --stripe, --tia or --interior angle and period");
+            std::process::exit(2);
+        }
+        if let Some(spec) = &args.interior {
+            let Some(interior) = Interior::parse(spec) else {
+                eprintln!("Invalid value for --interior: {} (expected palette, angle, period or a color such as #000000)", spec);
+                std::process::exit(2);
+            };
+            if interior.needs_orbit() && (args.trap.is_some() || args.stripe || args.tia) {
+                eprintln!("--interior {} shades escape-time renders, it cannot be combined with --trap, --stripe or --tia", spec);
+                std::process::exit(2);
+            }
+        }
+        if let Some(location) = location {
+            if args.formula() != Formula::Mandelbrot || args.newton.is_some() || args.lyapunov.is_some() || args.quaternion.is_some() || args.iim.is_some() {
+                eprintln!("--location {} is a view of the Mandelbrot set, drop --fractal, --power, --formula, --newton, --lyapunov, --quaternion and --iim", location.name);
+                std::process::exit(2);
+            }
+            center = center.or_else(|| Some(parse_center(location.center)));
+            zoom = zoom.or(Some(location.zoom));
+            iterations = iterations.or(Some(location.iterations));
+        }
+        if let Some(iterations) = iterations {
+            args.max_iterations = iterations;
+        }
+        let ((default_re, default_im), default_zoom) = if args.newton.is_some() {
+            newton::DEFAULT_VIEW
+        } else if args.lyapunov.is_some() {
+            lyapunov::DEFAULT_VIEW
+        } else if args.quaternion.is_some() {
+            quaternion::DEFAULT_VIEW
+        } else if args.iim.is_some() {
+            iim::DEFAULT_VIEW
+        } else {
+            args.formula().default_view()
+        };
+        args.center = center.unwrap_or((DoubleDouble::new(default_re), DoubleDouble::new(default_im)));
+        args.zoom = zoom.unwrap_or(default_zoom);
+        // Replaces any --iterations, e.g. one brought in by --from-image
+        if args.auto_iterations {
+            args.max_iterations = animation::auto_iterations(args.zoom);
+        }
+        args
+    }
+
+    fn mapping(&self) -> ViewMapping {
+        ViewMapping::new((self.center.0.to_f64(), self.center.1.to_f64()), self.zoom, self.size, self.aspect, self.sampling)
+    }
+
+    fn formula(&self) -> Formula<'_> {
+        match &self.program {
+            Some(program) => Formula::Custom(program),
+            None => Formula::new(self.fractal, self.power),
+        }
+    }
+
+    fn orbit_trap(&self) -> Option<Trap> {
+        let center = Complex::new(self.trap_center.0, self.trap_center.1);
+        Trap::new(self.trap.as_deref()?, center, self.trap_radius, self.trap_angle)
+    }
+
+    fn interior_coloring(&self) -> Interior {
+        self.interior.as_deref().and_then(Interior::parse).unwrap_or(Interior::Palette)
+    }
+
+    fn orbit_average(&self) -> Option<Average> {
+        if self.stripe {
+            Some(Average::Stripe { density: self.stripe_density })
+        } else if self.tia {
+            Some(Average::TriangleInequality)
+        } else {
+            None
+        }
+    }
+}
+
+// Keywords of the PNG tEXt chunks describing a render
+const TEXT_CENTER: &str = "Mandelbrot Center";
+const TEXT_ZOOM: &str = "Mandelbrot Zoom";
+const TEXT_ITERATIONS: &str = "Mandelbrot Iterations";
+const TEXT_SIZE: &str = "Mandelbrot Size";
+const TEXT_ASPECT: &str = "Mandelbrot Aspect";
+const TEXT_SAMPLING: &str = "Mandelbrot Sampling";
+const TEXT_PALETTE: &str = "Mandelbrot Palette";
+const TEXT_PALETTE_SCALE: &str = "Mandelbrot Palette Scale";
+const TEXT_PALETTE_OFFSET: &str = "Mandelbrot Palette Offset";
+const TEXT_COLORING: &str = "Mandelbrot Coloring";
+const TEXT_ESCAPE_RADIUS: &str = "Mandelbrot Escape Radius";
+const TEXT_SSAA: &str = "Mandelbrot SSAA";
+const TEXT_ADAPTIVE_AA: &str = "Mandelbrot Adaptive AA";
+const TEXT_DEPTH: &str = "Mandelbrot Depth";
+const TEXT_DITHER: &str = "Mandelbrot Dither";
+const TEXT_BLENDING: &str = "Mandelbrot Blending";
+const TEXT_FRACTAL: &str = "Mandelbrot Fractal";
+const TEXT_POWER: &str = "Mandelbrot Power";
+const TEXT_FORMULA: &str = "Mandelbrot Formula";
+const TEXT_COLOR_SCRIPT: &str = "Mandelbrot Color Script";
+const TEXT_SEED: &str = "Mandelbrot Seed";
+const TEXT_NEWTON: &str = "Mandelbrot Newton";
+const TEXT_LYAPUNOV: &str = "Mandelbrot Lyapunov";
+const TEXT_QUATERNION: &str = "Mandelbrot Quaternion";
+const TEXT_QUATERNION_SLICE: &str = "Mandelbrot Quaternion Slice";
+const TEXT_QUATERNION_PROJECTION: &str = "Mandelbrot Quaternion Projection";
+const TEXT_IIM: &str = "Mandelbrot IIM";
+const TEXT_IIM_POINTS: &str = "Mandelbrot IIM Points";
+const TEXT_BUDDHABROT: &str = "Mandelbrot Buddhabrot";
+const TEXT_NEBULABROT: &str = "Mandelbrot Nebulabrot";
+const TEXT_ORBITS: &str = "Mandelbrot Orbits";
+const TEXT_MIN_ITERATIONS: &str = "Mandelbrot Min Iterations";
+const TEXT_TRAP: &str = "Mandelbrot Trap";
+const TEXT_TRAP_CENTER: &str = "Mandelbrot Trap Center";
+const TEXT_TRAP_RADIUS: &str = "Mandelbrot Trap Radius";
+const TEXT_TRAP_ANGLE: &str = "Mandelbrot Trap Angle";
+const TEXT_STRIPE_DENSITY: &str = "Mandelbrot Stripe Density";
+const TEXT_INTERIOR: &str = "Mandelbrot Interior";
+
+// Render parameters written into PNG output, enough to reproduce the image
+// with `--from-image`. The palette is either a gradient file path, a
+// colormap name or "hsv".
+fn image_metadata(args: &Args) -> Vec<(&'static str, String)> {
+    let palette = args.palette.clone().or_else(|| args.colormap.clone()).unwrap_or_else(|| "hsv".to_string());
+    let mut coloring = Vec::new();
+    if args.smooth {
+        coloring.push("smooth");
+    }
+    if args.histogram {
+        coloring.push("histogram");
+    }
+    if args.stripe {
+        coloring.push("stripe");
+    }
+    if args.tia {
+        coloring.push("tia");
+    }
+    let mut metadata = vec![
+        ("Software", "lab82-mandelbrot-multi".to_string()),
+        (TEXT_CENTER, format!("{},{}", args.center.0, args.center.1)),
+        (TEXT_ZOOM, args.zoom.to_string()),
+        (TEXT_ITERATIONS, args.max_iterations.to_string()),
+        (TEXT_SIZE, format!("{}x{}", args.size.0, args.size.1)),
+        (TEXT_PALETTE, palette),
+        (TEXT_COLORING, coloring.join(" ")),
+        (TEXT_FRACTAL, args.fractal.name().to_string()),
+        (TEXT_POWER, args.power.to_string()),
+    ];
+    if args.aspect != Aspect::Extend {
+        metadata.push((TEXT_ASPECT, args.aspect.name().to_string()));
+    }
+    if args.sampling != Sampling::Center {
+        metadata.push((TEXT_SAMPLING, args.sampling.name().to_string()));
+    }
+    if args.escape_radius != 2.0 {
+        metadata.push((TEXT_ESCAPE_RADIUS, args.escape_radius.to_string()));
+    }
+    if args.ssaa > 1 {
+        metadata.push((TEXT_SSAA, args.ssaa.to_string()));
+    }
+    // Stored as the threshold, which only means anything with --adaptive-aa
+    if args.adaptive_aa {
+        metadata.push((TEXT_ADAPTIVE_AA, args.aa_threshold.to_string()));
+    }
+    if args.depth != 8 {
+        metadata.push((TEXT_DEPTH, args.depth.to_string()));
+    }
+    if args.cycle != Cycle::IDENTITY {
+        metadata.push((TEXT_PALETTE_SCALE, args.cycle.scale.to_string()));
+        metadata.push((TEXT_PALETTE_OFFSET, args.cycle.offset.to_string()));
+    }
+    if args.blending == Blending::Srgb {
+        metadata.push((TEXT_BLENDING, "srgb".to_string()));
+    }
+    if let Some(dither) = args.dither {
+        metadata.push((TEXT_DITHER, dither.name().to_string()));
+    }
+    if let Some(source) = &args.formula {
+        metadata.push((TEXT_FORMULA, source.clone()));
+    }
+    if let Some(path) = &args.color_script {
+        metadata.push((TEXT_COLOR_SCRIPT, path.clone()));
+    }
+    if let Some(seed) = args.seed {
+        metadata.push((TEXT_SEED, seed.to_string()));
+    }
+    if let Some(spec) = &args.newton {
+        metadata.push((TEXT_NEWTON, spec.clone()));
+    }
+    if let Some(spec) = &args.lyapunov {
+        metadata.push((TEXT_LYAPUNOV, spec.clone()));
+    }
+    if let Some(spec) = &args.quaternion {
+        metadata.push((TEXT_QUATERNION, spec.clone()));
+        metadata.push((TEXT_QUATERNION_SLICE, format!("{},{}", args.quaternion_slice.0, args.quaternion_slice.1)));
+    }
+    if let Some(depth) = args.quaternion_projection {
+        metadata.push((TEXT_QUATERNION_PROJECTION, depth.to_string()));
+    }
+    if let Some(spec) = &args.iim {
+        metadata.push((TEXT_IIM, spec.clone()));
+    }
+    if let Some(points) = args.iim_points {
+        metadata.push((TEXT_IIM_POINTS, points.to_string()));
+    }
+    if let Some(samples) = args.buddhabrot {
+        metadata.push((TEXT_BUDDHABROT, samples.to_string()));
+    }
+    if let Some(caps) = &args.nebulabrot {
+        metadata.push((TEXT_NEBULABROT, caps.clone()));
+    }
+    if args.anti_buddhabrot {
+        metadata.push((TEXT_ORBITS, "bounded".to_string()));
+    }
+    if args.min_iterations > 0 {
+        metadata.push((TEXT_MIN_ITERATIONS, args.min_iterations.to_string()));
+    }
+    if let Some(kind) = &args.trap {
+        metadata.push((TEXT_TRAP, kind.clone()));
+        metadata.push((TEXT_TRAP_CENTER, format!("{},{}", args.trap_center.0, args.trap_center.1)));
+        metadata.push((TEXT_TRAP_RADIUS, args.trap_radius.to_string()));
+        metadata.push((TEXT_TRAP_ANGLE, args.trap_angle.to_string()));
+    }
+    if args.stripe {
+        metadata.push((TEXT_STRIPE_DENSITY, args.stripe_density.to_string()));
+    }
+    if let Some(spec) = &args.interior {
+        metadata.push((TEXT_INTERIOR, spec.clone()));
+    }
+    metadata
+}
+
+// Turns the metadata of a previously rendered PNG back into command-line flags
+fn image_args(path: &str) -> Vec<String> {
+    let text = output::read_png_text(path).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", path, e);
+        std::process::exit(1);
+    });
+    let mut args = Vec::new();
+    for (keyword, value) in text {
+        match keyword.as_str() {
+            TEXT_CENTER => args.extend(["--center".to_string(), value]),
+            TEXT_ZOOM => args.extend(["--zoom".to_string(), value]),
+            TEXT_ITERATIONS => args.extend(["--iterations".to_string(), value]),
+            TEXT_SIZE => args.extend(["--size".to_string(), value]),
+            TEXT_ASPECT => args.extend(["--aspect".to_string(), value]),
+            TEXT_SAMPLING if value == "corner" => args.push("--corner-sampling".to_string()),
+            TEXT_PALETTE if value == "hsv" => {}
+            TEXT_PALETTE if colormap(&value).is_some() => args.extend(["--colormap".to_string(), value]),
+            TEXT_PALETTE => args.extend(["--palette".to_string(), value]),
+            TEXT_PALETTE_SCALE => args.extend(["--palette-scale".to_string(), value]),
+            TEXT_PALETTE_OFFSET => args.extend(["--palette-offset".to_string(), value]),
+            TEXT_COLORING => args.extend(value.split_whitespace().map(|mode| format!("--{}", mode))),
+            TEXT_ESCAPE_RADIUS => args.extend(["--escape-radius".to_string(), value]),
+            TEXT_SSAA => args.extend(["--ssaa".to_string(), value]),
+            TEXT_ADAPTIVE_AA => args.extend(["--adaptive-aa".to_string(), "--aa-threshold".to_string(), value]),
+            TEXT_DEPTH => args.extend(["--depth".to_string(), value]),
+            TEXT_BLENDING if value == "srgb" => args.push("--srgb-blending".to_string()),
+            TEXT_DITHER => args.extend(["--dither".to_string(), value]),
+            TEXT_FRACTAL => args.extend(["--fractal".to_string(), value]),
+            TEXT_POWER => args.extend(["--power".to_string(), value]),
+            TEXT_FORMULA => args.extend(["--formula".to_string(), value]),
+            TEXT_COLOR_SCRIPT => args.extend(["--color-script".to_string(), value]),
+            TEXT_SEED => args.extend(["--seed".to_string(), value]),
+            TEXT_NEWTON => args.extend(["--newton".to_string(), value]),
+            TEXT_LYAPUNOV => args.extend(["--lyapunov".to_string(), value]),
+            TEXT_QUATERNION => args.extend(["--quaternion".to_string(), value]),
+            TEXT_QUATERNION_SLICE => args.extend(["--quaternion-slice".to_string(), value]),
+            TEXT_QUATERNION_PROJECTION => args.extend(["--quaternion-projection".to_string(), value]),
+            TEXT_IIM => args.extend(["--iim".to_string(), value]),
+            TEXT_IIM_POINTS => args.extend(["--iim-points".to_string(), value]),
+            TEXT_BUDDHABROT => args.extend(["--buddhabrot".to_string(), value]),
+            TEXT_NEBULABROT => args.extend(["--nebulabrot".to_string(), value]),
+            TEXT_ORBITS if value == "bounded" => args.push("--anti-buddhabrot".to_string()),
+            TEXT_MIN_ITERATIONS => args.extend(["--min-iterations".to_string(), value]),
+            TEXT_TRAP => args.extend(["--trap".to_string(), value]),
+            TEXT_TRAP_CENTER => args.extend(["--trap-center".to_string(), value]),
+            TEXT_TRAP_RADIUS => args.extend(["--trap-radius".to_string(), value]),
+            TEXT_TRAP_ANGLE => args.extend(["--trap-angle".to_string(), value]),
+            TEXT_STRIPE_DENSITY => args.extend(["--stripe-density".to_string(), value]),
+            TEXT_INTERIOR => args.extend(["--interior".to_string(), value]),
+            _ => {}
+        }
+    }
+    if args.is_empty() {
+        eprintln!("{} has no render parameters", path);
+        std::process::exit(1);
+    }
+    args
+}
+
+// The program arguments with `--from-image` expanded in place
+fn command_line() -> Vec<String> {
+    let mut command_line: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(i) = command_line.iter().position(|arg| arg == "--from-image") {
+        let path = command_line.get(i + 1).cloned().unwrap_or_else(|| {
+            eprintln!("Missing value for --from-image");
+            std::process::exit(2);
+        });
+        command_line.drain(i..i + 2);
+        command_line.splice(0..0, image_args(&path));
+    }
+    command_line
+}
+
+// The command line as recorded in checkpoints and resume files: everything
+// except the checkpoint flags themselves.
+fn render_args(command_line: Vec<String>) -> Vec<String> {
+    let mut recorded = Vec::new();
+    let mut iter = command_line.into_iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--checkpoint" || arg == "--resume" {
+            iter.next();
+        } else {
+            recorded.push(arg);
+        }
+    }
+    recorded
+}
+
+fn parse_order(raw: &str) -> Order {
+    Order::from_name(raw).unwrap_or_else(|| {
+        eprintln!("Unknown tile order {} (expected row-major, z-order or hilbert)", raw);
+        std::process::exit(2);
+    })
+}
+
+fn parse_aspect(raw: &str) -> Aspect {
+    Aspect::from_name(raw).unwrap_or_else(|| {
+        eprintln!("Unknown aspect {} (expected extend or stretch)", raw);
+        std::process::exit(2);
+    })
+}
+
+fn parse_dither(raw: &str) -> Dither {
+    Dither::from_name(raw).unwrap_or_else(|| {
+        eprintln!("Unknown dither {} (expected ordered or blue-noise)", raw);
+        std::process::exit(2);
+    })
+}
+
+fn parse_fractal(raw: &str) -> Kind {
+    Kind::from_name(raw).unwrap_or_else(|| {
+        eprintln!("Unknown fractal {} (expected mandelbrot, burning-ship, tricorn, celtic, perpendicular-burning-ship, phoenix, nova, magnet1, magnet2 or collatz)", raw);
+        std::process::exit(2);
+    })
+}
+
+fn parse_location(raw: &str) -> &'static Location {
+    locations::find(raw).unwrap_or_else(|| {
+        eprintln!("Unknown location {}, expected one of:", raw);
+        for location in locations::LOCATIONS {
+            eprintln!("  {:<14} {}", location.name, location.description);
+        }
+        std::process::exit(2);
+    })
+}
+
+fn parse_protocol(raw: &str) -> Protocol {
+    Protocol::from_name(raw).unwrap_or_else(|| {
+        eprintln!("Unknown inline image protocol {} (expected kitty or sixel)", raw);
+        std::process::exit(2);
+    })
+}
+
+// Parses "WIDTHxHEIGHT", e.g. "3840x2160".
+fn parse_size(raw: &str) -> (u32, u32) {
+    let parsed = raw
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .filter(|&(w, h)| w > 0 && h > 0);
+    parsed.unwrap_or_else(|| {
+        eprintln!("Invalid value for --size: {} (expected WIDTHxHEIGHT)", raw);
+        std::process::exit(2);
+    })
+}
+
+fn parse_center(raw: &str) -> (DoubleDouble, DoubleDouble) {
+    double_double::parse_complex(raw).unwrap_or_else(|| {
+        eprintln!("Invalid value for --center: {} (expected re,im)", raw);
+        std::process::exit(2);
+    })
+}
+
+// Normalized iteration count: removes the integer banding by using how far
+// past the escape radius |z| landed when the orbit escaped. Each step
+// raises |z| to about the `degree`-th power, which sets the log base. That
+// only holds once |z| dwarfs c, so the bands even out better the larger
+// the radius.
+fn smooth_iteration(iteration: u32, z: Complex<f64>, degree: f64, escape_radius: f64) -> f64 {
+    let log_zn = z.norm_sqr().ln() / 2.0;
+    let nu = (log_zn / escape_radius.ln()).ln() / degree.ln();
+    iteration as f64 + 1.0 - nu
+}
+
+// The largest usable escape radius. An orbit leaves the radius at up to
+// radius^degree, and the squared magnitude of that last point, which the
+// kernels compare against and smooth coloring takes the log of, has to
+// stay finite, with room to spare for c.
+fn max_escape_radius(degree: f64) -> f64 {
+    (f64::MAX.sqrt() / 4.0).powf(1.0 / degree)
+}
+
+// Orbits closer than this to an earlier point count as periodic
+const PERIODICITY_EPSILON: f64 = 1e-24;
+// Looser bound for measuring the cycle length once the orbit has settled:
+// the orbit is then near the cycle, but not always within the bound above,
+// while distinct points of a cycle are much farther apart than this
+const CYCLE_EPSILON: f64 = 1e-12;
+
+// `iterate` with Brent's cycle detection: the orbit is compared against a
+// saved point that is refreshed at power-of-two step counts. Once an orbit
+// comes back to the saved point it is periodic and can never escape, so
+// interior points stop after a few cycle lengths instead of running the
+// full max_iterations. The length of the cycle is returned as the third
+// value: a match can come a multiple of it after the saved point while the
+// orbit was still settling, so it is measured again from the matching point.
+fn iterate_periodic<F: Fractal>(fractal: &F, c: Complex<f64>, max_iterations: u32, escape_radius: f64) -> (u32, Complex<f64>, Option<u32>) {
+    let bailout = escape_radius * escape_radius;
+    let mut z = Complex::new(0.0, 0.0);
+    let mut saved = z;
+    let (mut steps, mut window) = (0, 1);
+    let mut iteration = 0;
+    while iteration < max_iterations && z.norm_sqr() <= bailout {
+        z = fractal.step(z, c);
+        iteration += 1;
+        steps += 1;
+        if (z - saved).norm_sqr() < PERIODICITY_EPSILON {
+            let mut w = z;
+            let period = (1..steps)
+                .find(|_| {
+                    w = fractal.step(w, c);
+                    (w - z).norm_sqr() < CYCLE_EPSILON
+                })
+                .unwrap_or(steps);
+            return (max_iterations, z, Some(period));
+        }
+        if steps == window {
+            saved = z;
+            steps = 0;
+            window *= 2;
+        }
+    }
+    (iteration, z, None)
+}
+
+fn escape_value((iteration, z): (u32, Complex<f64>), max_iterations: u32, smooth: bool, degree: f64, escape_radius: f64) -> f32 {
+    if smooth && iteration < max_iterations {
+        smooth_iteration(iteration, z, degree, escape_radius).max(0.0) as f32
+    } else {
+        iteration as f32
+    }
+}
+
+// The view and kernel settings of `args` in the form the samplers need,
+// small enough to be captured by value.
+#[derive(Clone, Copy)]
+struct Plane {
+    center: (DoubleDouble, DoubleDouble),
+    mapping: ViewMapping,
+    deep: bool,
+    max_iterations: u32,
+    escape_radius: f64,
+    smooth: bool,
+    periodicity: bool,
+    trap: Option<Trap>,
+    average: Option<Average>,
+    interior: Interior,
+}
+
+impl Plane {
+    fn new(args: &Args) -> Self {
+        let mapping = args.mapping();
+        Plane {
+            center: args.center,
+            mapping,
+            deep: needs_deep_precision(args.center, mapping.scale.0.min(mapping.scale.1)),
+            max_iterations: args.max_iterations,
+            escape_radius: args.escape_radius,
+            smooth: args.smooth,
+            periodicity: args.periodicity,
+            trap: args.orbit_trap(),
+            average: args.orbit_average(),
+            interior: args.interior_coloring(),
+        }
+    }
+
+    // Escape value of `fractal` at pixel position (x, y). With an orbit trap or average
+    // coloring it is the palette position scaled to the iteration range
+    // instead, so histograms, previews and checkpoints handle it like any
+    // escape value. Both always run in f64.
+    fn value<F: Fractal>(&self, fractal: &F, x: f64, y: f64) -> f32 {
+        let max_iterations = self.max_iterations;
+        if let Some(trap) = self.trap {
+            let distance = trap.orbit_distance(fractal, self.mapping.point(x, y), max_iterations);
+            return trap::position(distance) * max_iterations as f32;
+        }
+        if let Some(average) = self.average {
+            // Kept just below the interior value, which a mean of 1 would hit
+            return match average.value(fractal, self.mapping.point(x, y), max_iterations) {
+                Some(mean) => (mean.min(0.999) * max_iterations as f64) as f32,
+                None => max_iterations as f32,
+            };
+        }
+        // Angle shading wants the orbit at the cap, so it never stops early
+        // on a cycle; period shading always looks for one
+        let periodic = (self.periodicity && self.interior != Interior::Angle) || self.interior == Interior::Period;
+        let c = self.mapping.point(x, y);
+        let (orbit, period) = if self.deep {
+            let (dx, dy) = self.mapping.offset(x, y);
+            let (c_re, c_im) = (self.center.0 + DoubleDouble::new(dx), self.center.1 + DoubleDouble::new(dy));
+            let (iteration, (z_re, z_im)) = iterate_deep(|z, c| fractal.step_deep(z, c), (c_re, c_im), max_iterations, self.escape_radius);
+            ((iteration, Complex::new(z_re, z_im)), None)
+        } else if !self.interior.needs_orbit() && fractal.is_interior(c) {
+            ((max_iterations, Complex::new(0.0, 0.0)), None)
+        } else if periodic {
+            let (iteration, z, period) = iterate_periodic(fractal, c, max_iterations, self.escape_radius);
+            ((iteration, z), period)
+        } else {
+            (iterate(fractal, c, max_iterations, self.escape_radius), None)
+        };
+        if orbit.0 >= max_iterations {
+            // Deep zooms find the period in f64, which is plenty for it
+            let period = match period {
+                None if self.deep && self.interior == Interior::Period => iterate_periodic(fractal, c, max_iterations, self.escape_radius).2,
+                period => period,
+            };
+            return max_iterations as f32 + self.interior.shade(orbit.1, period);
+        }
+        escape_value(orbit, max_iterations, self.smooth, fractal.degree(), self.escape_radius)
+    }
+
+    // Orbit of `fractal` at pixel position (x, y) continued from `state` up
+    // to max_iterations, with its escape value. Escaped orbits are final.
+    fn resume<F: Fractal>(&self, fractal: &F, x: f64, y: f64, (iteration, z): State) -> (State, f32) {
+        let c = self.mapping.point(x, y);
+        let state = if z.norm_sqr() > self.escape_radius * self.escape_radius || z.is_nan() {
+            (iteration, z)
+        } else if fractal.is_interior(c) {
+            (self.max_iterations, z)
+        } else {
+            iterate_from(fractal, c, (iteration, z), self.max_iterations, self.escape_radius)
+        };
+        (state, escape_value(state, self.max_iterations, self.smooth, fractal.degree(), self.escape_radius))
+    }
+
+    // Escape values of `fractal` at LANES points with the SIMD kernel
+    fn values4<F: Fractal>(&self, fractal: &F, c: [Complex<f64>; LANES]) -> [f32; LANES] {
+        let skip = c.map(|c| fractal.is_interior(c));
+        let orbits = simd::iterate4(fractal, c.map(|c| c.re), c.map(|c| c.im), self.max_iterations, self.escape_radius, skip);
+        orbits.map(|orbit| escape_value(orbit, self.max_iterations, self.smooth, fractal.degree(), self.escape_radius))
+    }
+}
+
+// Escape value of sample `i` on an `n`×`n` sub-pixel grid of pixel (x, y)
+// for the view described by `args`
+fn sampler(args: &Args) -> impl Fn(u32, u32, u32, u32) -> f32 + Sync + Copy {
+    let plane = Plane::new(args);
+    let formula = args.formula();
+    let seed = args.seed.unwrap_or(0);
+    move |x: u32, y: u32, i: u32, n: u32| -> f32 {
+        let (px, py) = plane.mapping.sample_position(x, y, subpixel_offset(x, y, i, n, seed), n);
+        match formula {
+            Formula::Mandelbrot => plane.value(&Mandelbrot, px, py),
+            Formula::BurningShip => plane.value(&BurningShip, px, py),
+            Formula::Tricorn => plane.value(&Tricorn, px, py),
+            Formula::Celtic => plane.value(&Celtic, px, py),
+            Formula::PerpendicularBurningShip => plane.value(&PerpendicularBurningShip, px, py),
+            Formula::Multibrot(multibrot) => plane.value(&multibrot, px, py),
+            Formula::Custom(program) => plane.value(program, px, py),
+            Formula::Direct(_) => unreachable!("direct formulas are rendered by render_formula"),
+        }
+    }
+}
+
+// `--orbit-cache` counterpart of `sampler`: continues the orbit of sample
+// `i` of pixel (x, y) from a stored state
+fn orbit_sampler(args: &Args) -> impl Fn(u32, u32, u32, u32, State) -> (State, f32) + Sync + Copy {
+    let plane = Plane::new(args);
+    let formula = args.formula();
+    let seed = args.seed.unwrap_or(0);
+    move |x: u32, y: u32, i: u32, n: u32, state: State| -> (State, f32) {
+        let (px, py) = plane.mapping.sample_position(x, y, subpixel_offset(x, y, i, n, seed), n);
+        match formula {
+            Formula::Mandelbrot => plane.resume(&Mandelbrot, px, py, state),
+            Formula::BurningShip => plane.resume(&BurningShip, px, py, state),
+            Formula::Tricorn => plane.resume(&Tricorn, px, py, state),
+            Formula::Celtic => plane.resume(&Celtic, px, py, state),
+            Formula::PerpendicularBurningShip => plane.resume(&PerpendicularBurningShip, px, py, state),
+            Formula::Multibrot(multibrot) => plane.resume(&multibrot, px, py, state),
+            Formula::Custom(program) => plane.resume(program, px, py, state),
+            Formula::Direct(_) => unreachable!("direct formulas are rendered by render_formula"),
+        }
+    }
+}
+
+// `--simd` counterpart of `sampler`: evaluates LANES samples given as
+// (x, y, i) at once. Deep zooms need double-double, and orbit traps,
+// averages and interior shading per-step bookkeeping, so they fall back to
+// the scalar path lane by lane.
+fn sampler_simd(args: &Args) -> impl Fn([(u32, u32, u32); LANES], u32) -> [f32; LANES] + Sync + Copy {
+    let scalar = sampler(args);
+    let plane = Plane::new(args);
+    let formula = args.formula();
+    let seed = args.seed.unwrap_or(0);
+    move |lanes: [(u32, u32, u32); LANES], n: u32| -> [f32; LANES] {
+        if plane.deep || plane.trap.is_some() || plane.average.is_some() || plane.interior.needs_orbit() {
+            return lanes.map(|(x, y, i)| scalar(x, y, i, n));
+        }
+        let c = lanes.map(|(x, y, i)| {
+            let (px, py) = plane.mapping.sample_position(x, y, subpixel_offset(x, y, i, n, seed), n);
+            plane.mapping.point(px, py)
+        });
+        match formula {
+            Formula::Mandelbrot => plane.values4(&Mandelbrot, c),
+            Formula::BurningShip => plane.values4(&BurningShip, c),
+            Formula::Tricorn => plane.values4(&Tricorn, c),
+            Formula::Celtic => plane.values4(&Celtic, c),
+            Formula::PerpendicularBurningShip => plane.values4(&PerpendicularBurningShip, c),
+            Formula::Multibrot(multibrot) => plane.values4(&multibrot, c),
+            Formula::Custom(program) => plane.values4(program, c),
+            Formula::Direct(_) => unreachable!("direct formulas are rendered by render_formula"),
+        }
+    }
+}
+
+// Renders one frame of `--animate` into memory: every sample in parallel,
+// then the usual coloring and optional adaptive refinement.
+fn render_frame(args: &Args, palette: &Option<Palette>) -> RgbImage {
+    let (image_width, image_height) = args.size;
+    let ssaa = args.ssaa.max(1);
+    let first_pass_ssaa = if args.adaptive_aa { 1 } else { ssaa };
+    let samples_per_pixel = first_pass_ssaa * first_pass_ssaa;
+    let sample = sampler(args);
+
+    let mut samples = vec![0.0; image_width as usize * image_height as usize * samples_per_pixel as usize];
+    samples
+        .par_chunks_mut(samples_per_pixel as usize)
+        .enumerate()
+        .for_each(|(pixel, pixel_samples)| {
+            let (x, y) = (pixel as u32 % image_width, pixel as u32 / image_width);
+            for (i, value) in pixel_samples.iter_mut().enumerate() {
+                *value = sample(x, y, i as u32, first_pass_ssaa);
+            }
+        });
+
+    let max_iterations = args.max_iterations;
+    let histogram = args.histogram.then(|| Histogram::new(samples.iter().copied(), max_iterations));
+    let interior = args.interior_coloring();
+    let color = |value: f32| -> Rgb<u8> {
+        if let Some(rgb) = interior.color(palette, value, max_iterations, args.blending) {
+            return rgb;
+        }
+        let t = match &histogram {
+            Some(histogram) => histogram.equalize(value),
+            None => value / max_iterations as f32,
+        };
+        u8::from_palette(palette, args.cycle.apply(t), args.blending)
+    };
+    let adaptive = args.adaptive_aa.then_some((if ssaa > 1 { ssaa } else { 4 }, args.aa_threshold));
+    shade(&samples, samples_per_pixel, args.size, adaptive, args.blending, &color, &sample)
+}
+
+// `--animate N`: N frames zooming from the --center/--zoom view to the
+// --end-center/--end-zoom view. `--keyframes file` plays a keyframe script
+// instead, at --fps frames per second unless --animate sets the frame
+// count. Frames are written to ./out/frames/ or, with a video `--output`
+// such as zoom.mp4, encoded by ffmpeg at --fps.
+fn animate(args: &Args, palette: &Option<Palette>, format: Format) {
+    if args.checkpoint.is_some() || args.stream_rows.is_some() || args.raw.is_some() || args.stats.is_some() || args.orbit_cache.is_some() {
+        eprintln!("--animate cannot be combined with --checkpoint, --stream-rows, --raw, --stats or --orbit-cache");
+        std::process::exit(2);
+    }
+    if args.output.as_deref().is_some_and(|path| !video::is_video_path(path)) {
+        eprintln!("--output with --animate must be a video file ({})", video::EXTENSIONS.join(", "));
+        std::process::exit(2);
+    }
+    if format == Format::Exr || args.depth != 8 {
+        eprintln!("--animate writes 8-bit frames, EXR and --depth 16 are not supported");
+        std::process::exit(2);
+    }
+
+    let (script, frames) = match &args.keyframes {
+        Some(path) => {
+            let script = Script::load(path, args.max_iterations).unwrap_or_else(|e| {
+                eprintln!("Failed to load keyframes {}: {}", path, e);
+                std::process::exit(1);
+            });
+            let frames = args.animate.unwrap_or((script.duration() * args.fps).round() as u32 + 1);
+            (script, frames)
+        }
+        None => {
+            let Some(end_zoom) = args.end_zoom else {
+                eprintln!("--animate needs --end-zoom");
+                std::process::exit(2);
+            };
+            let start_view = View { center: args.center, zoom: args.zoom, max_iterations: args.max_iterations };
+            let end_view = View {
+                center: args.end_center.unwrap_or(args.center),
+                zoom: end_zoom,
+                max_iterations: args
+                    .end_iterations
+                    .filter(|_| !args.auto_iterations)
+                    .unwrap_or_else(|| animation::scaled_iterations(args.max_iterations, args.zoom, end_zoom)),
+            };
+            (Script::between(start_view, end_view), args.animate.unwrap())
+        }
+    };
+    // Keyframes without a palette of their own use the command-line one
+    let palettes: Vec<Option<Palette>> = script
+        .keyframes
+        .iter()
+        .map(|keyframe| keyframe.palette.as_deref().map_or_else(|| palette.clone(), palette_from_spec))
+        .collect();
+
+    let directory = "./out/frames";
+    let mut encoder = match &args.output {
+        Some(path) => {
+            output::create_parent_dir(path);
+            let (width, height) = args.size;
+            Some(VideoEncoder::start(path, width, height, args.fps).unwrap_or_else(|e| {
+                eprintln!("Failed to encode {}: {}", path, e);
+                std::process::exit(1);
+            }))
+        }
+        None => {
+            std::fs::create_dir_all(directory).unwrap();
+            None
+        }
+    };
+    let start = Instant::now();
+    let progress = Progress::start(frames as u64, "frames");
+    let completed = progress.counter();
+    let mut written = 0;
+    for frame in 0..frames {
+        if interrupt::requested() {
+            break;
+        }
+        let t = if frames > 1 { frame as f64 / (frames - 1) as f64 } else { 0.0 };
+        let (i, s) = script.at(t * script.duration());
+        let (from, to) = (&script.keyframes[i], &script.keyframes[i + 1]);
+        let mut view = animation::interpolate(from.view, to.view, s);
+        if args.auto_iterations {
+            view.max_iterations = animation::auto_iterations(view.zoom);
+        }
+        let mut frame_args = Args { center: view.center, zoom: view.zoom, max_iterations: view.max_iterations, ..args.clone() };
+        // Metadata names the palette of the nearer keyframe
+        let nearest = if s < 0.5 { from } else { to };
+        if let Some(spec) = &nearest.palette {
+            (frame_args.palette, frame_args.colormap) = match spec.as_str() {
+                "hsv" => (None, None),
+                name if colormap(name).is_some() => (None, Some(name.to_string())),
+                path => (Some(path.to_string()), None),
+            };
+        }
+        let frame_palette = if from.palette == to.palette {
+            palettes[i].clone()
+        } else {
+            let wheel = Palette::hsv_wheel;
+            let (a, b) = (palettes[i].clone().unwrap_or_else(wheel), palettes[i + 1].clone().unwrap_or_else(wheel));
+            Some(a.blend(&b, s as f32))
+        };
+        let image = render_frame(&frame_args, &frame_palette);
+        match &mut encoder {
+            Some(encoder) => encoder.write_frame(image.as_raw()).unwrap(),
+            None => {
+                let path = format!("{}/frame_{:05}.{}", directory, frame, format.extension());
+                check_save(&path, output::save(&path, format, &image, &image_metadata(&frame_args)));
+            }
+        }
+        written += 1;
+        completed.fetch_add(1, Ordering::Relaxed);
+    }
+    progress.finish();
+    println!("Rendering time: {:?}", start.elapsed());
+    match (encoder, &args.output) {
+        (Some(encoder), Some(path)) => {
+            encoder.finish().unwrap_or_else(|e| {
+                eprintln!("Failed to encode {}: {}", path, e);
+                std::process::exit(1);
+            });
+            println!("{} of {} frames encoded to {}", written, frames, path);
+        }
+        _ => println!("{} of {} frames saved to {}", written, frames, directory),
+    }
+    if written < frames {
+        std::process::exit(130);
+    }
+}
+
+// Width of the `--explore` thumbnails; the height follows --size
+const EXPLORE_WIDTH: u32 = 480;
+
+// `--explore n`: starting from the given view, n times zooms into one of the
+// explore::GRID×GRID cells of the view, drawn by how interesting it looks.
+// Every step is saved as a thumbnail in ./out/explore/ whose metadata holds
+// the view at the full --size, so `--from-image` renders any of them as a
+// wallpaper. `--seed` makes the path reproducible.
+fn explore(args: &Args, palette: &Option<Palette>, steps: u32, format: Format) {
+    if args.animate.is_some() || args.keyframes.is_some() || args.checkpoint.is_some() || args.stream_rows.is_some() || args.raw.is_some() || args.stats.is_some() || args.orbit_cache.is_some() || args.output.is_some() {
+        eprintln!("--explore writes its own thumbnails, it cannot be combined with --animate, --keyframes, --checkpoint, --stream-rows, --raw, --stats, --orbit-cache or --output");
+        std::process::exit(2);
+    }
+    if format == Format::Exr || args.depth != 8 {
+        eprintln!("--explore writes 8-bit thumbnails, EXR and --depth 16 are not supported");
+        std::process::exit(2);
+    }
+    let seed = args.seed.unwrap_or_else(|| {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        now.as_nanos() as u64
+    });
+    println!("Exploring {} steps, --seed {}", steps, seed);
+    let mut rng = SplitMix64::new(seed);
+
+    let width = EXPLORE_WIDTH.min(args.size.0);
+    let height = ((width as u64 * args.size.1 as u64 / args.size.0 as u64) as u32).max(explore::GRID);
+    let directory = "./out/explore";
+    std::fs::create_dir_all(directory).unwrap();
+    let interior = args.interior_coloring();
+    let mut view = args.clone();
+    for step in 0..=steps {
+        if interrupt::requested() {
+            std::process::exit(130);
+        }
+        let thumbnail = Args { size: (width, height), ..view.clone() };
+        let sample = sampler(&thumbnail);
+        let values: Vec<f32> = (0..width * height).into_par_iter().map(|i| sample(i % width, i / width, 0, 1)).collect();
+
+        let max_iterations = view.max_iterations;
+        let histogram = args.histogram.then(|| Histogram::new(values.iter().copied(), max_iterations));
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            let value = values[(y * width + x) as usize];
+            interior.color(palette, value, max_iterations, args.blending).unwrap_or_else(|| {
+                let t = match &histogram {
+                    Some(histogram) => histogram.equalize(value),
+                    None => value / max_iterations as f32,
+                };
+                u8::from_palette(palette, args.cycle.apply(t), args.blending)
+            })
+        });
+        let path = format!("{}/step_{:02}.{}", directory, step, format.extension());
+        check_save(&path, output::save(&path, format, &image, &image_metadata(&view)));
+        println!(
+            "Step {}: --center {},{} --zoom {} --iterations {} ({})",
+            step, view.center.0, view.center.1, view.zoom, view.max_iterations, path
+        );
+        if step == steps {
+            break;
+        }
+
+        let scores = explore::cell_scores(&values, width, height, max_iterations);
+        let Some(cell) = explore::pick(&scores, &mut rng) else {
+            println!("Nothing left to explore in this view");
+            break;
+        };
+        // Centers move in double-double so the path can go past f64 zooms
+        let grid = explore::GRID as f64;
+        let u = ((cell as u32 % explore::GRID) as f64 + 0.5) / grid;
+        let v = ((cell as u32 / explore::GRID) as f64 + 0.5) / grid;
+        let mapping = thumbnail.mapping();
+        view.center = (
+            view.center.0 + DoubleDouble::new((u - 0.5) * mapping.width),
+            view.center.1 + DoubleDouble::new((v - 0.5) * mapping.height),
+        );
+        view.zoom *= grid;
+        view.max_iterations = if args.auto_iterations {
+            animation::auto_iterations(view.zoom)
+        } else {
+            animation::scaled_iterations(args.max_iterations, args.zoom, view.zoom)
+        };
+    }
+}
+
+// Width of the `--contact-sheet` thumbnails; the height follows --size
+const CONTACT_WIDTH: u32 = 240;
+
+// `--contact-sheet LIST`: a small render of every view in LIST, a file or
+// location names (see contact.rs), laid out in a captioned grid
+fn contact_sheet(args: &Args, spec: &str, palette: &Option<Palette>, output_path: &str, format: Format) {
+    if args.animate.is_some() || args.keyframes.is_some() || args.checkpoint.is_some() || args.stream_rows.is_some() || args.raw.is_some() || args.stats.is_some() || args.orbit_cache.is_some() {
+        eprintln!("--contact-sheet cannot be combined with --animate, --keyframes, --checkpoint, --stream-rows, --raw, --stats or --orbit-cache");
+        std::process::exit(2);
+    }
+    if format == Format::Exr || args.depth != 8 {
+        eprintln!("--contact-sheet writes an 8-bit image, EXR and --depth 16 are not supported");
+        std::process::exit(2);
+    }
+    let entries = if std::path::Path::new(spec).is_file() {
+        let text = std::fs::read_to_string(spec).unwrap_or_else(|e| {
+            eprintln!("Failed to read {}: {}", spec, e);
+            std::process::exit(1);
+        });
+        contact::parse(&text)
+    } else {
+        contact::parse_names(spec)
+    };
+    let entries = entries.unwrap_or_else(|e| {
+        eprintln!("Invalid contact sheet list {}: {}", spec, e);
+        std::process::exit(2);
+    });
+
+    let width = CONTACT_WIDTH.min(args.size.0);
+    let height = ((width as u64 * args.size.1 as u64 / args.size.0 as u64) as u32).max(1);
+    let mut thumbnails = Vec::new();
+    for entry in &entries {
+        if interrupt::requested() {
+            std::process::exit(130);
+        }
+        let zoom = entry.zoom;
+        let max_iterations = entry.iterations.unwrap_or(if args.auto_iterations { animation::auto_iterations(zoom) } else { args.max_iterations });
+        let view = Args { size: (width, height), center: parse_center(&entry.center), zoom, max_iterations, ..args.clone() };
+        thumbnails.push((render_frame(&view, palette), entry.caption()));
+        println!("Rendered {} (--center {} --zoom {} --iterations {})", entry.label, entry.center, zoom, max_iterations);
+    }
+    let sheet = contact::compose(&thumbnails, (width, height));
+    output::create_parent_dir(output_path);
+    check_save(output_path, output::save(output_path, format, &sheet, &[]));
+    println!("Contact sheet of {} views saved to {}", entries.len(), output_path);
+}
+
+// `--newton p`: basins of Newton's method for the polynomial p
+fn render_newton(args: &Args, spec: &str, palette: &Option<Palette>, output_path: &str, format: Format) {
+    check_direct_mode(args, "--newton", format);
+    let polynomial = Polynomial::parse(spec).unwrap_or_else(|| {
+        eprintln!("Invalid value for --newton: {} (expected coefficients from the highest power down, e.g. 1,0,0,-1)", spec);
+        std::process::exit(2);
+    });
+    let newton = Newton::new(polynomial, args.max_iterations);
+    let roots: Vec<String> = newton.roots().iter().map(|root| format!("{:.6}", root)).collect();
+    println!("Newton basins of {} roots: {}", roots.len(), roots.join(", "));
+    render_direct(args, output_path, format, |z| newton.color(newton.solve(z), palette, args.blending));
+}
+
+// `--lyapunov AB`: Lyapunov exponents over the (a, b) plane, a along x
+fn render_lyapunov(args: &Args, spec: &str, output_path: &str, format: Format) {
+    check_direct_mode(args, "--lyapunov", format);
+    if args.palette.is_some() || args.colormap.is_some() {
+        eprintln!("--lyapunov uses its own yellow/blue coloring, drop --palette and --colormap");
+        std::process::exit(2);
+    }
+    let lyapunov = Lyapunov::parse(spec, args.max_iterations).unwrap_or_else(|| {
+        eprintln!("Invalid value for --lyapunov: {} (expected a sequence of A and B, e.g. AABAB)", spec);
+        std::process::exit(2);
+    });
+    render_direct(args, output_path, format, |point| lyapunov::color(lyapunov.exponent(point.re, point.im)));
+}
+
+// `--quaternion c`: a slice through the quaternion Julia set of c, or with
+// `--quaternion-projection N` its projection along the j axis
+fn render_quaternion(args: &Args, spec: &str, palette: &Option<Palette>, output_path: &str, format: Format) {
+    check_direct_mode(args, "--quaternion", format);
+    let c = Quaternion::parse(spec).unwrap_or_else(|| {
+        eprintln!("Invalid value for --quaternion: {} (expected four components a,b,c,d, e.g. -0.2,0.8,0,0)", spec);
+        std::process::exit(2);
+    });
+    let julia = QuaternionJulia { c, slice: args.quaternion_slice, max_iterations: args.max_iterations };
+    match args.quaternion_projection {
+        Some(depth) => {
+            println!("Projecting {} points along j through every pixel", depth.max(1));
+            render_direct(args, output_path, format, |point| match julia.projection(point, depth.max(1)) {
+                0.0 => Rgb([0, 0, 0]),
+                density => u8::from_palette(palette, args.cycle.apply(density as f32), args.blending),
+            });
+        }
+        None => render_direct(args, output_path, format, |point| match julia.slice_value(point) {
+            Some(t) => u8::from_palette(palette, args.cycle.apply(t as f32), args.blending),
+            None => Rgb([0, 0, 0]),
+        }),
+    }
+}
+
+// `--iim c`: the Julia set of c drawn by inverse iteration, the density of
+// --iim-points preimages from random walks
+fn render_iim(args: &Args, spec: &str, palette: &Option<Palette>, output_path: &str, format: Format) {
+    check_direct_mode(args, "--iim", format);
+    if args.ssaa > 1 {
+        eprintln!("--iim accumulates points per pixel, --ssaa does not apply");
+        std::process::exit(2);
+    }
+    let c = iim::parse_constant(spec).unwrap_or_else(|| {
+        eprintln!("Invalid value for --iim: {} (expected the Julia constant re,im, e.g. -0.12,0.75)", spec);
+        std::process::exit(2);
+    });
+
+    let (image_width, image_height) = args.size;
+    let ((x_min, y_min), (x_max, y_max)) = args.mapping().bounds();
+    let grid = Grid {
+        x_min,
+        y_min,
+        x_max,
+        y_max,
+        width: image_width,
+        height: image_height,
+    };
+    let cloud = Cloud::new(grid, c);
+    let chunks = args.iim_points.unwrap_or(iim::DEFAULT_POINTS).div_ceil(iim::CHUNK);
+    let start = Instant::now();
+    let progress = Progress::start(chunks * iim::CHUNK, "points");
+    let completed = progress.counter();
+    (0..chunks).into_par_iter().for_each(|chunk| {
+        if interrupt::requested() {
+            return;
+        }
+        cloud.add_chunk(chunk, args.seed.unwrap_or(0));
+        completed.fetch_add(iim::CHUNK, Ordering::Relaxed);
+    });
+    progress.finish();
+
+    let levels = cloud.levels();
+    let imgbuf = RgbImage::from_fn(image_width, image_height, |x, y| match levels[(y * image_width + x) as usize] {
+        0.0 => Rgb([0, 0, 0]),
+        level if palette.is_some() => u8::from_palette(palette, args.cycle.apply(level), args.blending),
+        level => Rgb([(level * 255.0).round() as u8; 3]),
+    });
+    println!("Rendering time: {:?}", start.elapsed());
+
+    output::create_parent_dir(output_path);
+    check_save(output_path, output::save(output_path, format, &imgbuf, &image_metadata(args)));
+    println!("Image saved to {}", output_path);
+    if let Some(protocol) = args.inline {
+        show_inline(protocol, output_path, format);
+    }
+    if interrupt::requested() {
+        println!("Render interrupted, the image holds the points plotted so far");
+        std::process::exit(130);
+    }
+}
+
+// `--fractal phoenix`, `nova`, `magnet1`, `magnet2` or `collatz`: the step
+// count of each pixel along the palette, black where the orbit neither
+// escapes nor converges as the formula expects
+fn render_formula(args: &Args, direct: Direct, palette: &Option<Palette>, output_path: &str, format: Format) {
+    check_direct_mode(args, &format!("--fractal {}", args.fractal.name()), format);
+    render_direct(args, output_path, format, |point| match direct.value(point, args.max_iterations) {
+        Some(t) => u8::from_palette(palette, args.cycle.apply((t / args.max_iterations as f64) as f32), args.blending),
+        None => Rgb([0, 0, 0]),
+    });
+}
+
+// `--buddhabrot n`: density of the escaping orbits of n random c values,
+// or with `--nebulabrot r,g,b` one density per channel with those caps.
+// `--anti-buddhabrot` counts the bounded orbits instead.
+fn render_buddhabrot(args: &Args, samples: u64, palette: &Option<Palette>, output_path: &str, format: Format) {
+    check_direct_mode(args, "--buddhabrot", format);
+    if args.ssaa > 1 {
+        eprintln!("--buddhabrot accumulates orbit points per pixel, --ssaa does not apply");
+        std::process::exit(2);
+    }
+    let caps = match &args.nebulabrot {
+        Some(spec) => {
+            let caps: Option<Vec<u32>> = spec.split(',').map(|cap| cap.trim().parse().ok().filter(|&cap| cap > 0)).collect();
+            let caps = caps.filter(|caps| caps.len() == 3).unwrap_or_else(|| {
+                eprintln!("Invalid value for --nebulabrot: {} (expected red, green and blue iteration caps, e.g. 5000,500,50)", spec);
+                std::process::exit(2);
+            });
+            if palette.is_some() {
+                eprintln!("--nebulabrot colors each channel by its own density, drop --palette and --colormap");
+                std::process::exit(2);
+            }
+            caps
+        }
+        None => vec![args.max_iterations.max(1)],
+    };
+
+    let (image_width, image_height) = args.size;
+    let ((x_min, y_min), (x_max, y_max)) = args.mapping().bounds();
+    let grid = Grid {
+        x_min,
+        y_min,
+        x_max,
+        y_max,
+        width: image_width,
+        height: image_height,
+    };
+    let orbits = if args.anti_buddhabrot { Orbits::Bounded } else { Orbits::Escaping };
+    if caps.iter().any(|&cap| args.min_iterations >= cap) {
+        eprintln!("--min-iterations must be below the iteration cap of every channel");
+        std::process::exit(2);
+    }
+    let density = Density::new(grid, &caps, orbits, args.min_iterations);
+    let chunks = samples.div_ceil(buddhabrot::CHUNK);
+    let start = Instant::now();
+    let progress = Progress::start(chunks * buddhabrot::CHUNK, "samples");
+    let completed = progress.counter();
+    (0..chunks).into_par_iter().for_each(|chunk| {
+        if interrupt::requested() {
+            return;
+        }
+        density.add_chunk(chunk, args.seed.unwrap_or(0));
+        completed.fetch_add(buddhabrot::CHUNK, Ordering::Relaxed);
+    });
+    progress.finish();
+
+    let levels = density.levels();
+    let imgbuf = RgbImage::from_fn(image_width, image_height, |x, y| {
+        let pixel = (y * image_width + x) as usize;
+        match levels.as_slice() {
+            [level] if palette.is_some() => u8::from_palette(palette, level[pixel], args.blending),
+            [level] => Rgb([(level[pixel] * 255.0).round() as u8; 3]),
+            channels => Rgb([0, 1, 2].map(|channel| (channels[channel][pixel] * 255.0).round() as u8)),
+        }
+    });
+    println!("Rendering time: {:?}", start.elapsed());
+
+    output::create_parent_dir(output_path);
+    check_save(output_path, output::save(output_path, format, &imgbuf, &image_metadata(args)));
+    println!("Image saved to {}", output_path);
+    if let Some(protocol) = args.inline {
+        show_inline(protocol, output_path, format);
+    }
+    if interrupt::requested() {
+        println!("Render interrupted, the image holds the samples drawn so far");
+        std::process::exit(130);
+    }
+}
+
+// --newton, --lyapunov, --quaternion, --iim, --buddhabrot and the direct
+// --fractal formulas color pixels directly instead of going through escape
+// values, so none of the escape-time machinery (checkpoints, streaming,
+// histogram, raw values, ...) applies to them.
+fn check_direct_mode(args: &Args, flag: &str, format: Format) {
+    let escape_time_only = args.animate.is_some()
+        || args.keyframes.is_some()
+        || args.checkpoint.is_some()
+        || args.stream_rows.is_some()
+        || args.raw.is_some()
+        || args.histogram
+        || args.adaptive_aa
+        || args.subdivide
+        || args.periodicity
+        || args.simd
+        || args.preview
+        || args.term_preview
+        || (args.fractal != Kind::Mandelbrot && args.fractal.direct().is_none())
+        || args.power != 2.0
+        || args.formula.is_some()
+        || args.color_script.is_some()
+        || args.trap.is_some()
+        || args.stripe
+        || args.tia
+        || args.interior.is_some()
+        || args.stats.is_some()
+        || args.orbit_cache.is_some()
+        || [args.newton.is_some(), args.lyapunov.is_some(), args.quaternion.is_some(), args.iim.is_some(), args.buddhabrot.is_some(), args.fractal.direct().is_some()]
+            .iter().filter(|&&set| set).count() > 1;
+    if escape_time_only {
+        eprintln!("{} only supports --size, --center, --zoom, --iterations, --ssaa, --palette, --colormap, --output, --format and --inline", flag);
+        std::process::exit(2);
+    }
+    if format == Format::Exr || args.depth != 8 {
+        eprintln!("{} writes 8-bit color, EXR and --depth 16 are not supported", flag);
+        std::process::exit(2);
+    }
+}
+
+// `--color-script PATH`: an escape-time render colored sample by sample by
+// the script, compiled once up front and shared by the rayon workers
+fn render_scripted(args: &Args, path: &str, output_path: &str, format: Format) {
+    let unsupported = args.animate.is_some()
+        || args.keyframes.is_some()
+        || args.explore.is_some()
+        || args.checkpoint.is_some()
+        || args.stream_rows.is_some()
+        || args.raw.is_some()
+        || args.histogram
+        || args.adaptive_aa
+        || args.subdivide
+        || args.periodicity
+        || args.simd
+        || args.preview
+        || args.term_preview
+        || args.stripe
+        || args.tia
+        || args.interior.is_some()
+        || args.stats.is_some()
+        || args.orbit_cache.is_some()
+        || args.palette.is_some()
+        || args.colormap.is_some();
+    if unsupported {
+        eprintln!("--color-script only supports --size, --center, --zoom, --iterations, --escape-radius, --ssaa, --fractal, --power, --formula, the --trap options, --output, --format and --inline");
+        std::process::exit(2);
+    }
+    if format == Format::Exr || args.depth != 8 {
+        eprintln!("--color-script writes 8-bit color, EXR and --depth 16 are not supported");
+        std::process::exit(2);
+    }
+    let source = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", path, e);
+        std::process::exit(1);
+    });
+    // `.rhai` files run as Rhai scripts, anything else is one expression
+    // per channel
+    let parsed = if path.ends_with(".rhai") { ColorScript::parse_rhai(&source) } else { ColorScript::parse(&source) };
+    let script = parsed.unwrap_or_else(|e| {
+        eprintln!("Invalid color script {}: {}", path, e);
+        std::process::exit(2);
+    });
+    let (max_iterations, escape_radius, trap) = (args.max_iterations, args.escape_radius, args.orbit_trap());
+    let formula = args.formula();
+    render_direct(args, output_path, format, |c| {
+        let sample = match formula {
+            Formula::Mandelbrot => script_sample(&Mandelbrot, c, max_iterations, escape_radius, trap),
+            Formula::BurningShip => script_sample(&BurningShip, c, max_iterations, escape_radius, trap),
+            Formula::Tricorn => script_sample(&Tricorn, c, max_iterations, escape_radius, trap),
+            Formula::Celtic => script_sample(&Celtic, c, max_iterations, escape_radius, trap),
+            Formula::PerpendicularBurningShip => script_sample(&PerpendicularBurningShip, c, max_iterations, escape_radius, trap),
+            Formula::Multibrot(multibrot) => script_sample(&multibrot, c, max_iterations, escape_radius, trap),
+            Formula::Custom(program) => script_sample(program, c, max_iterations, escape_radius, trap),
+            Formula::Direct(_) => unreachable!("direct formulas are rendered by render_formula"),
+        };
+        script.color(&sample).unwrap_or_else(|e| {
+            eprintln!("Color script {} failed: {}", path, e);
+            std::process::exit(1);
+        })
+    });
+}
+
+// What a color script sees of the orbit of `c`
+fn script_sample<F: Fractal>(fractal: &F, c: Complex<f64>, max_iterations: u32, escape_radius: f64, trap: Option<Trap>) -> Sample {
+    let (iteration, z) = iterate(fractal, c, max_iterations, escape_radius);
+    let inside = iteration >= max_iterations;
+    Sample {
+        smooth_iteration: if inside { max_iterations as f64 } else { smooth_iteration(iteration, z, fractal.degree(), escape_radius).max(0.0) },
+        z,
+        trap_distance: trap.map_or(0.0, |trap| trap.orbit_distance(fractal, c, max_iterations)),
+        max_iterations,
+        inside,
+    }
+}
+
+// Renders the view in one parallel pass, `color` giving the color of each
+// sample point and --ssaa averaging them, then writes the image.
+fn render_direct(args: &Args, output_path: &str, format: Format, color: impl Fn(Complex<f64>) -> Rgb<u8> + Sync) {
+    let (image_width, image_height) = args.size;
+    let ssaa = args.ssaa.max(1);
+    let plane = Plane::new(args);
+    let seed = args.seed.unwrap_or(0);
+    let start = Instant::now();
+    let progress = Progress::start(image_width as u64 * image_height as u64, "pixels");
+    let completed = progress.counter();
+    let pixels: Vec<Rgb<u8>> = (0..image_width * image_height)
+        .into_par_iter()
+        .map(|pixel| {
+            if interrupt::requested() {
+                return Rgb([0, 0, 0]);
+            }
+            let (x, y) = (pixel % image_width, pixel / image_width);
+            let average = average_color((0..ssaa * ssaa).map(|i| {
+                let (px, py) = plane.mapping.sample_position(x, y, subpixel_offset(x, y, i, ssaa, seed), ssaa);
+                color(plane.mapping.point(px, py))
+            }), args.blending);
+            completed.fetch_add(1, Ordering::Relaxed);
+            average
+        })
+        .collect();
+    progress.finish();
+    let imgbuf = RgbImage::from_fn(image_width, image_height, |x, y| pixels[(y * image_width + x) as usize]);
+    println!("Rendering time: {:?}", start.elapsed());
+
+    output::create_parent_dir(output_path);
+    check_save(output_path, output::save(output_path, format, &imgbuf, &image_metadata(args)));
+    println!("Image saved to {}", output_path);
+    if let Some(protocol) = args.inline {
+        show_inline(protocol, output_path, format);
+    }
+    if interrupt::requested() {
+        println!("Render interrupted, unfinished pixels left black");
+        std::process::exit(130);
+    }
+}
+
+// Colors the first-pass samples, averaging all samples of a pixel. With
+// `adaptive` set to (edge ssaa, threshold), pixels on color edges are then
+// re-rendered on a finer sub-pixel grid.
+fn shade<T: Channel>(
+    samples: &[f32],
+    samples_per_pixel: u32,
+    (image_width, image_height): (u32, u32),
+    adaptive: Option<(u32, u8)>,
+    blending: Blending,
+    color: &(impl Fn(f32) -> Rgb<T> + Sync),
+    sample: &(impl Fn(u32, u32, u32, u32) -> f32 + Sync),
+) -> ImageBuffer<Rgb<T>, Vec<T>>
+where
+    Rgb<T>: Pixel<Subpixel = T>,
+{
+    // Each row of the output buffer is colored in place from its samples
+    let row_len = (image_width * 3) as usize;
+    let mut buffer = vec![T::DEFAULT_MIN_VALUE; row_len * image_height as usize];
+    buffer
+        .par_chunks_mut(row_len)
+        .zip(samples.par_chunks(image_width as usize * samples_per_pixel as usize))
+        .for_each(|(row, row_samples)| {
+            for (rgb, pixel_samples) in row.chunks_exact_mut(3).zip(row_samples.chunks(samples_per_pixel as usize)) {
+                rgb.copy_from_slice(&average_color(pixel_samples.iter().map(|&value| color(value)), blending).0);
+            }
+        });
+
+    if let Some((edge_ssaa, threshold)) = adaptive {
+        let edges = find_edges(&buffer, image_width, image_height, threshold);
+        let refined: Vec<(usize, Rgb<T>)> = edges
+            .par_iter()
+            .map(|&i| {
+                let (x, y) = (i as u32 % image_width, i as u32 / image_width);
+                let values = (0..edge_ssaa * edge_ssaa).map(|s| color(sample(x, y, s, edge_ssaa)));
+                (i, average_color(values, blending))
+            })
+            .collect();
+        println!("Adaptive AA: refined {} of {} pixels", refined.len(), buffer.len() / 3);
+        for (i, pixel) in refined {
+            buffer[i * 3..i * 3 + 3].copy_from_slice(&pixel.0);
+        }
+    }
+
+    ImageBuffer::from_raw(image_width, image_height, buffer).unwrap()
+}
+
+// Draws a terminal-wide, low-resolution render of the view so a bad framing
+// can be caught before the full render, which is useful over SSH. With
+// `--inline` it is a small image in that protocol instead. Asks whether to
+// go on when stdin is a terminal; returns false if declined.
+// Puts back the rows a resume file records as finished, from the partial
+// image the interrupted render saved
+fn paste_resumed<T: Channel>(
+    image: &mut ImageBuffer<Rgb<T>, Vec<T>>,
+    partial: Option<&(&Resume, DynamicImage)>,
+    convert: impl Fn(&DynamicImage) -> ImageBuffer<Rgb<T>, Vec<T>>,
+) where
+    Rgb<T>: Pixel<Subpixel = T>,
+{
+    let Some((resume, partial)) = partial else { return };
+    let partial = convert(partial);
+    for (y, row) in image.enumerate_rows_mut() {
+        if resume.row_done(y) {
+            for (x, _, pixel) in row {
+                *pixel = *partial.get_pixel(x, y);
+            }
+        }
+    }
+}
+
+fn term_preview(args: &Args, palette: &Option<Palette>) -> bool {
+    let width = match args.inline {
+        Some(_) => INLINE_PREVIEW_WIDTH,
+        None => terminal::columns(),
+    }
+    .min(args.size.0);
+    let height = ((width as u64 * args.size.1 as u64 / args.size.0 as u64) as u32).max(1);
+    let small = Args { size: (width, height), ..args.clone() };
+    let sample = sampler(&small);
+    let values: Vec<f32> = (0..width * height).into_par_iter().map(|i| sample(i % width, i / width, 0, 1)).collect();
+    let histogram = args.histogram.then(|| Histogram::new(values.iter().copied(), args.max_iterations));
+    let interior = args.interior_coloring();
+    let pixel = |x: u32, y: u32| {
+        let value = values[(y * width + x) as usize];
+        if let Some(Rgb(rgb)) = interior.color(palette, value, args.max_iterations, args.blending) {
+            return rgb;
+        }
+        let t = match &histogram {
+            Some(histogram) => histogram.equalize(value),
+            None => value / args.max_iterations as f32,
+        };
+        u8::from_palette(palette, args.cycle.apply(t), args.blending).0
+    };
+    match args.inline {
+        Some(protocol) => print!("{}", protocol.encode(&RgbImage::from_fn(width, height, |x, y| Rgb(pixel(x, y))))),
+        None => print!("{}", terminal::half_blocks(width, height, pixel)),
+    }
+
+    if !std::io::stdin().is_terminal() {
+        return true;
+    }
+    print!("Render the full {}x{} image? [Y/n] ", args.size.0, args.size.1);
+    std::io::stdout().flush().unwrap();
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).unwrap();
+    !matches!(answer.trim().to_ascii_lowercase().as_str(), "n" | "no")
+}
+
+// Width of the `--inline` term preview and bounds of the inline copy of the
+// finished image, in pixels
+const INLINE_PREVIEW_WIDTH: u32 = 480;
+const INLINE_SIZE: (u32, u32) = (960, 640);
+
+// Saving comes after the whole render, so a failure, such as an unwritable
+// path, is reported instead of panicking
+fn check_save(path: &str, result: image::ImageResult<()>) {
+    if let Err(e) = result {
+        eprintln!("Failed to save {}: {}", path, e);
+        std::process::exit(1);
+    }
+}
+
+// Shows a saved image in the terminal, scaled down to fit INLINE_SIZE
+fn show_inline(protocol: Protocol, path: &str, format: Format) {
+    if format == Format::Exr {
+        println!("EXR output holds iteration values, not colors; nothing to show inline");
+        return;
+    }
+    let mut image = image::open(path).unwrap();
+    if image.width() > INLINE_SIZE.0 || image.height() > INLINE_SIZE.1 {
+        image = image.thumbnail(INLINE_SIZE.0, INLINE_SIZE.1);
+    }
+    let image = image.to_rgb8();
+    print!("{}", protocol.encode(&image));
+}
+
+// Default rows per tile for the first pass (--chunk-rows); also the
+// granularity of checkpoints
+const TILE_ROWS: u32 = 16;
+// Default tile edge for the --tile-order curves
+const TILE_SIZE: u32 = 64;
+
+// Fills `samples` for `--orbit-cache`, continuing from the orbits stored in
+// `path` when they were left by a render of the same view with at most
+// max_iterations, and stores the new orbits there unless interrupted
+fn render_cached(args: &Args, path: &str, ssaa: u32, samples: &mut [f32]) {
+    let (image_width, image_height) = args.size;
+    let samples_per_pixel = ssaa * ssaa;
+    let header = orbit_cache::Header {
+        view: orbit_cache::View {
+            fractal: args.formula.clone().unwrap_or_else(|| args.fractal.name().to_string()),
+            power: args.power,
+            center: format!("{},{}", args.center.0, args.center.1),
+            zoom: args.zoom,
+            width: image_width,
+            height: image_height,
+            samples_per_pixel,
+            escape_radius: args.escape_radius,
+            aspect: args.aspect.name().to_string(),
+            sampling: args.sampling.name().to_string(),
+        },
+        max_iterations: args.max_iterations,
+    };
+    let cached = match orbit_cache::load(path) {
+        Ok((stored, states)) if stored.view == header.view && stored.max_iterations <= header.max_iterations => {
+            println!("Continuing the orbits in {} from {} iterations", path, stored.max_iterations);
+            Some(states)
+        }
+        Ok((stored, _)) if stored.view == header.view => {
+            println!("{} holds orbits run to {} iterations, more than {}, starting over", path, stored.max_iterations, header.max_iterations);
+            None
+        }
+        Ok(_) => {
+            println!("{} holds the orbits of another view, starting over", path);
+            None
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            println!("Ignoring unreadable orbit cache {}: {}", path, e);
+            None
+        }
+    };
+
+    let orbit = orbit_sampler(args);
+    let progress = Progress::start(image_width as u64 * image_height as u64, "pixels");
+    let completed = progress.counter();
+    let results: Vec<Option<(State, f32)>> = (0..samples.len())
+        .into_par_iter()
+        .map(|j| {
+            if interrupt::requested() {
+                return None;
+            }
+            let (pixel, i) = (j as u32 / samples_per_pixel, j as u32 % samples_per_pixel);
+            let from = cached.as_ref().map_or((0, Complex::new(0.0, 0.0)), |states| states[j]);
+            let result = orbit(pixel % image_width, pixel / image_width, i, ssaa, from);
+            if i + 1 == samples_per_pixel {
+                completed.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(result)
+        })
+        .collect();
+    progress.finish();
+    for (value, result) in samples.iter_mut().zip(&results) {
+        if let Some((_, escape)) = result {
+            *value = *escape;
+        }
+    }
+    if results.iter().all(Option::is_some) {
+        let states: Vec<State> = results.into_iter().map(|result| result.unwrap().0).collect();
+        output::create_parent_dir(path);
+        orbit_cache::save(path, &header, &states).unwrap();
+        println!("Orbit states saved to {}", path);
+    }
+}
+
+// Copies a tile's samples, stored row-major within the tile, into the
+// image-wide sample buffer with rows of `row_len` entries
+fn scatter_tile(samples: &mut [f32], row_len: usize, samples_per_pixel: u32, tile: Tile, values: &[f32]) {
+    let tile_row_len = (tile.width * samples_per_pixel) as usize;
+    for (row, source) in values.chunks(tile_row_len).enumerate() {
+        let offset = (tile.y0 as usize + row) * row_len + (tile.x0 * samples_per_pixel) as usize;
+        samples[offset..offset + source.len()].copy_from_slice(source);
+    }
+}
 
 fn main() {
-    let image_width:u32 = 1920;
-    let image_height:u32 = 1080;
-    let max_iterations:u32 = 1000;
+    let args = Args::parse();
+    interrupt::install();
+    if let Some(path) = &args.jobs {
+        run_jobs(path);
+        return;
+    }
+    if let Some(path) = &args.scaling {
+        run_scaling(&args, path);
+        return;
+    }
 
-    let mut imgbuf = ImageBuffer::new(image_width, image_height);
+    // The whole render runs inside its own pool so --threads can be varied
+    // independently of RAYON_NUM_THREADS and the machine's core count
+    let mut pool = rayon::ThreadPoolBuilder::new();
+    if let Some(threads) = args.threads {
+        pool = pool.num_threads(threads);
+    }
+    let pool = pool.build().unwrap_or_else(|e| {
+        eprintln!("Failed to start thread pool: {}", e);
+        std::process::exit(1);
+    });
+    println!("Rendering on {} threads", pool.current_num_threads());
+    pool.install(|| render(args));
+}
 
-    let x_min:f64 = -2.0;
-    let x_max:f64 = 1.0;
-    let y_min:f64 = -1.0;
-    let y_max:f64 = 1.0;
+// `--jobs FILE`: runs the renders listed in a batch file, `concurrency` of
+// them at a time, each in a child process with its share of the threads.
+// Ctrl-C lets the running jobs save their progress and starts no more.
+fn run_jobs(path: &str) {
+    let text = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", path, e);
+        std::process::exit(1);
+    });
+    let batch = Batch::parse(&text).unwrap_or_else(|e| {
+        eprintln!("Invalid batch file {}: {}", path, e);
+        std::process::exit(2);
+    });
+    let executable = std::env::current_exe().unwrap_or_else(|e| {
+        eprintln!("Failed to locate the renderer executable: {}", e);
+        std::process::exit(1);
+    });
+    let available = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let threads = batch.threads_per_job(available);
+    let directory = "./out/jobs";
+    std::fs::create_dir_all(directory).unwrap();
+    println!("Running {} jobs, {} at a time on {} threads each", batch.jobs.len(), batch.concurrency, threads);
 
     let start = Instant::now();
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..batch.concurrency.min(batch.jobs.len()) {
+            scope.spawn(|| {
+                while !interrupt::requested() {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(job) = batch.jobs.get(index) else { break };
+                    let log_path = format!("{}/{}.log", directory, job.name);
+                    let log = std::fs::File::create(&log_path).unwrap();
+                    println!("Started {} (log in {})", job.name, log_path);
+                    let job_start = Instant::now();
+                    let status = std::process::Command::new(&executable)
+                        .args(["--threads".to_string(), threads.to_string()])
+                        .args(&job.args)
+                        .stdout(log.try_clone().unwrap())
+                        .stderr(log)
+                        .status();
+                    let status = status.map_err(|e| eprintln!("Failed to start {}: {}", job.name, e)).ok().and_then(|status| status.code());
+                    let outcome = Outcome { name: job.name.clone(), threads, time: job_start.elapsed(), status };
+                    println!("Finished {} in {:.2?}", outcome.name, outcome.time);
+                    outcomes.lock().unwrap().push((index, outcome));
+                }
+            });
+        }
+    });
+
+    let mut outcomes = outcomes.into_inner().unwrap();
+    outcomes.sort_by_key(|&(index, _)| index);
+    let outcomes: Vec<Outcome> = outcomes.into_iter().map(|(_, outcome)| outcome).collect();
+    print!("{}", jobs::summary(&outcomes, start.elapsed()));
+    if outcomes.len() < batch.jobs.len() {
+        println!("Interrupted, {} jobs not started", batch.jobs.len() - outcomes.len());
+        std::process::exit(130);
+    }
+    if outcomes.iter().any(|outcome| outcome.status != Some(0)) {
+        std::process::exit(1);
+    }
+}
+
+// `--scaling FILE.csv`: renders the frame on 1, 2, ... --threads threads
+// (all of the machine's by default) and writes how the time scales to a
+// CSV, checking that every thread count gives the same image.
+fn run_scaling(args: &Args, path: &str) {
+    let other_modes = [
+        args.newton.is_some(),
+        args.lyapunov.is_some(),
+        args.quaternion.is_some(),
+        args.iim.is_some(),
+        args.buddhabrot.is_some(),
+        args.fractal.direct().is_some(),
+        args.color_script.is_some(),
+        args.explore.is_some(),
+        args.animate.is_some() || args.keyframes.is_some(),
+        args.checkpoint.is_some() || args.stream_rows.is_some() || args.raw.is_some(),
+        args.stats.is_some() || args.orbit_cache.is_some() || args.output.is_some(),
+    ];
+    if other_modes.contains(&true) {
+        eprintln!("--scaling renders in memory only, it cannot be combined with --newton, --lyapunov, --quaternion, --iim, --buddhabrot, --fractal phoenix, nova, magnet or collatz, --color-script, --explore, --animate, --keyframes, --checkpoint, --stream-rows, --raw, --stats, --orbit-cache or --output");
+        std::process::exit(2);
+    }
+    let palette = select_palette(args.palette.as_deref(), args.colormap.as_deref());
+    let max_threads = args.threads.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |threads| threads.get()));
+    println!("Rendering {}x{} on 1 to {} threads, {} runs each", args.size.0, args.size.1, max_threads, scaling::RUNS);
 
-    // TODO: Calculate all pixels in parallel (based on lab 81-mandelbrot-single)
-
-
-    // Placeholder for pixel calculations
-    let pixels: Vec<(u32, u32, Rgb<u8>)> =
-        (0..image_height).into_par_iter()
-        .flat_map(|y| {
-            (0..image_width).into_par_iter().map(move |x| {
-                let cx = x_min + (x as f64 / image_width as f64) * (x_max - x_min);
-                let cy = y_min + (y as f64 / image_height as f64) * (y_max - y_min);
-                let c = Complex::new(cx, cy);
-                let mut z = Complex::new(0.0, 0.0);
-                let mut iteration = 0;
-                while iteration < max_iterations && z.norm_sqr() <= 4.0 {
-                    z = z * z + c;
-                    iteration += 1;
-                }
-                let hue = (iteration as f32 / max_iterations as f32) * 360.0;
-                let pixel = hsv_to_rgb(hue, 1.0, 1.0);
-                (x, y, pixel)
+    let mut reference: Option<RgbImage> = None;
+    let mut measurements = Vec::new();
+    for threads in 1..=max_threads {
+        if interrupt::requested() {
+            println!("Interrupted, writing the thread counts measured so far");
+            break;
+        }
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap_or_else(|e| {
+            eprintln!("Failed to start thread pool: {}", e);
+            std::process::exit(1);
+        });
+        let mut times = Vec::new();
+        let mut image = None;
+        for _ in 0..scaling::RUNS {
+            let start = Instant::now();
+            image = Some(pool.install(|| render_frame(args, &palette)));
+            times.push(start.elapsed());
+        }
+        let image = image.unwrap();
+        let identical = reference.get_or_insert_with(|| image.clone()).as_raw() == image.as_raw();
+        let measurement = Measurement { threads, time: scaling::median(times), identical };
+        println!("{:>3} threads  {:>10.2?}{}", threads, measurement.time, if identical { "" } else { "  image differs from 1 thread" });
+        measurements.push(measurement);
+    }
+
+    if let Err(e) = std::fs::write(path, scaling::csv(&measurements)) {
+        eprintln!("Failed to write {}: {}", path, e);
+        std::process::exit(1);
+    }
+    println!("Saved {}", path);
+    if measurements.iter().any(|measurement| !measurement.identical) {
+        eprintln!("The image depends on the thread count");
+        std::process::exit(1);
+    }
+}
+
+fn render(args: Args) {
+    let palette = select_palette(args.palette.as_deref(), args.colormap.as_deref());
+    let ssaa = args.ssaa.max(1);
+
+    let (image_width, image_height) = args.size;
+    let (output_path, format) = output::resolve(args.output.as_deref(), args.format, "mandelbrot_multi");
+    if args.depth != 8 && args.depth != 16 {
+        eprintln!("--depth must be 8 or 16");
+        std::process::exit(2);
+    }
+    if args.depth == 16 && !matches!(format, Format::Png | Format::Tiff) {
+        eprintln!("--depth 16 needs PNG or TIFF output");
+        std::process::exit(2);
+    }
+    if args.dither.is_some() {
+        if args.depth != 8 || format == Format::Exr {
+            eprintln!("--dither quantizes to 8-bit color, drop --depth 16 and EXR output");
+            std::process::exit(2);
+        }
+        let other_modes = [
+            args.newton.is_some(),
+            args.lyapunov.is_some(),
+            args.quaternion.is_some(),
+            args.iim.is_some(),
+            args.buddhabrot.is_some(),
+            args.fractal.direct().is_some(),
+            args.color_script.is_some(),
+            args.explore.is_some(),
+            args.contact_sheet.is_some(),
+            args.animate.is_some() || args.keyframes.is_some(),
+            args.stream_rows.is_some(),
+        ];
+        if other_modes.contains(&true) {
+            eprintln!("--dither only applies to still escape-time renders, not --newton, --lyapunov, --quaternion, --iim, --buddhabrot, --fractal phoenix, nova, magnet or collatz, --color-script, --explore, --contact-sheet, --animate, --keyframes or --stream-rows");
+            std::process::exit(2);
+        }
+    }
+    if let Some(spec) = &args.newton {
+        render_newton(&args, spec, &palette, &output_path, format);
+        return;
+    }
+    if let Some(spec) = &args.lyapunov {
+        render_lyapunov(&args, spec, &output_path, format);
+        return;
+    }
+    if let Some(spec) = &args.quaternion {
+        render_quaternion(&args, spec, &palette, &output_path, format);
+        return;
+    }
+    if let Some(spec) = &args.iim {
+        render_iim(&args, spec, &palette, &output_path, format);
+        return;
+    }
+    if let Some(samples) = args.buddhabrot {
+        render_buddhabrot(&args, samples, &palette, &output_path, format);
+        return;
+    }
+    if let Some(direct) = args.fractal.direct() {
+        render_formula(&args, direct, &palette, &output_path, format);
+        return;
+    }
+    if let Some(path) = &args.color_script {
+        render_scripted(&args, path, &output_path, format);
+        return;
+    }
+    if let Some(steps) = args.explore {
+        explore(&args, &palette, steps, format);
+        return;
+    }
+    if let Some(spec) = &args.contact_sheet {
+        contact_sheet(&args, spec, &palette, &output_path, format);
+        return;
+    }
+    if args.animate.is_some() || args.keyframes.is_some() {
+        animate(&args, &palette, format);
+        return;
+    }
+    let max_iterations:u32 = args.max_iterations;
+    if args.auto_iterations {
+        println!("Using {} iterations for zoom {}", max_iterations, args.zoom);
+    }
+    let interior = args.interior_coloring();
+    let metadata = image_metadata(&args);
+
+    // Resuming from a resume file renders only the rows it lacks and takes
+    // the rest from the partial image, colored as they were then. Coloring
+    // that depends on the whole image, and outputs of every sample, need
+    // those rows rendered again.
+    let partial = args.resumed.as_ref().map(|resume| {
+        if args.histogram || args.raw.is_some() || args.stats.is_some() || args.orbit_cache.is_some() || args.subdivide || format == Format::Exr {
+            eprintln!("Resuming from {} cannot be combined with --histogram, --raw, --stats, --orbit-cache, --subdivide or EXR output", args.resume.as_deref().unwrap());
+            std::process::exit(2);
+        }
+        let partial = image::open(&output_path).unwrap_or_else(|e| {
+            eprintln!("Failed to read the partial image {}: {}", output_path, e);
+            std::process::exit(1);
+        });
+        if (partial.width(), partial.height()) != (image_width, image_height) || (resume.width, resume.height) != (image_width, image_height) {
+            eprintln!("{} is not the {}x{} partial image the resume file refers to", output_path, image_width, image_height);
+            std::process::exit(1);
+        }
+        (resume, partial)
+    });
+
+    let scale = args.mapping().scale;
+    let deep = needs_deep_precision(args.center, scale.0.min(scale.1));
+    if deep {
+        println!("Pixel spacing below f64 resolution, using double-double arithmetic");
+        if args.power.fract() != 0.0 {
+            println!("Non-integer --power has no double-double step, the image will be blocky at this zoom");
+        }
+        if args.formula.is_some() {
+            println!("--formula has no double-double step, the image will be blocky at this zoom");
+        }
+    }
+
+    let start = Instant::now();
+    let sample = sampler(&args);
+    let sample_simd = sampler_simd(&args);
+    if args.simd && args.periodicity {
+        eprintln!("--simd has no periodicity detection, drop one of --simd and --periodicity");
+        std::process::exit(2);
+    }
+    if args.subdivide && !args.formula().is_connected() {
+        match &args.formula {
+            Some(source) => eprintln!("--subdivide relies on a connected set, which --formula {} is not known to be", source),
+            None => eprintln!("--subdivide relies on a connected set, which --fractal {} --power {} is not", args.fractal.name(), args.power),
+        }
+        std::process::exit(2);
+    }
+    if args.trap.is_some() && args.subdivide {
+        eprintln!("--subdivide fills the interior with one value, which an orbit trap does not have");
+        std::process::exit(2);
+    }
+    if args.orbit_cache.is_some() && (args.checkpoint.is_some() || args.subdivide || args.simd || args.periodicity || args.preview) {
+        eprintln!("--orbit-cache runs its own pass, it cannot be combined with --checkpoint, --subdivide, --simd, --periodicity or --preview");
+        std::process::exit(2);
+    }
+    if args.orbit_cache.is_some() && deep {
+        eprintln!("--orbit-cache keeps orbits in f64, which this zoom is too deep for");
+        std::process::exit(2);
+    }
+    if args.preview && (args.stream_rows.is_some() || args.subdivide) {
+        eprintln!("--preview follows the tiled renderer, it cannot be combined with --stream-rows or --subdivide");
+        std::process::exit(2);
+    }
+    if args.term_preview && !term_preview(&args, &palette) {
+        println!("Render cancelled");
+        return;
+    }
+
+    if let Some(band_rows) = args.stream_rows {
+        // Histogram equalization and adaptive AA need the whole image at once
+        if args.histogram || args.adaptive_aa || args.checkpoint.is_some() || args.raw.is_some() || args.stats.is_some() || args.orbit_cache.is_some() || args.subdivide {
+            eprintln!("--stream-rows cannot be combined with --histogram, --adaptive-aa, --checkpoint, --raw, --stats, --orbit-cache or --subdivide");
+            std::process::exit(2);
+        }
+        if format != Format::Png || args.depth != 8 {
+            eprintln!("--stream-rows only supports 8-bit PNG output");
+            std::process::exit(2);
+        }
+        let samples_per_pixel = ssaa * ssaa;
+        let progress = Progress::start(image_width as u64 * image_height as u64, "pixels");
+        let completed = progress.counter();
+        let render_row = |y: u32, row: &mut [u8]| {
+            for (x, rgb) in row.chunks_exact_mut(3).enumerate() {
+                let values = (0..samples_per_pixel).map(|i| {
+                    let value = sample(x as u32, y, i, ssaa);
+                    interior
+                        .color(&palette, value, max_iterations, args.blending)
+                        .unwrap_or_else(|| u8::from_palette(&palette, args.cycle.apply(value / max_iterations as f32), args.blending))
+                });
+                rgb.copy_from_slice(&average_color(values, args.blending).0);
+            }
+            completed.fetch_add(image_width as u64, Ordering::Relaxed);
+        };
+
+        output::create_parent_dir(&output_path);
+        stream::render_png(&output_path, image_width, image_height, band_rows, &metadata, render_row).unwrap();
+        progress.finish();
+        println!("Rendering time: {:?}", start.elapsed());
+        println!("Image streamed to {}", output_path);
+        if let Some(protocol) = args.inline {
+            show_inline(protocol, &output_path, format);
+        }
+        if interrupt::requested() {
+            println!("Render interrupted, remaining rows left black");
+            std::process::exit(130);
+        }
+        return;
+    }
+
+    // Adaptive mode starts from a single sample per pixel and refines edges later
+    let first_pass_ssaa = if args.adaptive_aa { 1 } else { ssaa };
+    let samples_per_pixel = first_pass_ssaa * first_pass_ssaa;
+
+    // Row-major renders full-width bands, the curve orders square tiles
+    let tile_rows = args.chunk_rows.unwrap_or(if args.tile_order == Order::RowMajor { TILE_ROWS } else { TILE_SIZE }).max(1);
+    let tile_width = (args.tile_order != Order::RowMajor).then_some(tile_rows);
+    let layout = Layout::new(image_width, image_height, tile_width.unwrap_or(image_width), tile_rows);
+    let tile_count = layout.count();
+    let row_len = image_width as usize * samples_per_pixel as usize;
+
+    // Row-major escape values, `samples_per_pixel` consecutive entries per
+    // pixel; NaN marks samples that have not been rendered
+    let sample_count = image_width as usize * image_height as usize * samples_per_pixel as usize;
+    let megabytes = sample_count * size_of::<f32>() / (1024 * 1024);
+    let mut samples = Samples::new(sample_count, f32::NAN, args.mmap_buffer).unwrap_or_else(|e| {
+        eprintln!("Failed to map a sample buffer of {} MB: {}", megabytes, e);
+        std::process::exit(1);
+    });
+    if args.mmap_buffer {
+        println!("Keeping {} MB of samples in {}", megabytes, samples.location());
+    }
+    let mut tiles_done = vec![false; tile_count];
+
+    let checkpoint = args.checkpoint.as_deref().map(|path| {
+        let header = checkpoint::Header {
+            args: args.command_line.clone(),
+            width: image_width,
+            height: image_height,
+            samples_per_pixel,
+            tile_rows,
+            tile_width,
+        };
+        let opened = if args.resume.is_some() {
+            checkpoint::load(path).and_then(|(stored, tiles)| {
+                if stored.width != header.width
+                    || stored.height != header.height
+                    || stored.samples_per_pixel != header.samples_per_pixel
+                    || stored.tile_rows != header.tile_rows
+                    || stored.tile_width != header.tile_width
+                {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "checkpoint layout does not match this render"));
+                }
+                for (tile, values) in tiles {
+                    scatter_tile(&mut samples, row_len, samples_per_pixel, layout.tile(tile as usize), &values);
+                    tiles_done[tile as usize] = true;
+                }
+                println!("Resuming from {}: {} of {} tiles already rendered", path, tiles_done.iter().filter(|&&done| done).count(), tile_count);
+                Checkpoint::reopen(path)
             })
+        } else {
+            Checkpoint::create(path, &header)
+        };
+        opened.unwrap_or_else(|e| {
+            eprintln!("Failed to open checkpoint {}: {}", path, e);
+            std::process::exit(1);
+        })
+    });
+
+    // Rows the resume file has are not rendered again; zeros stand in for
+    // their samples so they count as done
+    let resumed_row = |y: u32| args.resumed.as_ref().is_some_and(|resume| resume.row_done(y));
+    for y in (0..image_height).filter(|&y| resumed_row(y)) {
+        samples[y as usize * row_len..(y as usize + 1) * row_len].fill(0.0);
+    }
+
+    // A view of a symmetric fractal centered on the real axis is symmetric,
+    // so the lower rows are copies of the upper ones mirrored. A lone
+    // sample at the pixel corner (--corner-sampling) puts row y opposite row
+    // height - y; one at the center, or supersamples spread over the whole
+    // pixel, put it opposite height - 1 - y. Returns the row to copy from.
+    // Traps, stripes and interior angles depend on where the orbit lies,
+    // and conjugating the orbit generally changes their value.
+    let symmetric = args.formula().is_symmetric()
+        && args.center.1 == DoubleDouble::ZERO
+        && !args.subdivide
+        && args.trap.is_none()
+        && !args.stripe
+        && args.interior_coloring() != Interior::Angle;
+    let mirror_source = |y: u32| -> Option<u32> {
+        let corner = first_pass_ssaa == 1 && args.sampling == Sampling::Corner;
+        let partner = if corner { image_height - y } else { image_height - 1 - y };
+        (symmetric && partner < y).then_some(partner)
+    };
+    if symmetric {
+        let mirrored = (0..image_height).filter(|&y| mirror_source(y).is_some()).count();
+        println!("View is symmetric about the real axis, mirroring {} of {} rows", mirrored, image_height);
+    }
+
+    if args.subdivide {
+        if samples_per_pixel != 1 || checkpoint.is_some() {
+            eprintln!("--subdivide needs one sample per pixel (no --ssaa without --adaptive-aa) and no --checkpoint");
+            std::process::exit(2);
+        }
+        let (values, evaluated) = subdivide::render(image_width, image_height, |x, y| sample(x, y, 0, 1));
+        samples.copy_from_slice(&values);
+        println!("Subdivision: iterated {} of {} pixels", evaluated, samples.len());
+    } else if let Some(path) = &args.orbit_cache {
+        render_cached(&args, path, first_pass_ssaa, &mut samples);
+    } else {
+        let progress = Progress::start(image_width as u64 * image_height as u64, "pixels");
+        let completed = progress.counter();
+        let resumed_pixels = samples.iter().filter(|value| !value.is_nan()).count() / samples_per_pixel as usize;
+        completed.fetch_add(resumed_pixels as u64, Ordering::Relaxed);
+        let preview = if args.preview { Preview::open(image_width, image_height) } else { None };
+
+        // Tiles are rendered into their own buffers and copied into place as
+        // they finish. `par_bridge` hands them out in traversal order.
+        let shared = Mutex::new(&mut samples);
+        layout.traverse(args.tile_order).into_iter().par_bridge().for_each(|index| {
+            if tiles_done[index] {
+                return;
+            }
+            let tile = layout.tile(index);
+            let mut values = vec![f32::NAN; tile.samples() * samples_per_pixel as usize];
+            // Work in groups of LANES samples so `--simd` can take a whole
+            // group at once; the scalar path still goes sample by sample
+            let mut finished = true;
+            for (k, group) in values.chunks_mut(LANES).enumerate() {
+                if interrupt::requested() {
+                    finished = false;
+                    break;
+                }
+                let lanes: [(u32, u32, u32); LANES] = std::array::from_fn(|lane| {
+                    // A short last group repeats its final sample
+                    let j = (k * LANES + lane.min(group.len() - 1)) as u32;
+                    let (pixel, i) = (j / samples_per_pixel, j % samples_per_pixel);
+                    (tile.x0 + pixel % tile.width, tile.y0 + pixel / tile.width, i)
+                });
+                let rendered = |y: u32| mirror_source(y).is_none() && !resumed_row(y);
+                let batch = (args.simd && lanes.iter().any(|&(_, y, _)| rendered(y)))
+                    .then(|| sample_simd(lanes, first_pass_ssaa));
+                for (lane, value) in group.iter_mut().enumerate() {
+                    let (x, y, i) = lanes[lane];
+                    if rendered(y) {
+                        *value = match batch {
+                            Some(batch) => batch[lane],
+                            None => sample(x, y, i, first_pass_ssaa),
+                        };
+                    }
+                    if i + 1 == samples_per_pixel {
+                        completed.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+            if let Some(preview) = &preview {
+                // The first sample of each pixel is plenty for a preview
+                preview.draw(tile, |x, y| {
+                    let value = values[(((y - tile.y0) * tile.width + x - tile.x0) * samples_per_pixel) as usize];
+                    (!value.is_nan()).then(|| {
+                        let color = interior.color(&palette, value, max_iterations, args.blending);
+                        color.unwrap_or_else(|| u8::from_palette(&palette, args.cycle.apply(value / max_iterations as f32), args.blending)).0
+                    })
+                });
+            }
+            scatter_tile(&mut shared.lock().unwrap(), row_len, samples_per_pixel, tile, &values);
+            if let (true, Some(checkpoint)) = (finished, &checkpoint) {
+                checkpoint.record(index as u32, &values).unwrap();
+            }
+        });
+        progress.finish();
+        if let Some(preview) = preview {
+            preview.close();
+        }
+    }
+    if let Some(checkpoint) = &checkpoint {
+        checkpoint.flush().unwrap();
+    }
+    if symmetric {
+        for y in 0..image_height {
+            if let Some(source) = mirror_source(y) {
+                let (upper, lower) = samples.split_at_mut(y as usize * row_len);
+                let source_row = &upper[source as usize * row_len..(source as usize + 1) * row_len];
+                lower[..row_len].copy_from_slice(source_row);
+            }
+        }
+    }
+
+    // After Ctrl-C, rows that were cut short are dropped entirely (NaN renders
+    // black) so the partial image only ever contains whole rows
+    let interrupted = interrupt::requested();
+    let rows_done: Vec<bool> = samples
+        .par_chunks_mut(image_width as usize * samples_per_pixel as usize)
+        .map(|row| {
+            let done = !row.iter().any(|value| value.is_nan());
+            if !done {
+                row.fill(f32::NAN);
+            }
+            done
         })
         .collect();
-    // Write pixels to image buffer
-    for (x, y, pixel) in pixels {
-        imgbuf.put_pixel(x, y, pixel);
+
+    // Optional second pass: spread the palette over the iteration distribution
+    let histogram = if args.histogram {
+        Some(Histogram::new(samples.iter().copied(), max_iterations))
+    } else {
+        None
+    };
+
+    let normalize = |value: f32| -> f32 {
+        let t = match &histogram {
+            Some(histogram) => histogram.equalize(value),
+            None => value / max_iterations as f32,
+        };
+        args.cycle.apply(t)
+    };
+    let adaptive = if args.adaptive_aa && !interrupted {
+        Some((if ssaa > 1 { ssaa } else { 4 }, args.aa_threshold))
+    } else {
+        None
+    };
+    // Per-pixel mean escape values, the input of EXR output and raw dumps
+    let pixel_values = || -> Vec<f32> {
+        samples
+            .par_chunks(samples_per_pixel as usize)
+            .map(|pixel_samples| pixel_samples.iter().sum::<f32>() / samples_per_pixel as f32)
+            .collect()
+    };
+    if let Some(path) = &args.raw {
+        output::create_parent_dir(path);
+        raw::save_npy(path, image_width, image_height, &pixel_values()).unwrap();
+        println!("Raw iteration data saved to {}", path);
     }
 
-    let duration = start.elapsed();
-    println!("Rendering time: {:?}", duration);
+    output::create_parent_dir(&output_path);
+    if format == Format::Exr {
+        let values = pixel_values();
+        println!("Rendering time: {:?}", start.elapsed());
+        check_save(&output_path, output::save_exr(&output_path, image_width, image_height, &values));
+    } else if args.depth == 16 || args.dither.is_some() {
+        let color = |value: f32| -> Rgb<u16> {
+            if value.is_nan() {
+                return Rgb([0, 0, 0]);
+            }
+            interior.color(&palette, value, max_iterations, args.blending).unwrap_or_else(|| u16::from_palette(&palette, normalize(value), args.blending))
+        };
+        let mut imgbuf = shade(&samples, samples_per_pixel, args.size, adaptive, args.blending, &color, &sample);
+        println!("Rendering time: {:?}", start.elapsed());
+        match args.dither {
+            // Colored at 16 bits so the dither has the fraction of the 8-bit step to work with
+            Some(dither) => {
+                let mut quantized = dither.quantize(&imgbuf);
+                paste_resumed(&mut quantized, partial.as_ref(), DynamicImage::to_rgb8);
+                check_save(&output_path, output::save(&output_path, format, &quantized, &metadata));
+            }
+            None => {
+                paste_resumed(&mut imgbuf, partial.as_ref(), DynamicImage::to_rgb16);
+                check_save(&output_path, output::save16(&output_path, format, &imgbuf, &metadata));
+            }
+        }
+    } else {
+        let color = |value: f32| -> Rgb<u8> {
+            if value.is_nan() {
+                return Rgb([0, 0, 0]);
+            }
+            interior.color(&palette, value, max_iterations, args.blending).unwrap_or_else(|| u8::from_palette(&palette, normalize(value), args.blending))
+        };
+        let mut imgbuf = shade(&samples, samples_per_pixel, args.size, adaptive, args.blending, &color, &sample);
+        println!("Rendering time: {:?}", start.elapsed());
+        paste_resumed(&mut imgbuf, partial.as_ref(), DynamicImage::to_rgb8);
+        check_save(&output_path, output::save(&output_path, format, &imgbuf, &metadata));
+    }
+    println!("Image saved to {}", output_path);
+    if let Some(protocol) = args.inline {
+        show_inline(protocol, &output_path, format);
+    }
+
+    if let Some(path) = &args.stats {
+        let stats = Stats::collect(&samples, args.size, max_iterations, start.elapsed(), rayon::current_num_threads());
+        output::create_parent_dir(path);
+        stats.save(path).unwrap();
+        println!("Render statistics saved to {}", path);
+    }
 
-    std::fs::create_dir_all("./out").unwrap();
-    imgbuf.save("./out/mandelbrot_multi.png").unwrap();
-    println!("Image saved to ./out/mandelbrot_multi.png");
-}
\ No newline at end of file
+    if interrupted {
+        if let Some(path) = &args.checkpoint {
+            println!("Render interrupted, continue it with --resume {}", path);
+            std::process::exit(130);
+        }
+        let resume_path = std::path::Path::new(&output_path).with_extension("resume.json");
+        let resume_path = resume_path.to_str().unwrap();
+        Resume::new(args.command_line.clone(), image_width, image_height, &rows_done).save(resume_path).unwrap();
+        let done = rows_done.iter().filter(|&&done| done).count();
+        println!("Render interrupted: {} of {} rows completed, continue it with --resume {}", done, image_height, resume_path);
+        std::process::exit(130);
+    }
+
+    // A finished render no longer needs its checkpoint or resume file
+    if let Some(path) = &args.checkpoint {
+        drop(checkpoint);
+        std::fs::remove_file(path).unwrap();
+    } else if let (Some(path), Some(_)) = (&args.resume, &args.resumed) {
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,161 @@
+use num_complex::Complex;
+
+// Pixel to complex-plane mapping.
+//
+// A view is a center and a zoom. At zoom 1 it spans SPAN: 3 units across
+// and 2 up and down, which frames the Mandelbrot set in a 3:2 image. For
+// images of another shape, `--aspect` picks between extending that range
+// along the longer side so pixels stay square (the default) and stretching
+// it onto the image as it is, which distorts the set. Scale and origin are
+// worked out once per view, so mapping a pixel position is one multiply-add
+// per axis.
+//
+// Pixel positions put pixel (x, y) between x and x + 1 across and y and
+// y + 1 down. Without supersampling each pixel is sampled at its center,
+// x + 0.5; `--corner-sampling` goes back to sampling at x, which shifts
+// the image by half a pixel towards the bottom right.
+pub const SPAN: (f64, f64) = (3.0, 2.0);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aspect {
+    Extend,
+    Stretch,
+}
+
+impl Aspect {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "extend" => Some(Aspect::Extend),
+            "stretch" => Some(Aspect::Stretch),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aspect::Extend => "extend",
+            Aspect::Stretch => "stretch",
+        }
+    }
+}
+
+// Where the single sample of a pixel without supersampling goes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sampling {
+    Center,
+    Corner,
+}
+
+impl Sampling {
+    pub fn name(self) -> &'static str {
+        match self {
+            Sampling::Center => "center",
+            Sampling::Corner => "corner",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ViewMapping {
+    // Size of the view in the plane
+    pub width: f64,
+    pub height: f64,
+    // Plane units per pixel along each axis
+    pub scale: (f64, f64),
+    // The point at pixel position (0, 0), the top-left corner of the image
+    origin: (f64, f64),
+    sampling: Sampling,
+}
+
+impl ViewMapping {
+    pub fn new(center: (f64, f64), zoom: f64, (image_width, image_height): (u32, u32), aspect: Aspect, sampling: Sampling) -> Self {
+        let (mut width, mut height) = (SPAN.0 / zoom, SPAN.1 / zoom);
+        if aspect == Aspect::Extend {
+            let ratio = image_width as f64 / image_height as f64;
+            if ratio > width / height {
+                width = height * ratio;
+            } else {
+                height = width / ratio;
+            }
+        }
+        ViewMapping {
+            width,
+            height,
+            scale: (width / image_width as f64, height / image_height as f64),
+            origin: (center.0 - width / 2.0, center.1 - height / 2.0),
+            sampling,
+        }
+    }
+
+    // Pixel position of the sample of pixel (x, y) at `offset`, as
+    // `sampling::subpixel_offset` gives it for `ssaa` samples per axis.
+    // Supersamples cover the whole pixel already, only a lone sample moves
+    // to the center.
+    pub fn sample_position(&self, x: u32, y: u32, (dx, dy): (f64, f64), ssaa: u32) -> (f64, f64) {
+        let anchor = if ssaa <= 1 && self.sampling == Sampling::Center { 0.5 } else { 0.0 };
+        (x as f64 + anchor + dx, y as f64 + anchor + dy)
+    }
+
+    // The point at pixel position (x, y); whole numbers are pixel corners
+    pub fn point(&self, x: f64, y: f64) -> Complex<f64> {
+        Complex::new(self.origin.0 + x * self.scale.0, self.origin.1 + y * self.scale.1)
+    }
+
+    // How far pixel position (x, y) lies from the center, for adding to a
+    // center held in more precision than f64
+    pub fn offset(&self, x: f64, y: f64) -> (f64, f64) {
+        (x * self.scale.0 - self.width / 2.0, y * self.scale.1 - self.height / 2.0)
+    }
+
+    // The smallest and largest point of the view, (x_min, y_min) and
+    // (x_max, y_max)
+    pub fn bounds(&self) -> ((f64, f64), (f64, f64)) {
+        (self.origin, (self.origin.0 + self.width, self.origin.1 + self.height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corners_and_center() {
+        let mapping = ViewMapping::new((-0.5, 0.25), 2.0, (300, 200), Aspect::Extend, Sampling::Center);
+        assert_eq!(mapping.point(0.0, 0.0), Complex::new(-1.25, -0.25));
+        assert_eq!(mapping.point(300.0, 200.0), Complex::new(0.25, 0.75));
+        assert_eq!(mapping.point(150.0, 100.0), Complex::new(-0.5, 0.25));
+        assert_eq!(mapping.offset(150.0, 100.0), (0.0, 0.0));
+        assert_eq!(mapping.bounds(), ((-1.25, -0.25), (0.25, 0.75)));
+        // A 3:2 image looks the same either way
+        assert_eq!(mapping, ViewMapping::new((-0.5, 0.25), 2.0, (300, 200), Aspect::Stretch, Sampling::Center));
+    }
+
+    #[test]
+    fn extending_keeps_pixels_square() {
+        let wide = ViewMapping::new((0.0, 0.0), 1.0, (1920, 1080), Aspect::Extend, Sampling::Center);
+        assert_eq!(wide.height, 2.0);
+        assert!((wide.width - 2.0 * 16.0 / 9.0).abs() < 1e-12);
+        assert!((wide.scale.0 - wide.scale.1).abs() < 1e-15);
+
+        let tall = ViewMapping::new((0.0, 0.0), 1.0, (100, 100), Aspect::Extend, Sampling::Center);
+        assert_eq!((tall.width, tall.height), (3.0, 3.0));
+
+        let stretched = ViewMapping::new((0.0, 0.0), 1.0, (100, 100), Aspect::Stretch, Sampling::Center);
+        assert_eq!((stretched.width, stretched.height), (3.0, 2.0));
+        assert_eq!(stretched.point(100.0, 0.0), Complex::new(1.5, -1.0));
+    }
+
+    #[test]
+    fn lone_samples_sit_at_the_pixel_center() {
+        let center = ViewMapping::new((0.0, 0.0), 1.0, (3, 2), Aspect::Extend, Sampling::Center);
+        assert_eq!(center.sample_position(1, 0, (0.0, 0.0), 1), (1.5, 0.5));
+        // The middle pixel of an odd-sized image is sampled at the view center
+        let (x, y) = center.sample_position(1, 0, (0.0, 0.0), 1);
+        assert_eq!(ViewMapping::new((0.0, 0.0), 1.0, (3, 1), Aspect::Stretch, Sampling::Center).offset(x, y).0, 0.0);
+        assert_eq!(center.sample_position(1, 0, (0.25, 0.75), 2), (1.25, 0.75));
+
+        let corner = ViewMapping::new((0.0, 0.0), 1.0, (3, 2), Aspect::Extend, Sampling::Corner);
+        assert_eq!(corner.sample_position(1, 0, (0.0, 0.0), 1), (1.0, 0.0));
+        assert_eq!(corner.point(0.0, 0.0), Complex::new(-1.5, -1.0));
+    }
+}
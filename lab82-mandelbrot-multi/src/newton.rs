@@ -0,0 +1,172 @@
+use hsv_to_rgb::Palette;
+use image::Rgb;
+use num_complex::Complex;
+
+use crate::color::{ Blending, Channel };
+
+// Newton basins for `--newton`.
+//
+// Every pixel is a starting point of Newton's method z <- z - p(z)/p'(z).
+// The roots of p are found once up front, so an orbit can be classified as
+// soon as it lands within TOLERANCE of one of them. The pixel takes the
+// color of that root, darkened by the number of steps it needed; starting
+// points that never settle (e.g. cycles, or hitting p'(z) = 0) are black.
+
+// Distance to a root at which an orbit counts as converged
+const TOLERANCE: f64 = 1e-6;
+
+// Brightness lost per Newton step, and the level it never drops below so
+// slow regions still show their root's hue
+const DECAY: f32 = 0.92;
+const FLOOR: f32 = 0.15;
+
+// The view used when the command line gives none. The roots of the usual
+// test polynomials (z³ - 1, z⁴ - 1, ...) sit on the unit circle.
+pub const DEFAULT_VIEW: ((f64, f64), f64) = ((0.0, 0.0), 0.75);
+
+// Coefficients from the highest power down, e.g. z³ - 1 is [1, 0, 0, -1]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polynomial {
+    coefficients: Vec<Complex<f64>>,
+}
+
+impl Polynomial {
+    // Parses comma-separated coefficients, highest power first. Each one is
+    // real or complex as num-complex writes it: "1,0,0,-1" or "1,0,2-1i".
+    // Leading zeros are dropped; constants have no roots and are rejected.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let coefficients: Vec<Complex<f64>> = spec
+            .split(',')
+            .map(|coefficient| coefficient.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        let first = coefficients.iter().position(|c| *c != Complex::new(0.0, 0.0))?;
+        let coefficients = coefficients[first..].to_vec();
+        (coefficients.len() >= 2).then_some(Polynomial { coefficients })
+    }
+
+    pub fn degree(&self) -> usize {
+        self.coefficients.len() - 1
+    }
+
+    // p(z) and p'(z) in one Horner pass
+    pub fn eval(&self, z: Complex<f64>) -> (Complex<f64>, Complex<f64>) {
+        let mut p = Complex::new(0.0, 0.0);
+        let mut dp = Complex::new(0.0, 0.0);
+        for &coefficient in &self.coefficients {
+            dp = dp * z + p;
+            p = p * z + coefficient;
+        }
+        (p, dp)
+    }
+
+    // All roots by Durand–Kerner: every estimate is refined against the
+    // others at once, starting from powers of a number that is neither
+    // real nor a root of unity.
+    pub fn roots(&self) -> Vec<Complex<f64>> {
+        let leading = self.coefficients[0];
+        let monic = |z: Complex<f64>| self.eval(z).0 / leading;
+        let seed = Complex::new(0.4, 0.9);
+        let mut roots: Vec<Complex<f64>> = (0..self.degree() as u32).map(|k| seed.powu(k)).collect();
+        for _ in 0..1000 {
+            let mut change: f64 = 0.0;
+            for i in 0..roots.len() {
+                let others: Complex<f64> = (0..roots.len())
+                    .filter(|&j| j != i)
+                    .map(|j| roots[i] - roots[j])
+                    .product();
+                let step = monic(roots[i]) / others;
+                if step.is_finite() {
+                    roots[i] -= step;
+                    change = change.max(step.norm());
+                }
+            }
+            if change < 1e-15 {
+                break;
+            }
+        }
+        roots
+    }
+}
+
+// Where the orbit of one starting point ended up
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Basin {
+    pub root: Option<usize>,
+    pub iterations: u32,
+}
+
+pub struct Newton {
+    polynomial: Polynomial,
+    roots: Vec<Complex<f64>>,
+    max_iterations: u32,
+}
+
+impl Newton {
+    pub fn new(polynomial: Polynomial, max_iterations: u32) -> Self {
+        let roots = polynomial.roots();
+        Newton { polynomial, roots, max_iterations }
+    }
+
+    pub fn roots(&self) -> &[Complex<f64>] {
+        &self.roots
+    }
+
+    pub fn solve(&self, mut z: Complex<f64>) -> Basin {
+        for iteration in 0..self.max_iterations {
+            if let Some(root) = self.roots.iter().position(|root| (z - root).norm_sqr() < TOLERANCE * TOLERANCE) {
+                return Basin { root: Some(root), iterations: iteration };
+            }
+            let (p, dp) = self.polynomial.eval(z);
+            if dp.norm_sqr() == 0.0 {
+                break;
+            }
+            z -= p / dp;
+        }
+        Basin { root: None, iterations: self.max_iterations }
+    }
+
+    // Root k takes the color at (k + 1/2)/n along the palette (the HSV wheel
+    // by default), dimmed by the steps the orbit took to get there
+    pub fn color(&self, basin: Basin, palette: &Option<Palette>, blending: Blending) -> Rgb<u8> {
+        let Some(root) = basin.root else { return Rgb([0, 0, 0]) };
+        let Rgb(rgb) = u8::from_palette(palette, (root as f32 + 0.5) / self.roots.len() as f32, blending);
+        let brightness = FLOOR + (1.0 - FLOOR) * DECAY.powi(basin.iterations as i32);
+        Rgb(rgb.map(|channel| (channel as f32 * brightness).round() as u8))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_cube_roots_of_unity() {
+        let polynomial = Polynomial::parse("1, 0, 0, -1").unwrap();
+        let mut roots = polynomial.roots();
+        roots.sort_by(|a, b| a.im.total_cmp(&b.im));
+        let h = 3f64.sqrt() / 2.0;
+        let expected = [Complex::new(-0.5, -h), Complex::new(1.0, 0.0), Complex::new(-0.5, h)];
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!((root - expected).norm() < 1e-12, "{} vs {}", root, expected);
+        }
+    }
+
+    #[test]
+    fn orbits_settle_on_the_nearest_root_close_to_it() {
+        let newton = Newton::new(Polynomial::parse("1,0,-1").unwrap(), 100);
+        let basin = newton.solve(Complex::new(0.9, 0.1));
+        let root = newton.roots()[basin.root.unwrap()];
+        assert!((root - Complex::new(1.0, 0.0)).norm() < 1e-9);
+        assert!(basin.iterations > 0 && basin.iterations < 10);
+        // On the imaginary axis z² - 1 is equidistant from both roots and
+        // Newton's method never leaves it
+        assert_eq!(newton.solve(Complex::new(0.0, 0.5)).root, None);
+    }
+
+    #[test]
+    fn parse_drops_leading_zeros_and_rejects_constants() {
+        assert_eq!(Polynomial::parse("0,1,2i").unwrap().degree(), 1);
+        assert_eq!(Polynomial::parse("0,5"), None);
+        assert_eq!(Polynomial::parse("1,x"), None);
+    }
+}
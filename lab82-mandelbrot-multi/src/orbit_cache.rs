@@ -0,0 +1,125 @@
+use num_complex::Complex;
+use serde::{ Deserialize, Serialize };
+use std::fs::File;
+use std::io::{ self, BufReader, BufWriter, Read, Write };
+
+// Orbit state files for `--orbit-cache`.
+//
+// Holds where the orbit of every sample stopped: the iteration it escaped
+// at, or max_iterations for one that never did, together with its last z.
+// Rendering the same view again with a higher --iterations picks the
+// bounded orbits up from there instead of starting over at z = 0, and
+// escaped ones are final already, so only the added iterations cost time.
+//
+//   b"MBOC" | header length: u32 | header JSON
+//   (iteration: u32 | Re z: f64 | Im z: f64) per sample
+//
+// Samples are stored in the order of the render buffer. All integers and
+// floats are little-endian.
+const MAGIC: &[u8; 4] = b"MBOC";
+
+// Iteration reached and last point of a sample's orbit
+pub type State = (u32, Complex<f64>);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Header {
+    pub view: View,
+    pub max_iterations: u32,
+}
+
+// Everything besides the iteration count that decides which points the
+// samples are; a cache is only picked up again when all of it matches
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct View {
+    pub fractal: String,
+    pub power: f64,
+    pub center: String,
+    pub zoom: f64,
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    // Caches written before --escape-radius ran orbits out to 2
+    #[serde(default = "default_escape_radius")]
+    pub escape_radius: f64,
+    // Missing from caches written before --aspect and --corner-sampling,
+    // which then count as another view
+    #[serde(default)]
+    pub aspect: String,
+    #[serde(default)]
+    pub sampling: String,
+}
+
+fn default_escape_radius() -> f64 {
+    2.0
+}
+
+pub fn save(path: &str, header: &Header, states: &[State]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let json = serde_json::to_vec(header)?;
+    writer.write_all(MAGIC)?;
+    writer.write_all(&(json.len() as u32).to_le_bytes())?;
+    writer.write_all(&json)?;
+    for (iteration, z) in states {
+        writer.write_all(&iteration.to_le_bytes())?;
+        writer.write_all(&z.re.to_le_bytes())?;
+        writer.write_all(&z.im.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+pub fn load(path: &str) -> io::Result<(Header, Vec<State>)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not an orbit cache file"));
+    }
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let mut json = vec![0u8; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut json)?;
+    let header: Header = serde_json::from_slice(&json)?;
+
+    let count = header.view.width as usize * header.view.height as usize * header.view.samples_per_pixel as usize;
+    let mut states = Vec::with_capacity(count);
+    let mut record = [0u8; 20];
+    for _ in 0..count {
+        reader.read_exact(&mut record)?;
+        let iteration = u32::from_le_bytes(record[0..4].try_into().unwrap());
+        let re = f64::from_le_bytes(record[4..12].try_into().unwrap());
+        let im = f64::from_le_bytes(record[12..20].try_into().unwrap());
+        states.push((iteration, Complex::new(re, im)));
+    }
+    Ok((header, states))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn states_round_trip() {
+        let header = Header {
+            view: View {
+                fractal: "mandelbrot".to_string(),
+                power: 2.0,
+                center: "-0.5,0".to_string(),
+                zoom: 1.0,
+                width: 3,
+                height: 1,
+                samples_per_pixel: 1,
+                escape_radius: 2.0,
+                aspect: "extend".to_string(),
+                sampling: "center".to_string(),
+            },
+            max_iterations: 100,
+        };
+        let states = [(3, Complex::new(2.5, -1.0)), (100, Complex::new(-0.1, 0.3)), (100, Complex::new(0.0, 0.0))];
+        let path = std::env::temp_dir().join(format!("orbit_cache_{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        save(path, &header, &states).unwrap();
+        let loaded = load(path);
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.unwrap(), (header, states.to_vec()));
+    }
+}
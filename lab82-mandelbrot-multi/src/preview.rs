@@ -0,0 +1,105 @@
+use minifb::{ Key, Window, WindowOptions };
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::interrupt;
+use crate::tiling::Tile;
+
+// Live preview window for `--preview`.
+//
+// Workers paint finished tiles into a shared, downscaled framebuffer; a
+// separate thread owns the window (minifb windows cannot change threads)
+// and redraws it a few times per second. Closing the window or pressing
+// Escape cancels the render just like Ctrl-C.
+const MAX_SIZE: (u32, u32) = (1280, 800);
+const REFRESH: Duration = Duration::from_millis(50);
+
+pub struct Preview {
+    // Image pixels per preview pixel along each axis
+    step: u32,
+    width: u32,
+    buffer: Arc<Mutex<Vec<u32>>>,
+    done: Arc<AtomicBool>,
+    window: Option<JoinHandle<()>>,
+}
+
+impl Preview {
+    // Returns None, after a warning, when no window can be opened (for
+    // example without a display)
+    pub fn open(image_width: u32, image_height: u32) -> Option<Self> {
+        let step = image_width.div_ceil(MAX_SIZE.0).max(image_height.div_ceil(MAX_SIZE.1)).max(1);
+        let (width, height) = (image_width.div_ceil(step), image_height.div_ceil(step));
+        let buffer = Arc::new(Mutex::new(vec![0u32; (width * height) as usize]));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let (opened, status) = std::sync::mpsc::channel();
+        let window = {
+            let buffer = Arc::clone(&buffer);
+            let done = Arc::clone(&done);
+            std::thread::spawn(move || {
+                let options = WindowOptions { resize: true, ..WindowOptions::default() };
+                let mut window = match Window::new("lab82 preview", width as usize, height as usize, options) {
+                    Ok(window) => window,
+                    Err(e) => {
+                        opened.send(Err(e.to_string())).unwrap();
+                        return;
+                    }
+                };
+                opened.send(Ok(())).unwrap();
+                while !done.load(Ordering::Relaxed) {
+                    if !window.is_open() || window.is_key_down(Key::Escape) {
+                        eprintln!("\nPreview closed, finishing in-flight pixels");
+                        interrupt::request();
+                        break;
+                    }
+                    let frame = buffer.lock().unwrap().clone();
+                    window.update_with_buffer(&frame, width as usize, height as usize).unwrap();
+                    std::thread::sleep(REFRESH);
+                }
+            })
+        };
+        match status.recv().unwrap() {
+            Ok(()) => Some(Preview { step, width, buffer, done, window: Some(window) }),
+            Err(e) => {
+                eprintln!("Preview window unavailable, rendering without it: {}", e);
+                None
+            }
+        }
+    }
+
+    // Paints a finished tile; `color` is asked for the image pixels that
+    // land on a preview pixel and returns None for those still missing
+    pub fn draw(&self, tile: Tile, color: impl Fn(u32, u32) -> Option<[u8; 3]>) {
+        let step = self.step;
+        let pixels: Vec<(usize, u32)> = (tile.y0.div_ceil(step)..(tile.y0 + tile.height).div_ceil(step))
+            .flat_map(|py| (tile.x0.div_ceil(step)..(tile.x0 + tile.width).div_ceil(step)).map(move |px| (px, py)))
+            .filter_map(|(px, py)| {
+                let [r, g, b] = color(px * step, py * step)?;
+                Some(((py * self.width + px) as usize, u32::from_be_bytes([0, r, g, b])))
+            })
+            .collect();
+        let mut buffer = self.buffer.lock().unwrap();
+        for (index, pixel) in pixels {
+            buffer[index] = pixel;
+        }
+    }
+
+    pub fn close(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(window) = self.window.take() {
+            window.join().unwrap();
+        }
+    }
+}
+
+impl Drop for Preview {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
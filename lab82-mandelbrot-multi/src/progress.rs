@@ -0,0 +1,69 @@
+use indicatif::{ ProgressBar, ProgressStyle };
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, AtomicU64, Ordering };
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// Render progress reporting.
+//
+// Workers only bump a shared atomic counter, which costs next to nothing in
+// the hot loop. A separate thread polls the counter a few times per second
+// and drives the indicatif bar (throughput and ETA come from indicatif).
+pub struct Progress {
+    counter: Arc<AtomicU64>,
+    done: Arc<AtomicBool>,
+    reporter: Option<JoinHandle<()>>,
+}
+
+impl Progress {
+    pub fn start(total: u64, unit: &str) -> Self {
+        let counter = Arc::new(AtomicU64::new(0));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let bar = ProgressBar::new(total);
+        bar.set_style(
+            ProgressStyle::with_template(&format!(
+                "{{elapsed_precise}} [{{bar:40.cyan/blue}}] {{pos}}/{{len}} {} ({{per_sec}}, ETA {{eta}})",
+                unit
+            ))
+            .unwrap()
+            .progress_chars("=> "),
+        );
+
+        let reporter = {
+            let counter = Arc::clone(&counter);
+            let done = Arc::clone(&done);
+            std::thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    bar.set_position(counter.load(Ordering::Relaxed));
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                bar.set_position(counter.load(Ordering::Relaxed));
+                bar.finish();
+            })
+        };
+
+        Progress { counter, done, reporter: Some(reporter) }
+    }
+
+    pub fn counter(&self) -> &AtomicU64 {
+        &self.counter
+    }
+
+    pub fn finish(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(reporter) = self.reporter.take() {
+            reporter.join().unwrap();
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
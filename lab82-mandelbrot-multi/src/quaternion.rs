@@ -0,0 +1,144 @@
+use num_complex::Complex;
+
+// Quaternion Julia sets for `--quaternion`.
+//
+// The Julia iteration q <- q² + c carried over from complex numbers to
+// quaternions q = a + bi + cj + dk, whose set is four-dimensional. An image
+// shows it one of two ways:
+//
+// - a slice: pixel (x, y) is the point x + yi + sj + tk, with (s, t) from
+//   `--quaternion-slice`, colored by escape time like the Mandelbrot set
+// - a projection, with `--quaternion-projection N`: N points are taken
+//   along the j axis through every pixel, from -RADIUS to RADIUS at the k
+//   of the slice, and the pixel shows the share of them that stay bounded,
+//   a look through the set as if it were a cloud
+//
+// With c in the complex plane (its j and k parts zero) the 0,0 slice is
+// the ordinary Julia set of c.
+
+// Beyond |q| = 2 every orbit escapes
+const BAILOUT: f64 = 4.0;
+
+// Half the extent of the set along any axis, which bounds the projection
+pub const RADIUS: f64 = 1.6;
+
+// The view used when the command line gives none
+pub const DEFAULT_VIEW: ((f64, f64), f64) = ((0.0, 0.0), 0.8);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quaternion {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+}
+
+impl Quaternion {
+    pub fn new(a: f64, b: f64, c: f64, d: f64) -> Self {
+        Quaternion { a, b, c, d }
+    }
+
+    // Four comma-separated components, e.g. "-0.2,0.8,0,0"
+    pub fn parse(spec: &str) -> Option<Self> {
+        let parts: Vec<f64> = spec.split(',').map(|part| part.trim().parse().ok()).collect::<Option<_>>()?;
+        match parts[..] {
+            [a, b, c, d] => Some(Quaternion::new(a, b, c, d)),
+            _ => None,
+        }
+    }
+
+    // (a + v)² = a² - |v|² + 2av, as the vector parts commute with
+    // themselves
+    pub fn square(self) -> Self {
+        let Quaternion { a, b, c, d } = self;
+        Quaternion::new(a * a - b * b - c * c - d * d, 2.0 * a * b, 2.0 * a * c, 2.0 * a * d)
+    }
+
+    pub fn norm_sqr(self) -> f64 {
+        self.a * self.a + self.b * self.b + self.c * self.c + self.d * self.d
+    }
+}
+
+impl std::ops::Add for Quaternion {
+    type Output = Quaternion;
+
+    fn add(self, other: Quaternion) -> Quaternion {
+        Quaternion::new(self.a + other.a, self.b + other.b, self.c + other.c, self.d + other.d)
+    }
+}
+
+pub struct QuaternionJulia {
+    pub c: Quaternion,
+    // The j and k parts of the points of a slice
+    pub slice: (f64, f64),
+    pub max_iterations: u32,
+}
+
+impl QuaternionJulia {
+    // Smooth escape time of q, or None if it stays bounded for
+    // max_iterations steps
+    pub fn escape(&self, mut q: Quaternion) -> Option<f64> {
+        for iteration in 0..self.max_iterations {
+            if q.norm_sqr() > BAILOUT {
+                let log_norm = q.norm_sqr().ln() / 2.0;
+                return Some((iteration as f64 + 1.0 - (log_norm / std::f64::consts::LN_2).log2()).max(0.0));
+            }
+            q = q.square() + self.c;
+        }
+        None
+    }
+
+    // Escape time at `point` of the slice, as a share of max_iterations
+    pub fn slice_value(&self, point: Complex<f64>) -> Option<f64> {
+        let q = Quaternion::new(point.re, point.im, self.slice.0, self.slice.1);
+        self.escape(q).map(|time| time / self.max_iterations as f64)
+    }
+
+    // Share of `depth` points along the j axis through `point` that stay
+    // bounded
+    pub fn projection(&self, point: Complex<f64>, depth: u32) -> f64 {
+        let bounded = (0..depth)
+            .filter(|&i| {
+                let j = -RADIUS + 2.0 * RADIUS * (i as f64 + 0.5) / depth as f64;
+                self.escape(Quaternion::new(point.re, point.im, j, self.slice.1)).is_none()
+            })
+            .count();
+        bounded as f64 / depth as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fractal::{ Fractal, Mandelbrot };
+
+    #[test]
+    fn squares_like_a_quaternion() {
+        // i² = j² = k² = -1
+        assert_eq!(Quaternion::new(0.0, 1.0, 0.0, 0.0).square(), Quaternion::new(-1.0, 0.0, 0.0, 0.0));
+        assert_eq!(Quaternion::new(0.0, 0.0, 0.0, 1.0).square(), Quaternion::new(-1.0, 0.0, 0.0, 0.0));
+        let q = Quaternion::new(1.0, 2.0, -1.0, 0.5);
+        assert_eq!(q.square().norm_sqr(), q.norm_sqr() * q.norm_sqr());
+        assert_eq!(Quaternion::parse("-0.2, 0.8,0,0"), Some(Quaternion::new(-0.2, 0.8, 0.0, 0.0)));
+        assert_eq!(Quaternion::parse("1,2,3"), None);
+    }
+
+    #[test]
+    fn complex_slice_is_the_julia_set() {
+        // The Douady rabbit
+        let c = Complex::new(-0.12, 0.75);
+        let julia = QuaternionJulia { c: Quaternion::new(c.re, c.im, 0.0, 0.0), slice: (0.0, 0.0), max_iterations: 100 };
+        for point in [Complex::new(0.0, 0.0), Complex::new(0.3, -0.2), Complex::new(1.0, 1.0)] {
+            let mut z = point;
+            let mut iterations = 0;
+            while iterations < 100 && z.norm_sqr() <= 4.0 {
+                z = Mandelbrot.step(z, c);
+                iterations += 1;
+            }
+            assert_eq!(julia.slice_value(point).is_none(), iterations == 100, "{}", point);
+        }
+        // Points inside the set show in the projection, far-off ones do not
+        assert!(julia.projection(Complex::new(0.0, 0.0), 16) > 0.0);
+        assert_eq!(julia.projection(Complex::new(2.0, 2.0), 16), 0.0);
+    }
+}
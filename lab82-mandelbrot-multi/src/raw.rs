@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::io::{ self, BufReader, BufWriter, Read, Write };
+
+// Raw per-pixel escape values as NumPy `.npy` files.
+//
+// The data is a row-major `height × width` array of little-endian f32, so it
+// loads directly with `numpy.load`. Interior points hold `max_iterations`
+// and pixels lost to an interrupted render are NaN.
+const MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+pub fn save_npy(path: &str, width: u32, height: u32, values: &[f32]) -> io::Result<()> {
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}",
+        height, width
+    );
+    // Magic, version and header length take 10 bytes; the header is padded
+    // with spaces and a newline so the data starts on a 64-byte boundary
+    let padding = 64 - (10 + header.len() + 1) % 64;
+    header.push_str(&" ".repeat(padding % 64));
+    header.push('\n');
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for value in values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+// Reads a 2-D little-endian f32 array as written by `save_npy`, returning
+// (width, height, values).
+pub fn load_npy(path: &str) -> io::Result<(u32, u32, Vec<f32>)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut preamble = [0u8; 8];
+    reader.read_exact(&mut preamble)?;
+    if &preamble[..6] != MAGIC {
+        return Err(invalid("not a .npy file"));
+    }
+    let header_len = match preamble[6] {
+        1 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_le_bytes(len) as usize
+        }
+        2 | 3 => {
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        }
+        version => return Err(invalid(&format!("unsupported .npy version {}", version))),
+    };
+    let mut header = vec![0u8; header_len];
+    reader.read_exact(&mut header)?;
+    let header = String::from_utf8_lossy(&header);
+
+    if !header.contains("'descr': '<f4'") {
+        return Err(invalid("expected little-endian f32 data"));
+    }
+    if header.contains("'fortran_order': True") {
+        return Err(invalid("expected C-ordered data"));
+    }
+    let (height, width) = header
+        .split_once("'shape': (")
+        .and_then(|(_, rest)| rest.split_once(')'))
+        .and_then(|(shape, _)| {
+            let (h, w) = shape.split_once(',')?;
+            Some((h.trim().parse().ok()?, w.trim().parse().ok()?))
+        })
+        .ok_or_else(|| invalid("expected a 2-D shape"))?;
+
+    let mut bytes = vec![0u8; width as usize * height as usize * 4];
+    reader.read_exact(&mut bytes)?;
+    let values = bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    Ok((width, height, values))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
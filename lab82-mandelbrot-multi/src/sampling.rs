@@ -0,0 +1,64 @@
+// Sub-pixel sample positions for supersampling.
+//
+// With `ssaa == 1` the offset is zero and `ViewMapping::sample_position`
+// puts the sample at the pixel center, or its corner with
+// `--corner-sampling`. Otherwise the pixel is split into an N×N grid and each
+// cell is sampled at a jittered position inside it (stratified sampling),
+// which trades the regular aliasing pattern for much less visible noise.
+//
+// The jitter is a hash of the pixel, the sample index and `--seed`, never
+// of time or scheduling. Together with the other seeded choices (the
+// Buddhabrot samples and the `--explore` path) this means the same seed and
+// parameters give byte-identical images on every run, whatever the thread
+// count, so renders can serve as golden images. Seed 0 is the default.
+
+// Returns the offset in 0.0..1.0 pixel units for sample `index` of pixel (x, y).
+pub fn subpixel_offset(x: u32, y: u32, index: u32, ssaa: u32, seed: u64) -> (f64, f64) {
+    if ssaa <= 1 {
+        return (0.0, 0.0);
+    }
+    let (sx, sy) = (index % ssaa, index / ssaa);
+    let jx = unit(hash(x, y, index, 0, seed));
+    let jy = unit(hash(x, y, index, 1, seed));
+    ((sx as f64 + jx) / ssaa as f64, (sy as f64 + jy) / ssaa as f64)
+}
+
+// Cheap integer hash so the jitter pattern is the same on every run and
+// independent of how rayon schedules the work. Seed 0 leaves the pattern
+// as it was before seeds existed.
+fn hash(x: u32, y: u32, index: u32, axis: u32, seed: u64) -> u32 {
+    let mut h = x
+        .wrapping_mul(0x8da6_b343)
+        ^ y.wrapping_mul(0xd816_3841)
+        ^ index.wrapping_mul(0xcb1a_b31f)
+        ^ axis.wrapping_mul(0x1656_67b1)
+        ^ (seed as u32).wrapping_mul(0x2c1b_3c6d)
+        ^ ((seed >> 32) as u32).wrapping_mul(0x297a_2d39);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846c_a68b);
+    h ^ (h >> 16)
+}
+
+fn unit(h: u32) -> f64 {
+    h as f64 / (u32::MAX as f64 + 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter_follows_the_seed() {
+        let offsets = |seed| (0..16).map(|i| subpixel_offset(3, 5, i, 4, seed)).collect::<Vec<_>>();
+        assert_eq!(offsets(0), offsets(0));
+        assert_ne!(offsets(0), offsets(1));
+        assert_ne!(offsets(1), offsets(1 << 32));
+        // Every sample stays in its own cell of the grid
+        for (i, (dx, dy)) in offsets(42).into_iter().enumerate() {
+            assert_eq!(((dx * 4.0) as usize, (dy * 4.0) as usize), (i % 4, i / 4));
+        }
+        assert_eq!(subpixel_offset(3, 5, 0, 1, 7), (0.0, 0.0));
+    }
+}
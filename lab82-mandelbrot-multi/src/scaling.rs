@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+// Thread scaling reports for `--scaling`.
+//
+// The same frame is rendered on pools of 1, 2, ... N threads, RUNS times
+// each, and the median time goes into a CSV:
+//
+//   threads,seconds,speedup,efficiency,identical
+//   1,2.104,1.000,1.000,true
+//   2,1.071,1.965,0.982,true
+//
+// Speedup is the one-thread time over this time, efficiency the speedup
+// per thread; 1.0 would be perfect scaling. `identical` says whether the
+// image came out byte for byte the same as on one thread, which it must:
+// every pixel is computed on its own, whatever thread picks it up.
+pub const RUNS: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Measurement {
+    pub threads: usize,
+    pub time: Duration,
+    pub identical: bool,
+}
+
+// Median of the times of several runs
+pub fn median(mut times: Vec<Duration>) -> Duration {
+    times.sort();
+    times[times.len() / 2]
+}
+
+pub fn csv(measurements: &[Measurement]) -> String {
+    let Some(single) = measurements.iter().find(|measurement| measurement.threads == 1) else {
+        return String::new();
+    };
+    let mut csv = "threads,seconds,speedup,efficiency,identical\n".to_string();
+    for measurement in measurements {
+        let speedup = single.time.as_secs_f64() / measurement.time.as_secs_f64();
+        csv += &format!(
+            "{},{:.3},{:.3},{:.3},{}\n",
+            measurement.threads,
+            measurement.time.as_secs_f64(),
+            speedup,
+            speedup / measurement.threads as f64,
+            measurement.identical
+        );
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speedup_is_relative_to_one_thread() {
+        let measurement = |threads, millis| Measurement { threads, time: Duration::from_millis(millis), identical: true };
+        let csv = csv(&[measurement(1, 2000), measurement(2, 1000), measurement(4, 800)]);
+        assert_eq!(
+            csv,
+            "threads,seconds,speedup,efficiency,identical\n1,2.000,1.000,1.000,true\n2,1.000,2.000,1.000,true\n4,0.800,2.500,0.625,true\n"
+        );
+        assert_eq!(median(vec![Duration::from_secs(3), Duration::from_secs(1), Duration::from_secs(2)]), Duration::from_secs(2));
+    }
+}
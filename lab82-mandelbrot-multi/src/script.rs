@@ -0,0 +1,182 @@
+use image::Rgb;
+use num_complex::Complex;
+use rhai::{ Array, CallFnOptions, Dynamic, Engine, Scope, AST };
+use std::fmt;
+
+use crate::expression::Program;
+
+// Color scripts for `--color-script`, compiled once before the render
+// starts and shared by the rayon workers.
+//
+// A plain script gives one expression per channel, in the language of the
+// `expression` module:
+//
+//   # comments run to the end of the line
+//   r = 0.5 + 0.5 * cos(n / 8)
+//   g = 0.5 + 0.5 * cos(n / 8 + 2)
+//   b = inside * abs(z) / 2
+//
+// Each pixel sample gets these variables:
+//
+// - n: the smooth iteration count, max for points that never escaped
+// - z: the last point of the orbit
+// - d: the `--trap` distance, 0 without a trap
+// - max: the iteration limit
+// - inside: 1 for points that never escaped, 0 otherwise
+//
+// The real part of each result is clamped to 0.0..=1.0.
+//
+// A `.rhai` file is a Rhai script instead, for coloring that needs more
+// than one expression a channel: local variables, branches, loops and
+// helper functions. It defines
+//
+//   fn color(n, re, im, d, max, inside) { ... }
+//
+// which receives the same values, z split into its real and imaginary
+// parts, `max` an integer and `inside` a bool, and returns [r, g, b] with
+// each channel clamped to 0.0..=1.0 as above.
+pub const VARIABLES: [&str; 5] = ["n", "z", "d", "max", "inside"];
+pub const RHAI_PARAMETERS: [&str; 6] = ["n", "re", "im", "d", "max", "inside"];
+
+#[derive(Debug)]
+pub enum ColorScript {
+    Channels([Program; 3]),
+    Rhai(Box<Rhai>),
+}
+
+// A compiled Rhai script and the engine that runs it
+pub struct Rhai {
+    engine: Engine,
+    ast: AST,
+}
+
+impl fmt::Debug for Rhai {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Rhai").field("ast", &self.ast).finish()
+    }
+}
+
+// What a script sees of one sample
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    pub smooth_iteration: f64,
+    pub z: Complex<f64>,
+    pub trap_distance: f64,
+    pub max_iterations: u32,
+    pub inside: bool,
+}
+
+impl ColorScript {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut channels: [Option<Program>; 3] = [None, None, None];
+        for (number, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            let (name, expression) = line.split_once('=').ok_or_else(|| error("expected r, g or b = expression".to_string()))?;
+            let channel = match name.trim() {
+                "r" => 0,
+                "g" => 1,
+                "b" => 2,
+                name => return Err(error(format!("unknown channel '{}' (expected r, g or b)", name))),
+            };
+            if channels[channel].is_some() {
+                return Err(error(format!("{} is already set", name.trim())));
+            }
+            channels[channel] = Some(Program::compile_with(expression, &VARIABLES).map_err(error)?);
+        }
+        let [Some(r), Some(g), Some(b)] = channels else {
+            let missing: Vec<&str> = ["r", "g", "b"].into_iter().zip(&channels).filter(|(_, program)| program.is_none()).map(|(name, _)| name).collect();
+            return Err(format!("no expression for {}", missing.join(", ")));
+        };
+        Ok(ColorScript::Channels([r, g, b]))
+    }
+
+    pub fn parse_rhai(source: &str) -> Result<Self, String> {
+        let engine = Engine::new();
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        if !ast.iter_functions().any(|function| function.name == "color" && function.params.len() == RHAI_PARAMETERS.len()) {
+            return Err(format!("no function color({})", RHAI_PARAMETERS.join(", ")));
+        }
+        Ok(ColorScript::Rhai(Box::new(Rhai { engine, ast })))
+    }
+
+    // Fails only for a Rhai script that throws or returns something other
+    // than three numbers
+    pub fn color(&self, sample: &Sample) -> Result<Rgb<u8>, String> {
+        match self {
+            ColorScript::Channels(channels) => {
+                let real = |value: f64| Complex::new(value, 0.0);
+                let variables = [
+                    real(sample.smooth_iteration),
+                    sample.z,
+                    real(sample.trap_distance),
+                    real(sample.max_iterations as f64),
+                    real(if sample.inside { 1.0 } else { 0.0 }),
+                ];
+                Ok(Rgb(channels.each_ref().map(|program| quantize(program.eval(&variables).re))))
+            }
+            ColorScript::Rhai(rhai) => {
+                let Rhai { engine, ast } = rhai.as_ref();
+                let args = (sample.smooth_iteration, sample.z.re, sample.z.im, sample.trap_distance, sample.max_iterations as i64, sample.inside);
+                // The functions are all the script is run for, so its top
+                // level is skipped
+                let options = CallFnOptions::new().eval_ast(false);
+                let rgb: Array = engine.call_fn_with_options(options, &mut Scope::new(), ast, "color", args).map_err(|e| e.to_string())?;
+                let [r, g, b] = <[Dynamic; 3]>::try_from(rgb).map_err(|rgb| format!("color returned {} values, expected [r, g, b]", rgb.len()))?;
+                let channel = |value: Dynamic| {
+                    value.as_float().or_else(|_| value.as_int().map(|value| value as f64)).map_err(|name| format!("color returned a {}, expected numbers", name))
+                };
+                Ok(Rgb([quantize(channel(r)?), quantize(channel(g)?), quantize(channel(b)?)]))
+            }
+        }
+    }
+}
+
+// NaN, e.g. from log(0), comes out black
+fn quantize(value: f64) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_from_the_sample_variables() {
+        let script = ColorScript::parse("# grey by iteration\nr = n / max\ng = inside\nb = abs(z) - d  # clamped\n").unwrap();
+        let sample = Sample { smooth_iteration: 25.0, z: Complex::new(3.0, 4.0), trap_distance: 4.5, max_iterations: 100, inside: false };
+        assert_eq!(script.color(&sample), Ok(Rgb([64, 0, 128])));
+        let sample = Sample { smooth_iteration: 100.0, inside: true, trap_distance: 9.0, ..sample };
+        assert_eq!(script.color(&sample), Ok(Rgb([255, 255, 0])));
+    }
+
+    #[test]
+    fn runs_rhai_scripts() {
+        let source = "
+            fn color(n, re, im, d, max, inside) {
+                let grey = n / max;
+                if inside { [1, 1, 0] } else { [grey, 0, sqrt(re * re + im * im) - d] }
+            }
+        ";
+        let script = ColorScript::parse_rhai(source).unwrap();
+        let sample = Sample { smooth_iteration: 25.0, z: Complex::new(3.0, 4.0), trap_distance: 4.5, max_iterations: 100, inside: false };
+        assert_eq!(script.color(&sample), Ok(Rgb([64, 0, 128])));
+        let sample = Sample { smooth_iteration: 100.0, inside: true, ..sample };
+        assert_eq!(script.color(&sample), Ok(Rgb([255, 255, 0])));
+
+        assert_eq!(ColorScript::parse_rhai("fn colour(n) { [n, n, n] }").unwrap_err(), "no function color(n, re, im, d, max, inside)");
+        let script = ColorScript::parse_rhai("fn color(n, re, im, d, max, inside) { [n, n] }").unwrap();
+        assert_eq!(script.color(&sample), Err("color returned 2 values, expected [r, g, b]".to_string()));
+    }
+
+    #[test]
+    fn reports_the_offending_line() {
+        assert_eq!(ColorScript::parse("r = n\ng = q\nb = 0").unwrap_err(), "line 2: unknown name 'q'");
+        assert_eq!(ColorScript::parse("r = n\nx = 1").unwrap_err(), "line 2: unknown channel 'x' (expected r, g or b)");
+        assert_eq!(ColorScript::parse("r = n\nr = 1").unwrap_err(), "line 2: r is already set");
+        assert_eq!(ColorScript::parse("g = n").unwrap_err(), "no expression for r, b");
+    }
+}
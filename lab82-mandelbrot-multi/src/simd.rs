@@ -0,0 +1,45 @@
+use num_complex::Complex;
+use wide::f64x4;
+
+use crate::fractal::Fractal;
+
+// SIMD escape-time kernel.
+//
+// Four points are iterated side by side in one f64x4 per coordinate. A lane
+// mask tracks which orbits are still bounded; escaped lanes keep their z and
+// stop counting while the rest carry on, and the batch ends once every lane
+// has escaped or max_iterations is reached. The arithmetic is the same as in
+// the scalar kernel, so results match it bit for bit.
+pub const LANES: usize = 4;
+
+// `skip` marks lanes already known to be interior (e.g. by the cardioid
+// test); they report max_iterations without being iterated.
+pub fn iterate4<F: Fractal>(
+    fractal: &F,
+    c_re: [f64; LANES],
+    c_im: [f64; LANES],
+    max_iterations: u32,
+    escape_radius: f64,
+    skip: [bool; LANES],
+) -> [(u32, Complex<f64>); LANES] {
+    let (cr, ci) = (f64x4::new(c_re), f64x4::new(c_im));
+    let (mut zr, mut zi) = (f64x4::ZERO, f64x4::ZERO);
+    let (bailout, one) = (f64x4::splat(escape_radius * escape_radius), f64x4::splat(1.0));
+    let mut count = f64x4::new(skip.map(|skip| if skip { max_iterations as f64 } else { 0.0 }));
+    // All bits set in lanes that are still iterating
+    let mut active = f64x4::new(skip.map(|skip| if skip { 0.0 } else { 1.0 })).simd_eq(one);
+
+    for _ in 0..max_iterations {
+        active &= (zr * zr + zi * zi).simd_le(bailout);
+        if !active.any() {
+            break;
+        }
+        let (new_zr, new_zi) = fractal.step4(zr, zi, cr, ci);
+        zr = active.bitselect(new_zr, zr);
+        zi = active.bitselect(new_zi, zi);
+        count = active.bitselect(count + one, count);
+    }
+
+    let (count, zr, zi) = (count.to_array(), zr.to_array(), zi.to_array());
+    std::array::from_fn(|lane| (count[lane] as u32, Complex::new(zr[lane], zi[lane])))
+}
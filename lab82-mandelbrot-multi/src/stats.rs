@@ -0,0 +1,83 @@
+use serde::Serialize;
+use std::time::Duration;
+
+// Render statistics for `--stats`.
+//
+// Summarizes the escape values of a finished render: how many samples
+// escaped at each iteration, how many never did, and how long it all took
+// on how many threads. Samples left unrendered by an interrupt are not
+// counted.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Stats {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u64,
+    pub max_iterations: u32,
+    pub threads: usize,
+    pub elapsed_seconds: f64,
+    pub interior_fraction: f64,
+    // Over the samples that escaped; absent if none did
+    pub iterations: Option<Iterations>,
+    // Escaped samples per whole iteration count, index 0 to max_iterations - 1
+    pub histogram: Vec<u64>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Iterations {
+    pub min: f32,
+    pub mean: f64,
+    pub max: f32,
+}
+
+impl Stats {
+    pub fn collect(values: &[f32], (width, height): (u32, u32), max_iterations: u32, elapsed: Duration, threads: usize) -> Self {
+        let mut histogram = vec![0u64; max_iterations as usize];
+        let (mut samples, mut interior) = (0u64, 0u64);
+        let (mut min, mut max, mut sum) = (f32::INFINITY, f32::NEG_INFINITY, 0.0);
+        for &value in values.iter().filter(|value| !value.is_nan()) {
+            samples += 1;
+            if value >= max_iterations as f32 {
+                interior += 1;
+                continue;
+            }
+            histogram[value as usize] += 1;
+            min = min.min(value);
+            max = max.max(value);
+            sum += value as f64;
+        }
+        let escaped = samples - interior;
+        Stats {
+            width,
+            height,
+            samples,
+            max_iterations,
+            threads,
+            elapsed_seconds: elapsed.as_secs_f64(),
+            interior_fraction: if samples > 0 { interior as f64 / samples as f64 } else { 0.0 },
+            iterations: (escaped > 0).then(|| Iterations { min, mean: sum / escaped as f64, max }),
+            histogram,
+        }
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_escaped_and_interior_samples() {
+        let values = [1.5, 3.0, 3.25, 10.0, 12.0, f32::NAN];
+        let stats = Stats::collect(&values, (3, 2), 10, Duration::from_millis(250), 4);
+        assert_eq!(stats.samples, 5);
+        assert_eq!(stats.interior_fraction, 0.4);
+        let iterations = stats.iterations.unwrap();
+        assert_eq!((iterations.min, iterations.max), (1.5, 3.25));
+        assert!((iterations.mean - 7.75 / 3.0).abs() < 1e-12);
+        assert_eq!(stats.histogram, [0, 1, 0, 2, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(stats.elapsed_seconds, 0.25);
+    }
+}
@@ -0,0 +1,178 @@
+use image::RgbImage;
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use crate::color::{ average_color, Blending };
+
+// Tile manifests and Deep Zoom pyramids for the `stitch` tool.
+//
+// A render split into tiles, for example one per worker machine, is put
+// back together from a JSON manifest, by default next to the tiles:
+//
+//   {
+//     "width": 8000,
+//     "height": 6000,
+//     "tiles": [
+//       { "file": "0_0.png", "x": 0, "y": 0 },
+//       { "file": "1_0.png", "x": 4000, "y": 0 },
+//       ...
+//     ]
+//   }
+//
+// Tile files are relative to the tile directory and placed with their
+// top-left corner at (x, y); their size is whatever the file holds. Where
+// tiles overlap, later ones win. Pyramidal TIFF is not written, the image
+// crate has no encoder for tiled multi-page TIFF.
+//
+// A Deep Zoom pyramid (.dzi), as OpenSeadragon and other pan-and-zoom web
+// viewers read it, holds the image at every power-of-two scale down to a
+// single pixel, each cut into square tiles that share `overlap` pixels
+// with their neighbours so the viewer can blend the seams.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub width: u32,
+    pub height: u32,
+    pub tiles: Vec<Placement>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Placement {
+    pub file: String,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Manifest {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let manifest: Manifest = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        if manifest.width == 0 || manifest.height == 0 {
+            return Err("the image needs a width and height of at least 1".to_string());
+        }
+        if manifest.tiles.is_empty() {
+            return Err("no tiles".to_string());
+        }
+        if let Some(tile) = manifest.tiles.iter().find(|tile| tile.x >= manifest.width || tile.y >= manifest.height) {
+            return Err(format!("{} starts outside the image", tile.file));
+        }
+        Ok(manifest)
+    }
+}
+
+// A tile of one pyramid level: its column and row, and the pixels it covers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PyramidTile {
+    pub column: u32,
+    pub row: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeepZoom {
+    pub tile_size: u32,
+    pub overlap: u32,
+}
+
+impl DeepZoom {
+    // Level 0 is one pixel, the highest level the full image
+    pub fn max_level(width: u32, height: u32) -> u32 {
+        width.max(height).next_power_of_two().trailing_zeros()
+    }
+
+    // Size of the image at `level`, halved and rounded up once per level
+    // below the top
+    pub fn level_size(width: u32, height: u32, level: u32) -> (u32, u32) {
+        let shift = Self::max_level(width, height) - level;
+        (width.div_ceil(1 << shift), height.div_ceil(1 << shift))
+    }
+
+    // The tiles of an image of this size, row by row
+    pub fn tiles(self, width: u32, height: u32) -> Vec<PyramidTile> {
+        let span = |index: u32, length: u32| {
+            let start = (index * self.tile_size).saturating_sub(self.overlap);
+            let end = ((index + 1) * self.tile_size + self.overlap).min(length);
+            (start, end - start)
+        };
+        let (columns, rows) = (width.div_ceil(self.tile_size), height.div_ceil(self.tile_size));
+        (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                let ((x, width), (y, height)) = (span(column, width), span(row, height));
+                PyramidTile { column, row, x, y, width, height }
+            })
+            .collect()
+    }
+
+    // The .dzi descriptor of an image of this size with tiles in `format`
+    pub fn descriptor(self, width: u32, height: u32, format: &str) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" TileSize=\"{}\" Overlap=\"{}\" Format=\"{}\">\n  \
+             <Size Width=\"{}\" Height=\"{}\"/>\n\
+             </Image>\n",
+            self.tile_size, self.overlap, format, width, height
+        )
+    }
+}
+
+// The next level down: every pixel the mean of the 2×2 block above it,
+// or of what is left of it at an odd right or bottom edge
+pub fn halve(image: &RgbImage, blending: Blending) -> RgbImage {
+    let (width, height) = (image.width().div_ceil(2), image.height().div_ceil(2));
+    let mut pixels = vec![0u8; (width * height * 3) as usize];
+    pixels.par_chunks_mut((width * 3) as usize).enumerate().for_each(|(y, row)| {
+        let y = y as u32;
+        for (x, rgb) in row.chunks_exact_mut(3).enumerate() {
+            let x = x as u32;
+            let block = (2 * y..(2 * y + 2).min(image.height()))
+                .flat_map(|sy| (2 * x..(2 * x + 2).min(image.width())).map(move |sx| (sx, sy)))
+                .map(|(sx, sy)| *image.get_pixel(sx, sy));
+            rgb.copy_from_slice(&average_color(block, blending).0);
+        }
+    });
+    RgbImage::from_raw(width, height, pixels).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn rejects_bad_manifests() {
+        let manifest = Manifest::parse(r#"{"width": 10, "height": 5, "tiles": [{"file": "a.png", "x": 5, "y": 0}]}"#).unwrap();
+        assert_eq!(manifest.tiles[0], Placement { file: "a.png".to_string(), x: 5, y: 0 });
+        assert_eq!(Manifest::parse(r#"{"width": 10, "height": 5, "tiles": []}"#).unwrap_err(), "no tiles");
+        let outside = r#"{"width": 10, "height": 5, "tiles": [{"file": "a.png", "x": 0, "y": 5}]}"#;
+        assert_eq!(Manifest::parse(outside).unwrap_err(), "a.png starts outside the image");
+    }
+
+    #[test]
+    fn pyramid_levels_and_tiles() {
+        assert_eq!(DeepZoom::max_level(1, 1), 0);
+        assert_eq!(DeepZoom::max_level(1000, 600), 10);
+        assert_eq!(DeepZoom::level_size(1000, 600, 10), (1000, 600));
+        assert_eq!(DeepZoom::level_size(1000, 600, 9), (500, 300));
+        assert_eq!(DeepZoom::level_size(1000, 600, 1), (2, 2));
+        assert_eq!(DeepZoom::level_size(1000, 600, 0), (1, 1));
+
+        let tiles = DeepZoom { tile_size: 254, overlap: 1 }.tiles(500, 300);
+        assert_eq!(tiles.len(), 4);
+        assert_eq!(tiles[0], PyramidTile { column: 0, row: 0, x: 0, y: 0, width: 255, height: 255 });
+        assert_eq!(tiles[1], PyramidTile { column: 1, row: 0, x: 253, y: 0, width: 247, height: 255 });
+        assert_eq!(tiles[3], PyramidTile { column: 1, row: 1, x: 253, y: 253, width: 247, height: 47 });
+    }
+
+    #[test]
+    fn halving_averages_blocks() {
+        let image = RgbImage::from_fn(3, 2, |x, _| if x == 0 { Rgb([255, 255, 255]) } else { Rgb([0, 0, 0]) });
+        let half = halve(&image, Blending::Srgb);
+        assert_eq!(half.dimensions(), (2, 1));
+        assert_eq!(*half.get_pixel(0, 0), Rgb([128, 128, 128]));
+        assert_eq!(*half.get_pixel(1, 0), Rgb([0, 0, 0]));
+    }
+}
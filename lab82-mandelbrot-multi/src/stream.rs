@@ -0,0 +1,48 @@
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{ BufWriter, Write };
+
+use crate::interrupt;
+
+// Streaming output for images too large to hold in memory.
+//
+// The image is rendered in horizontal bands of `band_rows` rows. Each band
+// is rendered in parallel into a reusable RGB buffer and handed straight to
+// the PNG encoder, so memory use is bounded by one band regardless of the
+// image size. After Ctrl-C the remaining bands are written out black, which
+// keeps the PNG valid.
+pub fn render_png(
+    path: &str,
+    width: u32,
+    height: u32,
+    band_rows: u32,
+    metadata: &[(&str, String)],
+    render_row: impl Fn(u32, &mut [u8]) + Sync,
+) -> Result<(), png::EncodingError> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (keyword, text) in metadata {
+        encoder.add_text_chunk(keyword.to_string(), text.clone())?;
+    }
+    let mut writer = encoder.write_header()?.into_stream_writer()?;
+
+    let row_len = (width * 3) as usize;
+    let band_rows = band_rows.max(1);
+    let mut band = vec![0u8; band_rows as usize * row_len];
+    for y0 in (0..height).step_by(band_rows as usize) {
+        let rows = band_rows.min(height - y0);
+        let band = &mut band[..rows as usize * row_len];
+        if interrupt::requested() {
+            band.fill(0);
+        } else {
+            band.par_chunks_mut(row_len)
+                .enumerate()
+                .for_each(|(r, row)| render_row(y0 + r as u32, row));
+        }
+        writer.write_all(band)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
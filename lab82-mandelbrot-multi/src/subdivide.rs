@@ -0,0 +1,160 @@
+use std::sync::atomic::{ AtomicU32, AtomicU64, Ordering };
+
+use crate::interrupt;
+
+// Mariani-Silver rectangle subdivision.
+//
+// Only the border of a rectangle is iterated. If every border pixel has the
+// same escape value the whole rectangle is filled with it, otherwise it is
+// split in two along its longer side and both halves are handled in
+// parallel. Since the Mandelbrot set and its escape-time bands are simply
+// connected, a uniform border cannot enclose anything else. The catch is
+// sampling: a filament thinner than a pixel can slip between border pixels,
+// so a handful of pixels may differ from a full render. Smooth values are
+// almost never uniform, so there it mostly saves the interior.
+//
+// Rectangles are inclusive and neighbours share their dividing row or column,
+// so every border pixel is evaluated once and then looked up.
+const MIN_SIZE: u32 = 4;
+
+struct Grid {
+    width: u32,
+    // f32 bits, NaN until a pixel has been evaluated or filled
+    values: Vec<AtomicU32>,
+    evaluated: AtomicU64,
+}
+
+impl Grid {
+    fn get(&self, x: u32, y: u32, eval: &(impl Fn(u32, u32) -> f32 + Sync)) -> f32 {
+        let cell = &self.values[(y * self.width + x) as usize];
+        let value = f32::from_bits(cell.load(Ordering::Relaxed));
+        if !value.is_nan() {
+            return value;
+        }
+        let value = eval(x, y);
+        cell.store(value.to_bits(), Ordering::Relaxed);
+        self.evaluated.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    fn set(&self, x: u32, y: u32, value: f32) {
+        self.values[(y * self.width + x) as usize].store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+// Escape values of a `width`×`height` image, row-major, plus the number of
+// pixels that actually had to be evaluated. After Ctrl-C the unfinished
+// pixels are left NaN.
+pub fn render(width: u32, height: u32, eval: impl Fn(u32, u32) -> f32 + Sync) -> (Vec<f32>, u64) {
+    let grid = Grid {
+        width,
+        values: (0..width as u64 * height as u64).map(|_| AtomicU32::new(f32::NAN.to_bits())).collect(),
+        evaluated: AtomicU64::new(0),
+    };
+    if width > 0 && height > 0 {
+        subdivide(&grid, (0, 0, width - 1, height - 1), &eval);
+    }
+    let values = grid.values.into_iter().map(|cell| f32::from_bits(cell.into_inner())).collect();
+    (values, grid.evaluated.into_inner())
+}
+
+fn subdivide(grid: &Grid, (x0, y0, x1, y1): (u32, u32, u32, u32), eval: &(impl Fn(u32, u32) -> f32 + Sync)) {
+    if interrupt::requested() {
+        return;
+    }
+    if x1 - x0 < MIN_SIZE || y1 - y0 < MIN_SIZE {
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                grid.get(x, y, eval);
+            }
+        }
+        return;
+    }
+
+    let first = grid.get(x0, y0, eval);
+    let mut uniform = true;
+    for x in x0..=x1 {
+        uniform &= grid.get(x, y0, eval) == first;
+        uniform &= grid.get(x, y1, eval) == first;
+    }
+    for y in y0 + 1..y1 {
+        uniform &= grid.get(x0, y, eval) == first;
+        uniform &= grid.get(x1, y, eval) == first;
+    }
+
+    if uniform {
+        for y in y0 + 1..y1 {
+            for x in x0 + 1..x1 {
+                grid.set(x, y, first);
+            }
+        }
+    } else if x1 - x0 >= y1 - y0 {
+        let mid = (x0 + x1) / 2;
+        rayon::join(
+            || subdivide(grid, (x0, y0, mid, y1), eval),
+            || subdivide(grid, (mid, y0, x1, y1), eval),
+        );
+    } else {
+        let mid = (y0 + y1) / 2;
+        rayon::join(
+            || subdivide(grid, (x0, y0, x1, mid), eval),
+            || subdivide(grid, (x0, mid, x1, y1), eval),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brute_force(width: u32, height: u32, eval: impl Fn(u32, u32) -> f32) -> Vec<f32> {
+        (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| eval(x, y)).collect()
+    }
+
+    // Integer escape counts of the default view, like `--iterations 200`
+    fn mandelbrot(width: u32, height: u32) -> impl Fn(u32, u32) -> f32 + Sync {
+        move |x, y| {
+            let cx = -2.0 + 3.0 * x as f64 / width as f64;
+            let cy = -1.0 + 2.0 * y as f64 / height as f64;
+            let (mut zx, mut zy, mut iteration) = (0.0f64, 0.0f64, 0);
+            while iteration < 200 && zx * zx + zy * zy <= 4.0 {
+                (zx, zy) = (zx * zx - zy * zy + cx, 2.0 * zx * zy + cy);
+                iteration += 1;
+            }
+            iteration as f32
+        }
+    }
+
+    #[test]
+    fn constant_image_only_evaluates_the_border() {
+        let (values, evaluated) = render(64, 48, |_, _| 7.0);
+        assert!(values.iter().all(|&value| value == 7.0));
+        assert_eq!(evaluated, 2 * 64 + 2 * 46);
+    }
+
+    #[test]
+    fn disk_matches_brute_force() {
+        let disk = |x: u32, y: u32| {
+            let (dx, dy) = (x as f32 - 40.0, y as f32 - 25.0);
+            if dx * dx + dy * dy < 400.0 { 100.0 } else { (dx.abs() + dy.abs()).floor() }
+        };
+        let (values, _) = render(90, 60, disk);
+        assert_eq!(values, brute_force(90, 60, disk));
+    }
+
+    #[test]
+    fn mandelbrot_matches_brute_force_with_fewer_evaluations() {
+        let (width, height) = (150, 100);
+        let (values, evaluated) = render(width, height, mandelbrot(width, height));
+        assert_eq!(values, brute_force(width, height, mandelbrot(width, height)));
+        assert!(evaluated < (width * height) as u64 * 3 / 4, "evaluated {} pixels", evaluated);
+    }
+
+    #[test]
+    fn covers_small_and_odd_sizes() {
+        for (width, height) in [(1, 1), (1, 9), (9, 1), (5, 5), (17, 3), (33, 31)] {
+            let (values, _) = render(width, height, |x, y| (x * 31 + y) as f32);
+            assert_eq!(values, brute_force(width, height, |x, y| (x * 31 + y) as f32));
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use image::RgbImage;
+use std::fmt::Write;
+
+// Images drawn straight into the terminal.
+//
+// `half_blocks` packs two pixels into each character cell: the upper half
+// block "▀" takes the top pixel as its foreground color and the bottom pixel
+// as its background, using 24-bit ANSI colors. Cells are about twice as
+// tall as they are wide, so the pixels come out roughly square.
+//
+// Terminals with a graphics protocol can show real pixels instead, see
+// `Protocol`.
+
+// Inline image protocols for `--inline`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    // kitty graphics protocol: base64 RGB data in APC escapes
+    Kitty,
+    // DEC sixel, quantized to a 6×6×6 color cube
+    Sixel,
+}
+
+impl Protocol {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "kitty" => Some(Protocol::Kitty),
+            "sixel" => Some(Protocol::Sixel),
+            _ => None,
+        }
+    }
+
+    pub fn encode(self, image: &RgbImage) -> String {
+        match self {
+            Protocol::Kitty => kitty(image),
+            Protocol::Sixel => sixel(image),
+        }
+    }
+}
+
+// Width of the terminal in character cells, from $COLUMNS when the shell
+// exports it
+pub fn columns() -> u32 {
+    std::env::var("COLUMNS").ok().and_then(|value| value.parse().ok()).filter(|&n| n > 0).unwrap_or(80)
+}
+
+// `pixel(x, y)` for a `width`×`height` image, as lines of escape sequences
+pub fn half_blocks(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 3]) -> String {
+    let mut text = String::new();
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let [r, g, b] = pixel(x, y);
+            write!(text, "\x1b[38;2;{};{};{}m", r, g, b).unwrap();
+            if y + 1 < height {
+                let [r, g, b] = pixel(x, y + 1);
+                write!(text, "\x1b[48;2;{};{};{}m", r, g, b).unwrap();
+            }
+            text.push('▀');
+        }
+        text.push_str("\x1b[0m\n");
+    }
+    text
+}
+
+// Payload chunks may be at most 4096 bytes; every chunk but the last says
+// `m=1`, and only the first carries the image keys
+fn kitty(image: &RgbImage) -> String {
+    let data = base64(image.as_raw());
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+    let mut text = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        if i == 0 {
+            write!(text, "\x1b_Ga=T,f=24,s={},v={},m={};", image.width(), image.height(), more).unwrap();
+        } else {
+            write!(text, "\x1b_Gm={};", more).unwrap();
+        }
+        text.push_str(std::str::from_utf8(chunk).unwrap());
+        text.push_str("\x1b\\");
+    }
+    text.push('\n');
+    text
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for group in bytes.chunks(3) {
+        let n = (group[0] as u32) << 16 | (*group.get(1).unwrap_or(&0) as u32) << 8 | *group.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= group.len() {
+                text.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+// Each band of six rows is drawn once per color present in it: "#n" selects
+// the color, then one character per column holds the six-pixel mask (63 +
+// bits, run-length coded as "!count"), "$" returns to the band start and "-"
+// moves to the next band.
+fn sixel(image: &RgbImage) -> String {
+    let level = |c: u8| (c as u32 * 5 + 127) / 255;
+    let index = |[r, g, b]: [u8; 3]| (level(r) * 36 + level(g) * 6 + level(b)) as usize;
+    let (width, height) = image.dimensions();
+
+    let mut text = format!("\x1bPq\"1;1;{};{}", width, height);
+    for i in 0..216u32 {
+        let percent = |level: u32| level * 20;
+        write!(text, "#{};2;{};{};{}", i, percent(i / 36), percent(i / 6 % 6), percent(i % 6)).unwrap();
+    }
+    for y0 in (0..height).step_by(6) {
+        let rows = (height - y0).min(6);
+        let mut masks = vec![vec![0u8; width as usize]; 216];
+        let mut used = [false; 216];
+        for dy in 0..rows {
+            for x in 0..width {
+                let color = index(image.get_pixel(x, y0 + dy).0);
+                masks[color][x as usize] |= 1 << dy;
+                used[color] = true;
+            }
+        }
+        let mut first = true;
+        for color in (0..216).filter(|&color| used[color]) {
+            if !first {
+                text.push('$');
+            }
+            first = false;
+            write!(text, "#{}", color).unwrap();
+            let mask = &masks[color];
+            let mut x = 0;
+            while x < mask.len() {
+                let run = mask[x..].iter().take_while(|&&m| m == mask[x]).count();
+                let ch = (63 + mask[x]) as char;
+                if run > 3 {
+                    write!(text, "!{}{}", run, ch).unwrap();
+                } else {
+                    (0..run).for_each(|_| text.push(ch));
+                }
+                x += run;
+            }
+        }
+        text.push('-');
+    }
+    text.push_str("\x1b\\\n");
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_pads_partial_groups() {
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+        assert_eq!(base64(b""), "");
+    }
+}
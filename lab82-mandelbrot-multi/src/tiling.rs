@@ -0,0 +1,162 @@
+// Tile layout and traversal order of the first pass.
+//
+// Tiles are numbered row-major over the tile grid; that number is what the
+// checkpoint records. The traversal order only decides in which sequence
+// the tiles are handed to the workers. Row-major uses full-width bands,
+// exactly like a plain top-to-bottom render. The space-filling curves use
+// square tiles and walk them so that consecutive tiles are neighbours, which
+// keeps the working set compact and makes a partial render grow as a blob
+// instead of a stripe.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Order {
+    RowMajor,
+    ZOrder,
+    Hilbert,
+}
+
+impl Order {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "row-major" | "rows" => Some(Order::RowMajor),
+            "z-order" | "morton" => Some(Order::ZOrder),
+            "hilbert" => Some(Order::Hilbert),
+            _ => None,
+        }
+    }
+}
+
+// A rectangle of pixels, clipped to the image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub x0: u32,
+    pub y0: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    pub fn samples(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+}
+
+pub struct Layout {
+    image_width: u32,
+    image_height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    columns: u32,
+    rows: u32,
+}
+
+impl Layout {
+    pub fn new(image_width: u32, image_height: u32, tile_width: u32, tile_height: u32) -> Self {
+        let (tile_width, tile_height) = (tile_width.clamp(1, image_width.max(1)), tile_height.max(1));
+        Layout {
+            image_width,
+            image_height,
+            tile_width,
+            tile_height,
+            columns: image_width.div_ceil(tile_width),
+            rows: image_height.div_ceil(tile_height),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+
+    pub fn tile(&self, index: usize) -> Tile {
+        let (column, row) = (index as u32 % self.columns, index as u32 / self.columns);
+        let (x0, y0) = (column * self.tile_width, row * self.tile_height);
+        Tile {
+            x0,
+            y0,
+            width: self.tile_width.min(self.image_width - x0),
+            height: self.tile_height.min(self.image_height - y0),
+        }
+    }
+
+    // Every tile index exactly once, in traversal order. The curves are laid
+    // over the smallest power-of-two square covering the grid and the cells
+    // outside it are skipped.
+    pub fn traverse(&self, order: Order) -> Vec<usize> {
+        let side = self.columns.max(self.rows).next_power_of_two();
+        let cell = |d: u32| match order {
+            Order::RowMajor => (d % self.columns, d / self.columns),
+            Order::ZOrder => (compact_bits(d), compact_bits(d >> 1)),
+            Order::Hilbert => hilbert(side, d),
+        };
+        let cells = if order == Order::RowMajor { self.columns * self.rows } else { side * side };
+        (0..cells)
+            .map(cell)
+            .filter(|&(x, y)| x < self.columns && y < self.rows)
+            .map(|(x, y)| (y * self.columns + x) as usize)
+            .collect()
+    }
+}
+
+// Even bits of `d` packed together: the x half of a Morton code
+fn compact_bits(d: u32) -> u32 {
+    let mut x = d & 0x5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333;
+    x = (x | (x >> 2)) & 0x0f0f_0f0f;
+    x = (x | (x >> 4)) & 0x00ff_00ff;
+    (x | (x >> 8)) & 0x0000_ffff
+}
+
+// Cell at distance `d` along the Hilbert curve filling a `side`×`side`
+// square, `side` a power of two
+fn hilbert(side: u32, d: u32) -> (u32, u32) {
+    let (mut x, mut y, mut t) = (0, 0, d);
+    let mut s = 1;
+    while s < side {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_order_visits_each_tile_once() {
+        let layout = Layout::new(1920, 1080, 64, 64);
+        for order in [Order::RowMajor, Order::ZOrder, Order::Hilbert] {
+            let mut tiles = layout.traverse(order);
+            tiles.sort();
+            assert_eq!(tiles, (0..layout.count()).collect::<Vec<_>>(), "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn hilbert_steps_to_a_neighbour() {
+        let layout = Layout::new(512, 512, 32, 32);
+        let cells: Vec<Tile> = layout.traverse(Order::Hilbert).into_iter().map(|i| layout.tile(i)).collect();
+        for pair in cells.windows(2) {
+            let distance = pair[0].x0.abs_diff(pair[1].x0) + pair[0].y0.abs_diff(pair[1].y0);
+            assert_eq!(distance, 32);
+        }
+    }
+
+    #[test]
+    fn edge_tiles_are_clipped() {
+        let layout = Layout::new(100, 50, 64, 16);
+        assert_eq!(layout.count(), 2 * 4);
+        assert_eq!(layout.tile(7), Tile { x0: 64, y0: 48, width: 36, height: 2 });
+    }
+}
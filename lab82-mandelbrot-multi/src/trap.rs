@@ -0,0 +1,101 @@
+use num_complex::Complex;
+
+use crate::fractal::Fractal;
+
+// Orbit traps for `--trap`.
+//
+// Instead of the escape iteration, a sample is colored by how close its
+// orbit came to a shape in the plane: a point, a line or a circle. Every
+// point that is not interior gets its orbit run to escape (or the iteration
+// cap) while the smallest distance to the trap is tracked, so the trap
+// shows up as repeated, distorted copies all over the image, inside the set
+// as well as outside.
+
+// How fast the palette position rises with the trap distance. Distance 0
+// is the start of the palette; at 1/FALLOFF about two thirds of it is used.
+const FALLOFF: f64 = 4.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    Point,
+    // Through the trap center, `direction` a unit vector along it
+    Line { direction: Complex<f64> },
+    Circle { radius: f64 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Trap {
+    pub shape: Shape,
+    pub center: Complex<f64>,
+}
+
+impl Trap {
+    // `angle` (degrees from the real axis) only applies to lines and
+    // `radius` only to circles
+    pub fn new(kind: &str, center: Complex<f64>, radius: f64, angle: f64) -> Option<Self> {
+        let shape = match kind {
+            "point" => Shape::Point,
+            "line" => Shape::Line { direction: Complex::from_polar(1.0, angle.to_radians()) },
+            "circle" => Shape::Circle { radius },
+            _ => return None,
+        };
+        Some(Trap { shape, center })
+    }
+
+    pub fn distance(&self, z: Complex<f64>) -> f64 {
+        let offset = z - self.center;
+        match self.shape {
+            Shape::Point => offset.norm(),
+            // The component of the offset across the line
+            Shape::Line { direction } => (offset * direction.conj()).im.abs(),
+            Shape::Circle { radius } => (offset.norm() - radius).abs(),
+        }
+    }
+
+    // Smallest trap distance along the orbit of c, from z₁ = c until the
+    // orbit escapes or `max_iterations` steps are done
+    pub fn orbit_distance<F: Fractal>(&self, fractal: &F, c: Complex<f64>, max_iterations: u32) -> f64 {
+        let mut z = Complex::new(0.0, 0.0);
+        let mut closest = f64::INFINITY;
+        for _ in 0..max_iterations {
+            z = fractal.step(z, c);
+            if z.norm_sqr() > 4.0 || z.is_nan() {
+                break;
+            }
+            closest = closest.min(self.distance(z));
+        }
+        closest
+    }
+}
+
+// Palette position in 0.0..1.0 for a trap distance
+pub fn position(distance: f64) -> f32 {
+    (1.0 - (-distance * FALLOFF).exp()).min(0.999) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fractal::Mandelbrot;
+
+    #[test]
+    fn distances_to_each_shape() {
+        let center = Complex::new(1.0, 1.0);
+        let z = Complex::new(4.0, 5.0);
+        assert_eq!(Trap::new("point", center, 0.0, 0.0).unwrap().distance(z), 5.0);
+        assert_eq!(Trap::new("circle", center, 2.0, 0.0).unwrap().distance(z), 3.0);
+        assert!((Trap::new("line", center, 0.0, 0.0).unwrap().distance(z) - 4.0).abs() < 1e-12);
+        assert!((Trap::new("line", center, 0.0, 90.0).unwrap().distance(z) - 3.0).abs() < 1e-12);
+        assert_eq!(Trap::new("square", center, 0.0, 0.0), None);
+    }
+
+    #[test]
+    fn orbit_distance_follows_the_orbit() {
+        // c = -1 cycles between -1 and 0 and never escapes
+        let trap = Trap::new("point", Complex::new(0.0, 0.5), 0.0, 0.0).unwrap();
+        assert_eq!(trap.orbit_distance(&Mandelbrot, Complex::new(-1.0, 0.0), 100), 0.5);
+        // c = 3 escapes on its first step, so no point is ever measured
+        assert_eq!(trap.orbit_distance(&Mandelbrot, Complex::new(3.0, 0.0), 100), f64::INFINITY);
+        assert!(position(f64::INFINITY) < 1.0);
+    }
+}
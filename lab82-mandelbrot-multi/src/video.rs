@@ -0,0 +1,57 @@
+use std::io::{ self, BufWriter, Write };
+use std::process::{ Child, ChildStdin, Command, Stdio };
+
+// Video output through an `ffmpeg` subprocess.
+//
+// Frames are piped to ffmpeg as raw RGB24 and encoded with H.264 (VP9 for
+// .webm) in yuv420p, which plays nearly everywhere. yuv420p needs even
+// dimensions, so odd sizes are padded by one pixel.
+pub const EXTENSIONS: [&str; 4] = ["mp4", "mkv", "mov", "webm"];
+
+pub fn is_video_path(path: &str) -> bool {
+    std::path::Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+pub struct VideoEncoder {
+    child: Child,
+    stdin: BufWriter<ChildStdin>,
+}
+
+impl VideoEncoder {
+    pub fn start(path: &str, width: u32, height: u32, fps: f64) -> io::Result<Self> {
+        let codec = if path.to_ascii_lowercase().ends_with(".webm") { "libvpx-vp9" } else { "libx264" };
+        let mut child = Command::new("ffmpeg")
+            .args(["-loglevel", "error", "-y"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgb24"])
+            .args(["-s", &format!("{}x{}", width, height)])
+            .args(["-r", &fps.to_string()])
+            .args(["-i", "-"])
+            .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"])
+            .args(["-c:v", codec, "-pix_fmt", "yuv420p"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("failed to start ffmpeg ({}), is it installed?", e)))?;
+        let stdin = BufWriter::new(child.stdin.take().unwrap());
+        Ok(VideoEncoder { child, stdin })
+    }
+
+    // One frame of tightly packed RGB24 pixels
+    pub fn write_frame(&mut self, rgb: &[u8]) -> io::Result<()> {
+        self.stdin.write_all(rgb)
+    }
+
+    // Closes the pipe and waits for ffmpeg to finish the file
+    pub fn finish(self) -> io::Result<()> {
+        let VideoEncoder { mut child, stdin } = self;
+        drop(stdin.into_inner().map_err(|e| e.into_error())?);
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("ffmpeg exited with {}", status)));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,326 @@
+use std::path::{ Path, PathBuf };
+use std::process::Command;
+
+// Golden-image regression tests.
+//
+// Every case renders a small, fixed view with the real binary and compares
+// the pixels with a reference PNG checked in under tests/golden/. The
+// comparison allows for the last-bit differences that other compilers or
+// CPUs can introduce, which flip a few pixels on the boundary of the set
+// or shift colors by a step or two, but not for a changed image.
+//
+// After an intended change of the output, refresh the references with
+//
+//   UPDATE_GOLDEN=1 cargo test --test golden
+//
+// and look over the new images before committing them.
+const SIZE: &str = "72x48";
+const ITERATIONS: &str = "100";
+
+// A channel off by more than this makes the pixel count as different
+const CHANNEL_TOLERANCE: u8 = 8;
+// Share of the pixels that may differ
+const PIXEL_TOLERANCE: f64 = 0.01;
+// Mean difference per channel over the whole image, in 0..255 steps
+const MEAN_TOLERANCE: f64 = 1.0;
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+fn output_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("golden_{}_{}.png", name, std::process::id()))
+}
+
+// Renders the fixed view with `args` added to `output`
+fn run(name: &str, output: &Path, args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_lab82-mandelbrot-multi"))
+        .args(["--size", SIZE, "--iterations", ITERATIONS, "--output", output.to_str().unwrap()])
+        .args(args)
+        .current_dir(golden_dir())
+        .output()
+        .unwrap();
+    assert!(status.status.success(), "{} failed: {}", name, String::from_utf8_lossy(&status.stderr));
+}
+
+fn render(name: &str, args: &[&str]) -> image::RgbImage {
+    let output = output_path(name);
+    run(name, &output, args);
+    let rendered = image::open(&output).unwrap().to_rgb8();
+    std::fs::remove_file(&output).unwrap();
+    rendered
+}
+
+fn check(name: &str, args: &[&str]) {
+    let rendered = render(name, args);
+    let reference_path = golden_dir().join(format!("{}.png", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        rendered.save(&reference_path).unwrap();
+        return;
+    }
+    let reference = image::open(&reference_path)
+        .unwrap_or_else(|e| panic!("no reference for {} ({}), render it with UPDATE_GOLDEN=1", name, e))
+        .to_rgb8();
+    assert_eq!(rendered.dimensions(), reference.dimensions(), "{} changed size", name);
+
+    let (mut differing, mut total) = (0usize, 0u64);
+    for (a, b) in rendered.pixels().zip(reference.pixels()) {
+        let diffs = [0, 1, 2].map(|channel| a[channel].abs_diff(b[channel]));
+        differing += diffs.iter().any(|&diff| diff > CHANNEL_TOLERANCE) as usize;
+        total += diffs.iter().map(|&diff| diff as u64).sum::<u64>();
+    }
+    let pixels = rendered.pixels().len();
+    let mean = total as f64 / (pixels * 3) as f64;
+    assert!(
+        differing as f64 <= pixels as f64 * PIXEL_TOLERANCE && mean <= MEAN_TOLERANCE,
+        "{} differs from its reference: {} of {} pixels, mean difference {:.2}",
+        name,
+        differing,
+        pixels,
+        mean
+    );
+}
+
+#[test]
+fn mandelbrot() {
+    check("mandelbrot", &[]);
+}
+
+#[test]
+fn mandelbrot_smooth() {
+    check("mandelbrot_smooth", &["--smooth", "--colormap", "viridis"]);
+}
+
+#[test]
+fn mandelbrot_histogram() {
+    check("mandelbrot_histogram", &["--histogram"]);
+}
+
+#[test]
+fn burning_ship() {
+    check("burning_ship", &["--fractal", "burning-ship", "--smooth"]);
+}
+
+#[test]
+fn tricorn() {
+    check("tricorn", &["--fractal", "tricorn", "--smooth"]);
+}
+
+#[test]
+fn celtic() {
+    check("celtic", &["--fractal", "celtic", "--smooth"]);
+}
+
+#[test]
+fn perpendicular_burning_ship() {
+    check("perpendicular_burning_ship", &["--fractal", "perpendicular-burning-ship", "--smooth"]);
+}
+
+#[test]
+fn multibrot() {
+    check("multibrot", &["--power", "3", "--smooth"]);
+}
+
+#[test]
+fn multibrot_fractional() {
+    check("multibrot_fractional", &["--power", "2.5", "--smooth"]);
+}
+
+#[test]
+fn custom_formula() {
+    check("custom_formula", &["--formula", "z*z*z + c*z + c", "--histogram"]);
+}
+
+#[test]
+fn corner_sampling() {
+    check("corner_sampling", &["--corner-sampling"]);
+}
+
+#[test]
+fn escape_radius() {
+    check("escape_radius", &["--smooth", "--colormap", "viridis", "--escape-radius", "1e6"]);
+}
+
+// Close to the largest radius for degree 2, where the squared magnitude of
+// an escaping orbit still fits in f64
+#[test]
+fn escape_radius_huge() {
+    check("escape_radius_huge", &["--smooth", "--colormap", "viridis", "--escape-radius", "1e70"]);
+}
+
+#[test]
+fn escape_radius_overflowing() {
+    let output = Command::new(env!("CARGO_BIN_EXE_lab82-mandelbrot-multi")).args(["--escape-radius", "1e200", "--smooth"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--escape-radius must be at most"));
+}
+
+#[test]
+fn palette_cycle() {
+    check("palette_cycle", &["--smooth", "--palette-scale", "6", "--palette-offset", "0.3"]);
+}
+
+#[test]
+fn supersampled() {
+    check("supersampled", &["--ssaa", "3", "--seed", "7", "--smooth"]);
+}
+
+#[test]
+fn ordered_dither() {
+    check("ordered_dither", &["--smooth", "--colormap", "magma", "--dither", "ordered"]);
+}
+
+#[test]
+fn blue_noise_dither() {
+    check("blue_noise_dither", &["--smooth", "--colormap", "magma", "--dither", "blue-noise"]);
+}
+
+#[test]
+fn srgb_blending() {
+    check("srgb_blending", &["--ssaa", "3", "--seed", "7", "--smooth", "--srgb-blending"]);
+}
+
+#[test]
+fn stripe_average() {
+    check("stripe_average", &["--stripe", "--colormap", "magma"]);
+}
+
+#[test]
+fn triangle_inequality_average() {
+    check("triangle_inequality_average", &["--tia", "--colormap", "magma"]);
+}
+
+#[test]
+fn point_trap() {
+    check("point_trap", &["--trap", "point", "--trap-center", "0,0"]);
+}
+
+#[test]
+fn line_trap() {
+    check("line_trap", &["--trap", "line", "--trap-angle", "30"]);
+}
+
+#[test]
+fn circle_trap() {
+    check("circle_trap", &["--trap", "circle", "--trap-radius", "0.5"]);
+}
+
+#[test]
+fn interior_angle() {
+    check("interior_angle", &["--interior", "angle", "--smooth"]);
+}
+
+#[test]
+fn interior_period() {
+    check("interior_period", &["--interior", "period", "--smooth"]);
+}
+
+#[test]
+fn interior_solid() {
+    check("interior_solid", &["--interior", "#204080", "--smooth"]);
+}
+
+#[test]
+fn color_script() {
+    check("color_script", &["--color-script", "bands.script"]);
+}
+
+// The same bands from a Rhai script match the same reference
+#[test]
+fn color_script_rhai() {
+    check("color_script", &["--color-script", "bands.rhai"]);
+}
+
+#[test]
+fn newton() {
+    check("newton", &["--newton", "1,0,0,-1"]);
+}
+
+#[test]
+fn lyapunov() {
+    check("lyapunov", &["--lyapunov", "AB"]);
+}
+
+#[test]
+fn quaternion() {
+    check("quaternion", &["--quaternion", "-0.2,0.8,0,0", "--quaternion-slice", "0.3,0"]);
+}
+
+#[test]
+fn quaternion_projection() {
+    check("quaternion_projection", &["--quaternion", "-0.2,0.8,0,0", "--quaternion-projection", "16"]);
+}
+
+#[test]
+fn iim() {
+    check("iim", &["--iim", "-0.12,0.75", "--iim-points", "131072", "--seed", "3"]);
+}
+
+#[test]
+fn phoenix() {
+    check("phoenix", &["--fractal", "phoenix"]);
+}
+
+#[test]
+fn nova() {
+    check("nova", &["--fractal", "nova"]);
+}
+
+#[test]
+fn magnet() {
+    check("magnet", &["--fractal", "magnet1"]);
+}
+
+#[test]
+fn collatz() {
+    check("collatz", &["--fractal", "collatz"]);
+}
+
+#[test]
+fn buddhabrot() {
+    check("buddhabrot", &["--buddhabrot", "65536", "--seed", "3"]);
+}
+
+#[test]
+fn nebulabrot() {
+    check("nebulabrot", &["--buddhabrot", "65536", "--nebulabrot", "100,50,20", "--seed", "3"]);
+}
+
+// Views centered on the real axis render only the upper half and mirror
+// it; a center a hair off the axis renders every row, which must give the
+// same image
+fn check_mirroring(name: &str, args: &[&str]) {
+    let mirrored = render(name, &[&["--center", "-0.5,0"], args].concat());
+    let unmirrored = render(&format!("{}_unmirrored", name), &[&["--center", "-0.5,1e-300"], args].concat());
+    let differing = mirrored.pixels().zip(unmirrored.pixels()).filter(|(a, b)| a != b).count();
+    assert_eq!(differing, 0, "{}: mirroring changed {} pixels", name, differing);
+}
+
+#[test]
+fn mirroring_center_sampling() {
+    check_mirroring("mirroring_center_sampling", &["--smooth"]);
+}
+
+#[test]
+fn mirroring_corner_sampling() {
+    check_mirroring("mirroring_corner_sampling", &["--smooth", "--corner-sampling"]);
+}
+
+#[test]
+fn mirroring_adaptive() {
+    check_mirroring("mirroring_adaptive", &["--smooth", "--ssaa", "2", "--adaptive-aa"]);
+}
+
+// A PNG carries every option that shapes its pixels, so `--from-image`
+// renders it again exactly, down to the supersampling jitter and 16 bits
+#[test]
+fn from_image_round_trip() {
+    let (original, reproduced) = (output_path("from_image"), output_path("from_image_again"));
+    run("from_image", &original, &["--smooth", "--ssaa", "3", "--adaptive-aa", "--aa-threshold", "8", "--seed", "5", "--depth", "16"]);
+    run("from_image_again", &reproduced, &["--from-image", original.to_str().unwrap()]);
+    let (a, b) = (image::open(&original).unwrap(), image::open(&reproduced).unwrap());
+    std::fs::remove_file(&original).unwrap();
+    std::fs::remove_file(&reproduced).unwrap();
+    assert_eq!(b.color(), image::ColorType::Rgb16);
+    assert!(a == b, "--from-image rendered a different image");
+}
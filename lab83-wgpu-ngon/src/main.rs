@@ -1,43 +1,395 @@
+use std::time::Instant;
 use winit::{
-    event::{ Event, WindowEvent },
-    event_loop::{ ControlFlow, EventLoop },
+    dpi::PhysicalSize,
+    event::{ ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent },
+    event_loop::{ ControlFlow, EventLoop, EventLoopWindowTarget },
     window::WindowBuilder,
 };
 
+mod adapter;
+mod overlay;
+mod painter;
+mod panel;
+mod particles;
+mod prism;
+mod recovery;
+mod reload;
+mod scene;
+mod screenshot;
+mod shape;
 mod state;
-use state::State;
+mod svg;
+mod tessellation;
+mod texture;
+mod timing;
+use state::{ Background, Geometry, Options, State, Tessellation };
+use tessellation::Stroke;
 
-fn main() {
-    let event_loop = EventLoop::new();
+const DEFAULT_SIDES: u32 = 6;
+const DEFAULT_MORPH: f32 = 0.3;
+// Size of the image `--headless` renders
+const HEADLESS_SIZE: PhysicalSize<u32> = PhysicalSize::new(800, 600);
+
+// The value following `flag`, ending the program with status 2 if there is none
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> String {
+    args.next().unwrap_or_else(|| {
+        eprintln!("Missing value for {}", flag);
+        std::process::exit(2);
+    })
+}
+
+// `--sides N` picks the starting polygon; Up and Down change it while running.
+// `--indexed` starts on the vertex and index buffer path; Space switches.
+// `--tessellation fan|lyon` picks how the indexed path's triangles are made; T switches.
+// `--stroke-width W` and `--stroke-join miter|round|bevel` shape lyon's outlines.
+// `--particles` starts with the particle swarm; Tab switches.
+// `--prism` starts with the polygons as 3D prisms, which the mouse turns about; X switches.
+// `--msaa N` draws with N samples per pixel, one of 1, 2, 4, 8 or 16.
+// `--texture PATH` loads the image of the texture fill and starts with it.
+// `--morph SECONDS` is how long a change of outline takes, 0 to snap.
+// `--import PATH` starts with the first outline in an SVG file; E exports the scene.
+// `--hot-reload` rebuilds the pipelines whenever src/shader.wgsl is saved.
+// `--no-push-constants` keeps to uniform buffers where push constants would do.
+// `--present-mode fifo|mailbox|immediate` picks how frames are shown; V cycles.
+// `--max-fps N` caps the frame rate; a still scene is only drawn when something changes.
+// `--windows N` opens N windows, each with a scene of its own; W opens another.
+// `--headless --output PATH` renders the first frame to a PNG, without a window.
+// `--backend vulkan|metal|dx12|gl` and `--adapter INDEX|NAME` choose the GPU;
+// `--list-adapters` prints the adapters `--adapter` picks from and exits.
+// `--surface-format srgb|linear` insists on either; sRGB is preferred anyway.
+// `--clear-color COLOR` sets the background, `--gradient COLOR` fades it from COLOR at the top.
+fn parse_args() -> (Options, bool, Option<String>, u32) {
+    let mut args = std::env::args().skip(1);
+    let mut hot_reload = false;
+    let mut windows = 1;
+    let mut headless = false;
+    let mut output = None;
+    let mut options = Options {
+        sides: DEFAULT_SIDES,
+        geometry: Geometry::Shader,
+        tessellation: Tessellation::Fan,
+        stroke: Stroke { width: tessellation::DEFAULT_STROKE_WIDTH, join: tessellation::DEFAULT_STROKE_JOIN },
+        particles: false,
+        prism: false,
+        sample_count: 1,
+        texture: None,
+        morph: DEFAULT_MORPH,
+        outlines: None,
+        push_constants: true,
+        present_mode: None,
+        backends: wgpu::Backends::all(),
+        adapter: None,
+        color_space: None,
+        max_fps: None,
+        background: Background { color: state::BACKGROUND, top: state::GRADIENT_TOP, gradient: false },
+    };
+    let mut list_adapters = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sides" => {
+                let raw = value(&mut args, &arg);
+                options.sides = raw.parse().ok().filter(|sides| (scene::MIN_SIDES..=scene::MAX_SIDES).contains(sides)).unwrap_or_else(|| {
+                    eprintln!("Invalid value for --sides: {} (expected {} to {})", raw, scene::MIN_SIDES, scene::MAX_SIDES);
+                    std::process::exit(2);
+                });
+            }
+            "--indexed" => options.geometry = Geometry::Indexed,
+            "--prism" => options.prism = true,
+            "--hot-reload" => hot_reload = true,
+            "--headless" => headless = true,
+            "--output" => output = Some(value(&mut args, &arg)),
+            "--particles" => options.particles = true,
+            "--no-push-constants" => options.push_constants = false,
+            "--msaa" => {
+                let raw = value(&mut args, &arg);
+                options.sample_count = raw.parse().ok().filter(|count: &u32| [1, 2, 4, 8, 16].contains(count)).unwrap_or_else(|| {
+                    eprintln!("Invalid value for --msaa: {} (expected 1, 2, 4, 8 or 16)", raw);
+                    std::process::exit(2);
+                });
+            }
+            "--present-mode" => {
+                let raw = value(&mut args, &arg);
+                options.present_mode = Some(state::PRESENT_MODES.into_iter().find(|&mode| state::present_mode_name(mode) == raw).unwrap_or_else(|| {
+                    eprintln!("Invalid value for --present-mode: {} (expected fifo, mailbox or immediate)", raw);
+                    std::process::exit(2);
+                }));
+            }
+            "--backend" => {
+                let raw = value(&mut args, &arg);
+                options.backends = adapter::parse_backend(&raw).unwrap_or_else(|| {
+                    eprintln!("Invalid value for --backend: {} (expected vulkan, metal, dx12 or gl)", raw);
+                    std::process::exit(2);
+                });
+            }
+            "--adapter" => options.adapter = Some(adapter::Choice::parse(&value(&mut args, &arg))),
+            "--list-adapters" => list_adapters = true,
+            "--surface-format" => {
+                let raw = value(&mut args, &arg);
+                options.color_space = Some(match raw.as_str() {
+                    "srgb" => state::ColorSpace::Srgb,
+                    "linear" => state::ColorSpace::Linear,
+                    _ => {
+                        eprintln!("Invalid value for --surface-format: {} (expected srgb or linear)", raw);
+                        std::process::exit(2);
+                    }
+                });
+            }
+            "--texture" => {
+                let path = value(&mut args, &arg);
+                options.texture = Some(texture::load(&path).unwrap_or_else(|e| {
+                    eprintln!("Failed to load texture {}: {}", path, e);
+                    std::process::exit(1);
+                }));
+            }
+            "--import" => {
+                let path = value(&mut args, &arg);
+                options.outlines = Some(svg::import(&path).unwrap_or_else(|e| {
+                    eprintln!("Failed to import {}: {}", path, e);
+                    std::process::exit(1);
+                }));
+            }
+            "--tessellation" => {
+                let raw = value(&mut args, &arg);
+                options.tessellation = match raw.as_str() {
+                    "fan" => Tessellation::Fan,
+                    "lyon" => Tessellation::Lyon,
+                    _ => {
+                        eprintln!("Invalid value for --tessellation: {} (expected fan or lyon)", raw);
+                        std::process::exit(2);
+                    }
+                };
+            }
+            "--stroke-width" => {
+                let raw = value(&mut args, &arg);
+                options.stroke.width = raw.parse().ok().filter(|width: &f32| width.is_finite() && *width > 0.0).unwrap_or_else(|| {
+                    eprintln!("Invalid value for --stroke-width: {} (expected a width above 0, the corners being {} out)", raw, scene::RADIUS);
+                    std::process::exit(2);
+                });
+            }
+            "--stroke-join" => {
+                let raw = value(&mut args, &arg);
+                options.stroke.join = tessellation::parse_join(&raw).unwrap_or_else(|| {
+                    eprintln!("Invalid value for --stroke-join: {} (expected miter, round or bevel)", raw);
+                    std::process::exit(2);
+                });
+            }
+            "--windows" => {
+                let raw = value(&mut args, &arg);
+                windows = raw.parse().ok().filter(|&count: &u32| count >= 1).unwrap_or_else(|| {
+                    eprintln!("Invalid value for --windows: {} (expected 1 or more)", raw);
+                    std::process::exit(2);
+                });
+            }
+            "--max-fps" => {
+                let raw = value(&mut args, &arg);
+                options.max_fps = Some(raw.parse().ok().filter(|fps: &f32| fps.is_finite() && *fps > 0.0).unwrap_or_else(|| {
+                    eprintln!("Invalid value for --max-fps: {} (expected frames per second, above 0)", raw);
+                    std::process::exit(2);
+                }));
+            }
+            "--clear-color" => {
+                let raw = value(&mut args, &arg);
+                options.background.color = svg::parse_color(&raw).unwrap_or_else(|| {
+                    eprintln!("Invalid value for --clear-color: {} (expected an SVG color, such as #334d4d or navy)", raw);
+                    std::process::exit(2);
+                });
+            }
+            "--gradient" => {
+                let raw = value(&mut args, &arg);
+                options.background.top = svg::parse_color(&raw).unwrap_or_else(|| {
+                    eprintln!("Invalid value for --gradient: {} (expected an SVG color, such as #334d4d or navy)", raw);
+                    std::process::exit(2);
+                });
+                options.background.gradient = true;
+            }
+            "--morph" => {
+                let raw = value(&mut args, &arg);
+                options.morph = raw.parse().ok().filter(|seconds: &f32| seconds.is_finite() && *seconds >= 0.0).unwrap_or_else(|| {
+                    eprintln!("Invalid value for --morph: {} (expected seconds, 0 or more)", raw);
+                    std::process::exit(2);
+                });
+            }
+            other => {
+                eprintln!("Unknown argument: {}", other);
+                std::process::exit(2);
+            }
+        }
+    }
+    // After the loop, so a --backend anywhere on the line narrows the list
+    if list_adapters {
+        adapter::list(options.backends);
+        std::process::exit(0);
+    }
+    if headless != output.is_some() {
+        eprintln!("--headless and --output go together");
+        std::process::exit(2);
+    }
+    (options, hot_reload, output, windows)
+}
+
+// Renders the scene the options start with to `output` and exits, for
+// checking the pipelines against a known image on machines without a display
+fn render_headless(options: &Options, output: &str) {
+    let state = pollster::block_on(State::headless(HEADLESS_SIZE, options));
+    let image = state.headless_frame().unwrap_or_else(|e| {
+        eprintln!("Failed to read back the frame: {}", e);
+        std::process::exit(1);
+    });
+    if let Err(e) = image.save(output) {
+        eprintln!("Failed to write {}: {}", output, e);
+        std::process::exit(1);
+    }
+}
+
+// A window and the state that draws its scene, from the same options as
+// the first one
+fn open_window(target: &EventLoopWindowTarget<()>, options: &Options) -> State {
     let window = WindowBuilder::new()
         .with_title("Hello N-Gon")
-        .build(&event_loop)
+        .build(target)
         .unwrap();
-    let mut state = pollster::block_on(State::new(window));
+    pollster::block_on(State::new(window, options))
+}
+
+// Hands a window event that the panel left to the scene of the window
+fn window_event(state: &mut State, event: WindowEvent) {
+    match event {
+        WindowEvent::Resized(physical_size) => {
+            state.resize(physical_size);
+        }
+        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+            state.resize(*new_inner_size);
+        }
+        WindowEvent::CursorMoved { position, .. } => state.cursor_moved(position),
+        WindowEvent::CursorLeft { .. } => state.cursor_left(),
+        WindowEvent::MouseInput { state: button_state, button, .. } => state.mouse_input(button, button_state),
+        WindowEvent::MouseWheel { delta, .. } => state.mouse_wheel(delta),
+        WindowEvent::KeyboardInput {
+            input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(key), .. },
+            ..
+        } => match key {
+            VirtualKeyCode::Up => state.change_sides(1),
+            VirtualKeyCode::Down => state.change_sides(-1),
+            VirtualKeyCode::Space => state.set_geometry(state.geometry().toggled()),
+            VirtualKeyCode::T => state.set_tessellation(state.tessellation().toggled()),
+            VirtualKeyCode::F => state.next_fill(),
+            VirtualKeyCode::C => state.next_color(),
+            VirtualKeyCode::Minus => state.adjust_opacity(-scene::OPACITY_STEP),
+            VirtualKeyCode::Equals => state.adjust_opacity(scene::OPACITY_STEP),
+            VirtualKeyCode::B => state.set_blend(state.blend().next()),
+            VirtualKeyCode::V => state.next_present_mode(),
+            VirtualKeyCode::S => state.toggle_star(),
+            VirtualKeyCode::K => state.next_star_step(),
+            VirtualKeyCode::LBracket => state.adjust_inner(-shape::INNER_STEP),
+            VirtualKeyCode::RBracket => state.adjust_inner(shape::INNER_STEP),
+            VirtualKeyCode::R => state.toggle_rounded(),
+            VirtualKeyCode::O => state.set_style(state.style().next()),
+            VirtualKeyCode::I => state.toggle_inertia(),
+            VirtualKeyCode::Tab => state.toggle_particles(),
+            VirtualKeyCode::X => state.toggle_prism(),
+            VirtualKeyCode::P => state.toggle_pause(),
+            VirtualKeyCode::Right => state.change_angular_velocity(scene::ANGULAR_VELOCITY_STEP),
+            VirtualKeyCode::Left => state.change_angular_velocity(-scene::ANGULAR_VELOCITY_STEP),
+            VirtualKeyCode::N => state.add_polygon(),
+            VirtualKeyCode::Delete | VirtualKeyCode::Back => state.remove_polygon(),
+            VirtualKeyCode::PageUp => state.select_next(1),
+            VirtualKeyCode::PageDown => state.select_next(-1),
+            VirtualKeyCode::Home => state.raise(1),
+            VirtualKeyCode::End => state.raise(-1),
+            VirtualKeyCode::F1 => state.toggle_panel(),
+            VirtualKeyCode::F3 => state.toggle_overlay(),
+            VirtualKeyCode::E => state.export_svg(),
+            VirtualKeyCode::F12 => state.take_screenshot(),
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+fn main() {
+    let (options, hot_reload, output, windows) = parse_args();
+    if let Some(output) = output {
+        render_headless(&options, &output);
+        return;
+    }
+    let watcher = hot_reload.then(|| reload::ShaderWatcher::new().unwrap_or_else(|e| {
+        eprintln!("Failed to watch {}: {}", reload::SHADER_PATH, e);
+        std::process::exit(1);
+    }));
+    let event_loop = EventLoop::new();
+    let mut states: Vec<State> = (0..windows).map(|_| open_window(&event_loop, &options)).collect();
 
-    event_loop.run(move |event, _, control_flow| {
+    event_loop.run(move |event, target, control_flow| {
         match event {
-            Event::WindowEvent { event, window_id }
-            if window_id == state.window.id() => match event {
-                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                WindowEvent::Resized(physical_size) => {
-                    state.resize(physical_size);
+            Event::WindowEvent { event, window_id } => {
+                let Some(index) = states.iter().position(|state| state.window().id() == window_id) else {
+                    return;
+                };
+                // The panel gets first pick of the window's events; what it
+                // takes goes no further
+                if states[index].window_event(&event) {
+                    return;
                 }
-                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                    state.resize(*new_inner_size);
-                }
-                _ => {}
-            },
-            Event::RedrawRequested(window_id) if window_id == state.window.id() => {
-                match state.render() {
-                    Ok(_) => {}
-                    Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
-                    Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                    Err(e) => eprintln!("{:?}", e),
+                match event {
+                    // Closing the state drops the surface before the
+                    // window, see State
+                    WindowEvent::CloseRequested => {
+                        states.remove(index).close();
+                        if states.is_empty() {
+                            *control_flow = ControlFlow::Exit;
+                        }
+                    }
+                    WindowEvent::KeyboardInput {
+                        input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(VirtualKeyCode::W), .. },
+                        ..
+                    } => states.push(open_window(target, &options)),
+                    event => states[index].guard(|state| window_event(state, event)),
                 }
             }
+            Event::RedrawRequested(window_id) => {
+                let Some(state) = states.iter_mut().find(|state| state.window().id() == window_id) else {
+                    return;
+                };
+                state.redraw();
+            }
             Event::MainEventsCleared => {
-                state.window.request_redraw();
+                match watcher.as_ref().and_then(|watcher| watcher.poll()) {
+                    Some(Ok(source)) => {
+                        for state in &mut states {
+                            state.guard(|state| state.reload_shader(&source));
+                        }
+                    }
+                    Some(Err(e)) => eprintln!("Failed to read {}: {}", reload::SHADER_PATH, e),
+                    None => {}
+                }
+                // Each window drawn when due, then straight back round for
+                // the next frame, which the present mode or the cap holds
+                // back, and asleep until the first window is due again or
+                // an event comes while nothing moves
+                let now = Instant::now();
+                let mut next: Option<Instant> = None;
+                for state in &states {
+                    let Some(at) = state.next_frame() else {
+                        continue;
+                    };
+                    if at <= now {
+                        state.window().request_redraw();
+                    }
+                    next = Some(next.map_or(at, |next| next.min(at)));
+                }
+                *control_flow = match next {
+                    Some(at) if at <= now => ControlFlow::Poll,
+                    Some(at) => ControlFlow::WaitUntil(at),
+                    None => ControlFlow::Wait,
+                };
+                if watcher.is_some() {
+                    let poll = now + reload::POLL_INTERVAL;
+                    *control_flow = match *control_flow {
+                        ControlFlow::Wait => ControlFlow::WaitUntil(poll),
+                        ControlFlow::WaitUntil(at) => ControlFlow::WaitUntil(at.min(poll)),
+                        control_flow => control_flow,
+                    };
+                }
             }
             _ => {}
         }
@@ -0,0 +1,320 @@
+use bytemuck::{ Pod, Zeroable };
+use glyph_brush::ab_glyph::FontArc;
+use glyph_brush::{ BrushAction, BrushError, GlyphBrush, GlyphBrushBuilder, GlyphVertex, Layout, Section, Text, VerticalAlign };
+use wgpu::util::DeviceExt;
+
+// F3: the frame rate, the current polygon and the scene's draw calls in
+// the window's bottom left corner, drawn in a render pass of its own over
+// the finished frame. glyph_brush lays the text out and keeps the glyphs
+// it has rasterized in an atlas, which it tells us to write to as new ones
+// come up and to grow when they no longer fit; this draws what it hands
+// back, as wgpu_glyph would, which has no release for the wgpu this lab is
+// on. The font is egui's Hack, so none has to be shipped with the lab.
+
+// Size of the text and its gap to the window's edges, in points, which the
+// window's scale factor turns into pixels
+const FONT_SIZE: f32 = 16.0;
+const MARGIN: f32 = 8.0;
+const COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.9];
+// Side of the atlas to start with, which fits a few lines at FONT_SIZE
+const ATLAS_SIZE: u32 = 256;
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct ScreenUniform {
+    size: [f32; 2],
+    _padding: [f32; 2],
+}
+
+// A glyph's quad, in window pixels and in texture coordinates of the atlas
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct Instance {
+    left_top: [f32; 2],
+    right_bottom: [f32; 2],
+    uv_left_top: [f32; 2],
+    uv_right_bottom: [f32; 2],
+    color: [f32; 4],
+}
+
+impl Instance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 5] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x2, 3 => Float32x2, 4 => Float32x4];
+
+    // The sections have no bounds, so no glyph needs clipping to them
+    fn new(glyph: GlyphVertex) -> Self {
+        let (pixels, uv) = (glyph.pixel_coords, glyph.tex_coords);
+        Instance {
+            left_top: [pixels.min.x, pixels.min.y],
+            right_bottom: [pixels.max.x, pixels.max.y],
+            uv_left_top: [uv.min.x, uv.min.y],
+            uv_right_bottom: [uv.max.x, uv.max.y],
+            color: glyph.extra.color,
+        }
+    }
+}
+
+fn create_atlas(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Glyph Atlas"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    screen_buffer: &wgpu::Buffer,
+    atlas: &wgpu::Texture,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Overlay Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: screen_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&atlas.create_view(&wgpu::TextureViewDescriptor::default())),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
+
+pub struct Overlay {
+    brush: GlyphBrush<Instance>,
+    pipeline: wgpu::RenderPipeline,
+    screen_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    atlas: wgpu::Texture,
+    // The screen and the atlas, made again whenever the atlas grows
+    bind_group: wgpu::BindGroup,
+    // The glyphs of the last text glyph_brush laid out, which it has us
+    // draw again as long as the text stays the same
+    instances: Option<wgpu::Buffer>,
+    instance_count: u32,
+    shown: bool,
+}
+
+impl Overlay {
+    // Draws into surfaces of `format`
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let font = egui::FontDefinitions::default().font_data.remove("Hack").expect("egui has Hack among its default fonts").font;
+        let font = FontArc::try_from_vec(font.into_owned()).expect("egui's fonts are valid");
+        let brush = GlyphBrushBuilder::using_font(font).initial_cache_size((ATLAS_SIZE, ATLAS_SIZE)).build();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Overlay Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("./overlay.wgsl").into()),
+        });
+        let screen_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Overlay Screen Buffer"),
+                contents: bytemuck::cast_slice(&[ScreenUniform { size: [1.0; 2], _padding: [0.0; 2] }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Overlay Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Glyph Atlas Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let atlas = create_atlas(device, ATLAS_SIZE, ATLAS_SIZE);
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Overlay Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Overlay Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &Instance::ATTRIBUTES,
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: if format.is_srgb() { "fs_srgb" } else { "fs_linear" },
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::COLOR,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let bind_group = create_bind_group(device, &bind_group_layout, &screen_buffer, &atlas, &sampler);
+        Overlay {
+            brush,
+            pipeline,
+            screen_buffer,
+            bind_group_layout,
+            sampler,
+            atlas,
+            bind_group,
+            instances: None,
+            instance_count: 0,
+            shown: true,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.shown = !self.shown;
+    }
+
+    // Lays `text` out in the bottom left corner of `view`, which is `size`
+    // pixels with `scale_factor` of them to a point, and draws it
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        size: [u32; 2],
+        scale_factor: f32,
+        text: &str,
+    ) {
+        if !self.shown {
+            return;
+        }
+        self.brush.queue(
+            Section::default()
+                .add_text(Text::new(text).with_scale(FONT_SIZE * scale_factor).with_color(COLOR))
+                .with_screen_position((MARGIN * scale_factor, size[1] as f32 - MARGIN * scale_factor))
+                .with_layout(Layout::default().v_align(VerticalAlign::Bottom)),
+        );
+        let action = loop {
+            let atlas = &self.atlas;
+            let update_atlas = |rect: glyph_brush::Rectangle<u32>, pixels: &[u8]| {
+                queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: atlas,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d { x: rect.min[0], y: rect.min[1], z: 0 },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    pixels,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(rect.width()),
+                        rows_per_image: Some(rect.height()),
+                    },
+                    wgpu::Extent3d {
+                        width: rect.width(),
+                        height: rect.height(),
+                        depth_or_array_layers: 1,
+                    },
+                );
+            };
+            match self.brush.process_queued(update_atlas, Instance::new) {
+                Ok(action) => break action,
+                // A larger atlas, which glyph_brush fills again from the
+                // start, unless it is already as large as the device allows
+                Err(BrushError::TextureTooSmall { suggested: (width, height) }) => {
+                    let max = device.limits().max_texture_dimension_2d;
+                    let (width, height) = (width.min(max), height.min(max));
+                    if (width, height) == (self.atlas.width(), self.atlas.height()) {
+                        eprintln!("The overlay's text does not fit a glyph atlas of {}x{}", width, height);
+                        return;
+                    }
+                    self.atlas = create_atlas(device, width, height);
+                    self.bind_group = create_bind_group(device, &self.bind_group_layout, &self.screen_buffer, &self.atlas, &self.sampler);
+                    self.brush.resize_texture(width, height);
+                }
+            }
+        };
+        if let BrushAction::Draw(instances) = action {
+            self.instance_count = instances.len() as u32;
+            self.instances = (!instances.is_empty()).then(|| {
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Overlay Instance Buffer"),
+                    contents: bytemuck::cast_slice(&instances),
+                    usage: wgpu::BufferUsages::VERTEX,
+                })
+            });
+        }
+        let Some(instances) = &self.instances else {
+            return;
+        };
+        let screen = ScreenUniform { size: size.map(|side| side as f32), _padding: [0.0; 2] };
+        queue.write_buffer(&self.screen_buffer, 0, bytemuck::cast_slice(&[screen]));
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Overlay Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, instances.slice(..));
+        // The four corners of each glyph's quad
+        render_pass.draw(0..4, 0..self.instance_count);
+    }
+}
@@ -0,0 +1,62 @@
+// The text of overlay.rs: one instance per glyph, a quad in window pixels
+// from the top left corner with its rectangle of the glyph atlas, whose
+// single channel is the glyph's coverage
+
+struct Screen {
+    // The window's size in pixels
+    size: vec2f,
+}
+
+@group(0) @binding(0)
+var<uniform> screen: Screen;
+
+@group(0) @binding(1)
+var atlas: texture_2d<f32>;
+
+@group(0) @binding(2)
+var atlas_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) uv: vec2f,
+    @location(1) color: vec4f,
+}
+
+// The corners of the quad as a triangle strip of four vertices
+@vertex
+fn vs_main(
+    @builtin(vertex_index) index: u32,
+    @location(0) left_top: vec2f,
+    @location(1) right_bottom: vec2f,
+    @location(2) uv_left_top: vec2f,
+    @location(3) uv_right_bottom: vec2f,
+    @location(4) color: vec4f,
+) -> VertexOutput {
+    let corner = vec2f(f32(index & 1u), f32(index >> 1u));
+    let position = mix(left_top, right_bottom, corner);
+    var out: VertexOutput;
+    out.clip_position = vec4f(2.0 * position.x / screen.size.x - 1.0, 1.0 - 2.0 * position.y / screen.size.y, 0.0, 1.0);
+    out.uv = mix(uv_left_top, uv_right_bottom, corner);
+    out.color = color;
+    return out;
+}
+
+// For a linear surface, which shows the sRGB color as it is
+@fragment
+fn fs_linear(in: VertexOutput) -> @location(0) vec4f {
+    let coverage = textureSample(atlas, atlas_sampler, in.uv).r;
+    return vec4f(in.color.rgb, in.color.a * coverage);
+}
+
+fn srgb_to_linear(color: vec3f) -> vec3f {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3f(2.4));
+    return select(high, low, color <= vec3f(0.04045));
+}
+
+// For an sRGB surface, which encodes what it is given
+@fragment
+fn fs_srgb(in: VertexOutput) -> @location(0) vec4f {
+    let coverage = textureSample(atlas, atlas_sampler, in.uv).r;
+    return vec4f(srgb_to_linear(in.color.rgb), in.color.a * coverage);
+}
@@ -0,0 +1,291 @@
+use bytemuck::{ Pod, Zeroable };
+use std::collections::HashMap;
+use wgpu::util::DeviceExt;
+
+// Draws what egui hands back from a frame of the panel: triangle meshes,
+// each with a texture and a clip rectangle, and the changes to those
+// textures, the font atlas among them. It is drawn over the finished frame
+// in a render pass of its own, which loads what the scene left.
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct ScreenUniform {
+    size: [f32; 2],
+    _padding: [f32; 2],
+}
+
+pub struct Painter {
+    pipeline: wgpu::RenderPipeline,
+    screen_buffer: wgpu::Buffer,
+    screen_bind_group: wgpu::BindGroup,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    textures: HashMap<egui::TextureId, (wgpu::Texture, wgpu::BindGroup)>,
+}
+
+impl Painter {
+    // Draws into surfaces of `format`
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Panel Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("./panel.wgsl").into()),
+        });
+        let screen_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Panel Screen Buffer"),
+                contents: bytemuck::cast_slice(&[ScreenUniform { size: [1.0; 2], _padding: [0.0; 2] }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let screen_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Panel Screen Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+        });
+        let screen_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Panel Screen Bind Group"),
+            layout: &screen_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: screen_buffer.as_entire_binding(),
+                }
+            ],
+        });
+        let texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Panel Texture Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Panel Pipeline Layout"),
+            bind_group_layouts: &[&screen_bind_group_layout, &texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Panel Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                // Laid out like egui::epaint::Vertex
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<egui::epaint::Vertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Unorm8x4],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: if format.is_srgb() { "fs_srgb" } else { "fs_linear" },
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::COLOR,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Painter { pipeline, screen_buffer, screen_bind_group, texture_bind_group_layout, textures: HashMap::new() }
+    }
+
+    // Creates and updates textures before a frame is painted
+    pub fn set_textures(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, textures: &egui::TexturesDelta) {
+        for (id, delta) in &textures.set {
+            let pixels: Vec<egui::Color32> = match &delta.image {
+                egui::ImageData::Color(image) => image.pixels.clone(),
+                egui::ImageData::Font(image) => image.srgba_pixels(None).collect(),
+            };
+            let [width, height] = delta.image.size().map(|side| side as u32);
+            let size = wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            };
+            // A patch of an existing texture, or a whole new one
+            let origin = match delta.pos {
+                Some([x, y]) => wgpu::Origin3d { x: x as u32, y: y as u32, z: 0 },
+                None => {
+                    let texture = self.create_texture(device, size, delta.options);
+                    self.textures.insert(*id, texture);
+                    wgpu::Origin3d::ZERO
+                }
+            };
+            let Some((texture, _)) = self.textures.get(id) else {
+                continue;
+            };
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture,
+                    mip_level: 0,
+                    origin,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                bytemuck::cast_slice(&pixels),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * width),
+                    rows_per_image: Some(height),
+                },
+                size,
+            );
+        }
+    }
+
+    // Drops the textures egui is done with, once a frame is painted
+    pub fn free_textures(&mut self, textures: &egui::TexturesDelta) {
+        for id in &textures.free {
+            self.textures.remove(id);
+        }
+    }
+
+    // The texels are Rgba8Unorm, so the shader gets egui's sRGB values as
+    // they are, as the vertex colors come
+    fn create_texture(&self, device: &wgpu::Device, size: wgpu::Extent3d, options: egui::TextureOptions) -> (wgpu::Texture, wgpu::BindGroup) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Panel Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let filter = |filter| match filter {
+            egui::TextureFilter::Nearest => wgpu::FilterMode::Nearest,
+            egui::TextureFilter::Linear => wgpu::FilterMode::Linear,
+        };
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Panel Sampler"),
+            mag_filter: filter(options.magnification),
+            min_filter: filter(options.minification),
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Panel Texture Bind Group"),
+            layout: &self.texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.create_view(&wgpu::TextureViewDescriptor::default())),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+        (texture, bind_group)
+    }
+
+    // Draws `primitives` over `view`, which is `size` pixels with
+    // `pixels_per_point` of them to each of egui's points. All the meshes
+    // go in one vertex and one index buffer, each drawn clipped to its
+    // rectangle with its own texture.
+    #[allow(clippy::too_many_arguments)]
+    pub fn paint(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        size: [u32; 2],
+        pixels_per_point: f32,
+        primitives: &[egui::ClippedPrimitive],
+    ) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut draws = Vec::new();
+        for primitive in primitives {
+            // Callbacks are for drawing of one's own inside egui, which
+            // the panel does not do
+            let egui::epaint::Primitive::Mesh(mesh) = &primitive.primitive else {
+                continue;
+            };
+            let first = indices.len() as u32;
+            draws.push((primitive.clip_rect, mesh.texture_id, first..first + mesh.indices.len() as u32, vertices.len() as i32));
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend_from_slice(&mesh.indices);
+        }
+        if draws.is_empty() {
+            return;
+        }
+        let screen = ScreenUniform { size: size.map(|side| side as f32 / pixels_per_point), _padding: [0.0; 2] };
+        queue.write_buffer(&self.screen_buffer, 0, bytemuck::cast_slice(&[screen]));
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Panel Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Panel Index Buffer"),
+                contents: bytemuck::cast_slice(&indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Panel Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.screen_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for (clip_rect, texture_id, range, base_vertex) in draws {
+            let Some((_, bind_group)) = self.textures.get(&texture_id) else {
+                continue;
+            };
+            // The clip rectangle in whole pixels, within the window
+            let x = ((clip_rect.min.x * pixels_per_point).round().max(0.0) as u32).min(size[0]);
+            let y = ((clip_rect.min.y * pixels_per_point).round().max(0.0) as u32).min(size[1]);
+            let right = ((clip_rect.max.x * pixels_per_point).round().max(0.0) as u32).clamp(x, size[0]);
+            let bottom = ((clip_rect.max.y * pixels_per_point).round().max(0.0) as u32).clamp(y, size[1]);
+            if right == x || bottom == y {
+                continue;
+            }
+            render_pass.set_scissor_rect(x, y, right - x, bottom - y);
+            render_pass.set_bind_group(1, bind_group, &[]);
+            render_pass.draw_indexed(range, base_vertex, 0..1);
+        }
+    }
+}
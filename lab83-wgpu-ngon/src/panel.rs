@@ -0,0 +1,139 @@
+use winit::event::WindowEvent;
+use winit::window::Window;
+
+use crate::painter::Painter;
+use crate::scene::{ MAX_ANGULAR_VELOCITY, MAX_SIDES, MIN_SIDES };
+use crate::state::{ Background, MAX_SCALE, MIN_SCALE };
+
+// F1: an egui window over the scene with sliders for the current polygon,
+// a switch for multisampling and the colors of the background. egui-winit turns the window's events
+// into egui's input; painter.rs draws the result, as egui-wgpu has no
+// release for the wgpu this lab is on.
+
+// What the panel edits. The state fills it in from the current polygon
+// before each frame of the panel and applies whatever comes back changed.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Controls {
+    pub sides: u32,
+    pub angular_velocity: f32,
+    pub color: [f32; 3],
+    pub scale: f32,
+    pub msaa: bool,
+    pub background: Background,
+}
+
+pub struct Panel {
+    context: egui::Context,
+    input: egui_winit::State,
+    painter: Painter,
+    shown: bool,
+    // The last frame of the panel, waiting to be painted
+    primitives: Vec<egui::ClippedPrimitive>,
+    textures: egui::TexturesDelta,
+    // egui asked for the next frame straight away, as it does while
+    // something of its own animates
+    repaint: bool,
+}
+
+impl Panel {
+    pub fn new(window: &Window, device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let mut input = egui_winit::State::new(window);
+        input.set_pixels_per_point(egui_winit::native_pixels_per_point(window));
+        input.set_max_texture_side(device.limits().max_texture_dimension_2d as usize);
+        Panel {
+            context: egui::Context::default(),
+            input,
+            painter: Painter::new(device, format),
+            shown: false,
+            primitives: Vec::new(),
+            textures: egui::TexturesDelta::default(),
+            repaint: false,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.shown = !self.shown;
+        if !self.shown {
+            self.primitives.clear();
+            self.repaint = false;
+        }
+    }
+
+    pub fn wants_repaint(&self) -> bool {
+        self.repaint
+    }
+
+    // Hands `event` to egui, returning whether the panel took it. Clicks,
+    // the wheel and keys over the panel are its own; the cursor's moves
+    // always go on to the scene as well, so a drag ends cleanly even where
+    // it crosses the panel.
+    pub fn on_event(&mut self, event: &WindowEvent) -> bool {
+        if !self.shown {
+            return false;
+        }
+        let response = self.input.on_event(&self.context, event);
+        match event {
+            WindowEvent::CursorMoved { .. } => false,
+            WindowEvent::MouseInput { .. } | WindowEvent::MouseWheel { .. } => {
+                self.context.is_pointer_over_area() || self.context.wants_pointer_input()
+            }
+            _ => response.consumed,
+        }
+    }
+
+    // Runs a frame of the panel, leaving what to draw for paint
+    pub fn run(&mut self, window: &Window, controls: &mut Controls) {
+        if !self.shown {
+            return;
+        }
+        let raw_input = self.input.take_egui_input(window);
+        let output = self.context.run(raw_input, |context| {
+            egui::Window::new("Polygon").resizable(false).show(context, |ui| {
+                ui.add(egui::Slider::new(&mut controls.sides, MIN_SIDES..=MAX_SIDES).text("sides"));
+                ui.add(
+                    egui::Slider::new(&mut controls.angular_velocity, -MAX_ANGULAR_VELOCITY..=MAX_ANGULAR_VELOCITY)
+                        .text("rotation (rad/s)"),
+                );
+                ui.add(egui::Slider::new(&mut controls.scale, MIN_SCALE..=MAX_SCALE).logarithmic(true).text("scale"));
+                color_edit(ui, &mut controls.color, "color");
+                ui.checkbox(&mut controls.msaa, "MSAA");
+                ui.collapsing("Background", |ui| {
+                    color_edit(ui, &mut controls.background.color, "clear color");
+                    ui.checkbox(&mut controls.background.gradient, "gradient");
+                    ui.add_enabled_ui(controls.background.gradient, |ui| color_edit(ui, &mut controls.background.top, "top"));
+                });
+            });
+        });
+        self.input.handle_platform_output(window, &self.context, output.platform_output);
+        self.repaint = output.repaint_after.is_zero();
+        self.primitives = self.context.tessellate(output.shapes);
+        self.textures.append(output.textures_delta);
+    }
+
+    // Draws the last frame of the panel over `view`, which is `size` pixels
+    pub fn paint(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        size: [u32; 2],
+    ) {
+        let textures = std::mem::take(&mut self.textures);
+        self.painter.set_textures(device, queue, &textures);
+        self.painter.paint(device, queue, encoder, view, size, self.context.pixels_per_point(), &self.primitives);
+        self.painter.free_textures(&textures);
+    }
+}
+
+// A color picker with a label. It works through bytes, so the color only
+// changes when the picker does.
+fn color_edit(ui: &mut egui::Ui, color: &mut [f32; 3], label: &str) {
+    ui.horizontal(|ui| {
+        let mut bytes = color.map(|channel| (channel * 255.0).round() as u8);
+        if ui.color_edit_button_srgb(&mut bytes).changed() {
+            *color = bytes.map(|channel| channel as f32 / 255.0);
+        }
+        ui.label(label);
+    });
+}
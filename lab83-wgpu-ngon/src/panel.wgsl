@@ -0,0 +1,52 @@
+// egui's meshes as painter.rs draws them: positions in points from the
+// window's top left corner, and colors and texels that are sRGB with
+// premultiplied alpha
+
+struct Screen {
+    // The window's size in points
+    size: vec2f,
+}
+
+@group(0) @binding(0)
+var<uniform> screen: Screen;
+
+@group(1) @binding(0)
+var panel_texture: texture_2d<f32>;
+
+@group(1) @binding(1)
+var panel_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) uv: vec2f,
+    @location(1) color: vec4f,
+}
+
+@vertex
+fn vs_main(@location(0) position: vec2f, @location(1) uv: vec2f, @location(2) color: vec4f) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4f(2.0 * position.x / screen.size.x - 1.0, 1.0 - 2.0 * position.y / screen.size.y, 0.0, 1.0);
+    out.uv = uv;
+    out.color = color;
+    return out;
+}
+
+// For a linear surface, which shows the sRGB values as they are
+@fragment
+fn fs_linear(in: VertexOutput) -> @location(0) vec4f {
+    return in.color * textureSample(panel_texture, panel_sampler, in.uv);
+}
+
+fn srgb_to_linear(color: vec3f) -> vec3f {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3f(2.4));
+    return select(high, low, color <= vec3f(0.04045));
+}
+
+// For an sRGB surface, which encodes what it is given, so the colors are
+// made linear first, as fs_main in shader.wgsl does
+@fragment
+fn fs_srgb(in: VertexOutput) -> @location(0) vec4f {
+    let color = in.color * textureSample(panel_texture, panel_sampler, in.uv);
+    return vec4f(srgb_to_linear(color.rgb), color.a);
+}
@@ -0,0 +1,86 @@
+use bytemuck::{ Pod, Zeroable };
+
+// A swarm of small copies of the polygon for the particle mode. The
+// particles live in a storage buffer that the compute pass in
+// particles.wgsl moves on every frame, spinning each one and bouncing it
+// off the edges of the window, and that vs_particle then reads to draw
+// one instance per particle. Nothing goes back to the CPU after the
+// initial upload.
+
+// Particles in the swarm
+pub const COUNT: u32 = 4096;
+
+// Invocations per workgroup of cs_main, which must match its
+// @workgroup_size
+pub const WORKGROUP_SIZE: u32 = 64;
+
+// Laid out like Particle in the shaders, with the padding WGSL rounds the
+// struct up to
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct Particle {
+    position: [f32; 2],
+    velocity: [f32; 2],
+    angle: f32,
+    spin: f32,
+    // Size relative to the polygon drawn on its own
+    scale: f32,
+    _padding: f32,
+}
+
+// What the compute pass needs besides the particles: the seconds since
+// the last frame, 0.0 while paused, and the radius the particles are
+// scaled down from, to keep their edges inside the window
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct StepUniform {
+    dt: f32,
+    radius: f32,
+    _padding: [f32; 2],
+}
+
+impl StepUniform {
+    pub fn new(dt: f32, radius: f32) -> Self {
+        StepUniform { dt, radius, _padding: [0.0; 2] }
+    }
+}
+
+// SplitMix64, enough to scatter the particles the same way on every run
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in low..high
+    fn range(&mut self, low: f32, high: f32) -> f32 {
+        let unit = (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32;
+        low + (high - low) * unit
+    }
+}
+
+// The swarm at the start: anywhere in the unit square, which every window
+// shape contains, heading every which way at a tenth to half a unit a
+// second and spinning up to half a turn a second either way
+pub fn initial() -> Vec<Particle> {
+    let mut rng = SplitMix64(0x6e67_6f6e);
+    (0..COUNT)
+        .map(|_| {
+            let heading = rng.range(0.0, std::f32::consts::TAU);
+            let speed = rng.range(0.1, 0.5);
+            Particle {
+                position: [rng.range(-0.9, 0.9), rng.range(-0.9, 0.9)],
+                velocity: [speed * heading.cos(), speed * heading.sin()],
+                angle: rng.range(0.0, std::f32::consts::TAU),
+                spin: rng.range(-std::f32::consts::PI, std::f32::consts::PI),
+                scale: rng.range(0.016, 0.048),
+                _padding: 0.0,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,47 @@
+// The particle step, run once a frame before drawing. See particles.rs.
+
+struct Particle {
+    position: vec2f,
+    velocity: vec2f,
+    angle: f32,
+    spin: f32,
+    scale: f32,
+}
+
+struct Step {
+    dt: f32,
+    radius: f32,
+}
+
+struct Projection {
+    scale: vec2f,
+}
+
+@group(0) @binding(0)
+var<storage, read_write> particles: array<Particle>;
+
+@group(0) @binding(1)
+var<uniform> step: Step;
+
+@group(0) @binding(2)
+var<uniform> projection: Projection;
+
+const TAU = 6.28318530718;
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3u) {
+    let i = id.x;
+    if i >= arrayLength(&particles) {
+        return;
+    }
+    var particle = particles[i];
+    particle.position += particle.velocity * step.dt;
+    particle.angle = fract((particle.angle + particle.spin * step.dt) / TAU) * TAU;
+    // The window reaches 1 / scale in each direction. A particle past
+    // the edge turns back only if it is still heading out, so one that a
+    // shrinking window left outside finds its way back in.
+    let bound = 1.0 / projection.scale - particle.scale * step.radius;
+    let outward = abs(particle.position) > bound & particle.position * particle.velocity > vec2f(0.0);
+    particle.velocity = select(particle.velocity, -particle.velocity, outward);
+    particles[i] = particle;
+}
@@ -0,0 +1,103 @@
+use glam::{ Mat4, Vec3 };
+use std::f32::consts::{ FRAC_PI_2, FRAC_PI_4 };
+
+use crate::shape;
+use crate::tessellation;
+
+// Prism mode: every polygon stood up as a prism, its outline swept
+// HEIGHT up out of the plane of the scene, seen through a perspective
+// camera that circles the scene as the mouse drags. The scene keeps its
+// plane at z = 0 with y up, as in 2D, and the prisms rise towards +z,
+// where the camera starts out.
+
+// Height of a prism in the polygon's own frame, where the corners are
+// RADIUS out, so it grows with the polygon
+pub const HEIGHT: f32 = 0.2;
+
+// The window's shorter side sees this much of a turn across, and the
+// camera stands far enough back that, looking straight down, the plane
+// fills the window as the 2D view does, 2 units across the shorter side
+const FOV: f32 = FRAC_PI_4;
+const NEAR: f32 = 0.1;
+const FAR: f32 = 100.0;
+
+// Tilt of the camera away from straight down that it starts at, so the
+// sides of the prisms show, and how far it tilts for each pixel dragged
+const DEFAULT_PITCH: f32 = 0.8;
+const RADIANS_PER_PIXEL: f32 = 0.01;
+// Short of edge on, either side, so the scene never turns its back
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.05;
+
+// Points of the prism's triangles, each with the normal of its face
+pub struct Mesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+}
+
+// The prism of `loops`, filled by the even-odd rule as lyon fills them:
+// a cap at either end, both from lyon's fill, and a wall of one quad for
+// each edge of each loop, its normal facing away from the filled side
+pub fn extrude(loops: &[Vec<[f32; 2]>]) -> Mesh {
+    let cap = tessellation::fill(loops);
+    let mut mesh = Mesh { positions: Vec::new(), normals: Vec::new(), indices: Vec::new() };
+    for (z, normal) in [(HEIGHT, 1.0), (0.0, -1.0)] {
+        let first = mesh.positions.len() as u32;
+        mesh.positions.extend(cap.vertices.iter().map(|&[x, y]| [x, y, z]));
+        mesh.normals.extend(cap.vertices.iter().map(|_| [0.0, 0.0, normal]));
+        mesh.indices.extend(cap.indices.iter().map(|&index| first + index as u32));
+    }
+    for (i, outline) in loops.iter().enumerate() {
+        // A loop inside an odd number of others is a hole, with the
+        // filled side outside it
+        let depth = loops.iter().enumerate().filter(|&(j, other)| j != i && shape::contains(other, outline[0])).count();
+        let counterclockwise = shape::area(outline) > 0.0;
+        let outward = if counterclockwise == (depth % 2 == 0) { 1.0 } else { -1.0 };
+        for (k, &[x1, y1]) in outline.iter().enumerate() {
+            let [x2, y2] = outline[(k + 1) % outline.len()];
+            let length = (x2 - x1).hypot(y2 - y1);
+            if length == 0.0 {
+                continue;
+            }
+            let normal = [outward * (y2 - y1) / length, outward * (x1 - x2) / length, 0.0];
+            let first = mesh.positions.len() as u32;
+            mesh.positions.extend([[x1, y1, 0.0], [x2, y2, 0.0], [x2, y2, HEIGHT], [x1, y1, HEIGHT]]);
+            mesh.normals.extend([normal; 4]);
+            mesh.indices.extend([0, 1, 2, 0, 2, 3].map(|index| first + index));
+        }
+    }
+    mesh
+}
+
+// Where the camera looks from: turned `yaw` about the z axis and tilted
+// `pitch` away from straight down, always at the origin
+pub struct Camera {
+    yaw: f32,
+    pitch: f32,
+}
+
+impl Camera {
+    pub fn new() -> Self {
+        Camera { yaw: 0.0, pitch: DEFAULT_PITCH }
+    }
+
+    // Dragging across the window turns the scene about its z axis, and
+    // up and down tilts it towards or away from the camera
+    pub fn orbit(&mut self, dx: f32, dy: f32) {
+        self.yaw += dx * RADIANS_PER_PIXEL;
+        self.pitch = (self.pitch - dy * RADIANS_PER_PIXEL).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    // The scene to clip space for a window of `width` by `height`
+    pub fn view_projection(&self, width: u32, height: u32) -> Mat4 {
+        let aspect = width.max(1) as f32 / height.max(1) as f32;
+        // The field of view is set across the shorter side
+        let fov_y = if aspect >= 1.0 { FOV } else { 2.0 * ((FOV / 2.0).tan() / aspect).atan() };
+        let distance = 1.0 / (FOV / 2.0).tan();
+        let projection = Mat4::perspective_rh(fov_y, aspect, NEAR, FAR);
+        let view = Mat4::from_translation(Vec3::new(0.0, 0.0, -distance))
+            * Mat4::from_rotation_x(-self.pitch)
+            * Mat4::from_rotation_z(-self.yaw);
+        projection * view
+    }
+}
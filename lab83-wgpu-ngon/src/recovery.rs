@@ -0,0 +1,79 @@
+use std::any::Any;
+use std::panic::{ self, AssertUnwindSafe };
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::time::Duration;
+
+// Surviving the GPU going away under the window: a driver reset, the
+// adapter unplugged, or the device out of memory. wgpu 0.17 has no
+// callback for a lost device. Everything done with it afterwards fails
+// instead, some calls with errors no error scope is waiting for, which
+// wgpu's own handler panics on, and others, such as submitting or
+// presenting, with a panic of their own. Lost catches both and flags the
+// device, and State::recover starts over on a new one.
+
+// How long to wait before trying again when no new device is to be had,
+// as while the driver is still coming back
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+// What wgpu-core's errors say when the device they were meant for is gone
+// or has run out of memory
+const LOSS_MESSAGES: [&str; 2] = ["Parent device is lost", "Not enough memory left"];
+
+fn is_loss(message: &str) -> bool {
+    LOSS_MESSAGES.iter().any(|loss| message.contains(loss))
+}
+
+// Set once the device it watches is lost, shared with the error handler
+// installed on that device
+#[derive(Clone, Default)]
+pub struct Lost(Arc<AtomicBool>);
+
+impl Lost {
+    // Installs a handler on `device` in place of wgpu's. Errors from a lost
+    // device flag it; any other error is a bug, and panics as before.
+    pub fn watch(device: &wgpu::Device) -> Self {
+        let lost = Lost::default();
+        let flag = lost.clone();
+        device.on_uncaptured_error(Box::new(move |error| {
+            let loss = match &error {
+                wgpu::Error::OutOfMemory { .. } => true,
+                wgpu::Error::Validation { description, .. } => is_loss(description),
+            };
+            if !loss {
+                panic!("wgpu error: {}", error);
+            }
+            flag.set();
+        }));
+        lost
+    }
+
+    pub fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    // Reports the loss the first time only, as every call after it fails
+    // the same way
+    pub fn set(&self) {
+        if !self.0.swap(true, Ordering::Relaxed) {
+            eprintln!("The GPU device was lost, starting over on a new one");
+        }
+    }
+
+    // Runs `work`, returning None and flagging the device if wgpu panics
+    // because it is lost. Other panics go on unwinding.
+    pub fn catch<T>(&self, work: impl FnOnce() -> T) -> Option<T> {
+        match panic::catch_unwind(AssertUnwindSafe(work)) {
+            Ok(result) => Some(result),
+            Err(payload) if panic_message(payload.as_ref()).is_some_and(is_loss) => {
+                self.set();
+                None
+            }
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> Option<&str> {
+    payload.downcast_ref::<String>().map(String::as_str).or_else(|| payload.downcast_ref::<&str>().copied())
+}
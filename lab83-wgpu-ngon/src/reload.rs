@@ -0,0 +1,50 @@
+use notify::{ RecursiveMode, Watcher };
+use std::path::Path;
+use std::sync::mpsc::{ self, Receiver };
+use std::time::Duration;
+
+// `--hot-reload`: watches shader.wgsl in the source tree, the one built
+// into the binary, and hands its new source to State::reload_shader
+// whenever it is saved. Only the render shader is watched; particles.wgsl
+// still needs a rebuild.
+
+pub const SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader.wgsl");
+
+// The watcher has no way to wake the event loop, so while the loop would
+// otherwise wait for an event it looks in this often
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub struct ShaderWatcher {
+    // Watching stops when this is dropped
+    _watcher: notify::RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+}
+
+impl ShaderWatcher {
+    // Watches the directory rather than the file, as editors that save by
+    // writing a new file and renaming it over the old one would otherwise
+    // leave the watch behind on the old file
+    pub fn new() -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        let path = Path::new(SHADER_PATH);
+        watcher.watch(path.parent().unwrap_or(path), RecursiveMode::NonRecursive)?;
+        Ok(ShaderWatcher { _watcher: watcher, events })
+    }
+
+    // The shader's source if it changed since the last call. A save comes
+    // as several events, which all count as one change.
+    pub fn poll(&self) -> Option<std::io::Result<String>> {
+        let mut changed = false;
+        for event in self.events.try_iter() {
+            match event {
+                Ok(event) => {
+                    let ours = event.paths.iter().any(|path| path.file_name() == Path::new(SHADER_PATH).file_name());
+                    changed |= ours && (event.kind.is_create() || event.kind.is_modify());
+                }
+                Err(e) => eprintln!("Watching {}: {}", SHADER_PATH, e),
+            }
+        }
+        changed.then(|| std::fs::read_to_string(SHADER_PATH))
+    }
+}
@@ -0,0 +1,275 @@
+use std::rc::Rc;
+
+use crate::shape::{ self, Shape };
+use crate::state::Fill;
+
+// The polygons on screen. The scene is a list drawn back to front, so a
+// polygon covers the ones before it; z-order is simply the position in
+// the list. Each polygon has its own outline, fill, color, rotation and
+// mouse transform, and reaches the GPU as a uniform of its own that
+// shader.wgsl reads at group 1.
+
+pub const MIN_SIDES: u32 = 3;
+pub const MAX_SIDES: u32 = 100;
+
+// Radius of the corners before the mouse scales a polygon
+pub const RADIUS: f32 = 0.5;
+
+// Rotation speed of the first polygon, one turn every eight seconds, and
+// the step of the speed keys. Negative speeds turn clockwise.
+pub const DEFAULT_ANGULAR_VELOCITY: f32 = std::f32::consts::FRAC_PI_4;
+pub const ANGULAR_VELOCITY_STEP: f32 = std::f32::consts::FRAC_PI_8;
+pub const MAX_ANGULAR_VELOCITY: f32 = std::f32::consts::TAU;
+
+// Range and step of the opacity keys
+const MIN_OPACITY: f32 = 0.1;
+pub const OPACITY_STEP: f32 = 0.1;
+
+// Solid fills, which new polygons take in turn. The first is the lab's
+// original orange.
+pub const PALETTE: [[f32; 3]; 6] = [
+    [1.0, 0.5, 0.2],
+    [0.3, 0.65, 1.0],
+    [0.4, 0.85, 0.4],
+    [0.95, 0.35, 0.55],
+    [0.7, 0.5, 1.0],
+    [0.2, 0.8, 0.75],
+];
+
+// The palette color after `color`, or the first one for colors not in it
+pub fn next_color(color: [f32; 3]) -> [f32; 3] {
+    let next = PALETTE.iter().position(|&c| c == color).map_or(0, |i| i + 1);
+    PALETTE[next % PALETTE.len()]
+}
+
+#[derive(Clone, Debug)]
+pub struct Polygon {
+    pub sides: u32,
+    pub shape: Shape,
+    pub fill: Fill,
+    // Used by the solid fill; the gradients have colors of their own
+    pub color: [f32; 3],
+    // Where the mouse has put the polygon: moved by `offset` and grown by
+    // `scale` after turning, in the units the projection works in
+    pub offset: [f32; 2],
+    pub scale: f32,
+    // The polygon is turned by phase + angular_velocity·time radians
+    pub angular_velocity: f32,
+    pub phase: f32,
+    // Alpha of the fill and the outline, 1.0 for opaque. How it combines
+    // with the polygons below is up to the blend mode.
+    pub opacity: f32,
+    // Outlines from `--import` in place of the shape's, the outer one
+    // first and any holes after it, until a key changes the sides or the
+    // shape. The shader path and the fan cannot fill them, so the polygon
+    // is drawn from lyon's meshes whatever the geometry and tessellation,
+    // and the particles keep to the regular polygon of `sides`.
+    pub imported: Option<Rc<[Vec<[f32; 2]>]>>,
+}
+
+impl Polygon {
+    pub fn new(sides: u32, color: [f32; 3]) -> Self {
+        Polygon {
+            sides: sides.clamp(MIN_SIDES, MAX_SIDES),
+            shape: Shape::default(),
+            fill: Fill::Solid,
+            color,
+            offset: [0.0; 2],
+            scale: 1.0,
+            angular_velocity: DEFAULT_ANGULAR_VELOCITY,
+            phase: 0.0,
+            opacity: 1.0,
+            imported: None,
+        }
+    }
+
+    // A polygon of the imported `loops`, the outer one first, with as many
+    // sides as that has points as far as MAX_SIDES goes
+    pub fn from_outlines(loops: Vec<Vec<[f32; 2]>>, color: [f32; 3]) -> Self {
+        let sides = loops[0].len() as u32;
+        Polygon { imported: Some(loops.into()), ..Polygon::new(sides, color) }
+    }
+
+    pub fn describe(&self) -> String {
+        let description = match &self.imported {
+            Some(loops) if loops.len() > 1 => {
+                let points: usize = loops.iter().map(Vec::len).sum();
+                format!("imported, {} points in {} loops, {}", points, loops.len(), self.fill.name())
+            }
+            Some(loops) => format!("imported, {} points, {}", loops[0].len(), self.fill.name()),
+            None => format!("N = {}, {}, {}", self.sides, self.shape.describe(self.sides), self.fill.name()),
+        };
+        if self.opacity < 1.0 { format!("{}, {:.0}% opaque", description, 100.0 * self.opacity) } else { description }
+    }
+
+    // How far the polygon has turned at `time`
+    pub fn angle(&self, time: f32) -> f32 {
+        self.phase + self.angular_velocity * time
+    }
+
+    // Speeds are clamped to a turn per second either way. Changing the
+    // speed at `time` moves the phase to match, so the polygon carries on
+    // from where it is instead of jumping to where the new speed would
+    // have put it.
+    pub fn set_angular_velocity(&mut self, angular_velocity: f32, time: f32) {
+        let angular_velocity = angular_velocity.clamp(-MAX_ANGULAR_VELOCITY, MAX_ANGULAR_VELOCITY);
+        self.phase += (self.angular_velocity - angular_velocity) * time;
+        self.angular_velocity = angular_velocity;
+    }
+
+    // Clamped to MIN_SIDES..=MAX_SIDES. A {N/k} star stays {N/k} if there
+    // is one, and otherwise keeps its inner radius.
+    pub fn set_sides(&mut self, sides: u32) {
+        self.imported = None;
+        self.sides = sides.clamp(MIN_SIDES, MAX_SIDES);
+        if let Some(step) = self.shape.step {
+            match shape::star_inner(self.sides, step) {
+                Some(inner) => self.shape.inner = inner,
+                None => self.shape.step = None,
+            }
+        }
+    }
+
+    pub fn toggle_star(&mut self) {
+        self.imported = None;
+        self.shape = Shape { rounded: self.shape.rounded, ..if self.shape.star { Shape::default() } else { Shape::star(self.sides) } };
+    }
+
+    // Moves a star on to the next {N/k}, back to the first after the last
+    pub fn next_star_step(&mut self) {
+        if !self.shape.star {
+            return;
+        }
+        let current = self.shape.step.unwrap_or(1);
+        let next = shape::stars(self.sides).find(|&(step, _)| step > current).or_else(|| shape::stars(self.sides).next());
+        if let Some((step, inner)) = next {
+            self.shape.inner = inner;
+            self.shape.step = Some(step);
+        }
+    }
+
+    // Grows or shrinks the inner radius of a star by `delta`, leaving
+    // {N/k} behind
+    pub fn adjust_inner(&mut self, delta: f32) {
+        if !self.shape.star {
+            return;
+        }
+        self.shape.inner = (self.shape.inner + delta).clamp(shape::MIN_INNER, shape::MAX_INNER);
+        self.shape.step = None;
+    }
+
+    pub fn toggle_rounded(&mut self) {
+        self.imported = None;
+        self.shape.rounded = !self.shape.rounded;
+    }
+
+    // Kept to whole steps, so stepping back up lands on opaque exactly
+    pub fn adjust_opacity(&mut self, delta: f32) {
+        let steps = ((self.opacity + delta) / OPACITY_STEP).round();
+        self.opacity = (steps * OPACITY_STEP).clamp(MIN_OPACITY, 1.0);
+    }
+
+    // Points of the outline the shader path works out, which is not the
+    // imported one
+    pub fn outline_count(&self) -> u32 {
+        self.shape.outline_count(self.sides)
+    }
+
+    // The outline in the polygon's own frame, before turning and the
+    // mouse transform; the outer one of an imported polygon with holes
+    pub fn outline(&self) -> Vec<[f32; 2]> {
+        match &self.imported {
+            Some(loops) => loops[0].clone(),
+            None => self.shape.outline(self.sides, RADIUS),
+        }
+    }
+
+    // Every loop of the outline, holes and all, as lyon fills and strokes
+    // them
+    pub fn loops(&self) -> Vec<Vec<[f32; 2]>> {
+        match &self.imported {
+            Some(loops) => loops.to_vec(),
+            None => vec![self.outline()],
+        }
+    }
+
+    // Whether the polygon, as drawn at `time`, covers `point`, in the units
+    // of the offset. The point is taken back through the mouse transform
+    // and the rotation into the polygon's own frame and tested against the
+    // same loops the meshes are built from, by the even-odd rule they are
+    // filled with, so a hole is not part of the polygon.
+    pub fn contains(&self, point: [f32; 2], time: f32) -> bool {
+        let (x, y) = ((point[0] - self.offset[0]) / self.scale, (point[1] - self.offset[1]) / self.scale);
+        let (sin, cos) = self.angle(time).sin_cos();
+        let point = [cos * x + sin * y, cos * y - sin * x];
+        self.loops().iter().filter(|outline| shape::contains(outline, point)).count() % 2 == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Where `point` of the polygon's own frame ends up on screen at `time`
+    fn place(polygon: &Polygon, point: [f32; 2], time: f32) -> [f32; 2] {
+        let (sin, cos) = polygon.angle(time).sin_cos();
+        let turned = [cos * point[0] - sin * point[1], sin * point[0] + cos * point[1]];
+        [polygon.offset[0] + polygon.scale * turned[0], polygon.offset[1] + polygon.scale * turned[1]]
+    }
+
+    fn moved_square() -> Polygon {
+        // A diamond of radius RADIUS in its own frame, corners on the axes
+        Polygon { offset: [0.3, -0.2], scale: 2.0, phase: 0.4, angular_velocity: 0.5, ..Polygon::new(4, PALETTE[0]) }
+    }
+
+    #[test]
+    fn contains_points_inside_a_moved_polygon() {
+        let polygon = moved_square();
+        for point in [[0.0, 0.0], [0.45, 0.0], [0.0, -0.45], [0.2, 0.2]] {
+            let on_screen = place(&polygon, point, 1.5);
+            assert!(polygon.contains(on_screen, 1.5), "{:?} at {:?}", point, on_screen);
+        }
+        // Only inside once turned, moved and scaled
+        let on_screen = place(&polygon, [0.45, 0.0], 1.5);
+        assert!(!Polygon::new(4, PALETTE[0]).contains(on_screen, 0.0));
+    }
+
+    #[test]
+    fn does_not_contain_points_outside_a_moved_polygon() {
+        let polygon = moved_square();
+        for point in [[0.3, 0.3], [0.55, 0.0], [0.0, 0.6], [-0.26, -0.26]] {
+            let on_screen = place(&polygon, point, 1.5);
+            assert!(!polygon.contains(on_screen, 1.5), "{:?} at {:?}", point, on_screen);
+        }
+    }
+
+    #[test]
+    fn holes_of_imported_polygons_are_not_part_of_them() {
+        let square = |half: f32| vec![[-half, -half], [half, -half], [half, half], [-half, half]];
+        let mut hole = square(0.2);
+        hole.reverse();
+        let polygon = Polygon::from_outlines(vec![square(0.5), hole], PALETTE[1]);
+        assert!(!polygon.contains([0.0, 0.0], 0.0));
+        assert!(!polygon.contains([0.1, -0.15], 0.0));
+        assert!(polygon.contains([0.35, 0.0], 0.0));
+        assert!(polygon.contains([-0.3, 0.4], 0.0));
+        assert!(!polygon.contains([0.6, 0.0], 0.0));
+    }
+
+    #[test]
+    fn star_steps_cycle_through_the_star_polygons() {
+        let steps = |sides: u32| {
+            let mut polygon = Polygon::new(sides, PALETTE[0]);
+            polygon.toggle_star();
+            let mut steps = vec![polygon.shape.step];
+            for _ in 0..3 {
+                polygon.next_star_step();
+                steps.push(polygon.shape.step);
+            }
+            steps
+        };
+        assert_eq!(steps(9), [Some(2), Some(4), Some(2), Some(4)]);
+        assert_eq!(steps(12), [Some(5); 4]);
+        assert_eq!(steps(6), [None; 4]);
+    }
+}
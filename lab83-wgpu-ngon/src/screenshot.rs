@@ -0,0 +1,155 @@
+use image::RgbaImage;
+use std::path::PathBuf;
+use std::sync::mpsc::{ self, Receiver };
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+// F12: the frame drawn again into a texture of its own, copied into a
+// buffer the CPU can map, and written out as out/screenshot-<UTC time>.png
+// in the working directory. The copy is mapped asynchronously, so the
+// frames go on while it finishes, and the PNG is encoded on a thread of
+// its own. `--headless` reads its one frame back the same way, but waits
+// for it.
+
+pub const OUT_DIR: &str = "out";
+
+// The texture the screenshot is drawn into, in the surface's format so the
+// pipelines draw into it as they do into the surface
+pub fn target(device: &wgpu::Device, format: wgpu::TextureFormat, width: u32, height: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Screenshot Texture"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+// Whether screenshots of `format` can be written; they are PNGs of 8 bit
+// RGBA, so only the 8 bit RGBA and BGRA formats can
+pub fn supported(format: wgpu::TextureFormat) -> bool {
+    use wgpu::TextureFormat::*;
+    matches!(format, Rgba8Unorm | Rgba8UnormSrgb | Bgra8Unorm | Bgra8UnormSrgb)
+}
+
+// A screenshot on its way back from the GPU
+pub struct Capture {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    // Rows of the buffer are padded out to a multiple of
+    // COPY_BYTES_PER_ROW_ALIGNMENT
+    padded_bytes_per_row: u32,
+    bgra: bool,
+    mapped: Option<Receiver<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+impl Capture {
+    // Records the copy of `texture`, one made by `target`, into a new
+    // buffer. Call map once the encoder is submitted.
+    pub fn record(device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture) -> Self {
+        let (width, height) = (texture.width(), texture.height());
+        let padded_bytes_per_row = (4 * width).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Screenshot Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+        let bgra = matches!(texture.format(), wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb);
+        Capture { buffer, width, height, padded_bytes_per_row, bgra, mapped: None }
+    }
+
+    // Asks for the buffer to be mapped once the copy is done
+    pub fn map(&mut self) {
+        let (sender, receiver) = mpsc::channel();
+        self.buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.mapped = Some(receiver);
+    }
+
+    // The screenshot, once the buffer has been mapped. The device has to be
+    // polled for the mapping to come through.
+    pub fn image(&self) -> Option<Result<RgbaImage, wgpu::BufferAsyncError>> {
+        let result = self.mapped.as_ref()?.try_recv().ok()?;
+        Some(result.map(|()| {
+            let row_bytes = 4 * self.width as usize;
+            let mut pixels = Vec::with_capacity(row_bytes * self.height as usize);
+            {
+                let padded = self.buffer.slice(..).get_mapped_range();
+                for row in padded.chunks(self.padded_bytes_per_row as usize) {
+                    pixels.extend_from_slice(&row[..row_bytes]);
+                }
+            }
+            self.buffer.unmap();
+            if self.bgra {
+                for pixel in pixels.chunks_exact_mut(4) {
+                    pixel.swap(0, 2);
+                }
+            }
+            RgbaImage::from_raw(self.width, self.height, pixels).expect("four bytes for every pixel")
+        }))
+    }
+}
+
+// Writes `image` to a new file in OUT_DIR named after the time, encoding it
+// on a thread of its own
+pub fn save(image: RgbaImage) {
+    let path = PathBuf::from(OUT_DIR).join(format!("screenshot-{}.png", timestamp(SystemTime::now())));
+    std::thread::spawn(move || {
+        let written = std::fs::create_dir_all(OUT_DIR)
+            .map_err(image::ImageError::from)
+            .and_then(|_| image.save(&path));
+        match written {
+            Ok(()) => println!("Saved {}", path.display()),
+            Err(e) => eprintln!("Failed to write {}: {}", path.display(), e),
+        }
+    });
+}
+
+// `time` in UTC as 20261016-142501-123, which sorts in time order and is a
+// valid file name everywhere
+pub fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (days, seconds) = (since_epoch.as_secs() / 86_400, since_epoch.as_secs() % 86_400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+        year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60, since_epoch.subsec_millis()
+    )
+}
+
+// The date `days` after 1970-01-01 in the proleptic Gregorian calendar,
+// after Howard Hinnant's algorithm, which counts in 400 year eras that
+// start on March 1st so the leap day comes last
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -1,9 +1,360 @@
+struct Time {
+    time: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> time: Time;
+
+struct Projection {
+    scale: vec2f,
+}
+
+@group(0) @binding(1)
+var<uniform> projection: Projection;
+
+// The image of the texture fill, see texture.rs
+@group(0) @binding(2)
+var fill_texture: texture_2d<f32>;
+
+@group(0) @binding(3)
+var fill_sampler: sampler;
+
+// Prism mode's view of the scene, see prism.rs
+struct Camera {
+    view_projection: mat4x4f,
+}
+
+@group(0) @binding(4)
+var<uniform> camera: Camera;
+
+// The ends of the background gradient, see state::Background
+struct Background {
+    top: vec3f,
+    bottom: vec3f,
+}
+
+@group(0) @binding(5)
+var<uniform> background: Background;
+
+// The polygon being drawn, one bind group per polygon of the scene
+struct Polygon {
+    sides: u32,
+    radius: f32,
+    // 0 solid, 1 angular gradient, 2 radial gradient, 3 texture, as in
+    // state::Fill
+    fill: u32,
+    // The shape::Shape, with corner_radius only used when arc_points > 1
+    star: u32,
+    inner: f32,
+    corner_radius: f32,
+    arc_points: u32,
+    // 1 once a click has hit the polygon, see State::hit
+    selected: u32,
+}
+
+@group(1) @binding(0)
+var<uniform> polygon: Polygon;
+
+// What changes from draw to draw without touching the outline: the
+// polygon's color and opacity, and where the mouse has put it and how it
+// turns, as in scene::Polygon
+struct Draw {
+    color: vec3f,
+    // The alpha of everything the polygon draws
+    opacity: f32,
+    offset: vec2f,
+    scale: f32,
+    angular_velocity: f32,
+    phase: f32,
+}
+
+// A uniform of its own next to the polygon's, or push constants where the
+// adapter has them, in which case State::new swaps these two lines for
+// `var<push_constant> draw: Draw;`
+@group(1) @binding(1)
+var<uniform> draw: Draw;
+
+// Turns a point or a direction about the center by the angle the polygon
+// has reached
+fn turn(position: vec2f) -> vec2f {
+    let angle = draw.phase + draw.angular_velocity * time.time;
+    let c = cos(angle);
+    let s = sin(angle);
+    return vec2f(c * position.x - s * position.y, s * position.x + c * position.y);
+}
+
+// Turns a point about the center, scales and moves it to where the mouse
+// has put the polygon, then fits it to the window's aspect ratio
+fn to_clip(position: vec2f) -> vec4f {
+    return vec4f((turn(position) * draw.scale + draw.offset) * projection.scale, 0.0, 1.0);
+}
+
+// How far the selected polygon's colors are lifted towards white. This
+// happens in fs_main, so the texture is lifted too.
+fn lift() -> f32 {
+    return select(0.0, 0.4, polygon.selected != 0u);
+}
+
+// Where a point in the polygon's own frame falls on the texture, as
+// state::uv works it out
+fn uv_at(position: vec2f) -> vec2f {
+    return vec2f(0.5 + position.x / (2.0 * polygon.radius), 0.5 - position.y / (2.0 * polygon.radius));
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) color: vec3f,
+    @location(1) uv: vec2f,
+    // 1 where the color is multiplied by the texture
+    @location(2) @interpolate(flat) textured: u32,
+    @location(3) @interpolate(flat) lift: f32,
+    @location(4) @interpolate(flat) opacity: f32,
+}
+
+// The output of the polygon's fill at `position` with the vertex color
+// `color`, the same for both paths
+fn fill_output(clip_position: vec4f, color: vec3f, position: vec2f) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = clip_position;
+    out.color = color;
+    out.uv = uv_at(position);
+    out.textured = u32(polygon.fill == 3u);
+    out.lift = lift();
+    out.opacity = draw.opacity;
+    return out;
+}
+
+// The fully saturated hue wheel, red at 0.0 and 1.0
+fn hue(h: f32) -> vec3f {
+    let x = fract(h) * 6.0;
+    return clamp(vec3f(abs(x - 3.0) - 1.0, 2.0 - abs(x - 2.0), 2.0 - abs(x - 4.0)), vec3f(0.0), vec3f(1.0));
+}
+
+// Colors of the center and of the outline point at `position`, matching
+// Fill::center and Fill::corner
+fn center_color() -> vec3f {
+    switch polygon.fill {
+        case 1u, 3u: { return vec3f(1.0, 1.0, 1.0); }
+        case 2u: { return vec3f(1.0, 0.9, 0.5); }
+        default: { return draw.color; }
+    }
+}
+
+fn corner_color(position: vec2f) -> vec3f {
+    switch polygon.fill {
+        case 1u: { return hue((atan2(position.y, position.x) - TAU / 4.0) / TAU); }
+        case 2u: { return mix(center_color(), vec3f(0.8, 0.25, 0.05), length(position) / polygon.radius); }
+        case 3u: { return vec3f(1.0, 1.0, 1.0); }
+        default: { return draw.color; }
+    }
+}
+
+const PI = 3.14159265359;
+const TAU = 6.28318530718;
+
+// The outline of Shape::outline, which the two must agree on
+fn corner_count() -> u32 {
+    if polygon.star != 0u {
+        return 2u * polygon.sides;
+    }
+    return polygon.sides;
+}
+
+fn outline_count() -> u32 {
+    return corner_count() * polygon.arc_points;
+}
+
+// Corner j before rounding, the first one pointing straight up
+fn corner_position(j: u32) -> vec2f {
+    let count = corner_count();
+    let k = j % count;
+    let angle = TAU / 4.0 + TAU * f32(k) / f32(count);
+    var radius = polygon.radius;
+    if polygon.star != 0u && k % 2u == 1u {
+        radius *= polygon.inner;
+    }
+    return radius * vec2f(cos(angle), sin(angle));
+}
+
+// Point i of the outline: corner i itself, or a point on the arc that
+// rounds corner i / arc_points, as in shape::fillet
+fn outline_point(i: u32) -> vec2f {
+    let count = corner_count();
+    let j = (i / polygon.arc_points) % count;
+    let at = corner_position(j);
+    if polygon.arc_points == 1u {
+        return at;
+    }
+    let to_before = corner_position(j + count - 1u) - at;
+    let to_after = corner_position(j + 1u) - at;
+    let u = normalize(to_before);
+    let v = normalize(to_after);
+    let cos_angle = clamp(dot(u, v), -1.0, 1.0);
+    if cos_angle < -0.9999 {
+        return at;
+    }
+    let half = acos(cos_angle) / 2.0;
+    let reach = min(polygon.corner_radius / tan(half), 0.5 * min(length(to_before), length(to_after)));
+    let radius = reach * tan(half);
+    let center = at + normalize(u + v) * (radius / sin(half));
+    let start = at + u * reach - center;
+    let end = at + v * reach - center;
+    let start_angle = atan2(start.y, start.x);
+    // rem_euclid, which WGSL's % is not for negative numbers
+    let turn = atan2(end.y, end.x) - start_angle + PI;
+    let sweep = turn - TAU * floor(turn / TAU) - PI;
+    let t = f32(i % polygon.arc_points) / f32(polygon.arc_points - 1u);
+    let angle = start_angle + sweep * t;
+    return center + radius * vec2f(cos(angle), sin(angle));
+}
+
+struct FanVertex {
+    position: vec2f,
+    color: vec3f,
+}
+
+// Vertex i belongs to triangle i / 3, which runs from the center to the
+// outline points i / 3 and i / 3 + 1
+fn fan_vertex(index: u32) -> FanVertex {
+    let corner = index % 3u;
+    if corner == 0u {
+        return FanVertex(vec2f(0.0, 0.0), center_color());
+    }
+    // Point outline_count() is point 0 again
+    let position = outline_point((index / 3u + corner - 1u) % outline_count());
+    return FanVertex(position, corner_color(position));
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let vertex = fan_vertex(index);
+    return fill_output(to_clip(vertex.position), vertex.color, vertex.position);
+}
+
+// Laid out like particles::Particle
+struct Particle {
+    position: vec2f,
+    velocity: vec2f,
+    angle: f32,
+    spin: f32,
+    scale: f32,
+}
+
+@group(2) @binding(0)
+var<storage, read> particles: array<Particle>;
+
+// Particle mode: the fan of vs_main once per instance, shrunk, turned and
+// moved to where cs_main has put that particle. The particles bounce off
+// the window's edges rather than turn and move with the polygon they copy,
+// so only the projection applies on top.
+@vertex
+fn vs_particle(@builtin(vertex_index) index: u32, @builtin(instance_index) instance: u32) -> VertexOutput {
+    let vertex = fan_vertex(index);
+    let particle = particles[instance];
+    let c = cos(particle.angle);
+    let s = sin(particle.angle);
+    let local = particle.scale * vertex.position;
+    let turned = vec2f(c * local.x - s * local.y, s * local.x + c * local.y);
+    var out = fill_output(vec4f((particle.position + turned) * projection.scale, 0.0, 1.0), vertex.color, vertex.position);
+    // The swarm is never the selection
+    out.lift = 0.0;
+    return out;
+}
+
+// The indexed path, which reads the outline, its colors and its texture
+// coordinates from a vertex buffer
+@vertex
+fn vs_indexed(@location(0) position: vec3f, @location(1) color: vec3f, @location(2) uv: vec2f) -> VertexOutput {
+    var out = fill_output(to_clip(position.xy), color, position.xy);
+    out.uv = uv;
+    return out;
+}
+
+// The outline, a line strip through points 0 to outline_count(), the last
+// being point 0 again. It is drawn the same way for both paths, in white so it
+// stands out against any fill, or yellow while selected.
 @vertex
-fn vs_main(@location(0) position: vec3f) -> @builtin(position) vec4f {
-    return vec4f(position, 1.0);
+fn vs_outline(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = to_clip(outline_point(index % outline_count()));
+    out.color = select(vec3f(1.0, 1.0, 1.0), vec3f(1.0, 0.9, 0.2), polygon.selected != 0u);
+    out.textured = 0u;
+    out.lift = 0.0;
+    out.opacity = draw.opacity;
+    return out;
+}
+
+// The outline from a vertex buffer: the fan's, the center left out, for
+// polygons in the middle of a morph, or the triangles of lyon's stroke
+@vertex
+fn vs_indexed_outline(@location(0) position: vec3f) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = to_clip(position.xy);
+    out.color = select(vec3f(1.0, 1.0, 1.0), vec3f(1.0, 0.9, 0.2), polygon.selected != 0u);
+    out.textured = 0u;
+    out.lift = 0.0;
+    out.opacity = draw.opacity;
+    return out;
+}
+
+// Where the light of prism mode comes from, the same for every polygon
+// however the camera turns, and how much of it reaches the faces turned
+// away from it
+const LIGHT = vec3f(-0.36, -0.48, 0.8);
+const AMBIENT = 0.25;
+
+// Prism mode: prism.rs's prism of the polygon from a vertex buffer, turned
+// and moved like the 2D polygon and seen through the camera. Each face is
+// lit by Lambert's cosine law from its normal, which is the same at all
+// its corners, so shading the vertex colors shades the whole face.
+@vertex
+fn vs_prism(
+    @location(0) position: vec3f,
+    @location(1) color: vec3f,
+    @location(2) uv: vec2f,
+    @location(3) normal: vec3f,
+) -> VertexOutput {
+    let world = vec3f(turn(position.xy) * draw.scale + draw.offset, position.z * draw.scale);
+    let world_normal = vec3f(turn(normal.xy), normal.z);
+    let shade = AMBIENT + (1.0 - AMBIENT) * max(dot(world_normal, LIGHT), 0.0);
+    var out = fill_output(camera.view_projection * vec4f(world, 1.0), color * shade, position.xy);
+    out.uv = uv;
+    return out;
+}
+
+// The background gradient, drawn before the scene: one triangle over the
+// whole window, its corners at (-1, -1), (3, -1) and (-1, 3) in clip
+// space. Its colors run on past the window's edges so that the top edge,
+// at y = 1, has the top color and the bottom edge the bottom one.
+@vertex
+fn vs_background(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let position = vec2f(f32(index == 1u), f32(index == 2u)) * 4.0 - 1.0;
+    var out: VertexOutput;
+    out.clip_position = vec4f(position, 0.0, 1.0);
+    out.color = mix(background.bottom, background.top, (position.y + 1.0) / 2.0);
+    out.uv = vec2f(0.0);
+    out.textured = 0u;
+    out.lift = 0.0;
+    out.opacity = 1.0;
+    return out;
+}
+
+// Whether the surface is sRGB. Every color here, the texture's included,
+// is written as sRGB, so for an sRGB surface, which encodes what it is
+// given, fs_main makes them linear first. State::new swaps in `true`.
+const SRGB_SURFACE = false;
+
+fn srgb_to_linear(color: vec3f) -> vec3f {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3f(2.4));
+    return select(high, low, color <= vec3f(0.04045));
 }
 
 @fragment
-fn fs_main() -> @location(0) vec4f {
-    return vec4f(1.0, 0.5, 0.2, 1.0);
-}
\ No newline at end of file
+fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    // Sampled everywhere, as textureSample has to be in uniform control
+    // flow, and only used where the fill asks for it
+    let texel = textureSample(fill_texture, fill_sampler, in.uv).rgb;
+    let color = mix(select(in.color, in.color * texel, in.textured != 0u), vec3f(1.0), in.lift);
+    return vec4f(select(color, srgb_to_linear(color), SRGB_SURFACE), in.opacity);
+}
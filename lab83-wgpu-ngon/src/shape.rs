@@ -0,0 +1,292 @@
+use std::f32::consts::{ FRAC_PI_2, PI, TAU };
+
+// The outline the polygon is drawn from, as a loop of points around the
+// center. Every shape here is star-shaped about the center, so both paths
+// can fill it as a fan of triangles from there.
+//
+// - A regular polygon has its N corners on the circle.
+// - A star alternates N outer corners with N inner ones, `inner` times as
+//   far out and halfway between. The star polygon {N/k}, the outline of
+//   the figure that joins every kth corner, is the inner radius
+//   cos(πk/N) / cos(π(k - 1)/N) for k coprime to N; other radii give
+//   fatter or thinner stars.
+// - Rounded corners replace every corner, inner ones included, by
+//   ARC_POINTS points along a circular arc tangent to both edges.
+//
+// vs_main and vs_outline work out the same points in shader.wgsl from
+// the polygon uniform, so the two must change together.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shape {
+    pub star: bool,
+    pub inner: f32,
+    // The k of {N/k} while `inner` is that star polygon's radius
+    pub step: Option<u32>,
+    pub rounded: bool,
+}
+
+// Points along each rounded corner, both ends included
+pub const ARC_POINTS: u32 = 8;
+
+// Radius of the rounded corners, in the units of the polygon's radius of
+// 0.5. Corners on short edges get smaller arcs so neighbours never overlap.
+pub const CORNER_RADIUS: f32 = 0.06;
+
+// Range and step of the inner radius keys
+pub const MIN_INNER: f32 = 0.1;
+pub const MAX_INNER: f32 = 1.0;
+pub const INNER_STEP: f32 = 0.05;
+
+// The inner radius used when a star has no {N/k} of its own, as with
+// triangles and squares
+const DEFAULT_INNER: f32 = 0.5;
+
+impl Default for Shape {
+    fn default() -> Self {
+        Shape { star: false, inner: DEFAULT_INNER, step: None, rounded: false }
+    }
+}
+
+// Inner radius of {sides/step}, for 2 <= step < sides/2 and coprime to
+// sides. A step with a factor in common joins the corners into several
+// smaller polygons instead, as {6/2} makes two triangles.
+pub fn star_inner(sides: u32, step: u32) -> Option<f32> {
+    (step >= 2 && 2 * step < sides && gcd(sides, step) == 1).then(|| {
+        let (n, k) = (sides as f32, step as f32);
+        (PI * k / n).cos() / (PI * (k - 1.0) / n).cos()
+    })
+}
+
+// The steps of the star polygons of `sides` points and their inner radii,
+// smallest step first
+pub fn stars(sides: u32) -> impl Iterator<Item = (u32, f32)> {
+    (2..sides).filter_map(move |step| Some((step, star_inner(sides, step)?)))
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+impl Shape {
+    // A star of `sides` points that is the first {sides/k} if there is one
+    pub fn star(sides: u32) -> Self {
+        match stars(sides).next() {
+            Some((step, inner)) => Shape { star: true, inner, step: Some(step), rounded: false },
+            None => Shape { star: true, ..Shape::default() },
+        }
+    }
+
+    pub fn describe(&self, sides: u32) -> String {
+        let kind = match (self.star, self.step) {
+            (false, _) => "regular".to_string(),
+            (true, Some(step)) => format!("star {{{}/{}}}", sides, step),
+            (true, None) => format!("star, inner {:.2}", self.inner),
+        };
+        if self.rounded { format!("{}, rounded", kind) } else { kind }
+    }
+
+    // Corners before rounding
+    pub fn corner_count(&self, sides: u32) -> u32 {
+        if self.star { 2 * sides } else { sides }
+    }
+
+    pub fn arc_points(&self) -> u32 {
+        if self.rounded { ARC_POINTS } else { 1 }
+    }
+
+    // Points around the outline, and so triangles in the fan
+    pub fn outline_count(&self, sides: u32) -> u32 {
+        self.corner_count(sides) * self.arc_points()
+    }
+
+    // Corner j of `sides`, the first one pointing straight up
+    fn corner(&self, sides: u32, radius: f32, j: u32) -> [f32; 2] {
+        let count = self.corner_count(sides);
+        let j = j % count;
+        let angle = FRAC_PI_2 + TAU * j as f32 / count as f32;
+        let radius = if self.star && j % 2 == 1 { radius * self.inner } else { radius };
+        [radius * angle.cos(), radius * angle.sin()]
+    }
+
+    // The whole outline, counterclockwise
+    pub fn outline(&self, sides: u32, radius: f32) -> Vec<[f32; 2]> {
+        let count = self.corner_count(sides);
+        let arc_points = self.arc_points();
+        (0..count)
+            .flat_map(|j| {
+                let corner = |j| self.corner(sides, radius, j);
+                let (before, at, after) = (corner(j + count - 1), corner(j), corner(j + 1));
+                (0..arc_points).map(move |a| {
+                    if arc_points == 1 { at } else { fillet(before, at, after, a as f32 / (arc_points - 1) as f32) }
+                })
+            })
+            .collect()
+    }
+}
+
+// The area of the loop `outline`, positive if it runs counterclockwise
+pub fn area(outline: &[[f32; 2]]) -> f32 {
+    let twice: f32 = outline
+        .iter()
+        .enumerate()
+        .map(|(i, &[x1, y1])| {
+            let [x2, y2] = outline[(i + 1) % outline.len()];
+            x1 * y2 - x2 * y1
+        })
+        .sum();
+    twice / 2.0
+}
+
+// Whether `point` lies inside the loop `outline`, by the even-odd rule: a
+// ray from the point crosses the edges an odd number of times if it
+// starts inside. Any simple loop works, stars and rounded corners
+// included.
+pub fn contains(outline: &[[f32; 2]], point: [f32; 2]) -> bool {
+    let [x, y] = point;
+    let mut inside = false;
+    for (i, &[x1, y1]) in outline.iter().enumerate() {
+        let [x2, y2] = outline[(i + 1) % outline.len()];
+        // Edges that straddle the horizontal through the point, crossed to
+        // its right
+        if (y1 > y) != (y2 > y) && x < x1 + (y - y1) / (y2 - y1) * (x2 - x1) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+// The outline a fraction t of the way from `from` to `to`, which may have
+// any number of points each. Both are sampled along the same rays from the
+// center, one through every point of either, so the result is exactly
+// `from` at 0.0 and `to` at 1.0, and in between each point slides along
+// its ray. The outlines here are all star-shaped about the center, so
+// every ray leaves them once.
+pub fn morph(from: &[[f32; 2]], to: &[[f32; 2]], t: f32) -> Vec<[f32; 2]> {
+    // Angles from straight up, where every outline starts, counterclockwise
+    let mut angles: Vec<f32> = from.iter().chain(to).map(|p| (p[1].atan2(p[0]) - FRAC_PI_2).rem_euclid(TAU)).collect();
+    angles.sort_by(f32::total_cmp);
+    angles.dedup_by(|a, b| *a - *b < 1e-5);
+    angles
+        .iter()
+        .map(|&angle| {
+            let direction = [(FRAC_PI_2 + angle).cos(), (FRAC_PI_2 + angle).sin()];
+            let radius = (1.0 - t) * radius_along(from, direction) + t * radius_along(to, direction);
+            [radius * direction[0], radius * direction[1]]
+        })
+        .collect()
+}
+
+// How far from the center the ray along the unit vector `direction`
+// leaves the loop `outline`
+fn radius_along(outline: &[[f32; 2]], direction: [f32; 2]) -> f32 {
+    let cross = |a: [f32; 2], b: [f32; 2]| a[0] * b[1] - a[1] * b[0];
+    let mut radius = 0.0f32;
+    for (i, &point) in outline.iter().enumerate() {
+        let edge = sub(outline[(i + 1) % outline.len()], point);
+        let denominator = cross(direction, edge);
+        if denominator.abs() < 1e-12 {
+            continue;
+        }
+        // Where point + s·edge = r·direction; the loose bounds on s keep
+        // rays through a corner from slipping between its two edges
+        let (s, r) = (cross(point, direction) / denominator, cross(point, edge) / denominator);
+        if (-1e-4..=1.0 + 1e-4).contains(&s) && r > 0.0 {
+            radius = radius.max(r);
+        }
+    }
+    radius
+}
+
+fn sub(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn length(a: [f32; 2]) -> f32 {
+    a[0].hypot(a[1])
+}
+
+fn normalize(a: [f32; 2]) -> [f32; 2] {
+    [a[0] / length(a), a[1] / length(a)]
+}
+
+// The point a fraction t of the way along the arc that rounds the corner
+// `at` between the edges to `before` and `after`. Convex and reflex
+// corners alike: the arc's circle sits on whichever side of the corner
+// the edges make the smaller angle.
+fn fillet(before: [f32; 2], at: [f32; 2], after: [f32; 2], t: f32) -> [f32; 2] {
+    let (to_before, to_after) = (sub(before, at), sub(after, at));
+    let (u, v) = (normalize(to_before), normalize(to_after));
+    let cos_angle = (u[0] * v[0] + u[1] * v[1]).clamp(-1.0, 1.0);
+    // A straight corner, as in a star whose inner radius puts it on the
+    // edge, has nothing to round
+    if cos_angle < -0.9999 {
+        return at;
+    }
+    let half = cos_angle.acos() / 2.0;
+    // Where the arc meets the edges, at most halfway along the shorter one
+    let reach = (CORNER_RADIUS / half.tan()).min(0.5 * length(to_before).min(length(to_after)));
+    let radius = reach * half.tan();
+    let bisector = normalize([u[0] + v[0], u[1] + v[1]]);
+    let offset = radius / half.sin();
+    let center = [at[0] + bisector[0] * offset, at[1] + bisector[1] * offset];
+    let start = sub([at[0] + u[0] * reach, at[1] + u[1] * reach], center);
+    let end = sub([at[0] + v[0] * reach, at[1] + v[1] * reach], center);
+    let start_angle = start[1].atan2(start[0]);
+    // The short way round, which is the side facing the corner
+    let sweep = (end[1].atan2(end[0]) - start_angle + PI).rem_euclid(TAU) - PI;
+    let angle = start_angle + sweep * t;
+    [center[0] + radius * angle.cos(), center[1] + radius * angle.sin()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn star_polygons_need_a_coprime_step_below_half() {
+        for (sides, step) in [(5, 2), (7, 2), (7, 3), (8, 3), (12, 5), (100, 49)] {
+            assert!(star_inner(sides, step).is_some(), "{{{}/{}}}", sides, step);
+        }
+        // Steps of half the sides or more go back over the same corners
+        for (sides, step) in [(5, 3), (6, 3), (8, 4), (8, 5), (3, 2), (4, 2)] {
+            assert_eq!(star_inner(sides, step), None, "{{{}/{}}}", sides, step);
+        }
+        // Common factors make compounds, and a step of 1 the polygon itself
+        for (sides, step) in [(6, 2), (9, 3), (10, 4), (12, 2), (12, 3), (7, 1), (7, 0)] {
+            assert_eq!(star_inner(sides, step), None, "{{{}/{}}}", sides, step);
+        }
+        assert_eq!(stars(12).map(|(step, _)| step).collect::<Vec<_>>(), vec![5]);
+        assert_eq!(stars(9).map(|(step, _)| step).collect::<Vec<_>>(), vec![2, 4]);
+        assert_eq!(stars(6).count(), 0);
+    }
+
+    #[test]
+    fn star_inner_corners_lie_on_the_lines_of_the_star_polygon() {
+        // The pentagram's inner radius is 1/φ²
+        assert!((star_inner(5, 2).unwrap() - 0.381_966).abs() < 1e-5);
+        for sides in [5, 7, 8, 11, 20] {
+            for (step, inner) in stars(sides) {
+                let shape = Shape { star: true, inner, step: Some(step), rounded: false };
+                let outline = shape.outline(sides, 0.5);
+                // Outer corners are every other point. The line from corner 0
+                // to corner `step` passes through the inner corner after 0.
+                let (from, to, inner_corner) = (outline[0], outline[2 * step as usize], outline[1]);
+                let (edge, offset) = (sub(to, from), sub(inner_corner, from));
+                let cross = edge[0] * offset[1] - edge[1] * offset[0];
+                assert!(cross.abs() < 1e-5, "{{{}/{}}}: {:?} off {:?} to {:?}", sides, step, inner_corner, from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn outlines_have_as_many_points_as_counted() {
+        for sides in [3, 4, 5, 8, 17, 100] {
+            let shapes = [Shape::default(), Shape::star(sides), Shape { star: true, inner: 0.3, step: None, rounded: false }];
+            for shape in shapes {
+                for rounded in [false, true] {
+                    let shape = Shape { rounded, ..shape };
+                    assert_eq!(shape.outline(sides, 0.5).len() as u32, shape.outline_count(sides), "{} sides, {:?}", sides, shape);
+                }
+            }
+        }
+    }
+}
@@ -1,12 +1,31 @@
 use bytemuck::{ Pod, Zeroable };
 use std::iter;
+use std::time::{ Duration, Instant };
 use wgpu::util::DeviceExt;
+use winit::dpi::{ PhysicalPosition, PhysicalSize };
+use winit::event::{ ElementState, MouseButton, MouseScrollDelta, WindowEvent };
 use winit::window::Window;
 
+use crate::adapter::{ self, Choice };
+use crate::overlay::Overlay;
+use crate::panel::{ Controls, Panel };
+use crate::particles::{ self, StepUniform };
+use crate::prism::{ self, Camera };
+use crate::recovery::{ Lost, RETRY_INTERVAL };
+use crate::scene::{ self, Polygon, RADIUS };
+use crate::screenshot;
+use crate::timing::{ FrameStats, GpuTimer };
+use crate::shape;
+use crate::svg;
+use crate::tessellation::{ self, Stroke };
+use crate::texture;
+
 #[repr(C)]
 #[derive(Copy, Clone, Pod, Zeroable)]
 struct Vertex {
     position: [f32; 3],
+    color: [f32; 3],
+    uv: [f32; 2],
 }
 
 impl Vertex {
@@ -19,137 +38,2263 @@ impl Vertex {
                     offset: 0,
                     shader_location: 0,
                     format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x2,
                 }
             ]
         }
     }
 }
 
-const VERTICES: &[Vertex] = &[
-    Vertex { position: [-0.5, 0.5, 0.0] },
-    Vertex { position: [ 0.5, -0.5, 0.0] },
-    Vertex { position: [ -0.5,  -0.5, 0.0] },
+// A corner of a face of prism mode's prisms, as Vertex with the face's
+// normal added
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct PrismVertex {
+    position: [f32; 3],
+    color: [f32; 3],
+    uv: [f32; 2],
+    normal: [f32; 3],
+}
 
-    Vertex { position: [ 0.5,  0.5, 0.0] },
-    Vertex { position: [ 0.5, -0.5, 0.0] },
-    Vertex { position: [ -0.5, -0.5, 0.0] }
-];
+impl PrismVertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<PrismVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x3,
+                }
+            ]
+        }
+    }
+}
 
-pub struct State {
-    surface: wgpu::Surface,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-    config: wgpu::SurfaceConfiguration,
-    pub size: winit::dpi::PhysicalSize<u32>,
-    render_pipeline: wgpu::RenderPipeline,
+// How the polygon reaches the GPU
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Geometry {
+    // No vertex buffer, vs_main places each vertex from its index
+    Shader,
+    // A triangle fan around a center vertex: N + 1 vertices shared through
+    // 3N indices, drawn with draw_indexed
+    Indexed,
+}
+
+impl Geometry {
+    pub fn name(self) -> &'static str {
+        match self {
+            Geometry::Shader => "shader",
+            Geometry::Indexed => "indexed",
+        }
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            Geometry::Shader => Geometry::Indexed,
+            Geometry::Indexed => Geometry::Shader,
+        }
+    }
+}
+
+// How the triangles of the indexed path are made
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tessellation {
+    // From the center to each edge, which only fills outlines that are
+    // star-shaped about it
+    Fan,
+    // By lyon, from the outline alone, which fills any outline and also
+    // strokes it. Every polygon is drawn from lyon's meshes, whatever the
+    // geometry, but for a morph's frames.
+    Lyon,
+}
+
+impl Tessellation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Tessellation::Fan => "fan",
+            Tessellation::Lyon => "lyon",
+        }
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            Tessellation::Fan => Tessellation::Lyon,
+            Tessellation::Lyon => Tessellation::Fan,
+        }
+    }
+}
+
+// How the polygon is colored. Each vertex gets a color and the rasterizer
+// blends them across the triangles; the shader path works the colors out
+// in vs_main, the indexed path stores them in the vertex buffer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fill {
+    Solid,
+    // The hue wheel around the corners, fading to white at the center
+    Angular,
+    // Pale yellow at the center to deep orange at the corners
+    Radial,
+    // The image of `--texture`, or a checkerboard, as the vertex colors are
+    // all white
+    Texture,
+}
+
+impl Fill {
+    pub fn name(self) -> &'static str {
+        match self {
+            Fill::Solid => "solid",
+            Fill::Angular => "angular gradient",
+            Fill::Radial => "radial gradient",
+            Fill::Texture => "texture",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Fill::Solid => Fill::Angular,
+            Fill::Angular => Fill::Radial,
+            Fill::Radial => Fill::Texture,
+            Fill::Texture => Fill::Solid,
+        }
+    }
+
+    // Colors of the center and of the outline point at `position`, matching
+    // the ones vs_main works out. Solid is the polygon's own `color`.
+    fn center(self, color: [f32; 3]) -> [f32; 3] {
+        match self {
+            Fill::Solid => color,
+            Fill::Angular | Fill::Texture => [1.0, 1.0, 1.0],
+            Fill::Radial => [1.0, 0.9, 0.5],
+        }
+    }
+
+    fn corner(self, color: [f32; 3], position: [f32; 2]) -> [f32; 3] {
+        match self {
+            Fill::Solid => color,
+            // By angle from straight up, so a morph between outlines keeps
+            // the colors where they are
+            Fill::Angular => hue((position[1].atan2(position[0]) - std::f32::consts::FRAC_PI_2) / std::f32::consts::TAU),
+            Fill::Texture => [1.0, 1.0, 1.0],
+            // By distance, so the inner corners of a star come out between
+            // the two
+            Fill::Radial => {
+                let t = position[0].hypot(position[1]) / RADIUS;
+                let center = self.center(color);
+                [0, 1, 2].map(|c| center[c] + (RADIAL_RIM[c] - center[c]) * t)
+            }
+        }
+    }
+}
+
+// What gets drawn: the filled polygon, its outline as a line strip around
+// the corners, or both. Wireframe draws the edges of every triangle of the
+// fill, which needs PolygonMode::Line and so the POLYGON_MODE_LINE feature.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Style {
+    Filled,
+    Outlined,
+    FilledOutlined,
+    Wireframe,
+}
+
+impl Style {
+    pub fn name(self) -> &'static str {
+        match self {
+            Style::Filled => "filled",
+            Style::Outlined => "outlined",
+            Style::FilledOutlined => "filled and outlined",
+            Style::Wireframe => "wireframe",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Style::Filled => Style::Outlined,
+            Style::Outlined => Style::FilledOutlined,
+            Style::FilledOutlined => Style::Wireframe,
+            Style::Wireframe => Style::Filled,
+        }
+    }
+}
+
+// How a polygon's fragments combine with what is already drawn, with
+// `opacity` as their alpha
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Blend {
+    // Opaque whatever the opacity
+    Replace,
+    // Over what is below, by opacity
+    Alpha,
+    // Added to what is below, scaled by opacity, so overlaps brighten
+    Additive,
+}
+
+impl Blend {
+    const ALL: [Blend; 3] = [Blend::Replace, Blend::Alpha, Blend::Additive];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Blend::Replace => "replace",
+            Blend::Alpha => "alpha blending",
+            Blend::Additive => "additive",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Blend::Replace => Blend::Alpha,
+            Blend::Alpha => Blend::Additive,
+            Blend::Additive => Blend::Replace,
+        }
+    }
+
+    fn state(self) -> wgpu::BlendState {
+        match self {
+            Blend::Replace => wgpu::BlendState::REPLACE,
+            Blend::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+            Blend::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::OVER,
+            },
+        }
+    }
+}
+
+// The present modes that `--present-mode` and the V key pick from, in the
+// order V goes through them. Fifo waits for vertical blank and is always
+// there; mailbox replaces a waiting frame with a newer one instead of
+// waiting behind it, and immediate does not wait at all, so it tears.
+pub const PRESENT_MODES: [wgpu::PresentMode; 3] = [wgpu::PresentMode::Fifo, wgpu::PresentMode::Mailbox, wgpu::PresentMode::Immediate];
+
+pub fn present_mode_name(present_mode: wgpu::PresentMode) -> &'static str {
+    match present_mode {
+        wgpu::PresentMode::AutoVsync => "auto vsync",
+        wgpu::PresentMode::AutoNoVsync => "auto no vsync",
+        wgpu::PresentMode::Fifo => "fifo",
+        wgpu::PresentMode::FifoRelaxed => "fifo relaxed",
+        wgpu::PresentMode::Immediate => "immediate",
+        wgpu::PresentMode::Mailbox => "mailbox",
+    }
+}
+
+const RADIAL_RIM: [f32; 3] = [0.8, 0.25, 0.05];
+
+// The fully saturated hue wheel, red at 0.0 and 1.0, piecewise linear in
+// between like the one in hsv-to-rgb
+fn hue(h: f32) -> [f32; 3] {
+    let h = h.rem_euclid(1.0) * 6.0;
+    [
+        ((h - 3.0).abs() - 1.0).clamp(0.0, 1.0),
+        (2.0 - (h - 2.0).abs()).clamp(0.0, 1.0),
+        (2.0 - (h - 4.0).abs()).clamp(0.0, 1.0),
+    ]
+}
+
+// One polygon of the scene as the vertex shader sees it, at group 1. There
+// is no vertex buffer on the shader path: the shader places every vertex
+// from its index and these, so changing the side count is a uniform write
+// and a different vertex count. Laid out like Polygon in shader.wgsl.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct PolygonUniform {
+    sides: u32,
+    radius: f32,
+    fill: u32,
+    star: u32,
+    inner: f32,
+    corner_radius: f32,
+    arc_points: u32,
+    selected: u32,
+}
+
+impl PolygonUniform {
+    fn new(polygon: &Polygon, selected: bool) -> Self {
+        PolygonUniform {
+            sides: polygon.sides,
+            radius: RADIUS,
+            fill: polygon.fill as u32,
+            star: polygon.shape.star as u32,
+            inner: polygon.shape.inner,
+            corner_radius: shape::CORNER_RADIUS,
+            arc_points: polygon.shape.arc_points(),
+            selected: selected as u32,
+        }
+    }
+}
+
+// The rest of a polygon, which the mouse changes from frame to frame. It is
+// pushed with every draw where the adapter has push constants, which skips
+// the buffer write and keeps nothing on the GPU between draws, and is
+// otherwise a second uniform at group 1. Laid out like Draw in
+// shader.wgsl, where `color` is a vec3f that `opacity` fills out to 16
+// bytes, and the whole struct rounds up to a multiple of 16.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct Draw {
+    color: [f32; 3],
+    opacity: f32,
+    offset: [f32; 2],
+    scale: f32,
+    angular_velocity: f32,
+    phase: f32,
+    _padding: [f32; 3],
+}
+
+impl Draw {
+    fn new(polygon: &Polygon) -> Self {
+        Draw {
+            color: polygon.color,
+            opacity: polygon.opacity,
+            offset: polygon.offset,
+            scale: polygon.scale,
+            angular_velocity: polygon.angular_velocity,
+            phase: polygon.phase,
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+const DRAW_SIZE: u32 = std::mem::size_of::<Draw>() as u32;
+
+// The declaration of `draw` in shader.wgsl and what replaces it on the push
+// constant path
+const DRAW_UNIFORM: &str = "@group(1) @binding(1)\nvar<uniform> draw: Draw;";
+const DRAW_PUSH_CONSTANT: &str = "var<push_constant> draw: Draw;";
+
+// The same for the constant that tells fs_main the surface is sRGB
+const LINEAR_SURFACE: &str = "const SRGB_SURFACE = false;";
+const SRGB_SURFACE: &str = "const SRGB_SURFACE = true;";
+
+// The source of shader.wgsl for the path in use and the surface's format.
+// A reloaded shader that no longer declares `draw` as above is left as it
+// is, and fails to fit the push constant pipelines.
+fn shader_source(source: &str, push_constants: bool, format: wgpu::TextureFormat) -> String {
+    let source = if push_constants { source.replace(DRAW_UNIFORM, DRAW_PUSH_CONSTANT) } else { source.to_string() };
+    if format.is_srgb() { source.replace(LINEAR_SURFACE, SRGB_SURFACE) } else { source }
+}
+
+// An sRGB color component as the linear value an sRGB surface turns back
+// into it, the inverse of the encoding the surface does on writes
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+// `--surface-format`: the kind of format the surface is taken in. Either
+// way the colors on screen are those written in the code; an sRGB surface
+// also blends and resolves MSAA in linear light, as they should be.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpace {
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "srgb",
+            ColorSpace::Linear => "linear",
+        }
+    }
+
+    fn matches(self, format: wgpu::TextureFormat) -> bool {
+        format.is_srgb() == (self == ColorSpace::Srgb)
+    }
+}
+
+// The animation clock, rewritten every frame. Every polygon turns by its
+// own phase + angular_velocity·time radians.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct TimeUniform {
+    time: f32,
+    _padding: [f32; 3],
+}
+
+// The camera of prism mode, rewritten as it turns or the window resizes
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct CameraUniform {
+    view_projection: [[f32; 4]; 4],
+}
+
+impl CameraUniform {
+    fn new(camera: &Camera, size: PhysicalSize<u32>) -> Self {
+        CameraUniform { view_projection: camera.view_projection(size.width, size.height).to_cols_array_2d() }
+    }
+}
+
+// Scale from the square the polygons are laid out in to clip space, which
+// squeezes the longer side of the window so the polygons stay regular
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct ProjectionUniform {
+    scale: [f32; 2],
+    _padding: [f32; 2],
+}
+
+impl ProjectionUniform {
+    fn new(size: PhysicalSize<u32>) -> Self {
+        let (width, height) = (size.width.max(1) as f32, size.height.max(1) as f32);
+        let scale = if width > height { [height / width, 1.0] } else { [1.0, width / height] };
+        ProjectionUniform { scale, _padding: [0.0; 2] }
+    }
+}
+
+// Range of the wheel zoom, and the change per notch
+pub const MIN_SCALE: f32 = 0.1;
+pub const MAX_SCALE: f32 = 10.0;
+const SCALE_PER_LINE: f32 = 1.1;
+// Touchpads scroll in pixels; this many make one notch
+const PIXELS_PER_LINE: f32 = 50.0;
+
+// The color the frame is cleared to, and the one at the top of the
+// window that the background gradient fades from, unless the command line
+// says otherwise
+pub const BACKGROUND: [f32; 3] = [0.2, 0.3, 0.3];
+pub const GRADIENT_TOP: [f32; 3] = [0.05, 0.08, 0.12];
+
+// What the scene is drawn over: the clear color, and while `gradient` is
+// on, a fade from `top` at the top of the window down to the clear color
+// at the bottom, which the background pipeline draws before the scene
+#[derive(Clone, Copy, PartialEq)]
+pub struct Background {
+    pub color: [f32; 3],
+    pub top: [f32; 3],
+    pub gradient: bool,
+}
+
+// The two ends of the background gradient
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct BackgroundUniform {
+    top: [f32; 3],
+    _padding: f32,
+    bottom: [f32; 3],
+    _padding2: f32,
+}
+
+impl BackgroundUniform {
+    fn new(background: &Background) -> Self {
+        BackgroundUniform { top: background.top, _padding: 0.0, bottom: background.color, _padding2: 0.0 }
+    }
+}
+
+// A spin stops counting towards the inertia if the mouse rests this long
+// before the button comes up
+const SPIN_REST: f32 = 0.1;
+
+// What a held mouse button is doing to the current polygon
+enum Drag {
+    // Left button: the polygon follows the cursor
+    Move,
+    // Right button: the polygon turns with the cursor about its center.
+    // Its own rotation is held at `resume` meanwhile.
+    Spin {
+        angle: f32,
+        moved: Instant,
+        velocity: f32,
+        resume: f32,
+    },
+    // Either button in prism mode: the camera circles the scene until
+    // `button` comes up
+    Orbit {
+        button: MouseButton,
+    },
+}
+
+// Texture coordinates of a point in the polygon's own frame, as uv_at in
+// shader.wgsl works them out: the square around the circle of the corners
+// covers the whole image, with v running down the image as its rows do
+fn uv(position: [f32; 2]) -> [f32; 2] {
+    [0.5 + position[0] / (2.0 * RADIUS), 0.5 - position[1] / (2.0 * RADIUS)]
+}
+
+// A vertex at `point` of the outline, or anywhere off center, in the fill
+// of `polygon`
+fn outline_vertex(polygon: &Polygon, &[x, y]: &[f32; 2]) -> Vertex {
+    Vertex { position: [x, y, 0.0], color: polygon.fill.corner(polygon.color, [x, y]), uv: uv([x, y]) }
+}
+
+// The fan of the indexed path: the center followed by the points of
+// `outline` in the fill of `polygon`, and the first point again so a line
+// strip through all but the center goes all the way round
+fn fan_vertices(polygon: &Polygon, outline: &[[f32; 2]]) -> Vec<Vertex> {
+    let point = |point| outline_vertex(polygon, point);
+    let center = Vertex { position: [0.0, 0.0, 0.0], color: polygon.fill.center(polygon.color), uv: uv([0.0, 0.0]) };
+    iter::once(center).chain(outline.iter().map(point)).chain(outline.first().map(point)).collect()
+}
+
+// One triangle from the center to each edge of an outline of `count`
+// points, wrapping around to the first. Even a rounded star of MAX_SIDES
+// points keeps the indices well within u16.
+fn fan_indices(count: u32) -> Vec<u16> {
+    (0..count)
+        .flat_map(|i| [0, i + 1, (i + 1) % count + 1])
+        .map(|index| index as u16)
+        .collect()
+}
+
+// Vertex and index buffers of the fan. The vertex buffer can be rewritten
+// with another outline of as many points, as a morph does every frame.
+fn fan_buffers(device: &wgpu::Device, polygon: &Polygon, outline: &[[f32; 2]]) -> (wgpu::Buffer, wgpu::Buffer) {
+    let vertex_buffer = device.create_buffer_init(
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(&fan_vertices(polygon, outline)),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        }
+    );
+    let index_buffer = device.create_buffer_init(
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: bytemuck::cast_slice(&fan_indices(outline.len() as u32)),
+            usage: wgpu::BufferUsages::INDEX,
+        }
+    );
+    (vertex_buffer, index_buffer)
+}
+
+// The buffers of a mesh of lyon's or a prism, with its vertices in the
+// fill of the polygon. The gradients are by position rather than by
+// corner, so they look the same as on the fan but for where lyon puts no
+// vertex.
+struct MeshBuffers {
     vertex_buffer: wgpu::Buffer,
-    pub window: Window,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    index_format: wgpu::IndexFormat,
 }
 
-impl State {
-    pub async fn new(window: Window) -> Self {
-        let size = window.inner_size();
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
-        let surface = unsafe { instance.create_surface(&window) }.unwrap();
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
+impl MeshBuffers {
+    fn new(device: &wgpu::Device, polygon: &Polygon, mesh: &tessellation::Mesh) -> Self {
+        let vertices: Vec<Vertex> = mesh.vertices.iter().map(|point| outline_vertex(polygon, point)).collect();
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Mesh Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Mesh Index Buffer"),
+                contents: bytemuck::cast_slice(&mesh.indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+        MeshBuffers { vertex_buffer, index_buffer, index_count: mesh.indices.len() as u32, index_format: wgpu::IndexFormat::Uint16 }
+    }
+
+    // The prism of the polygon. Its walls alone have four vertices for
+    // every point of the outline, so the indices are u32.
+    fn prism(device: &wgpu::Device, polygon: &Polygon) -> Self {
+        let mesh = prism::extrude(&polygon.loops());
+        let vertices: Vec<PrismVertex> = mesh
+            .positions
+            .iter()
+            .zip(&mesh.normals)
+            .map(|(&[x, y, z], &normal)| PrismVertex {
+                position: [x, y, z],
+                color: polygon.fill.corner(polygon.color, [x, y]),
+                uv: uv([x, y]),
+                normal,
             })
-            .await
-            .unwrap();
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: Some("Main Device"),
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                },
-                None,
-            )
-            .await
-            .unwrap();
-        let surface_caps = surface.get_capabilities(&adapter);
-        let surface_format = surface_caps.formats.iter()
-            .find(|f| !f.is_srgb())
-            .copied()
-            .unwrap_or(surface_caps.formats[0]);
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: size.width,
-            height: size.height,
-            present_mode: surface_caps.present_modes[0],
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
-        };
-        surface.configure(&device, &config);
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Triangle Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("./shader.wgsl").into()),
-        });
+            .collect();
         let vertex_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(VERTICES),
+                label: Some("Prism Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertices),
                 usage: wgpu::BufferUsages::VERTEX,
             }
         );
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[],
-                push_constant_ranges: &[],
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Prism Index Buffer"),
+                contents: bytemuck::cast_slice(&mesh.indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+        MeshBuffers { vertex_buffer, index_buffer, index_count: mesh.indices.len() as u32, index_format: wgpu::IndexFormat::Uint32 }
+    }
+}
+
+// lyon's fill of a polygon's loops and the stroke around them
+struct Tessellated {
+    fill: MeshBuffers,
+    stroke: MeshBuffers,
+}
+
+impl Tessellated {
+    fn new(device: &wgpu::Device, polygon: &Polygon, stroke: Stroke) -> Self {
+        let loops = polygon.loops();
+        Tessellated {
+            fill: MeshBuffers::new(device, polygon, &tessellation::fill(&loops)),
+            stroke: MeshBuffers::new(device, polygon, &tessellation::stroke(&loops, stroke)),
+        }
+    }
+}
+
+// A change of outline being eased in: the outline on screen when it
+// started, which may be partway through another morph, and the polygon's
+// new one
+struct Morph {
+    from: Vec<[f32; 2]>,
+    to: Vec<[f32; 2]>,
+    started: Instant,
+}
+
+impl Morph {
+    // How far along a morph of `duration` seconds is, eased in and out
+    fn progress(&self, duration: f32) -> f32 {
+        let t = ((Instant::now() - self.started).as_secs_f32() / duration).min(1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+// A polygon of the scene with what draws it: its uniforms and the bind
+// group that puts them at group 1, the fan buffers of the indexed path and
+// lyon's meshes where it has them
+struct Layer {
+    polygon: Polygon,
+    polygon_buffer: wgpu::Buffer,
+    // The Draw uniform, None on the push constant path
+    draw_buffer: Option<wgpu::Buffer>,
+    bind_group: wgpu::BindGroup,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    // Points of the outline in the fan buffers
+    outline_count: u32,
+    // While there is one, the polygon is drawn from the fan buffers
+    // whatever the geometry and tessellation, as neither the shader path
+    // nor lyon's meshes have the outline of the moment
+    morph: Option<Morph>,
+    // With lyon's tessellation, or an imported outline, the polygon is
+    // drawn from these the rest of the time
+    tessellated: Option<Tessellated>,
+    // The polygon's prism, only kept in prism mode. It is built from the
+    // polygon's own outline, so a morph snaps there.
+    prism: Option<MeshBuffers>,
+}
+
+impl Layer {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, polygon: Polygon, push_constants: bool) -> Self {
+        let polygon_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Polygon Buffer"),
+                contents: bytemuck::cast_slice(&[PolygonUniform::new(&polygon, false)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let draw_buffer = (!push_constants).then(|| device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Draw Buffer"),
+                contents: bytemuck::cast_slice(&[Draw::new(&polygon)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        ));
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: polygon_buffer.as_entire_binding(),
+            }
+        ];
+        if let Some(draw_buffer) = &draw_buffer {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 1,
+                resource: draw_buffer.as_entire_binding(),
             });
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Polygon Bind Group"),
+            layout,
+            entries: &entries,
+        });
+        let outline = polygon.outline();
+        let (vertex_buffer, index_buffer) = fan_buffers(device, &polygon, &outline);
+        Layer {
+            polygon,
+            polygon_buffer,
+            draw_buffer,
+            bind_group,
+            vertex_buffer,
+            index_buffer,
+            outline_count: outline.len() as u32,
+            morph: None,
+            tessellated: None,
+            prism: None,
+        }
+    }
+
+    // The outline on screen, the polygon's own or as far as a morph of
+    // `duration` seconds has got
+    fn outline(&self, duration: f32) -> Vec<[f32; 2]> {
+        match &self.morph {
+            Some(morph) => shape::morph(&morph.from, &morph.to, morph.progress(duration)),
+            None => self.polygon.outline(),
+        }
+    }
+
+    // Whether the polygon has to be drawn from the fan buffers, which the
+    // shader path cannot stand in for while it morphs
+    fn drawn_from_buffers(&self) -> bool {
+        self.morph.is_some()
+    }
+
+    // lyon's meshes, when they are drawn
+    fn meshes(&self) -> Option<&Tessellated> {
+        self.tessellated.as_ref().filter(|_| self.morph.is_none())
+    }
+
+    fn rebuild_fan(&mut self, device: &wgpu::Device, outline: &[[f32; 2]]) {
+        (self.vertex_buffer, self.index_buffer) = fan_buffers(device, &self.polygon, outline);
+        self.outline_count = outline.len() as u32;
+    }
+
+    // Builds lyon's meshes of the polygon if `lyon` asks for them or the
+    // fan cannot fill its outline, and drops them otherwise
+    fn tessellate(&mut self, device: &wgpu::Device, lyon: bool, stroke: Stroke) {
+        self.tessellated = (lyon || self.polygon.imported.is_some()).then(|| Tessellated::new(device, &self.polygon, stroke));
+    }
+
+    // Builds the polygon's prism in prism mode, and drops it otherwise
+    fn extrude(&mut self, device: &wgpu::Device, prism: bool) {
+        self.prism = prism.then(|| MeshBuffers::prism(device, &self.polygon));
+    }
+}
+
+// The render pipelines of one blend mode
+struct Pipelines {
+    render: wgpu::RenderPipeline,
+    indexed: wgpu::RenderPipeline,
+    outline: wgpu::RenderPipeline,
+    // The outline from the fan's vertex buffer, for morphs
+    indexed_outline: wgpu::RenderPipeline,
+    // lyon's stroke of the outline, in triangles
+    stroke: wgpu::RenderPipeline,
+    // Prism mode's prisms, the only pipeline with a depth test
+    prism: wgpu::RenderPipeline,
+    // The background gradient, replacing whatever the blend mode, and its
+    // twin for prism mode's pass, which has a depth target it leaves be
+    background: wgpu::RenderPipeline,
+    prism_background: wgpu::RenderPipeline,
+    // The fill pipelines of the shader and indexed paths with
+    // PolygonMode::Line, if the adapter can draw that
+    wireframe: Option<(wgpu::RenderPipeline, wgpu::RenderPipeline)>,
+    particle: wgpu::RenderPipeline,
+}
+
+// The render pipelines of every blend mode, in the order of Blend::ALL,
+// from the entry points of `shader`
+fn create_pipelines(
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+    layout: &wgpu::PipelineLayout,
+    particle_layout: &wgpu::PipelineLayout,
+    background_layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> [Pipelines; 3] {
+    let depth_pipeline = |label, layout, entry_point, buffers, topology, polygon_mode, blend: Blend, depth_stencil| {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
             vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc()],
+                module: shader,
+                entry_point,
+                buffers,
             },
             fragment: Some(wgpu::FragmentState {
-                module: &shader,
+                module: shader,
                 entry_point: "fs_main",
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
+                    format,
+                    blend: Some(blend.state()),
+                    // The alpha stays at the clear's 1.0, so the window
+                    // never turns see-through where the surface could
+                    write_mask: wgpu::ColorWrites::COLOR,
                 })],
             }),
             primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
+                topology,
+                polygon_mode,
+                ..Default::default()
+            },
+            depth_stencil,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
                 ..Default::default()
             },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
             multiview: None,
-        });
-        Self {
+        })
+    };
+    let pipeline = |label, layout, entry_point, buffers, topology, polygon_mode, blend| {
+        depth_pipeline(label, layout, entry_point, buffers, topology, polygon_mode, blend, None)
+    };
+    // Nearer faces hide farther ones whatever order they come in, and as
+    // the depth test leaves nothing to culling, the faces may wind either
+    // way
+    let depth = wgpu::DepthStencilState {
+        format: DEPTH_FORMAT,
+        depth_write_enabled: true,
+        depth_compare: wgpu::CompareFunction::Less,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+    };
+    let behind = wgpu::DepthStencilState {
+        depth_write_enabled: false,
+        depth_compare: wgpu::CompareFunction::Always,
+        ..depth.clone()
+    };
+    let prism_layout = [PrismVertex::desc()];
+    let triangles = wgpu::PrimitiveTopology::TriangleList;
+    let fan_layout = [Vertex::desc()];
+    Blend::ALL.map(|blend| Pipelines {
+        render: pipeline("Render Pipeline", layout, "vs_main", &[], triangles, wgpu::PolygonMode::Fill, blend),
+        indexed: pipeline("Indexed Pipeline", layout, "vs_indexed", &fan_layout, triangles, wgpu::PolygonMode::Fill, blend),
+        outline: pipeline("Outline Pipeline", layout, "vs_outline", &[], wgpu::PrimitiveTopology::LineStrip, wgpu::PolygonMode::Fill, blend),
+        indexed_outline: pipeline("Indexed Outline Pipeline", layout, "vs_indexed_outline", &fan_layout, wgpu::PrimitiveTopology::LineStrip, wgpu::PolygonMode::Fill, blend),
+        stroke: pipeline("Stroke Pipeline", layout, "vs_indexed_outline", &fan_layout, triangles, wgpu::PolygonMode::Fill, blend),
+        prism: depth_pipeline("Prism Pipeline", layout, "vs_prism", &prism_layout, triangles, wgpu::PolygonMode::Fill, blend, Some(depth.clone())),
+        background: pipeline("Background Pipeline", background_layout, "vs_background", &[], triangles, wgpu::PolygonMode::Fill, Blend::Replace),
+        prism_background: depth_pipeline(
+            "Prism Background Pipeline", background_layout, "vs_background", &[], triangles, wgpu::PolygonMode::Fill, Blend::Replace, Some(behind.clone())
+        ),
+        wireframe: device.features().contains(wgpu::Features::POLYGON_MODE_LINE).then(|| (
+            pipeline("Wireframe Pipeline", layout, "vs_main", &[], triangles, wgpu::PolygonMode::Line, blend),
+            pipeline("Indexed Wireframe Pipeline", layout, "vs_indexed", &fan_layout, triangles, wgpu::PolygonMode::Line, blend),
+        )),
+        particle: pipeline("Particle Pipeline", particle_layout, "vs_particle", &[], triangles, wgpu::PolygonMode::Fill, blend),
+    })
+}
+
+// How the window starts out, from the command line, and starts out again
+// on a new device after the last one was lost
+#[derive(Clone)]
+pub struct Options {
+    pub sides: u32,
+    pub geometry: Geometry,
+    pub tessellation: Tessellation,
+    // Width and joins of lyon's stroke, of the outlined styles
+    pub stroke: Stroke,
+    pub particles: bool,
+    // Start in prism mode
+    pub prism: bool,
+    // Samples per pixel, 1 for no multisampling. Counts the surface format
+    // does not support fall back to the largest one below that it does.
+    pub sample_count: u32,
+    // The image of the texture fill, which the first polygon starts with
+    // if there is one
+    pub texture: Option<image::RgbaImage>,
+    // Seconds a change of outline takes to morph into place, 0.0 to snap
+    pub morph: f32,
+    // The loops of an outline from `--import`, which the first polygon
+    // takes in place of `sides`
+    pub outlines: Option<Vec<Vec<[f32; 2]>>>,
+    // Hand each polygon's Draw to the shader in push constants, if the
+    // adapter has them, rather than a uniform buffer
+    pub push_constants: bool,
+    // One of PRESENT_MODES, falling back to fifo if the surface lacks it,
+    // or None for the surface's own first choice
+    pub present_mode: Option<wgpu::PresentMode>,
+    // The graphics APIs to look for adapters in, all of them by default,
+    // and the adapter among them to run on, if not the one wgpu prefers
+    pub backends: wgpu::Backends,
+    pub adapter: Option<Choice>,
+    // The kind of surface format to insist on, or None to take an sRGB one
+    // where there is one
+    pub color_space: Option<ColorSpace>,
+    // Frames per second not to go over, None to keep up with the present
+    // mode
+    pub max_fps: Option<f32>,
+    pub background: Background,
+}
+
+// The depth target of prism mode, as multisampled as the color target
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+fn depth_view(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Texture"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+// The multisampled color target that the render pass draws into and
+// resolves to the surface, or None without multisampling
+fn msaa_view(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32) -> Option<wgpu::TextureView> {
+    (sample_count > 1).then(|| {
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("MSAA Texture"),
+                size: wgpu::Extent3d {
+                    width: config.width,
+                    height: config.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: config.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    })
+}
+
+// The adapter and the device on it that a state draws with: the part of
+// setting one up that can fail, and that recover tries again until it
+// does not
+struct Gpu {
+    adapter: wgpu::Adapter,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    // Whether Draw goes in push constants, which the device is asked for
+    push_constants: bool,
+}
+
+impl Gpu {
+    async fn request(instance: &wgpu::Instance, surface: Option<&wgpu::Surface>, options: &Options, choice: Option<&Choice>) -> Result<Self, String> {
+        let adapter = adapter::request(
+            instance, options.backends, choice, surface, wgpu::PowerPreference::default()
+        ).await?;
+        // Push constants are a native extension, and even adapters that have
+        // them may take fewer bytes than a Draw. The GL backend only mimics
+        // them with plain uniforms, and fails on fields a shader leaves
+        // unread, such as vs_particle does most of Draw.
+        let push_constants = options.push_constants
+            && adapter.features().contains(wgpu::Features::PUSH_CONSTANTS)
+            && adapter.limits().max_push_constant_size >= DRAW_SIZE
+            && adapter.get_info().backend != wgpu::Backend::Gl;
+        if options.push_constants && !push_constants {
+            eprintln!("Push constants are not supported by this adapter, using a uniform buffer per polygon");
+        }
+        let push_constant_feature = if push_constants { wgpu::Features::PUSH_CONSTANTS } else { wgpu::Features::empty() };
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("Main Device"),
+                    features: adapter.features()
+                        & (wgpu::Features::POLYGON_MODE_LINE
+                            | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                            | wgpu::Features::TIMESTAMP_QUERY)
+                        | push_constant_feature,
+                    limits: wgpu::Limits {
+                        max_push_constant_size: if push_constants { DRAW_SIZE } else { 0 },
+                        ..Default::default()
+                    },
+                },
+                None,
+            )
+            .await
+            .map_err(|e| format!("Failed to create a device: {}", e))?;
+        Ok(Gpu { adapter, device, queue, push_constants })
+    }
+}
+
+pub struct State {
+    // None when headless, as is the window
+    surface: Option<wgpu::Surface>,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    // Those of PRESENT_MODES the surface supports, none when headless
+    present_modes: Vec<wgpu::PresentMode>,
+    sample_count: u32,
+    // What the panel's MSAA switch turns on: the count asked for, or 4
+    // if that was 1, as far as the format supports it
+    msaa_samples: u32,
+    msaa_view: Option<wgpu::TextureView>,
+    depth_view: wgpu::TextureView,
+    pub size: PhysicalSize<u32>,
+    // One set for each blend mode, in the order of Blend::ALL
+    pipelines: [Pipelines; 3],
+    blend: Blend,
+    time_buffer: wgpu::Buffer,
+    projection_buffer: wgpu::Buffer,
+    camera_buffer: wgpu::Buffer,
+    background_buffer: wgpu::Buffer,
+    // The clock, the projection, the texture, the camera and the
+    // background at group 0, the same for every polygon
+    uniform_bind_group: wgpu::BindGroup,
+    polygon_bind_group_layout: wgpu::BindGroupLayout,
+    // Whether Draw goes in push constants rather than a uniform per layer
+    push_constants: bool,
+    // Kept to build the pipelines again when the shader is reloaded or
+    // the sample count changes
+    shader: wgpu::ShaderModule,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    particle_pipeline_layout: wgpu::PipelineLayout,
+    background_pipeline_layout: wgpu::PipelineLayout,
+    step_buffer: wgpu::Buffer,
+    compute_pipeline: wgpu::ComputePipeline,
+    compute_bind_group: wgpu::BindGroup,
+    particle_bind_group: wgpu::BindGroup,
+    // Draw the particle swarm, made of copies of the current polygon,
+    // instead of the scene
+    particles: bool,
+    // Draw the scene as prisms through the camera instead of flat
+    prism: bool,
+    camera: Camera,
+    geometry: Geometry,
+    tessellation: Tessellation,
+    stroke: Stroke,
+    style: Style,
+    background: Background,
+    // The scene back to front, never empty
+    layers: Vec<Layer>,
+    // The polygon the last click hit, which is drawn lighter until a click
+    // misses. The keys and the mouse act on it, or on the topmost polygon
+    // while there is none.
+    selected: Option<usize>,
+    // Seconds the animation has run, not counting pauses, and by how much
+    // the last update moved it on
+    time: f32,
+    dt: f32,
+    last_frame: Instant,
+    paused: bool,
+    // The least time from one frame to the next, zero without a cap
+    frame_interval: Duration,
+    // Something has changed that the next frame has to show, which nothing
+    // else would draw while the scene stands still
+    dirty: bool,
+    // Whether the scene was moving as of the last frame. Otherwise the
+    // time since then was spent waiting for an event, and is neither
+    // animation time nor a frame time.
+    animated: bool,
+    cursor: Option<PhysicalPosition<f64>>,
+    drag: Option<Drag>,
+    // Let go of a spin, the polygon carries on at the speed of the drag
+    inertia: bool,
+    morph_duration: f32,
+    // Draw the next frame into a screenshot as well
+    screenshot: bool,
+    // Screenshots waiting for their buffers to be mapped
+    captures: Vec<screenshot::Capture>,
+    // None without TIMESTAMP_QUERY, when the title only has the CPU's
+    // frame time
+    gpu_timer: Option<GpuTimer>,
+    frame_stats: FrameStats,
+    // The last report of frame_stats, shown in the title
+    frame_times: Option<String>,
+    // Draw calls of the last frame's render pass, for the overlay
+    draw_calls: u32,
+    // None when headless, as is the panel
+    overlay: Option<Overlay>,
+    // None when headless
+    panel: Option<Panel>,
+    // What the state was made from, for recover to make the next one
+    options: Options,
+    // The source of the last shader hot reloaded, which recover builds
+    // the new pipelines from in place of the one built in
+    reloaded_shader: Option<String>,
+    // Set once the device is lost, after which nothing draws until
+    // recover has made a new one, tried no sooner than retry_at
+    lost: Lost,
+    retry_at: Instant,
+    // Last, as fields are dropped in order and the surface made for the
+    // window has to go before it does
+    window: Option<Window>,
+}
+
+impl State {
+    pub async fn new(window: Window, options: &Options) -> Self {
+        let size = window.inner_size();
+        let instance = adapter::instance(options.backends);
+        let surface = unsafe { instance.create_surface(&window) }.unwrap();
+        let gpu = Gpu::request(&instance, Some(&surface), options, options.adapter.as_ref()).await.unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        Self::create(gpu, Some(surface), Some(window), size, options)
+    }
+
+    // A state with no window or surface, which only draws through
+    // headless_frame, for `--headless`. Nothing here needs a display.
+    pub async fn headless(size: PhysicalSize<u32>, options: &Options) -> Self {
+        let instance = adapter::instance(options.backends);
+        let gpu = Gpu::request(&instance, None, options, options.adapter.as_ref()).await.unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        Self::create(gpu, None, None, size, options)
+    }
+
+    fn create(
+        gpu: Gpu,
+        surface: Option<wgpu::Surface>,
+        window: Option<Window>,
+        size: PhysicalSize<u32>,
+        options: &Options,
+    ) -> Self {
+        let (geometry, particles) = (options.geometry, options.particles);
+        let Gpu { adapter, device, queue, push_constants } = gpu;
+        let lost = Lost::watch(&device);
+        // Headless, the configuration is never applied to anything; it
+        // only gives the format and size of the offscreen frame
+        let color_space = options.color_space.unwrap_or(ColorSpace::Srgb);
+        let (config, present_modes) = match &surface {
+            Some(surface) => {
+                let surface_caps = surface.get_capabilities(&adapter);
+                let surface_format = surface_caps.formats.iter()
+                    .find(|&&format| color_space.matches(format))
+                    .copied()
+                    .unwrap_or_else(|| {
+                        if options.color_space.is_some() {
+                            eprintln!("This surface has no {} format, using {:?}", color_space.name(), surface_caps.formats[0]);
+                        }
+                        surface_caps.formats[0]
+                    });
+                let present_mode = match options.present_mode {
+                    Some(present_mode) if surface_caps.present_modes.contains(&present_mode) => present_mode,
+                    Some(present_mode) => {
+                        eprintln!("{} is not supported by this surface, using fifo", present_mode_name(present_mode));
+                        wgpu::PresentMode::Fifo
+                    }
+                    None => surface_caps.present_modes[0],
+                };
+                let present_modes = PRESENT_MODES.into_iter().filter(|mode| surface_caps.present_modes.contains(mode)).collect();
+                let config = wgpu::SurfaceConfiguration {
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    format: surface_format,
+                    width: size.width,
+                    height: size.height,
+                    present_mode,
+                    alpha_mode: surface_caps.alpha_modes[0],
+                    view_formats: vec![],
+                };
+                (config, present_modes)
+            }
+            None => (wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                format: match color_space {
+                    ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+                    ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+                },
+                width: size.width,
+                height: size.height,
+                present_mode: wgpu::PresentMode::Fifo,
+                alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+                view_formats: vec![],
+            }, Vec::new()),
+        };
+        if let Some(surface) = &surface {
+            surface.configure(&device, &config);
+        }
+        // Beyond the counts every adapter guarantees, which are 1 and 4 for
+        // the usual surface formats, the adapter has to say what it can do
+        let format_features = if device.features().contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+            adapter.get_texture_format_features(config.format)
+        } else {
+            config.format.guaranteed_format_features(device.features())
+        };
+        let supported_sample_count = |requested: u32| {
+            (0..=requested.ilog2())
+                .rev()
+                .map(|exponent| 1 << exponent)
+                .find(|&count| format_features.flags.sample_count_supported(count))
+                .unwrap_or(1)
+        };
+        let sample_count = supported_sample_count(options.sample_count);
+        let msaa_samples = supported_sample_count(options.sample_count.max(4));
+        if sample_count != options.sample_count {
+            eprintln!("{}x MSAA is not supported for {:?}, using {}x", options.sample_count, config.format, sample_count);
+        }
+        let msaa_view = msaa_view(&device, &config, sample_count);
+        let depth_view = depth_view(&device, &config, sample_count);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Triangle Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source(include_str!("./shader.wgsl"), push_constants, config.format).into()),
+        });
+        let time_uniform = TimeUniform { time: 0.0, _padding: [0.0; 3] };
+        let time_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Time Buffer"),
+                contents: bytemuck::cast_slice(&[time_uniform]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let projection_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Projection Buffer"),
+                contents: bytemuck::cast_slice(&[ProjectionUniform::new(size)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let camera = Camera::new();
+        let camera_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Camera Buffer"),
+                contents: bytemuck::cast_slice(&[CameraUniform::new(&camera, size)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let background_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Background Buffer"),
+                contents: bytemuck::cast_slice(&[BackgroundUniform::new(&options.background)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let texture_view = texture::upload(&device, &queue, options.texture.as_ref().unwrap_or(&texture::checkerboard()));
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Texture Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Uniform Bind Group Layout"),
+                entries: &[
+                    uniform_entry(0),
+                    uniform_entry(1),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    uniform_entry(4),
+                    uniform_entry(5),
+                ],
+            });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Uniform Bind Group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: time_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: projection_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: background_buffer.as_entire_binding(),
+                }
+            ],
+        });
+        let polygon_entries = [uniform_entry(0), uniform_entry(1)];
+        let polygon_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Polygon Bind Group Layout"),
+                entries: if push_constants { &polygon_entries[..1] } else { &polygon_entries },
+            });
+        let draw_range = [wgpu::PushConstantRange {
+            stages: wgpu::ShaderStages::VERTEX,
+            range: 0..DRAW_SIZE,
+        }];
+        let push_constant_ranges: &[wgpu::PushConstantRange] = if push_constants { &draw_range } else { &[] };
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[&uniform_bind_group_layout, &polygon_bind_group_layout],
+                push_constant_ranges,
+            });
+        let particle_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Particle Buffer"),
+                contents: bytemuck::cast_slice(&particles::initial()),
+                usage: wgpu::BufferUsages::STORAGE,
+            }
+        );
+        let step_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Step Buffer"),
+                contents: bytemuck::cast_slice(&[StepUniform::new(0.0, RADIUS)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let buffer_entry = |binding, visibility, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let compute_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Compute Bind Group Layout"),
+                entries: &[
+                    buffer_entry(0, wgpu::ShaderStages::COMPUTE, wgpu::BufferBindingType::Storage { read_only: false }),
+                    buffer_entry(1, wgpu::ShaderStages::COMPUTE, wgpu::BufferBindingType::Uniform),
+                    buffer_entry(2, wgpu::ShaderStages::COMPUTE, wgpu::BufferBindingType::Uniform),
+                ],
+            });
+        let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Compute Bind Group"),
+            layout: &compute_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: particle_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: step_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: projection_buffer.as_entire_binding(),
+                }
+            ],
+        });
+        let compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Particle Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("./particles.wgsl").into()),
+        });
+        let compute_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Compute Pipeline Layout"),
+                bind_group_layouts: &[&compute_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline"),
+            layout: Some(&compute_pipeline_layout),
+            module: &compute_shader,
+            entry_point: "cs_main",
+        });
+        let particle_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Particle Bind Group Layout"),
+                entries: &[buffer_entry(0, wgpu::ShaderStages::VERTEX, wgpu::BufferBindingType::Storage { read_only: true })],
+            });
+        let particle_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particle Bind Group"),
+            layout: &particle_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: particle_buffer.as_entire_binding(),
+                }
+            ],
+        });
+        let particle_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Particle Pipeline Layout"),
+                bind_group_layouts: &[&uniform_bind_group_layout, &polygon_bind_group_layout, &particle_bind_group_layout],
+                push_constant_ranges,
+            });
+        // Only the clock's group, as the gradient belongs to no polygon
+        let background_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Background Pipeline Layout"),
+                bind_group_layouts: &[&uniform_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let pipelines = create_pipelines(
+            &device, &shader, &render_pipeline_layout, &particle_pipeline_layout, &background_pipeline_layout, config.format, sample_count
+        );
+        let mut polygon = match &options.outlines {
+            Some(outlines) => Polygon::from_outlines(outlines.clone(), scene::PALETTE[0]),
+            None => Polygon::new(options.sides, scene::PALETTE[0]),
+        };
+        if options.texture.is_some() {
+            polygon.fill = Fill::Texture;
+        }
+        let mut layer = Layer::new(&device, &polygon_bind_group_layout, polygon, push_constants);
+        layer.tessellate(&device, options.tessellation == Tessellation::Lyon, options.stroke);
+        layer.extrude(&device, options.prism);
+        let layers = vec![layer];
+        let gpu_timer = GpuTimer::new(&device, &queue);
+        let overlay = window.as_ref().map(|_| Overlay::new(&device, config.format));
+        let panel = window.as_ref().map(|window| Panel::new(window, &device, config.format));
+        let state = Self {
             window,
             surface,
             device,
             queue,
             config,
+            present_modes,
+            sample_count,
+            msaa_samples,
+            msaa_view,
+            depth_view,
             size,
-            render_pipeline,
-            vertex_buffer,
+            pipelines,
+            blend: Blend::Alpha,
+            time_buffer,
+            projection_buffer,
+            camera_buffer,
+            background_buffer,
+            uniform_bind_group,
+            polygon_bind_group_layout,
+            push_constants,
+            shader,
+            render_pipeline_layout,
+            particle_pipeline_layout,
+            background_pipeline_layout,
+            step_buffer,
+            compute_pipeline,
+            compute_bind_group,
+            particle_bind_group,
+            particles,
+            prism: options.prism,
+            camera,
+            geometry,
+            tessellation: options.tessellation,
+            stroke: options.stroke,
+            style: Style::Filled,
+            background: options.background,
+            layers,
+            selected: None,
+            time: 0.0,
+            dt: 0.0,
+            last_frame: Instant::now(),
+            paused: false,
+            frame_interval: options.max_fps.map_or(Duration::ZERO, |fps| Duration::from_secs_f32(1.0 / fps)),
+            dirty: true,
+            animated: false,
+            cursor: None,
+            drag: None,
+            inertia: true,
+            morph_duration: options.morph,
+            screenshot: false,
+            captures: Vec::new(),
+            gpu_timer,
+            frame_stats: FrameStats::new(),
+            frame_times: None,
+            draw_calls: 0,
+            overlay,
+            panel,
+            options: options.clone(),
+            reloaded_shader: None,
+            lost,
+            retry_at: Instant::now(),
+        };
+        state.refresh_title();
+        state
+    }
+
+    fn refresh_title(&self) {
+        // Particles are always filled fans of the shader path, and lyon
+        // stands in for either path
+        let drawing = if self.particles {
+            format!("{} particles", particles::COUNT)
+        } else if self.prism {
+            "prisms".to_string()
+        } else if self.tessellation == Tessellation::Lyon {
+            format!("{}, {}", self.tessellation.name(), self.style.name())
+        } else {
+            format!("{}, {}", self.geometry.name(), self.style.name())
+        };
+        let drawing = format!("{}, {}, {}", drawing, self.blend.name(), present_mode_name(self.config.present_mode));
+        let mut title = format!(
+            "Hello N-Gon (polygon {} of {}, {}, {})",
+            self.current() + 1, self.layers.len(), self.layers[self.current()].polygon.describe(), drawing
+        );
+        if let Some(frame_times) = &self.frame_times {
+            title = format!("{} - {}", title, frame_times);
+        }
+        if let Some(window) = &self.window {
+            window.set_title(&title);
+        }
+    }
+
+    // Only a headless state, which never gets to the event loop, has no
+    // window
+    pub fn window(&self) -> &Window {
+        self.window.as_ref().expect("headless states have no window")
+    }
+
+    // Index of the polygon the keys and the mouse act on
+    fn current(&self) -> usize {
+        self.selected.unwrap_or(self.layers.len() - 1)
+    }
+
+    // Changes the current polygon and brings its uniform, its fan buffers,
+    // lyon's meshes and the title in line. Both paths are kept up to date so toggling
+    // between them costs nothing. A new outline morphs in from the one on
+    // screen.
+    fn edit(&mut self, change: impl FnOnce(&mut Polygon)) {
+        let current = self.current();
+        let duration = self.morph_duration;
+        let layer = &mut self.layers[current];
+        let outline_of = |polygon: &Polygon| (polygon.sides, polygon.shape, polygon.imported.clone());
+        let before = outline_of(&layer.polygon);
+        let shown = layer.outline(duration);
+        change(&mut layer.polygon);
+        if duration > 0.0 && outline_of(&layer.polygon) != before {
+            layer.morph = Some(Morph { from: shown, to: layer.polygon.outline(), started: Instant::now() });
+        }
+        let outline = layer.outline(duration);
+        layer.rebuild_fan(&self.device, &outline);
+        layer.tessellate(&self.device, self.tessellation == Tessellation::Lyon, self.stroke);
+        layer.extrude(&self.device, self.prism);
+        self.write_polygon(current);
+        self.refresh_title();
+    }
+
+    fn write_polygon(&self, index: usize) {
+        let layer = &self.layers[index];
+        let polygon = PolygonUniform::new(&layer.polygon, self.selected == Some(index));
+        self.queue.write_buffer(&layer.polygon_buffer, 0, bytemuck::cast_slice(&[polygon]));
+        self.write_draw(index);
+    }
+
+    // Only the uniform path keeps a Draw to write. The mouse moves and
+    // turns polygons through this alone, so with push constants a drag
+    // writes no buffers at all.
+    fn write_draw(&self, index: usize) {
+        let layer = &self.layers[index];
+        if let Some(draw_buffer) = &layer.draw_buffer {
+            self.queue.write_buffer(draw_buffer, 0, bytemuck::cast_slice(&[Draw::new(&layer.polygon)]));
+        }
+    }
+
+    // Adds or removes `delta` sides, within MIN_SIDES..=MAX_SIDES
+    pub fn change_sides(&mut self, delta: i32) {
+        self.edit(|polygon| polygon.set_sides(polygon.sides.saturating_add_signed(delta)));
+    }
+
+    pub fn toggle_star(&mut self) {
+        self.edit(Polygon::toggle_star);
+    }
+
+    pub fn next_star_step(&mut self) {
+        self.edit(Polygon::next_star_step);
+    }
+
+    pub fn adjust_inner(&mut self, delta: f32) {
+        self.edit(|polygon| polygon.adjust_inner(delta));
+    }
+
+    pub fn toggle_rounded(&mut self) {
+        self.edit(Polygon::toggle_rounded);
+    }
+
+    pub fn next_fill(&mut self) {
+        self.edit(|polygon| polygon.fill = polygon.fill.next());
+    }
+
+    pub fn next_color(&mut self) {
+        self.edit(|polygon| polygon.color = scene::next_color(polygon.color));
+    }
+
+    pub fn adjust_opacity(&mut self, delta: f32) {
+        self.edit(|polygon| polygon.adjust_opacity(delta));
+    }
+
+    pub fn change_angular_velocity(&mut self, delta: f32) {
+        let time = self.time;
+        self.edit(|polygon| polygon.set_angular_velocity(polygon.angular_velocity + delta, time));
+    }
+
+    // The scene keys wait while a mouse button is held, so a drag always
+    // stays on the polygon it started on
+
+    // Adds a copy of the current polygon on top of the others, in the next
+    // color of the palette and under the cursor if it is in the window,
+    // and selects it
+    pub fn add_polygon(&mut self) {
+        if self.drag.is_some() {
+            return;
+        }
+        let source = &self.layers[self.current()].polygon;
+        let polygon = Polygon {
+            color: scene::next_color(source.color),
+            offset: self.cursor.map_or([0.0; 2], |cursor| self.to_world(cursor)),
+            ..source.clone()
+        };
+        let layer = self.layer(polygon);
+        self.layers.push(layer);
+        self.select(Some(self.layers.len() - 1));
+    }
+
+    // A layer for `polygon` on this state's device, with the meshes the
+    // current settings draw it from
+    fn layer(&self, polygon: Polygon) -> Layer {
+        let mut layer = Layer::new(&self.device, &self.polygon_bind_group_layout, polygon, self.push_constants);
+        layer.tessellate(&self.device, self.tessellation == Tessellation::Lyon, self.stroke);
+        layer.extrude(&self.device, self.prism);
+        layer
+    }
+
+    // Removes the current polygon, unless it is the last one
+    pub fn remove_polygon(&mut self) {
+        if self.drag.is_some() || self.layers.len() == 1 {
+            return;
+        }
+        self.layers.remove(self.current());
+        self.select(None);
+    }
+
+    // Selects the polygon above the current one, or below with a negative
+    // step, wrapping around at either end of the scene
+    pub fn select_next(&mut self, step: isize) {
+        if self.drag.is_some() {
+            return;
+        }
+        let count = self.layers.len() as isize;
+        self.select(Some((self.current() as isize + step).rem_euclid(count) as usize));
+    }
+
+    // Moves the current polygon `step` places towards the front of the
+    // scene, or towards the back with a negative step, and selects it
+    pub fn raise(&mut self, step: isize) {
+        if self.drag.is_some() {
+            return;
+        }
+        let current = self.current();
+        let target = (current as isize + step).clamp(0, self.layers.len() as isize - 1) as usize;
+        let layer = self.layers.remove(current);
+        self.layers.insert(target, layer);
+        self.select(Some(target));
+    }
+
+    // Rewrites every uniform, as layers may have moved as well as changed
+    // selection
+    fn select(&mut self, selected: Option<usize>) {
+        self.selected = selected;
+        for index in 0..self.layers.len() {
+            self.write_polygon(index);
+        }
+        self.refresh_title();
+    }
+
+    pub fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    pub fn set_geometry(&mut self, geometry: Geometry) {
+        self.geometry = geometry;
+        self.refresh_title();
+    }
+
+    pub fn tessellation(&self) -> Tessellation {
+        self.tessellation
+    }
+
+    // Builds or drops lyon's meshes of every polygon to match
+    pub fn set_tessellation(&mut self, tessellation: Tessellation) {
+        self.tessellation = tessellation;
+        for layer in &mut self.layers {
+            layer.tessellate(&self.device, tessellation == Tessellation::Lyon, self.stroke);
+        }
+        self.refresh_title();
+    }
+
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    // Wireframe falls through to the style after it when the adapter
+    // lacks POLYGON_MODE_LINE
+    pub fn set_style(&mut self, style: Style) {
+        self.style = if style == Style::Wireframe && self.pipelines().wireframe.is_none() {
+            eprintln!("Wireframe needs POLYGON_MODE_LINE, which this adapter does not support");
+            style.next()
+        } else {
+            style
+        };
+        self.refresh_title();
+    }
+
+    pub fn blend(&self) -> Blend {
+        self.blend
+    }
+
+    pub fn set_blend(&mut self, blend: Blend) {
+        self.blend = blend;
+        self.refresh_title();
+    }
+
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+        self.queue.write_buffer(&self.background_buffer, 0, bytemuck::cast_slice(&[BackgroundUniform::new(&background)]));
+        self.dirty = true;
+    }
+
+    // Moves on to the next of PRESENT_MODES the surface supports
+    pub fn next_present_mode(&mut self) {
+        let Some(surface) = &self.surface else {
+            return;
+        };
+        let current = self.present_modes.iter().position(|&mode| mode == self.config.present_mode);
+        let next = current.map_or(0, |index| (index + 1) % self.present_modes.len());
+        self.config.present_mode = self.present_modes[next];
+        surface.configure(&self.device, &self.config);
+        self.refresh_title();
+    }
+
+    fn pipelines(&self) -> &Pipelines {
+        &self.pipelines[self.blend as usize]
+    }
+
+    // Builds every render pipeline again from new source for shader.wgsl.
+    // A shader that does not compile, or does not fit the pipelines, is
+    // reported and leaves the old pipelines drawing.
+    pub fn reload_shader(&mut self, source: &str) {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Triangle Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source(source, self.push_constants, self.config.format).into()),
+        });
+        let pipelines = create_pipelines(
+            &self.device, &shader, &self.render_pipeline_layout, &self.particle_pipeline_layout, &self.background_pipeline_layout,
+            self.config.format, self.sample_count
+        );
+        match pollster::block_on(self.device.pop_error_scope()) {
+            Some(error) => eprintln!("Keeping the previous shader: {}", error),
+            None => {
+                self.pipelines = pipelines;
+                self.shader = shader;
+                self.reloaded_shader = Some(source.to_string());
+                self.dirty = true;
+                println!("Reloaded the shader");
+            }
+        }
+    }
+
+    // Switches multisampling between off and msaa_samples, building the
+    // pipelines and the multisampled target again for the new count
+    fn set_msaa(&mut self, on: bool) {
+        let sample_count = if on { self.msaa_samples } else { 1 };
+        if on && sample_count == 1 {
+            eprintln!("MSAA is not supported for {:?}", self.config.format);
+        }
+        if sample_count == self.sample_count {
+            return;
+        }
+        self.sample_count = sample_count;
+        self.msaa_view = msaa_view(&self.device, &self.config, sample_count);
+        self.depth_view = depth_view(&self.device, &self.config, sample_count);
+        self.pipelines = create_pipelines(
+            &self.device, &self.shader, &self.render_pipeline_layout, &self.particle_pipeline_layout, &self.background_pipeline_layout,
+            self.config.format, sample_count
+        );
+    }
+
+    pub fn toggle_overlay(&mut self) {
+        if let Some(overlay) = &mut self.overlay {
+            overlay.toggle();
         }
     }
 
-    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+    pub fn toggle_panel(&mut self) {
+        if let Some(panel) = &mut self.panel {
+            panel.toggle();
+        }
+    }
+
+    // Takes note of a window event, which may well change what is on
+    // screen, and hands it to the panel first, returning whether it took
+    // the event, in which case the scene should not see it
+    pub fn window_event(&mut self, event: &WindowEvent) -> bool {
+        self.dirty = true;
+        self.panel.as_mut().is_some_and(|panel| panel.on_event(event))
+    }
+
+    // Whether anything on screen changes from frame to frame by itself:
+    // a polygon turning or the particles flying, unless paused, a morph,
+    // which goes on paused or not, a screenshot on its way back, or the
+    // panel in the middle of an animation of its own
+    fn animating(&self) -> bool {
+        let turning = self.particles || self.layers.iter().any(|layer| layer.polygon.angular_velocity != 0.0);
+        (turning && !self.paused)
+            || self.layers.iter().any(|layer| layer.morph.is_some())
+            || !self.captures.is_empty()
+            || self.panel.as_ref().is_some_and(Panel::wants_repaint)
+    }
+
+    // When the next frame is due: as soon as the cap allows while the
+    // scene moves or something has changed, and None while nothing will
+    // change until an event comes. With the device lost, it is when to
+    // try for a new one.
+    pub fn next_frame(&self) -> Option<Instant> {
+        if self.lost.get() {
+            return Some(self.retry_at);
+        }
+        (self.dirty || self.animating()).then_some(self.last_frame + self.frame_interval)
+    }
+
+    // Runs a frame of the panel on the current polygon and applies what
+    // it changed, the way the keys and the wheel would
+    fn run_panel(&mut self) {
+        let polygon = &self.layers[self.current()].polygon;
+        let before = Controls {
+            sides: polygon.sides,
+            angular_velocity: polygon.angular_velocity,
+            color: polygon.color,
+            scale: polygon.scale,
+            msaa: self.sample_count > 1,
+            background: self.background,
+        };
+        let (Some(panel), Some(window)) = (&mut self.panel, &self.window) else {
+            return;
+        };
+        let mut controls = before;
+        panel.run(window, &mut controls);
+        if controls == before {
+            return;
+        }
+        let time = self.time;
+        if controls.sides != before.sides {
+            self.edit(|polygon| polygon.set_sides(controls.sides));
+        }
+        if controls.angular_velocity != before.angular_velocity {
+            self.edit(|polygon| polygon.set_angular_velocity(controls.angular_velocity, time));
+        }
+        if controls.color != before.color {
+            self.edit(|polygon| polygon.color = controls.color);
+        }
+        if controls.scale != before.scale {
+            let current = self.current();
+            self.layers[current].polygon.scale = controls.scale.clamp(MIN_SCALE, MAX_SCALE);
+            self.write_draw(current);
+        }
+        if controls.msaa != before.msaa {
+            self.set_msaa(controls.msaa);
+        }
+        if controls.background != before.background {
+            self.set_background(controls.background);
+        }
+    }
+
+    pub fn toggle_particles(&mut self) {
+        self.particles = !self.particles;
+        self.refresh_title();
+    }
+
+    // Builds or drops every polygon's prism to match. Not during a drag,
+    // as the buttons do something else in either mode.
+    pub fn toggle_prism(&mut self) {
+        if self.drag.is_some() {
+            return;
+        }
+        self.prism = !self.prism;
+        for layer in &mut self.layers {
+            layer.extrude(&self.device, self.prism);
+        }
+        self.refresh_title();
+    }
+
+    // Writes the scene as it is on screen to an SVG file in OUT_DIR
+    pub fn export_svg(&self) {
+        let elements: Vec<svg::Element> = self
+            .layers
+            .iter()
+            .map(|layer| svg::Element {
+                polygon: &layer.polygon,
+                loops: if layer.morph.is_some() { vec![layer.outline(self.morph_duration)] } else { layer.polygon.loops() },
+                filled: matches!(self.style, Style::Filled | Style::FilledOutlined),
+                // A wireframe's triangles come from the fan or lyon, so
+                // only its outline is the polygon's
+                outlined: matches!(self.style, Style::Outlined | Style::FilledOutlined | Style::Wireframe),
+                // The wireframe's outline is a line whatever the tessellation
+                stroke: (layer.meshes().is_some() && self.style != Style::Wireframe).then_some(self.stroke),
+            })
+            .collect();
+        let projection = ProjectionUniform::new(self.size);
+        let document = svg::document(self.size.width, self.size.height, projection.scale, &self.background, self.time, &elements);
+        let path = std::path::PathBuf::from(screenshot::OUT_DIR).join(format!("scene-{}.svg", screenshot::timestamp(std::time::SystemTime::now())));
+        match std::fs::create_dir_all(screenshot::OUT_DIR).and_then(|_| std::fs::write(&path, document)) {
+            Ok(()) => println!("Saved {}", path.display()),
+            Err(e) => eprintln!("Failed to write {}: {}", path.display(), e),
+        }
+    }
+
+    pub fn take_screenshot(&mut self) {
+        if screenshot::supported(self.config.format) {
+            self.screenshot = true;
+        } else {
+            eprintln!("Screenshots of a {:?} surface are not supported", self.config.format);
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    // The point under a window position, in the units of the offset
+    fn to_world(&self, position: PhysicalPosition<f64>) -> [f32; 2] {
+        let projection = ProjectionUniform::new(self.size);
+        let x = 2.0 * position.x as f32 / self.size.width.max(1) as f32 - 1.0;
+        let y = 1.0 - 2.0 * position.y as f32 / self.size.height.max(1) as f32;
+        [x / projection.scale[0], y / projection.scale[1]]
+    }
+
+    // The topmost polygon that, as drawn right now, covers a window
+    // position. The particles are not the scene, so they are never hit.
+    fn hit(&self, position: PhysicalPosition<f64>) -> Option<usize> {
+        if self.particles {
+            return None;
+        }
+        let point = self.to_world(position);
+        self.layers.iter().rposition(|layer| layer.polygon.contains(point, self.time))
+    }
+
+    // Angle of a window position about the current polygon's center
+    fn angle_at(&self, position: PhysicalPosition<f64>) -> f32 {
+        let [x, y] = self.to_world(position);
+        let offset = self.layers[self.current()].polygon.offset;
+        (y - offset[1]).atan2(x - offset[0])
+    }
+
+    pub fn toggle_inertia(&mut self) {
+        self.inertia = !self.inertia;
+    }
+
+    pub fn cursor_moved(&mut self, position: PhysicalPosition<f64>) {
+        let Some(previous) = self.cursor.replace(position) else {
+            return;
+        };
+        let current = self.current();
+        match self.drag {
+            Some(Drag::Move) => {
+                let (from, to) = (self.to_world(previous), self.to_world(position));
+                let polygon = &mut self.layers[current].polygon;
+                polygon.offset = [polygon.offset[0] + to[0] - from[0], polygon.offset[1] + to[1] - from[1]];
+                self.write_draw(current);
+            }
+            Some(Drag::Spin { angle, moved, velocity, resume }) => {
+                let now = Instant::now();
+                let new_angle = self.angle_at(position);
+                // The short way round, so crossing the negative x axis is
+                // not a full turn
+                let turn = (new_angle - angle + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU) - std::f32::consts::PI;
+                self.layers[current].polygon.phase += turn;
+                self.write_draw(current);
+                let dt = (now - moved).as_secs_f32();
+                // Smoothed, as single moves come in uneven steps
+                let velocity = if dt > 0.0 { 0.5 * velocity + 0.5 * turn / dt } else { velocity };
+                self.drag = Some(Drag::Spin { angle: new_angle, moved: now, velocity, resume });
+            }
+            Some(Drag::Orbit { .. }) => {
+                self.camera.orbit((position.x - previous.x) as f32, (position.y - previous.y) as f32);
+                self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[CameraUniform::new(&self.camera, self.size)]));
+            }
+            None => {}
+        }
+    }
+
+    // Forgets the cursor, so coming back in somewhere else is not a drag
+    // across the window
+    pub fn cursor_left(&mut self) {
+        self.cursor = None;
+    }
+
+    // One drag at a time: the other button does nothing until it is over
+    pub fn mouse_input(&mut self, button: MouseButton, state: ElementState) {
+        let time = self.time;
+        match (button, state) {
+            // Prism mode has no picking, only the camera to turn
+            (MouseButton::Left | MouseButton::Right, ElementState::Pressed) if self.prism && self.drag.is_none() => {
+                self.drag = Some(Drag::Orbit { button });
+            }
+            (_, ElementState::Released) if matches!(self.drag, Some(Drag::Orbit { button: held }) if held == button) => {
+                self.drag = None;
+            }
+            // Only a click on a polygon grabs it, the topmost one where
+            // several overlap
+            (MouseButton::Left, ElementState::Pressed) if self.drag.is_none() => {
+                let Some(cursor) = self.cursor else {
+                    return;
+                };
+                let hit = self.hit(cursor);
+                let [x, y] = self.to_world(cursor);
+                match hit {
+                    Some(index) => println!("Click at ({:.3}, {:.3}): hit polygon {}", x, y, index + 1),
+                    None => println!("Click at ({:.3}, {:.3}): missed", x, y),
+                }
+                if hit != self.selected {
+                    self.select(hit);
+                }
+                if hit.is_some() {
+                    self.drag = Some(Drag::Move);
+                }
+            }
+            (MouseButton::Right, ElementState::Pressed) if self.drag.is_none() => {
+                let Some(cursor) = self.cursor else {
+                    return;
+                };
+                let resume = self.layers[self.current()].polygon.angular_velocity;
+                self.edit(|polygon| polygon.set_angular_velocity(0.0, time));
+                self.drag = Some(Drag::Spin { angle: self.angle_at(cursor), moved: Instant::now(), velocity: 0.0, resume });
+            }
+            (MouseButton::Left, ElementState::Released) if matches!(self.drag, Some(Drag::Move)) => self.drag = None,
+            (MouseButton::Right, ElementState::Released) => {
+                if let Some(Drag::Spin { moved, velocity, resume, .. }) = self.drag.take() {
+                    let resting = (Instant::now() - moved).as_secs_f32() > SPIN_REST;
+                    let velocity = if resting { 0.0 } else { velocity };
+                    let angular_velocity = if self.inertia { velocity } else { resume };
+                    self.edit(|polygon| polygon.set_angular_velocity(angular_velocity, time));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Scales the current polygon
+    pub fn mouse_wheel(&mut self, delta: MouseScrollDelta) {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, lines) => lines,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+        };
+        let current = self.current();
+        let polygon = &mut self.layers[current].polygon;
+        polygon.scale = (polygon.scale * SCALE_PER_LINE.powf(lines)).clamp(MIN_SCALE, MAX_SCALE);
+        self.write_draw(current);
+    }
+
+    // Advances the clock by the time since the last frame, unless paused
+    // or the scene was standing still, and hands it to the shader. Morphs
+    // go on in real time, paused or not, through their fan buffers.
+    fn update(&mut self) {
+        let now = Instant::now();
+        let frame_time = (now - self.last_frame).as_secs_f32();
+        self.dt = if self.paused || !self.animated { 0.0 } else { frame_time };
+        self.time += self.dt;
+        self.last_frame = now;
+        let time_uniform = TimeUniform { time: self.time, _padding: [0.0; 3] };
+        self.queue.write_buffer(&self.time_buffer, 0, bytemuck::cast_slice(&[time_uniform]));
+        self.queue.write_buffer(&self.step_buffer, 0, bytemuck::cast_slice(&[StepUniform::new(self.dt, RADIUS)]));
+        let duration = self.morph_duration;
+        for layer in &mut self.layers {
+            let Some(morph) = &layer.morph else {
+                continue;
+            };
+            if morph.progress(duration) < 1.0 {
+                let vertices = fan_vertices(&layer.polygon, &layer.outline(duration));
+                self.queue.write_buffer(&layer.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+            } else {
+                layer.morph = None;
+                let outline = layer.polygon.outline();
+                layer.rebuild_fan(&self.device, &outline);
+            }
+        }
+        // For the screenshots and the timestamps on their way back
+        self.device.poll(wgpu::Maintain::Poll);
+        if self.animated {
+            self.frame_stats.add_cpu(frame_time);
+        }
+        if let Some(gpu_time) = self.gpu_timer.as_mut().and_then(GpuTimer::read) {
+            self.frame_stats.add_gpu(gpu_time);
+        }
+        if let Some(report) = self.frame_stats.report() {
+            self.frame_times = Some(report);
+            self.refresh_title();
+        }
+        self.run_panel();
+        self.captures.retain(|capture| match capture.image() {
+            Some(Ok(image)) => {
+                screenshot::save(image);
+                false
+            }
+            Some(Err(e)) => {
+                eprintln!("Failed to read back the screenshot: {}", e);
+                false
+            }
+            None => true,
+        });
+        self.dirty = false;
+        self.animated = self.animating();
+    }
+
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.config);
+            }
+            self.msaa_view = msaa_view(&self.device, &self.config, self.sample_count);
+            self.depth_view = depth_view(&self.device, &self.config, self.sample_count);
+            self.queue.write_buffer(&self.projection_buffer, 0, bytemuck::cast_slice(&[ProjectionUniform::new(new_size)]));
+            self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[CameraUniform::new(&self.camera, new_size)]));
+        }
+    }
+
+    // Draws a frame in the window and deals with whatever the surface or
+    // the device throws up on the way: a surface that no longer fits the
+    // window is configured again, a frame that does not come in time is
+    // skipped, and a device that is lost or out of memory is made anew
+    pub fn redraw(&mut self) {
+        if self.lost.get() {
+            self.recover();
+            return;
+        }
+        let lost = self.lost.clone();
+        match lost.catch(|| {
+            self.update();
+            self.render()
+        }) {
+            Some(Ok(())) | Some(Err(wgpu::SurfaceError::Timeout)) | None => {}
+            Some(Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated)) => {
+                lost.catch(|| self.resize(self.size));
+            }
+            Some(Err(wgpu::SurfaceError::OutOfMemory)) => lost.set(),
         }
     }
 
-    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output_frame = self.surface.get_current_texture()?;
+    // Runs `work` on the state unless the device is lost, when events
+    // wait for the next one. A device lost on the way is caught there.
+    pub fn guard(&mut self, work: impl FnOnce(&mut Self)) {
+        let lost = self.lost.clone();
+        if !lost.get() {
+            lost.catch(|| work(self));
+        }
+    }
+
+    // Starts over on a new instance, surface and device after the last
+    // device was lost, with every pipeline and buffer made again and the
+    // scene, camera and settings carried over. While there is no device
+    // to be had, it tries again after RETRY_INTERVAL.
+    fn recover(&mut self) {
+        if Instant::now() < self.retry_at {
+            return;
+        }
+        match self.rebuild() {
+            // The rest of the old state is leaked rather than dropped, as
+            // dropping a lost device waits for it, which panics in wgpu 0.17
+            Ok(state) => std::mem::forget(std::mem::replace(self, state)),
+            Err(e) => {
+                eprintln!("No new device yet, trying again in {:?}: {}", RETRY_INTERVAL, e);
+                self.retry_at = Instant::now() + RETRY_INTERVAL;
+            }
+        }
+    }
+
+    fn rebuild(&mut self) -> Result<Self, String> {
+        // A window takes one surface at a time, so the old one goes first
+        if let Some(surface) = self.surface.take() {
+            self.lost.catch(|| drop(surface));
+        }
+        let size = self.window.as_ref().map_or(self.size, Window::inner_size);
+        let options = Options {
+            geometry: self.geometry,
+            tessellation: self.tessellation,
+            stroke: self.stroke,
+            particles: self.particles,
+            prism: self.prism,
+            present_mode: Some(self.config.present_mode),
+            background: self.background,
+            ..self.options.clone()
+        };
+        let instance = adapter::instance(options.backends);
+        let surface = match &self.window {
+            Some(window) => Some(unsafe { instance.create_surface(window) }.map_err(|e| e.to_string())?),
+            None => None,
+        };
+        // The adapter asked for may be the one that went away
+        let gpu = match pollster::block_on(Gpu::request(&instance, surface.as_ref(), &options, options.adapter.as_ref())) {
+            Err(e) if options.adapter.is_some() => {
+                eprintln!("{}, trying the adapter wgpu prefers", e);
+                pollster::block_on(Gpu::request(&instance, surface.as_ref(), &options, None))?
+            }
+            gpu => gpu?,
+        };
+        let mut state = Self::create(gpu, surface, self.window.take(), size, &options);
+        if let Some(source) = &self.reloaded_shader {
+            state.reload_shader(source);
+        }
+        state.set_msaa(self.sample_count > 1);
+        state.camera = std::mem::replace(&mut self.camera, Camera::new());
+        state.queue.write_buffer(&state.camera_buffer, 0, bytemuck::cast_slice(&[CameraUniform::new(&state.camera, size)]));
+        state.layers = self.layers.iter().map(|layer| state.layer(layer.polygon.clone())).collect();
+        state.style = self.style;
+        state.blend = self.blend;
+        state.time = self.time;
+        state.paused = self.paused;
+        state.inertia = self.inertia;
+        state.cursor = self.cursor;
+        state.select(self.selected);
+        Ok(state)
+    }
+
+    // Closes the window. With the device lost, the rest of the state is
+    // leaked, as in recover.
+    pub fn close(mut self) {
+        if self.lost.get() {
+            let _window = self.window.take();
+            std::mem::forget(self);
+        }
+    }
+
+    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let output_frame = self.surface.as_ref().expect("headless states draw with headless_frame").get_current_texture()?;
         let view = output_frame
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
@@ -158,32 +2303,257 @@ impl State {
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.2,
-                            g: 0.3,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
+        // The particles' compute pass counts towards the GPU's frame time,
+        // a screenshot's second render pass does not
+        let timed = self.gpu_timer.as_ref().is_some_and(|timer| timer.start(&mut encoder));
+        if self.particles {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass"),
             });
-
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.draw(0..6, 0..1);
+            compute_pass.set_pipeline(&self.compute_pipeline);
+            compute_pass.set_bind_group(0, &self.compute_bind_group, &[]);
+            compute_pass.dispatch_workgroups(particles::COUNT.div_ceil(particles::WORKGROUP_SIZE), 1, 1);
         }
+        self.draw_calls = self.draw_scene(&mut encoder, &view);
+        // The overlay and the panel go over the resolved frame, so they
+        // are never multisampled, and stay out of screenshots. The panel
+        // is drawn last, so it covers the text.
+        let text = self.overlay_text();
+        let scale_factor = self.window().scale_factor() as f32;
+        if let Some(overlay) = &mut self.overlay {
+            overlay.draw(&self.device, &self.queue, &mut encoder, &view, [self.config.width, self.config.height], scale_factor, &text);
+        }
+        if let Some(panel) = &mut self.panel {
+            panel.paint(&self.device, &self.queue, &mut encoder, &view, [self.config.width, self.config.height]);
+        }
+        if let Some(timer) = self.gpu_timer.as_ref().filter(|_| timed) {
+            timer.stop(&mut encoder);
+        }
+        // The same frame again for a screenshot, through the same
+        // multisampled target if there is one, as it is the surface's size
+        let capture = std::mem::take(&mut self.screenshot).then(|| {
+            let texture = screenshot::target(&self.device, self.config.format, self.config.width, self.config.height);
+            self.draw_scene(&mut encoder, &texture.create_view(&wgpu::TextureViewDescriptor::default()));
+            screenshot::Capture::record(&self.device, &mut encoder, &texture)
+        });
         self.queue.submit(iter::once(encoder.finish()));
+        if let Some(timer) = self.gpu_timer.as_mut().filter(|_| timed) {
+            timer.map();
+        }
+        if let Some(mut capture) = capture {
+            capture.map();
+            self.captures.push(capture);
+        }
         output_frame.present();
 
         Ok(())
     }
+
+    // The scene as it starts out, drawn offscreen and read back. The clock
+    // stays at zero and the particles where they start, so the same
+    // options always give the same image.
+    pub fn headless_frame(&self) -> Result<image::RgbaImage, wgpu::BufferAsyncError> {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Headless Encoder"),
+            });
+        let texture = screenshot::target(&self.device, self.config.format, self.config.width, self.config.height);
+        self.draw_scene(&mut encoder, &texture.create_view(&wgpu::TextureViewDescriptor::default()));
+        let mut capture = screenshot::Capture::record(&self.device, &mut encoder, &texture);
+        self.queue.submit(iter::once(encoder.finish()));
+        capture.map();
+        self.device.poll(wgpu::Maintain::Wait);
+        capture.image().expect("the buffer is mapped once the device is idle")
+    }
+
+    // The overlay's lines: the frame rate and times of the title, the
+    // current polygon's sides, and the draw calls of the last frame's scene
+    fn overlay_text(&self) -> String {
+        let polygon = &self.layers[self.current()].polygon;
+        format!(
+            "{}\nPolygon {} of {}: {} sides\nDraw calls: {}",
+            self.frame_times.as_deref().unwrap_or("Measuring..."),
+            self.current() + 1, self.layers.len(), polygon.sides, self.draw_calls
+        )
+    }
+
+    // The background, made linear for an sRGB surface like the colors in
+    // fs_main
+    fn clear_color(&self) -> wgpu::Color {
+        let [r, g, b] = self.background.color.map(f64::from);
+        if self.config.format.is_srgb() {
+            wgpu::Color { r: srgb_to_linear(r), g: srgb_to_linear(g), b: srgb_to_linear(b), a: 1.0 }
+        } else {
+            wgpu::Color { r, g, b, a: 1.0 }
+        }
+    }
+
+    // Whether the frame is prism mode's, which the particles take over from
+    fn draws_prisms(&self) -> bool {
+        self.prism && !self.particles
+    }
+
+    // The render pass of a frame, drawn into `view`. Returns how many draw
+    // calls it took.
+    fn draw_scene(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) -> u32 {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            // With multisampling, drawn into the multisampled target and
+            // resolved to `view`; the samples themselves are not kept
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: self.msaa_view.as_ref().unwrap_or(view),
+                resolve_target: self.msaa_view.as_ref().map(|_| view),
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.clear_color()),
+                    store: self.msaa_view.is_none(),
+                },
+            })],
+            // Only the prisms test depth, and it is not needed after the
+            // pass
+            depth_stencil_attachment: self.draws_prisms().then_some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: false,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        let pipelines = self.pipelines();
+        let mut draw_calls = 0;
+        if self.background.gradient {
+            render_pass.set_pipeline(if self.draws_prisms() { &pipelines.prism_background } else { &pipelines.background });
+            render_pass.draw(0..3, 0..1);
+            draw_calls += 1;
+        }
+        if self.particles {
+            let layer = &self.layers[self.current()];
+            render_pass.set_pipeline(&pipelines.particle);
+            self.push_draw(&mut render_pass, layer);
+            render_pass.set_bind_group(1, &layer.bind_group, &[]);
+            render_pass.set_bind_group(2, &self.particle_bind_group, &[]);
+            render_pass.draw(0..3 * layer.polygon.outline_count(), 0..particles::COUNT);
+            draw_calls += 1;
+        } else if self.draws_prisms() {
+            // In any order, as the depth test sorts them out
+            render_pass.set_pipeline(&pipelines.prism);
+            for layer in &self.layers {
+                let Some(prism) = &layer.prism else {
+                    continue;
+                };
+                self.push_draw(&mut render_pass, layer);
+                render_pass.set_bind_group(1, &layer.bind_group, &[]);
+                render_pass.set_vertex_buffer(0, prism.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(prism.index_buffer.slice(..), prism.index_format);
+                render_pass.draw_indexed(0..prism.index_count, 0, 0..1);
+                draw_calls += 1;
+            }
+        } else {
+            // Back to front, each polygon with its outline before the ones
+            // above cover it. That is also the order translucent polygons
+            // need to blend over the ones below them.
+            for layer in &self.layers {
+                render_pass.set_bind_group(1, &layer.bind_group, &[]);
+                if let Some(meshes) = layer.meshes() {
+                    draw_calls += self.draw_meshes(&mut render_pass, layer, meshes);
+                    continue;
+                }
+                match (self.style, &pipelines.wireframe) {
+                    (Style::Filled | Style::FilledOutlined, _) => {
+                        self.draw_fan(&mut render_pass, layer, &pipelines.render, &pipelines.indexed);
+                        draw_calls += 1;
+                    }
+                    (Style::Wireframe, Some((shader_pipeline, indexed_pipeline))) => {
+                        self.draw_fan(&mut render_pass, layer, shader_pipeline, indexed_pipeline);
+                        draw_calls += 1;
+                    }
+                    _ => {}
+                }
+                if matches!(self.style, Style::Outlined | Style::FilledOutlined) {
+                    if layer.drawn_from_buffers() {
+                        render_pass.set_pipeline(&pipelines.indexed_outline);
+                        self.push_draw(&mut render_pass, layer);
+                        render_pass.set_vertex_buffer(0, layer.vertex_buffer.slice(..));
+                        // Every vertex but the center, ending on the first
+                        // point again
+                        render_pass.draw(1..layer.outline_count + 2, 0..1);
+                        draw_calls += 1;
+                    } else {
+                        render_pass.set_pipeline(&pipelines.outline);
+                        self.push_draw(&mut render_pass, layer);
+                        // Back around to the first corner to close the strip
+                        render_pass.draw(0..layer.polygon.outline_count() + 1, 0..1);
+                        draw_calls += 1;
+                    }
+                }
+            }
+        }
+        draw_calls
+    }
+
+    // Draws the triangles of a polygon the way the current geometry path
+    // does, or from the fan buffers while it morphs, with the given
+    // pipeline for each path
+    fn draw_fan<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        layer: &'a Layer,
+        shader_pipeline: &'a wgpu::RenderPipeline,
+        indexed_pipeline: &'a wgpu::RenderPipeline,
+    ) {
+        match if layer.drawn_from_buffers() { Geometry::Indexed } else { self.geometry } {
+            Geometry::Shader => {
+                render_pass.set_pipeline(shader_pipeline);
+                self.push_draw(render_pass, layer);
+                // One triangle from the center to each edge
+                render_pass.draw(0..3 * layer.polygon.outline_count(), 0..1);
+            }
+            Geometry::Indexed => {
+                render_pass.set_pipeline(indexed_pipeline);
+                self.push_draw(render_pass, layer);
+                render_pass.set_vertex_buffer(0, layer.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(layer.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                render_pass.draw_indexed(0..3 * layer.outline_count, 0, 0..1);
+            }
+        }
+    }
+
+    // Draws a polygon from lyon's meshes: the fill, as a wireframe if the
+    // style is one, and the stroke for the outlined styles. Returns how
+    // many draw calls it took.
+    fn draw_meshes<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, layer: &'a Layer, meshes: &'a Tessellated) -> u32 {
+        let pipelines = self.pipelines();
+        let fill = match (self.style, &pipelines.wireframe) {
+            (Style::Filled | Style::FilledOutlined, _) => Some(&pipelines.indexed),
+            (Style::Wireframe, Some((_, indexed_pipeline))) => Some(indexed_pipeline),
+            _ => None,
+        };
+        let stroke = matches!(self.style, Style::Outlined | Style::FilledOutlined).then_some(&pipelines.stroke);
+        let mut draw_calls = 0;
+        for (pipeline, mesh) in [(fill, &meshes.fill), (stroke, &meshes.stroke)] {
+            // A mesh lyon failed on is empty, and has nothing to bind
+            let Some(pipeline) = pipeline.filter(|_| mesh.index_count > 0) else {
+                continue;
+            };
+            render_pass.set_pipeline(pipeline);
+            self.push_draw(render_pass, layer);
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+            render_pass.draw_indexed(0..mesh.index_count, 0, 0..1);
+            draw_calls += 1;
+        }
+        draw_calls
+    }
+
+    // Hands the layer's Draw to the draws that follow on the push constant
+    // path. It goes after every set_pipeline, as a pipeline with another
+    // layout clears the push constants.
+    fn push_draw(&self, render_pass: &mut wgpu::RenderPass, layer: &Layer) {
+        if self.push_constants {
+            render_pass.set_push_constants(wgpu::ShaderStages::VERTEX, 0, bytemuck::cast_slice(&[Draw::new(&layer.polygon)]));
+        }
+    }
 }
\ No newline at end of file
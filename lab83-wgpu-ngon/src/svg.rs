@@ -0,0 +1,418 @@
+use std::fmt;
+use std::path::Path;
+use svgtypes::{ Color, PointsParser, SimplePathSegment, SimplifyingPathParser, Transform };
+
+use crate::scene::{ Polygon, RADIUS };
+use crate::state::Background;
+use crate::tessellation::{ self, Stroke };
+
+// E writes the scene to out/scene-<UTC time>.svg, and `--import PATH`
+// starts with an outline taken from an SVG file.
+//
+// The export has one <polygon> per polygon, back to front, or a <path> of
+// even-odd subpaths for one with holes, with the points of its outline in
+// the polygon's own frame and its rotation at the time
+// of the export, scale and offset as a transform, inside a group that maps
+// the projection's units onto the window's pixels, so vector tools get
+// the geometry as the lab builds it and the picture as it was on screen.
+//
+// The import takes the first <polygon>, <polyline> or <path> in the file,
+// through the transforms of it and its parents, and every subpath of a
+// path as a loop of its own, with curves flattened into CURVE_POINTS
+// points each. The largest loop is the outer one, the rest are filled by
+// the even-odd rule, so those inside it are holes. The loops are centered
+// on the outer one's centroid and scaled so the farthest point is RADIUS
+// out, like the corners of the lab's own polygons.
+
+// Points each Bézier curve of an imported path is flattened into
+const CURVE_POINTS: u32 = 8;
+
+// Loops of more points would not fit the u16 indices of the meshes
+const MAX_POINTS: usize = u16::MAX as usize - 2;
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    Xml(roxmltree::Error),
+    Svg(svgtypes::Error),
+    NoOutline,
+    TooFewPoints(usize),
+    TooManyPoints(usize),
+    NoArea,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "{}", e),
+            ImportError::Xml(e) => write!(f, "{}", e),
+            ImportError::Svg(e) => write!(f, "{}", e),
+            ImportError::NoOutline => write!(f, "no <polygon>, <polyline> or <path> found"),
+            ImportError::TooFewPoints(count) => write!(f, "the outline has {} points at most, at least 3 are needed", count),
+            ImportError::TooManyPoints(count) => write!(f, "the outline has {} points, at most {} fit", count, MAX_POINTS),
+            ImportError::NoArea => write!(f, "the outline encloses no area"),
+        }
+    }
+}
+
+impl From<std::io::Error> for ImportError {
+    fn from(e: std::io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+impl From<roxmltree::Error> for ImportError {
+    fn from(e: roxmltree::Error) -> Self {
+        ImportError::Xml(e)
+    }
+}
+
+impl From<svgtypes::Error> for ImportError {
+    fn from(e: svgtypes::Error) -> Self {
+        ImportError::Svg(e)
+    }
+}
+
+// `[0.0..=1.0; 3]` as #rrggbb
+fn hex(color: [f32; 3]) -> String {
+    let [r, g, b] = color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+// A color as SVG and CSS write it, #rgb, #rrggbb, rgb(...) or a name such
+// as navy, as `[0.0..=1.0; 3]`, or None if it is none of those. Any alpha
+// is dropped.
+pub fn parse_color(raw: &str) -> Option<[f32; 3]> {
+    let color: Color = raw.parse().ok()?;
+    Some([color.red, color.green, color.blue].map(|channel| channel as f32 / 255.0))
+}
+
+// One polygon of the scene: the loops of its outline, in its own frame,
+// and whether it is filled and outlined the way the style draws it
+pub struct Element<'a> {
+    pub polygon: &'a Polygon,
+    pub loops: Vec<Vec<[f32; 2]>>,
+    pub filled: bool,
+    pub outlined: bool,
+    // lyon's stroke, if that is how the outline is drawn, rather than a
+    // line a pixel wide
+    pub stroke: Option<Stroke>,
+}
+
+// The scene at `time` as an SVG document of `width` by `height` pixels.
+// `scale` takes the offsets' units to clip space, as the projection does.
+// Gradient and texture fills are written as the polygon's solid color.
+pub fn document(width: u32, height: u32, scale: [f32; 2], background: &Background, time: f32, elements: &[Element]) -> String {
+    let (half_width, half_height) = (width as f32 / 2.0, height as f32 / 2.0);
+    // SVG's gradients blend the sRGB values, as the background pipeline does
+    let (defs, fill) = if background.gradient {
+        (
+            format!(
+                "<defs>\n    \
+                 <linearGradient id=\"background\" x1=\"0\" y1=\"0\" x2=\"0\" y2=\"1\">\n      \
+                 <stop offset=\"0\" stop-color=\"{}\"/>\n      \
+                 <stop offset=\"1\" stop-color=\"{}\"/>\n    \
+                 </linearGradient>\n  \
+                 </defs>\n  ",
+                hex(background.top), hex(background.color)
+            ),
+            "url(#background)".to_string(),
+        )
+    } else {
+        (String::new(), hex(background.color))
+    };
+    let mut svg = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n  \
+         {}<rect width=\"{w}\" height=\"{h}\" fill=\"{}\"/>\n  \
+         <g transform=\"matrix({} 0 0 {} {} {})\">\n",
+        defs, fill, half_width * scale[0], -half_height * scale[1], half_width, half_height, w = width, h = height
+    );
+    for element in elements {
+        let polygon = element.polygon;
+        let points = |outline: &[[f32; 2]]| {
+            outline.iter().map(|[x, y]| format!("{:.5},{:.5}", x, y)).collect::<Vec<String>>().join(" ")
+        };
+        let shape = match element.loops.as_slice() {
+            [outline] => format!("<polygon points=\"{}\"", points(outline)),
+            loops => {
+                let subpaths: Vec<String> = loops.iter().map(|outline| format!("M {} Z", points(outline))).collect();
+                format!("<path d=\"{}\" fill-rule=\"evenodd\"", subpaths.join(" "))
+            }
+        };
+        let (fill, opacity) = if element.filled { (hex(polygon.color), polygon.opacity) } else { ("none".to_string(), 1.0) };
+        let stroke = match (element.outlined, element.stroke) {
+            (false, _) => String::new(),
+            (true, None) => " stroke=\"#ffffff\" stroke-width=\"1\" vector-effect=\"non-scaling-stroke\"".to_string(),
+            (true, Some(stroke)) => format!(
+                " stroke=\"#ffffff\" stroke-width=\"{:.5}\" stroke-linejoin=\"{}\"",
+                stroke.width, tessellation::join_name(stroke.join)
+            ),
+        };
+        svg += &format!(
+            "    {} transform=\"translate({:.5} {:.5}) rotate({:.3}) scale({:.5})\" fill=\"{}\" fill-opacity=\"{:.2}\"{}/>\n",
+            shape, polygon.offset[0], polygon.offset[1], polygon.angle(time).to_degrees(), polygon.scale, fill, opacity, stroke
+        );
+    }
+    svg += "  </g>\n</svg>\n";
+    svg
+}
+
+// The loops of the first outline in the SVG file at `path`, the outer one
+// first, ready to be a polygon's
+pub fn import(path: impl AsRef<Path>) -> Result<Vec<Vec<[f32; 2]>>, ImportError> {
+    let source = std::fs::read_to_string(path)?;
+    let document = roxmltree::Document::parse(&source)?;
+    let (node, loops) = document
+        .descendants()
+        .find_map(|node| {
+            let loops = match node.tag_name().name() {
+                "polygon" | "polyline" => Ok(vec![PointsParser::from(node.attribute("points")?).collect()]),
+                "path" => path_loops(node.attribute("d")?),
+                _ => return None,
+            };
+            Some((node, loops))
+        })
+        .ok_or(ImportError::NoOutline)?;
+    let mut loops: Vec<Vec<(f64, f64)>> = loops?;
+    // The element's own transform first, then each parent's
+    for ancestor in node.ancestors() {
+        if let Some(transform) = ancestor.attribute("transform") {
+            let Transform { a, b, c, d, e, f } = transform.parse()?;
+            for (x, y) in loops.iter_mut().flatten() {
+                (*x, *y) = (a * *x + c * *y + e, b * *x + d * *y + f);
+            }
+        }
+    }
+    for points in &mut loops {
+        if points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+    }
+    normalize(loops)
+}
+
+// The points of each subpath of path data `d`
+fn path_loops(d: &str) -> Result<Vec<Vec<(f64, f64)>>, ImportError> {
+    let mut loops = Vec::new();
+    let mut points: Vec<(f64, f64)> = Vec::new();
+    for segment in SimplifyingPathParser::from(d) {
+        let last = points.last().copied().unwrap_or_default();
+        match segment? {
+            SimplePathSegment::MoveTo { x, y } => {
+                if !points.is_empty() {
+                    loops.push(std::mem::take(&mut points));
+                }
+                points.push((x, y));
+            }
+            SimplePathSegment::LineTo { x, y } => points.push((x, y)),
+            SimplePathSegment::CurveTo { x1, y1, x2, y2, x, y } => {
+                points.extend((1..=CURVE_POINTS).map(|i| {
+                    let t = i as f64 / CURVE_POINTS as f64;
+                    let s = 1.0 - t;
+                    let bezier = |p0: f64, p1: f64, p2: f64, p3: f64| s * s * s * p0 + 3.0 * s * s * t * p1 + 3.0 * s * t * t * p2 + t * t * t * p3;
+                    (bezier(last.0, x1, x2, x), bezier(last.1, y1, y2, y))
+                }));
+            }
+            SimplePathSegment::Quadratic { x1, y1, x, y } => {
+                points.extend((1..=CURVE_POINTS).map(|i| {
+                    let t = i as f64 / CURVE_POINTS as f64;
+                    let s = 1.0 - t;
+                    let bezier = |p0: f64, p1: f64, p2: f64| s * s * p0 + 2.0 * s * t * p1 + t * t * p2;
+                    (bezier(last.0, x1, x), bezier(last.1, y1, y))
+                }));
+            }
+            SimplePathSegment::ClosePath => {
+                if !points.is_empty() {
+                    loops.push(std::mem::take(&mut points));
+                }
+            }
+        }
+    }
+    if !points.is_empty() {
+        loops.push(points);
+    }
+    Ok(loops)
+}
+
+// Twice the signed area of `points`, positive counterclockwise, and the
+// centroid of the area rather than of the points, which crowd where curves
+// were
+fn area_centroid(points: &[(f64, f64)]) -> (f64, (f64, f64)) {
+    let (mut area, mut cx, mut cy) = (0.0, 0.0, 0.0);
+    for (i, &(x1, y1)) in points.iter().enumerate() {
+        let (x2, y2) = points[(i + 1) % points.len()];
+        let cross = x1 * y2 - x2 * y1;
+        area += cross;
+        cx += (x1 + x2) * cross;
+        cy += (y1 + y2) * cross;
+    }
+    (area, (cx / (3.0 * area), cy / (3.0 * area)))
+}
+
+// `loops`, in SVG's y down pixels, in the y up units of the lab, about the
+// centroid of the largest and RADIUS out at the most, with the largest
+// first and counterclockwise. Loops of fewer than 3 points are dropped.
+fn normalize(loops: Vec<Vec<(f64, f64)>>) -> Result<Vec<Vec<[f32; 2]>>, ImportError> {
+    let most = loops.iter().map(Vec::len).max().unwrap_or(0);
+    let mut loops: Vec<Vec<(f64, f64)>> = loops
+        .into_iter()
+        .filter(|points| points.len() >= 3)
+        .map(|points| points.into_iter().map(|(x, y)| (x, -y)).collect())
+        .collect();
+    if loops.is_empty() {
+        return Err(ImportError::TooFewPoints(most));
+    }
+    let count = loops.iter().map(Vec::len).sum();
+    if count > MAX_POINTS {
+        return Err(ImportError::TooManyPoints(count));
+    }
+    let areas: Vec<f64> = loops.iter().map(|points| area_centroid(points).0).collect();
+    let outer = (0..loops.len()).max_by(|&i, &j| areas[i].abs().total_cmp(&areas[j].abs())).unwrap_or(0);
+    loops.swap(0, outer);
+    let (area, (cx, cy)) = area_centroid(&loops[0]);
+    let extent = loops.iter().flatten().fold(0.0f64, |extent, &(x, y)| extent.max(x.abs()).max(y.abs()));
+    if area.abs() <= 1e-9 * extent * extent {
+        return Err(ImportError::NoArea);
+    }
+    let farthest = loops.iter().flatten().fold(0.0f64, |farthest, &(x, y)| farthest.max((x - cx).hypot(y - cy)));
+    Ok(loops
+        .iter()
+        .map(|points| {
+            let mut outline: Vec<[f32; 2]> = points
+                .iter()
+                .map(|&(x, y)| [((x - cx) / farthest) as f32 * RADIUS, ((y - cy) / farthest) as f32 * RADIUS])
+                .collect();
+            // Holes keep their winding to the outer loop, though the
+            // even-odd rule fills them the same either way
+            if area < 0.0 {
+                outline.reverse();
+            }
+            outline
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape;
+    use crate::state::{ BACKGROUND, GRADIENT_TOP };
+
+    fn import_text(name: &str, svg: &str) -> Result<Vec<Vec<[f32; 2]>>, ImportError> {
+        let path = std::env::temp_dir().join(format!("svg_{}_{}.svg", name, std::process::id()));
+        std::fs::write(&path, svg).unwrap();
+        let loops = import(&path);
+        std::fs::remove_file(&path).unwrap();
+        loops
+    }
+
+    fn svg(body: &str) -> String {
+        format!("<svg xmlns=\"http://www.w3.org/2000/svg\">{}</svg>", body)
+    }
+
+    fn extent(outline: &[[f32; 2]]) -> [f32; 2] {
+        let span = |axis: usize| {
+            let values = outline.iter().map(|point| point[axis]);
+            values.clone().fold(f32::MIN, f32::max) - values.fold(f32::MAX, f32::min)
+        };
+        [span(0), span(1)]
+    }
+
+    fn assert_close(a: &[Vec<[f32; 2]>], b: &[Vec<[f32; 2]>]) {
+        assert_eq!(a.len(), b.len(), "{:?} against {:?}", a, b);
+        for (a, b) in a.iter().zip(b) {
+            assert_eq!(a.len(), b.len(), "{:?} against {:?}", a, b);
+            for (p, q) in a.iter().zip(b) {
+                assert!((p[0] - q[0]).abs() < 1e-4 && (p[1] - q[1]).abs() < 1e-4, "{:?} against {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn polygons_and_paths_import_alike() {
+        let polygon = import_text("polygon", &svg("<polygon points=\"0,0 10,0 10,10 0,10\"/>")).unwrap();
+        let path = import_text("path", &svg("<path d=\"M 0 0 L 10 0 L 10 10 L 0 10 Z\"/>")).unwrap();
+        assert_close(&polygon, &path);
+        let outline = &polygon[0];
+        assert_eq!(outline.len(), 4);
+        // Centered, RADIUS out at the corners and counterclockwise
+        assert!(outline.iter().all(|&[x, y]| (x.hypot(y) - RADIUS).abs() < 1e-6));
+        assert!(shape::area(outline) > 0.0);
+    }
+
+    #[test]
+    fn subpaths_inside_the_largest_are_holes() {
+        // The hole comes first in the file, the outer loop first after import
+        let loops = import_text("hole", &svg("<path d=\"M 3 3 H 7 V 7 H 3 Z M 0 0 H 10 V 10 H 0 Z\"/>")).unwrap();
+        assert_eq!(loops.len(), 2);
+        assert!(shape::area(&loops[0]).abs() > shape::area(&loops[1]).abs());
+        let polygon = Polygon::from_outlines(loops, [1.0; 3]);
+        assert!(!polygon.contains([0.0, 0.0], 0.0));
+        assert!(polygon.contains([0.3, 0.0], 0.0));
+    }
+
+    #[test]
+    fn transforms_apply_innermost_first() {
+        // 10 by 20, turned to 20 by 10, then stretched to 60 by 10; scaling
+        // before turning would give 20 by 30
+        let loops = import_text(
+            "transforms",
+            &svg("<g transform=\"translate(100 50)\"><g transform=\"scale(3 1)\"><polygon transform=\"rotate(90)\" points=\"0,0 10,0 10,20 0,20\"/></g></g>"),
+        )
+        .unwrap();
+        let [width, height] = extent(&loops[0]);
+        assert!((width / height - 6.0).abs() < 1e-4, "{} by {}", width, height);
+    }
+
+    #[test]
+    fn curves_are_flattened() {
+        // Both arch 10 wide from (0, 0) to (10, 0), to 7.5 and 5 deep halfway
+        for (name, d, depth) in [("cubic", "M 0 0 C 0 10 10 10 10 0 Z", 0.75), ("quadratic", "M 0 0 Q 5 10 10 0 Z", 0.5)] {
+            let loops = import_text(name, &svg(&format!("<path d=\"{}\"/>", d))).unwrap();
+            let outline = &loops[0];
+            // The start and CURVE_POINTS along the curve, the last its end
+            assert_eq!(outline.len(), 1 + CURVE_POINTS as usize, "{}", name);
+            let (start, middle, end) = (outline[0], outline[CURVE_POINTS as usize / 2], outline[CURVE_POINTS as usize]);
+            let chord = (end[0] - start[0]).abs();
+            assert!((end[1] - start[1]).abs() < 1e-6, "{}: {:?}", name, outline);
+            assert!((middle[0] - (start[0] + end[0]) / 2.0).abs() < 1e-6, "{}: {:?}", name, outline);
+            // SVG's y runs down, the lab's up
+            assert!(((start[1] - middle[1]) / chord - depth).abs() < 1e-5, "{}: {:?}", name, outline);
+        }
+    }
+
+    #[test]
+    fn outlines_without_area_are_rejected() {
+        assert!(matches!(import_text("line", &svg("<polygon points=\"0,0 5,5 10,10\"/>")), Err(ImportError::NoArea)));
+        assert!(matches!(import_text("short", &svg("<polyline points=\"0,0 5,5\"/>")), Err(ImportError::TooFewPoints(2))));
+        assert!(matches!(import_text("none", &svg("<rect width=\"10\" height=\"10\"/>")), Err(ImportError::NoOutline)));
+    }
+
+    #[test]
+    fn colors_parse_as_svg_writes_them() {
+        assert_eq!(parse_color("#ff8000"), Some([1.0, 128.0 / 255.0, 0.0]));
+        assert_eq!(parse_color("#f80"), Some([1.0, 136.0 / 255.0, 0.0]));
+        assert_eq!(parse_color("navy"), Some([0.0, 0.0, 128.0 / 255.0]));
+        assert_eq!(parse_color("rgb(0, 255, 0)"), Some([0.0, 1.0, 0.0]));
+        assert_eq!(parse_color(&hex([0.2, 0.4, 0.6])), Some([0.2, 0.4, 0.6]));
+        for raw in ["", "#12345", "nope", "rgb(1, 2)"] {
+            assert_eq!(parse_color(raw), None, "{:?} parsed", raw);
+        }
+    }
+
+    #[test]
+    fn exported_scenes_import_again() {
+        let background = Background { color: BACKGROUND, top: GRADIENT_TOP, gradient: true };
+        let square = |half: f32| vec![[-half, -half], [half, -half], [half, half], [-half, half]];
+        let mut hole = square(0.1);
+        hole.reverse();
+        let polygons = [Polygon::new(5, [0.2, 0.4, 0.6]), Polygon::from_outlines(vec![square(RADIUS / 2f32.sqrt()), hole], [1.0; 3])];
+        for (i, polygon) in polygons.iter().enumerate() {
+            let element = Element { polygon, loops: polygon.loops(), filled: true, outlined: true, stroke: None };
+            // Square pixels, so the export keeps the outline's proportions
+            let document = document(800, 600, [0.75, 1.0], &background, 0.0, &[element]);
+            assert_close(&import_text(&format!("export{}", i), &document).unwrap(), &polygon.loops());
+        }
+    }
+}
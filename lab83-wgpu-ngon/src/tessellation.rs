@@ -0,0 +1,89 @@
+use lyon::math::point;
+use lyon::path::Path;
+use lyon::tessellation::{
+    BuffersBuilder, FillOptions, FillRule, FillTessellator, FillVertex, LineJoin, StrokeOptions, StrokeTessellator, StrokeVertex,
+    VertexBuffers,
+};
+
+// lyon's triangles for outlines of any shape, which the fan from the
+// center cannot fill: the fill of a set of loops by the even-odd rule, so
+// a loop inside another is a hole, as in the letters of text outlines, and
+// their stroke as a band of triangles of any width, with the joins cut,
+// rounded or mitred.
+
+// Width of the stroke in the units of the polygon's own frame, where the
+// corners are RADIUS out, so it grows and shrinks with the polygon
+pub const DEFAULT_STROKE_WIDTH: f32 = 0.01;
+pub const DEFAULT_STROKE_JOIN: LineJoin = LineJoin::Miter;
+
+// How far lyon's curves, the round joins, may stray from the true arc.
+// lyon's own default is for pixels, which would be a fifth of a polygon.
+const TOLERANCE: f32 = 0.0005;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Stroke {
+    pub width: f32,
+    pub join: LineJoin,
+}
+
+// The join named on the command line, or None for a name lyon has no join
+// for
+pub fn parse_join(name: &str) -> Option<LineJoin> {
+    match name {
+        "miter" => Some(LineJoin::Miter),
+        "round" => Some(LineJoin::Round),
+        "bevel" => Some(LineJoin::Bevel),
+        _ => None,
+    }
+}
+
+// The join's name in SVG's stroke-linejoin, which parse_join takes back
+// but for miter-clip
+pub fn join_name(join: LineJoin) -> &'static str {
+    match join {
+        LineJoin::Miter => "miter",
+        LineJoin::MiterClip => "miter-clip",
+        LineJoin::Round => "round",
+        LineJoin::Bevel => "bevel",
+    }
+}
+
+// Positions in the polygon's own frame, and the triangles between them
+pub type Mesh = VertexBuffers<[f32; 2], u16>;
+
+// Every loop as a closed subpath
+fn path(loops: &[Vec<[f32; 2]>]) -> Path {
+    let mut builder = Path::builder();
+    for points in loops.iter().filter(|points| points.len() > 1) {
+        builder.begin(point(points[0][0], points[0][1]));
+        for &[x, y] in &points[1..] {
+            builder.line_to(point(x, y));
+        }
+        builder.end(true);
+    }
+    builder.build()
+}
+
+// A mesh lyon could not make, with too many vertices for u16 indices for
+// one, is reported and left empty, so the polygon is not drawn
+pub fn fill(loops: &[Vec<[f32; 2]>]) -> Mesh {
+    let mut mesh = Mesh::new();
+    let options = FillOptions::tolerance(TOLERANCE).with_fill_rule(FillRule::EvenOdd);
+    let mut builder = BuffersBuilder::new(&mut mesh, |vertex: FillVertex| vertex.position().to_array());
+    if let Err(e) = FillTessellator::new().tessellate_path(&path(loops), &options, &mut builder) {
+        eprintln!("Failed to tessellate the fill: {}", e);
+        mesh = Mesh::new();
+    }
+    mesh
+}
+
+pub fn stroke(loops: &[Vec<[f32; 2]>], stroke: Stroke) -> Mesh {
+    let mut mesh = Mesh::new();
+    let options = StrokeOptions::tolerance(TOLERANCE).with_line_width(stroke.width).with_line_join(stroke.join);
+    let mut builder = BuffersBuilder::new(&mut mesh, |vertex: StrokeVertex| vertex.position().to_array());
+    if let Err(e) = StrokeTessellator::new().tessellate_path(&path(loops), &options, &mut builder) {
+        eprintln!("Failed to tessellate the stroke: {}", e);
+        mesh = Mesh::new();
+    }
+    mesh
+}
@@ -0,0 +1,72 @@
+use image::{ Rgba, RgbaImage };
+
+// The image of the texture fill: the one given with `--texture`, or a
+// checkerboard made on the spot without one. There is a single texture
+// for the whole scene, and every polygon with the texture fill shows all
+// of it, stretched over the square its corners' circle fits in.
+
+// Size and squares across of the checkerboard
+const CHECKERBOARD_SIZE: u32 = 256;
+const CHECKERBOARD_SQUARES: u32 = 8;
+
+pub fn load(path: &str) -> image::ImageResult<RgbaImage> {
+    Ok(image::open(path)?.to_rgba8())
+}
+
+pub fn checkerboard() -> RgbaImage {
+    let square = CHECKERBOARD_SIZE / CHECKERBOARD_SQUARES;
+    RgbaImage::from_fn(CHECKERBOARD_SIZE, CHECKERBOARD_SIZE, |x, y| {
+        if (x / square + y / square).is_multiple_of(2) { Rgba([235, 235, 235, 255]) } else { Rgba([40, 70, 90, 255]) }
+    })
+}
+
+// Uploads `image` as a texture and returns a view of it. Images past the
+// largest texture the device takes are scaled down to fit first.
+//
+// The texels are Rgba8Unorm rather than sRGB, so the shader samples the
+// image's bytes as they are, sRGB values like the vertex colors, and
+// fs_main makes them linear along with the rest where the surface is sRGB.
+pub fn upload(device: &wgpu::Device, queue: &wgpu::Queue, image: &RgbaImage) -> wgpu::TextureView {
+    let max = device.limits().max_texture_dimension_2d;
+    let scaled;
+    let image = if image.width() > max || image.height() > max {
+        let ratio = max as f32 / image.width().max(image.height()) as f32;
+        let (width, height) = (((image.width() as f32 * ratio) as u32).max(1), ((image.height() as f32 * ratio) as u32).max(1));
+        eprintln!("Texture is {}x{}, more than this GPU takes, scaling it to {}x{}", image.width(), image.height(), width, height);
+        scaled = image::imageops::resize(image, width, height, image::imageops::FilterType::Triangle);
+        &scaled
+    } else {
+        image
+    };
+    let size = wgpu::Extent3d {
+        width: image.width(),
+        height: image.height(),
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Fill Texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        image,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * image.width()),
+            rows_per_image: Some(image.height()),
+        },
+        size,
+    );
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
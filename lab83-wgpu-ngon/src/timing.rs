@@ -0,0 +1,136 @@
+use std::sync::mpsc::{ self, Receiver };
+use std::time::Instant;
+
+// The frame rate and frame times for the title: the CPU's, from one
+// frame to the next, and the GPU's, from timestamps written either side
+// of the frame's passes where the adapter has TIMESTAMP_QUERY. Both are
+// averaged over REPORT_INTERVAL, as single frames vary too much to read.
+
+const REPORT_INTERVAL: f32 = 0.5;
+
+// The two timestamps of a frame and the buffers they are read back
+// through. Only one frame is timed at a time: frames that start while the
+// last timing is still on its way back go untimed.
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    read_buffer: wgpu::Buffer,
+    // Nanoseconds per tick of a timestamp
+    period: f32,
+    mapped: Option<Receiver<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+impl GpuTimer {
+    // None without TIMESTAMP_QUERY
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Frame Timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2,
+        });
+        let size = 2 * std::mem::size_of::<u64>() as wgpu::BufferAddress;
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Timestamp Resolve Buffer"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let read_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Timestamp Read Buffer"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        Some(GpuTimer { query_set, resolve_buffer, read_buffer, period: queue.get_timestamp_period(), mapped: None })
+    }
+
+    // Writes the first timestamp, unless the last timing is not back yet.
+    // Returns whether the frame is timed, in which case stop and map have
+    // to follow.
+    pub fn start(&self, encoder: &mut wgpu::CommandEncoder) -> bool {
+        if self.mapped.is_some() {
+            return false;
+        }
+        encoder.write_timestamp(&self.query_set, 0);
+        true
+    }
+
+    // Writes the second timestamp and copies both where they can be read
+    pub fn stop(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.write_timestamp(&self.query_set, 1);
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.read_buffer, 0, self.read_buffer.size());
+    }
+
+    // Asks for the timestamps once the encoder is submitted
+    pub fn map(&mut self) {
+        let (sender, receiver) = mpsc::channel();
+        self.read_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.mapped = Some(receiver);
+    }
+
+    // Seconds between the timestamps of the timed frame, once they are
+    // back. The device has to be polled for them to come through.
+    pub fn read(&mut self) -> Option<f32> {
+        let result = self.mapped.as_ref()?.try_recv().ok()?;
+        self.mapped = None;
+        if let Err(e) = result {
+            eprintln!("Failed to read back the timestamps: {}", e);
+            return None;
+        }
+        let ticks = {
+            let timestamps = self.read_buffer.slice(..).get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&timestamps);
+            timestamps[1].wrapping_sub(timestamps[0])
+        };
+        self.read_buffer.unmap();
+        Some(ticks as f32 * self.period * 1e-9)
+    }
+}
+
+// Sums of frame times since the last report
+pub struct FrameStats {
+    since: Instant,
+    cpu: f32,
+    cpu_frames: u32,
+    gpu: f32,
+    gpu_frames: u32,
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        FrameStats { since: Instant::now(), cpu: 0.0, cpu_frames: 0, gpu: 0.0, gpu_frames: 0 }
+    }
+
+    pub fn add_cpu(&mut self, seconds: f32) {
+        self.cpu += seconds;
+        self.cpu_frames += 1;
+    }
+
+    pub fn add_gpu(&mut self, seconds: f32) {
+        self.gpu += seconds;
+        self.gpu_frames += 1;
+    }
+
+    // The frame rate and the averages as "60 FPS, CPU 16.67 ms, GPU 0.214
+    // ms" once every REPORT_INTERVAL, leaving out the GPU if no frame was
+    // timed, and starts over
+    pub fn report(&mut self) -> Option<String> {
+        let elapsed = (Instant::now() - self.since).as_secs_f32();
+        if elapsed < REPORT_INTERVAL || self.cpu_frames == 0 {
+            return None;
+        }
+        let fps = self.cpu_frames as f32 / elapsed;
+        let mut report = format!("{:.0} FPS, CPU {:.2} ms", fps, 1e3 * self.cpu / self.cpu_frames as f32);
+        if self.gpu_frames > 0 {
+            report += &format!(", GPU {:.3} ms", 1e3 * self.gpu / self.gpu_frames as f32);
+        }
+        *self = FrameStats::new();
+        Some(report)
+    }
+}
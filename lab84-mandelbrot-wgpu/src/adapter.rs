@@ -0,0 +1,97 @@
+use std::fmt;
+
+// `--backend`, `--adapter` and `--list-adapters`: which graphics API wgpu
+// goes through and which GPU it runs on, for machines with more than one,
+// such as laptops with an integrated and a discrete GPU.
+
+// The backend named on the command line, or None for a name wgpu has no
+// backend for
+pub fn parse_backend(name: &str) -> Option<wgpu::Backends> {
+    match name {
+        "vulkan" => Some(wgpu::Backends::VULKAN),
+        "metal" => Some(wgpu::Backends::METAL),
+        "dx12" => Some(wgpu::Backends::DX12),
+        "gl" => Some(wgpu::Backends::GL),
+        _ => None,
+    }
+}
+
+// An adapter as `--adapter` picks it: its place in the list that
+// `--list-adapters` prints, or part of its name, in any case
+#[derive(Clone, Debug)]
+pub enum Choice {
+    Index(usize),
+    Name(String),
+}
+
+impl Choice {
+    pub fn parse(raw: &str) -> Self {
+        raw.parse().map_or_else(|_| Choice::Name(raw.to_string()), Choice::Index)
+    }
+
+    fn matches(&self, index: usize, info: &wgpu::AdapterInfo) -> bool {
+        match self {
+            Choice::Index(wanted) => index == *wanted,
+            Choice::Name(name) => info.name.to_lowercase().contains(&name.to_lowercase()),
+        }
+    }
+}
+
+impl fmt::Display for Choice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Choice::Index(index) => write!(f, "adapter {}", index),
+            Choice::Name(name) => write!(f, "\"{}\"", name),
+        }
+    }
+}
+
+pub fn instance(backends: wgpu::Backends) -> wgpu::Instance {
+    wgpu::Instance::new(wgpu::InstanceDescriptor { backends, ..Default::default() })
+}
+
+fn describe(index: usize, info: &wgpu::AdapterInfo) -> String {
+    format!("{}: {} ({:?}, {:?})", index, info.name, info.backend, info.device_type)
+}
+
+// Prints every adapter of `backends`, numbered as `--adapter` counts them
+pub fn list(backends: wgpu::Backends) {
+    let adapters: Vec<_> = instance(backends).enumerate_adapters(backends).collect();
+    if adapters.is_empty() {
+        println!("No adapters found");
+    }
+    for (index, adapter) in adapters.iter().enumerate() {
+        println!("{}", describe(index, &adapter.get_info()));
+    }
+}
+
+// The adapter `choice` picks among those of `backends`, or without a
+// choice the one wgpu prefers. Exits if nothing matches, or if the match
+// cannot present to `surface`.
+pub async fn request(
+    instance: &wgpu::Instance,
+    backends: wgpu::Backends,
+    choice: Option<&Choice>,
+    surface: Option<&wgpu::Surface>,
+    power_preference: wgpu::PowerPreference,
+) -> wgpu::Adapter {
+    let Some(choice) = choice else {
+        return instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference,
+                compatible_surface: surface,
+                force_fallback_adapter: false,
+            })
+            .await
+            .expect("No suitable GPU adapter found");
+    };
+    let Some((index, adapter)) = instance.enumerate_adapters(backends).enumerate().find(|(index, adapter)| choice.matches(*index, &adapter.get_info())) else {
+        eprintln!("No adapter matches {}; --list-adapters shows them", choice);
+        std::process::exit(1);
+    };
+    if surface.is_some_and(|surface| !adapter.is_surface_supported(surface)) {
+        eprintln!("{} cannot draw to this window", describe(index, &adapter.get_info()));
+        std::process::exit(1);
+    }
+    adapter
+}
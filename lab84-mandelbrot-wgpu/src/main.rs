@@ -4,10 +4,69 @@ use winit::{
     window::WindowBuilder,
 };
 
+mod adapter;
 mod state;
-use state::State;
+use state::{ColorSpace, Options, State};
+
+// `--backend vulkan|metal|dx12|gl` and `--adapter INDEX|NAME` choose the GPU;
+// `--list-adapters` prints the adapters `--adapter` picks from and exits.
+// `--surface-format srgb|linear` insists on either; sRGB is preferred anyway.
+// The wheel zooms in and out about the point under the cursor, and dragging with
+// the left button pans.
+fn parse_args() -> Options {
+    let mut args = std::env::args().skip(1);
+    let mut options = Options { backends: wgpu::Backends::all(), adapter: None, color_space: None };
+    let mut list_adapters = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--backend" => {
+                let raw = args.next().unwrap_or_else(|| {
+                    eprintln!("Missing value for --backend");
+                    std::process::exit(2);
+                });
+                options.backends = adapter::parse_backend(&raw).unwrap_or_else(|| {
+                    eprintln!("Invalid value for --backend: {} (expected vulkan, metal, dx12 or gl)", raw);
+                    std::process::exit(2);
+                });
+            }
+            "--adapter" => {
+                let raw = args.next().unwrap_or_else(|| {
+                    eprintln!("Missing value for --adapter");
+                    std::process::exit(2);
+                });
+                options.adapter = Some(adapter::Choice::parse(&raw));
+            }
+            "--list-adapters" => list_adapters = true,
+            "--surface-format" => {
+                let raw = args.next().unwrap_or_else(|| {
+                    eprintln!("Missing value for --surface-format");
+                    std::process::exit(2);
+                });
+                options.color_space = Some(match raw.as_str() {
+                    "srgb" => ColorSpace::Srgb,
+                    "linear" => ColorSpace::Linear,
+                    _ => {
+                        eprintln!("Invalid value for --surface-format: {} (expected srgb or linear)", raw);
+                        std::process::exit(2);
+                    }
+                });
+            }
+            other => {
+                eprintln!("Unknown argument: {}", other);
+                std::process::exit(2);
+            }
+        }
+    }
+    // After the loop, so a --backend anywhere on the line narrows the list
+    if list_adapters {
+        adapter::list(options.backends);
+        std::process::exit(0);
+    }
+    options
+}
 
 fn main() {
+    let options = parse_args();
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
         .with_title("Mandelbrot Set Renderer")
@@ -15,7 +74,7 @@ fn main() {
         .build(&event_loop)
         .unwrap();
 
-    let mut state = pollster::block_on(State::new(window));
+    let mut state = pollster::block_on(State::new(window, &options));
 
     event_loop.run(move |event, _, control_flow| {
         match event {
@@ -30,6 +89,11 @@ fn main() {
                     state.resize(*new_inner_size);
                 }
 
+                WindowEvent::CursorMoved { position, .. } => state.cursor_moved(position),
+                WindowEvent::CursorLeft { .. } => state.cursor_left(),
+                WindowEvent::MouseWheel { delta, .. } => state.mouse_wheel(delta),
+                WindowEvent::MouseInput { state: button_state, button, .. } => state.mouse_input(button, button_state),
+
                 _ => {}
             },
 
@@ -40,6 +40,17 @@ fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
     return out;
 }
 
+// Whether the surface is sRGB. The textures hold sRGB values, so for an
+// sRGB surface, which encodes what it is given, they are made linear
+// first. State::new swaps in `true`.
+const SRGB_SURFACE = false;
+
+fn srgb_to_linear(color: vec3f) -> vec3f {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3f(2.4));
+    return select(high, low, color <= vec3f(0.04045));
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4f {
     // TODO: Sample the texture at the UV coordinates
@@ -47,5 +58,5 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4f {
     let color = textureSample(my_texture, my_sampler, in.uv);
 
     // TODO: Sample and return the texture color
-    return color;
+    return vec4f(select(color.rgb, srgb_to_linear(color.rgb), SRGB_SURFACE), color.a);
 }
\ No newline at end of file
@@ -2,13 +2,56 @@ use bytemuck::{Pod, Zeroable};
 use rayon::prelude::*;
 use std::iter;
 use wgpu::util::DeviceExt;
+use winit::dpi::PhysicalPosition;
+use winit::event::{ElementState, MouseButton, MouseScrollDelta};
 use winit::window::Window;
 
+use crate::adapter::{self, Choice};
+
 const LOW_RES_WIDTH: u32 = 320;
 const LOW_RES_HEIGHT: u32 = 180;
 const MAX_ITERATIONS: u32 = 1000;
 const PREVIEW_ITERATIONS: u32 = 300;
 
+// How much one notch of the wheel zooms, and how far, as the width of the
+// view on the complex plane. Much narrower than MIN_WIDTH, neighbouring
+// pixels land on the same f32.
+const ZOOM_PER_LINE: f32 = 1.2;
+const MIN_WIDTH: f32 = 5e-4;
+const MAX_WIDTH: f32 = 8.0;
+// Touchpads scroll in pixels; this many make one notch
+const PIXELS_PER_LINE: f32 = 50.0;
+
+// Which GPU to run on, and the kind of surface format to insist on, if
+// any, from the command line
+pub struct Options {
+    pub backends: wgpu::Backends,
+    pub adapter: Option<Choice>,
+    pub color_space: Option<ColorSpace>,
+}
+
+// `--surface-format`: sRGB by default where the surface has it. The
+// colors are the same on screen either way, as render.wgsl makes them
+// linear for an sRGB surface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpace {
+    fn name(self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "srgb",
+            ColorSpace::Linear => "linear",
+        }
+    }
+}
+
+// The constant in render.wgsl that tells fs_main the surface is sRGB
+const LINEAR_SURFACE: &str = "const SRGB_SURFACE = false;";
+const SRGB_SURFACE: &str = "const SRGB_SURFACE = true;";
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
 struct ViewParams {
@@ -39,22 +82,31 @@ pub struct State {
     compute_bind_group: wgpu::BindGroup,
 
     show_low_res: bool,
+    // Where the mouse is, while it is over the window
+    cursor: Option<PhysicalPosition<f64>>,
+    // While the left button drags the view, where the cursor was when the
+    // view last moved. The preview is drawn instead of the GPU's render
+    // until the button comes up.
+    drag: Option<PhysicalPosition<f64>>,
+    // The view has moved since the preview was computed. The mouse can
+    // move many times a frame, so render catches up once per frame.
+    preview_stale: bool,
 }
 
 impl State {
-    pub async fn new(window: Window) -> Self {
+    pub async fn new(window: Window, options: &Options) -> Self {
         let size = window.inner_size();
 
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let instance = adapter::instance(options.backends);
         let surface = unsafe { instance.create_surface(&window) }.unwrap();
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
+        let adapter = adapter::request(
+            &instance,
+            options.backends,
+            options.adapter.as_ref(),
+            Some(&surface),
+            wgpu::PowerPreference::HighPerformance,
+        )
+        .await;
 
         let (device, queue) = adapter
             .request_device(
@@ -69,7 +121,16 @@ impl State {
             .unwrap();
 
         let surface_caps = surface.get_capabilities(&adapter);
-        let surface_format = surface_caps.formats[0];
+        let color_space = options.color_space.unwrap_or(ColorSpace::Srgb);
+        let surface_format = surface_caps.formats.iter()
+            .find(|format| format.is_srgb() == (color_space == ColorSpace::Srgb))
+            .copied()
+            .unwrap_or_else(|| {
+                if options.color_space.is_some() {
+                    eprintln!("This surface has no {} format, using {:?}", color_space.name(), surface_caps.formats[0]);
+                }
+                surface_caps.formats[0]
+            });
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface_format,
@@ -81,9 +142,14 @@ impl State {
         };
         surface.configure(&device, &config);
 
+        let render_source = if surface_format.is_srgb() {
+            include_str!("./render.wgsl").replace(LINEAR_SURFACE, SRGB_SURFACE)
+        } else {
+            include_str!("./render.wgsl").to_string()
+        };
         let render_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Render Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("./render.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(render_source.into()),
         });
         let compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Compute Shader"),
@@ -284,35 +350,13 @@ impl State {
             low_res_render_bind_group,
             compute_bind_group,
             show_low_res: false,
+            cursor: None,
+            drag: None,
+            preview_stale: false,
         };
 
         s.trigger_render(false);
-
-        let preview_params = ViewParams {
-            screen_dims: [LOW_RES_WIDTH, LOW_RES_HEIGHT],
-            ..s.view_params
-        };
-        let low_res_pixels = compute_cpu_preview(&preview_params);
-        s.queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &s.low_res_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &low_res_pixels,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * LOW_RES_WIDTH),
-                rows_per_image: Some(LOW_RES_HEIGHT),
-            },
-            wgpu::Extent3d {
-                width: LOW_RES_WIDTH,
-                height: LOW_RES_HEIGHT,
-                depth_or_array_layers: 1,
-            },
-        );
-        s.show_low_res = true;
+        s.update_preview();
 
         s
     }
@@ -365,34 +409,111 @@ impl State {
         }
     }
 
+    // Moves the view with the cursor while dragging, so the point under
+    // it stays there
+    pub fn cursor_moved(&mut self, position: PhysicalPosition<f64>) {
+        if let Some(last) = self.drag {
+            let range = self.view_params.range;
+            self.view_params.center[0] -= (position.x - last.x) as f32 / self.size.width as f32 * range[0];
+            self.view_params.center[1] -= (position.y - last.y) as f32 / self.size.height as f32 * range[1];
+            self.drag = Some(position);
+            self.preview_stale = true;
+        }
+        self.cursor = Some(position);
+    }
+
+    // The left button starts a drag, and letting go of it renders the view
+    // it ended on at full resolution
+    pub fn mouse_input(&mut self, button: MouseButton, state: ElementState) {
+        if button != MouseButton::Left {
+            return;
+        }
+        match state {
+            ElementState::Pressed => self.drag = self.cursor,
+            ElementState::Released => {
+                if self.drag.take().is_some() {
+                    self.trigger_render(false);
+                }
+            }
+        }
+    }
+
+    pub fn cursor_left(&mut self) {
+        self.cursor = None;
+    }
+
+    // The point of the complex plane at `position` in the window, mapped
+    // the way compute.wgsl maps its pixels
+    fn to_plane(&self, position: PhysicalPosition<f64>) -> [f32; 2] {
+        let norm = [
+            position.x as f32 / self.size.width as f32 - 0.5,
+            position.y as f32 / self.size.height as f32 - 0.5,
+        ];
+        let ViewParams { center, range, .. } = self.view_params;
+        [center[0] + norm[0] * range[0], center[1] + norm[1] * range[1]]
+    }
+
+    // Zooms in for the wheel turned away from the user and out for it
+    // turned towards them, keeping the point under the cursor in place, or
+    // the center while the cursor is outside the window
+    pub fn mouse_wheel(&mut self, delta: MouseScrollDelta) {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, y) => y,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+        };
+        let width = self.view_params.range[0];
+        let factor = (width / ZOOM_PER_LINE.powf(lines)).clamp(MIN_WIDTH, MAX_WIDTH) / width;
+        if factor == 1.0 {
+            return;
+        }
+        let anchor = match self.cursor {
+            Some(position) => self.to_plane(position),
+            None => self.view_params.center,
+        };
+        let ViewParams { center, range, .. } = self.view_params;
+        self.view_params.center = [
+            anchor[0] + (center[0] - anchor[0]) * factor,
+            anchor[1] + (center[1] - anchor[1]) * factor,
+        ];
+        self.view_params.range = [range[0] * factor, range[1] * factor];
+        self.trigger_render(true);
+    }
+
+    // Computes the preview of the view on the CPU and shows it for the
+    // next frame
+    fn update_preview(&mut self) {
+        let preview_params = ViewParams {
+            screen_dims: [LOW_RES_WIDTH, LOW_RES_HEIGHT],
+            ..self.view_params
+        };
+        let low_res_pixels = compute_cpu_preview(&preview_params);
+
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.low_res_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &low_res_pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * LOW_RES_WIDTH),
+                rows_per_image: Some(LOW_RES_HEIGHT),
+            },
+            wgpu::Extent3d {
+                width: LOW_RES_WIDTH,
+                height: LOW_RES_HEIGHT,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.show_low_res = true;
+        self.preview_stale = false;
+    }
+
     fn trigger_render(&mut self, with_preview: bool) {
         if with_preview {
-            let preview_params = ViewParams {
-                screen_dims: [LOW_RES_WIDTH, LOW_RES_HEIGHT],
-                ..self.view_params
-            };
-            let low_res_pixels = compute_cpu_preview(&preview_params);
-
-            self.queue.write_texture(
-                wgpu::ImageCopyTexture {
-                    texture: &self.low_res_texture,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d::ZERO,
-                    aspect: wgpu::TextureAspect::All,
-                },
-                &low_res_pixels,
-                wgpu::ImageDataLayout {
-                    offset: 0,
-                    bytes_per_row: Some(4 * LOW_RES_WIDTH),
-                    rows_per_image: Some(LOW_RES_HEIGHT),
-                },
-                wgpu::Extent3d {
-                    width: LOW_RES_WIDTH,
-                    height: LOW_RES_HEIGHT,
-                    depth_or_array_layers: 1,
-                },
-            );
-            self.show_low_res = true;
+            self.update_preview();
         }
 
         self.view_params.screen_dims = [self.size.width, self.size.height];
@@ -430,6 +551,9 @@ impl State {
 
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        if self.preview_stale {
+            self.update_preview();
+        }
         let output_frame = self.surface.get_current_texture()?;
         let view = output_frame
             .texture
@@ -454,7 +578,7 @@ impl State {
 
             render_pass.set_pipeline(&self.render_pipeline);
 
-            if self.show_low_res {
+            if self.show_low_res || self.drag.is_some() {
                 render_pass.set_bind_group(0, &self.low_res_render_bind_group, &[]);
                 self.show_low_res = false;
             } else {